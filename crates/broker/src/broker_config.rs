@@ -0,0 +1,313 @@
+//! Optional broker configuration file, `.agent-relay/config.toml`.
+//!
+//! Every setting has an environment variable that takes precedence over it.
+//! The file is loaded once at startup and the parsed [`BrokerConfigFile`] is
+//! held by the runtime and passed to whatever reads a setting; nothing is
+//! written back to the process environment, so workers do not see it.
+//!
+//! ```toml
+//! [api]
+//! port = 3889
+//!
+//! [delivery]
+//! retry_ms = 2000
+//!
+//! [agents]
+//! default_channels = ["general", "ops"]
+//! idle_threshold_secs = 60
+//! max_concurrent = 8
+//!
+//! [docker]
+//! image = "ghcr.io/acme/agent:latest"
+//! run_args = ["--network", "host"]
+//!
+//! [kubernetes]
+//! namespace = "agents"
+//! pod_template = "deploy/agent-pod.json"
+//!
+//! [ssh]
+//! opts = ["-i", "~/.ssh/build_box"]
+//!
+//! [headless.aider]
+//! command = ["aider", "--yes", "--message", "{prompt}"]
+//!
+//! [profiles.reviewer]
+//! cli = "claude"
+//! model = "opus"
+//! channels = ["reviews"]
+//!
+//! [logs]
+//! retention_days = 7
+//!
+//! [cli.codex]
+//! bypass_permissions = false
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    headless_template::{templates_from_env, HeadlessTemplate},
+    spawn_profile::{spawn_profiles_from_env, SpawnProfile},
+};
+
+/// Looked up relative to the broker's working directory.
+pub(crate) const DEFAULT_CONFIG_PATH: &str = ".agent-relay/config.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BrokerConfigFile {
+    pub(crate) api: ApiSection,
+    pub(crate) delivery: DeliverySection,
+    pub(crate) agents: AgentsSection,
+    pub(crate) docker: DockerSection,
+    pub(crate) kubernetes: KubernetesSection,
+    pub(crate) ssh: SshSection,
+    /// Headless provider command templates keyed by provider name.
+    pub(crate) headless: BTreeMap<String, HeadlessTemplate>,
+    /// Spawn profiles keyed by profile name.
+    pub(crate) profiles: BTreeMap<String, SpawnProfile>,
+    pub(crate) logs: LogsSection,
+    pub(crate) orphans: OrphansSection,
+    /// `<count>/<window>` or `off`, keyed by `spawn`, `send`, `release`.
+    pub(crate) rate_limits: RateLimitsSection,
+    /// Per-CLI settings keyed by CLI name (`claude`, `codex`, `gemini`, `grok`).
+    pub(crate) cli: BTreeMap<String, CliSection>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ApiSection {
+    /// Used when `--api-port` is not passed.
+    pub(crate) port: Option<u16>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DeliverySection {
+    pub(crate) retry_ms: Option<u64>,
+    pub(crate) drain_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AgentsSection {
+    pub(crate) default_channels: Option<Vec<String>>,
+    /// Applied to spawns that do not pass their own threshold.
+    pub(crate) idle_threshold_secs: Option<u64>,
+    pub(crate) scrollback_lines: Option<usize>,
+    /// Spawns past this many running agents are queued.
+    pub(crate) max_concurrent: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DockerSection {
+    /// Image for `docker` runtime agents that do not name one.
+    pub(crate) image: Option<String>,
+    /// Extra options for every `docker run`.
+    pub(crate) run_args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct KubernetesSection {
+    pub(crate) namespace: Option<String>,
+    /// JSON Pod manifest that `kubernetes` runtime agents are built from.
+    pub(crate) pod_template: Option<String>,
+    pub(crate) poll_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SshSection {
+    /// Extra options for every `ssh` call to an agent host.
+    pub(crate) opts: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LogsSection {
+    /// Daily broker log files to keep.
+    pub(crate) retention_days: Option<usize>,
+    /// `text` or `json` worker log lines.
+    pub(crate) worker_format: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OrphansSection {
+    pub(crate) audit_secs: Option<u64>,
+    pub(crate) reap: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RateLimitsSection {
+    pub(crate) spawn: Option<String>,
+    pub(crate) send: Option<String>,
+    pub(crate) release: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CliSection {
+    /// `false` stops the broker auto-injecting this CLI's permission-bypass
+    /// flag (`--dangerously-skip-permissions`, `--yolo`, ...).
+    pub(crate) bypass_permissions: Option<bool>,
+}
+
+/// A parsed config file and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LoadedBrokerConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) config: BrokerConfigFile,
+}
+
+impl BrokerConfigFile {
+    pub(crate) fn parse(raw: &str) -> Result<Self> {
+        Ok(toml::from_str(raw)?)
+    }
+
+    /// Load `explicit`, or [`DEFAULT_CONFIG_PATH`] under `cwd` when it exists.
+    /// A missing default file is not an error; a missing explicit one is.
+    pub(crate) fn load(explicit: Option<&Path>, cwd: &Path) -> Result<Option<LoadedBrokerConfig>> {
+        let path = match explicit {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = cwd.join(DEFAULT_CONFIG_PATH);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read broker config {}", path.display()))?;
+        let config = Self::parse(&raw)
+            .with_context(|| format!("invalid broker config {}", path.display()))?;
+        Ok(Some(LoadedBrokerConfig { path, config }))
+    }
+
+    /// Overlay the headless templates and spawn profiles set as JSON in
+    /// `AGENT_RELAY_HEADLESS_PROVIDERS` and `AGENT_RELAY_SPAWN_PROFILES`. The
+    /// environment wins for names defined in both.
+    pub(crate) fn with_env_overrides(mut self) -> Result<Self> {
+        self.headless.extend(templates_from_env()?);
+        self.profiles.extend(spawn_profiles_from_env()?);
+        Ok(self)
+    }
+}
+
+/// `key` from the environment when it is set, else the file's value. An
+/// unparseable env value yields `None` rather than falling back, so the env
+/// stays authoritative.
+pub(crate) fn env_or<T: FromStr>(key: &str, file: Option<T>) -> Option<T> {
+    match std::env::var(key) {
+        Ok(raw) => raw.trim().parse().ok(),
+        Err(_) => file,
+    }
+}
+
+/// Like [`env_or`] for option lists: the env value is shell-split.
+pub(crate) fn env_args_or(key: &str, file: Option<&[String]>) -> Result<Vec<String>> {
+    match std::env::var(key) {
+        Ok(raw) if !raw.trim().is_empty() => {
+            shlex::split(&raw).with_context(|| format!("invalid {key} (check quoting)"))
+        }
+        Ok(_) => Ok(Vec::new()),
+        Err(_) => Ok(file.map(<[String]>::to_vec).unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_is_the_default() {
+        assert_eq!(
+            BrokerConfigFile::parse("").unwrap(),
+            BrokerConfigFile::default()
+        );
+    }
+
+    #[test]
+    fn sections_parse_into_typed_settings() {
+        let config = BrokerConfigFile::parse(
+            r#"
+            [api]
+            port = 3889
+
+            [agents]
+            default_channels = ["general", "ops"]
+            max_concurrent = 8
+
+            [docker]
+            run_args = ["--network", "host", "--label", "team=core ops"]
+
+            [headless.aider]
+            command = ["aider", "--message", "{prompt}"]
+
+            [profiles.reviewer]
+            cli = "claude"
+            channels = ["reviews"]
+            restart_policy = { max_restarts = 2 }
+
+            [rate_limits]
+            send = "off"
+
+            [cli.codex]
+            bypass_permissions = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.api.port, Some(3889));
+        assert_eq!(
+            config.agents.default_channels,
+            Some(vec!["general".to_string(), "ops".to_string()])
+        );
+        assert_eq!(config.agents.max_concurrent, Some(8));
+        assert_eq!(
+            config.docker.run_args.as_deref(),
+            Some(&["--network", "host", "--label", "team=core ops"].map(String::from)[..])
+        );
+        assert_eq!(
+            config.headless["aider"].command,
+            ["aider", "--message", "{prompt}"]
+        );
+        assert_eq!(config.profiles["reviewer"].cli.as_deref(), Some("claude"));
+        assert_eq!(config.rate_limits.send.as_deref(), Some("off"));
+        assert_eq!(config.cli["codex"].bypass_permissions, Some(false));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let error = BrokerConfigFile::parse("[delivery]\nretry_msec = 10\n").unwrap_err();
+        assert!(error.to_string().contains("retry_msec"), "{error}");
+    }
+
+    #[test]
+    fn missing_default_file_is_not_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(BrokerConfigFile::load(None, dir.path()).unwrap(), None);
+        assert!(BrokerConfigFile::load(Some(&dir.path().join("nope.toml")), dir.path()).is_err());
+
+        std::fs::create_dir_all(dir.path().join(".agent-relay")).unwrap();
+        std::fs::write(
+            dir.path().join(DEFAULT_CONFIG_PATH),
+            "[orphans]\nreap = true\n",
+        )
+        .unwrap();
+        let loaded = BrokerConfigFile::load(None, dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.path, dir.path().join(DEFAULT_CONFIG_PATH));
+        assert_eq!(loaded.config.orphans.reap, Some(true));
+    }
+}
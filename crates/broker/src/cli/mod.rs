@@ -1,7 +1,7 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use crate::{
-    protocol::HeadlessProvider as ProtocolHeadlessProvider,
+    broker_config::BrokerConfigFile,
     telemetry::{TelemetryClient, TelemetryEvent},
 };
 use anyhow::Result;
@@ -109,7 +109,21 @@ fn non_empty_name(value: Option<&str>) -> Option<String> {
 
 pub(crate) async fn run() -> Result<()> {
     let cli = Cli::parse();
-    runtime::init_tracing(&cli.command.log_identifier());
+    // Loaded before tracing starts so log settings from the file take effect.
+    let (config_path, broker_config) = match &cli.command {
+        Commands::Init(cmd) => {
+            match BrokerConfigFile::load(cmd.config.as_deref(), &std::env::current_dir()?)? {
+                Some(loaded) => (Some(loaded.path), loaded.config.with_env_overrides()?),
+                None => (None, BrokerConfigFile::default().with_env_overrides()?),
+            }
+        }
+        _ => (None, BrokerConfigFile::default()),
+    };
+    let broker_config = Arc::new(broker_config);
+    runtime::init_tracing(&cli.command.log_identifier(), &broker_config);
+    if let Some(path) = &config_path {
+        tracing::info!(path = %path.display(), "loaded broker config");
+    }
 
     let telemetry = TelemetryClient::new();
     telemetry.track(TelemetryEvent::CliCommandRun {
@@ -117,7 +131,7 @@ pub(crate) async fn run() -> Result<()> {
     });
 
     match cli.command {
-        Commands::Init(cmd) => runtime::run_init(cmd, telemetry).await,
+        Commands::Init(cmd) => runtime::run_init(cmd, broker_config, telemetry).await,
         Commands::Pty(cmd) => pty_worker::run_pty_worker(cmd).await,
         Commands::Headless(cmd) => runtime::run_headless_worker(cmd).await,
         Commands::HeadlessAppServer(cmd) => runtime::run_headless_app_server_worker(cmd).await,
@@ -255,6 +269,19 @@ pub(crate) struct InitCommand {
     /// working directory when `--persist` is set, or a temp directory otherwise.
     #[arg(long)]
     pub(crate) state_dir: Option<String>,
+
+    /// Broker config file. Defaults to `.agent-relay/config.toml` in the
+    /// working directory when present. Environment variables take
+    /// precedence over its settings.
+    #[arg(long)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// On SIGTERM, drain instead of exiting immediately: refuse new spawns
+    /// and node deliveries, notify agents, and exit once pending deliveries
+    /// settle (or after AGENT_RELAY_DRAIN_TIMEOUT_SECS, default 30). A second
+    /// SIGTERM exits right away.
+    #[arg(long, default_value_t = false)]
+    pub(crate) drain: bool,
 }
 
 impl InitCommand {
@@ -323,6 +350,8 @@ mod tests {
             api_bind: "127.0.0.1".to_string(),
             persist: false,
             state_dir: None,
+            config: None,
+            drain: false,
         }
     }
 
@@ -407,7 +436,9 @@ pub(crate) struct PtyCommand {
 
 #[derive(Debug, clap::Args, Clone)]
 pub(crate) struct HeadlessCommand {
-    pub(crate) provider: HeadlessCliProvider,
+    /// `claude`, `opencode`, `codex`, `gemini`, or a configured headless
+    /// provider template.
+    pub(crate) provider: String,
 
     #[arg(last = true)]
     pub(crate) args: Vec<String>,
@@ -436,18 +467,3 @@ pub(crate) struct HeadlessAppServerCommand {
     #[arg(long)]
     pub(crate) agent_name: Option<String>,
 }
-
-#[derive(Debug, Clone, Copy, ValueEnum)]
-pub(crate) enum HeadlessCliProvider {
-    Claude,
-    Opencode,
-}
-
-impl From<HeadlessCliProvider> for ProtocolHeadlessProvider {
-    fn from(value: HeadlessCliProvider) -> Self {
-        match value {
-            HeadlessCliProvider::Claude => Self::Claude,
-            HeadlessCliProvider::Opencode => Self::Opencode,
-        }
-    }
-}
@@ -0,0 +1,347 @@
+//! Command templates for headless providers.
+//!
+//! A headless agent runs its CLI once per delivery. A template says how: the
+//! command to run, how the delivery text reaches it, and how to read the
+//! response from its stdout. Templates configured in
+//! `AGENT_RELAY_HEADLESS_PROVIDERS` (a JSON object keyed by provider name, or
+//! `[headless.<name>]` in the broker config) let any CLI with a print mode
+//! run headless; the broker resolves them at startup and passes a worker only
+//! its own template:
+//!
+//! ```toml
+//! [headless.aider]
+//! command = ["aider", "--yes", "--message", "{prompt}"]
+//!
+//! [headless.mycli]
+//! command = ["mycli", "run", "--output", "jsonl"]
+//! prompt = "stdin"
+//! output = "jsonl"
+//! text_pointer = "/message/text"
+//! model_flag = "--model"
+//! session_args = ["--session", "{session_id}"]
+//! resume_args = ["--resume", "{session_id}"]
+//! ```
+//!
+//! A worker keeps one conversation across its deliveries when the template
+//! has `session_args` (passed on the first run) and `resume_args` (passed on
+//! every run after one that succeeded).
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub(crate) const HEADLESS_PROVIDERS_ENV: &str = "AGENT_RELAY_HEADLESS_PROVIDERS";
+
+/// Set by the broker on a headless worker that runs a configured template:
+/// that one template, as JSON.
+pub(crate) const HEADLESS_TEMPLATE_ENV: &str = "AGENT_RELAY_HEADLESS_TEMPLATE";
+
+/// Replaced by the delivery text wherever it appears in `command`.
+const PROMPT_PLACEHOLDER: &str = "{prompt}";
+
+/// Replaced by the worker's conversation id in `session_args` and
+/// `resume_args`.
+const SESSION_PLACEHOLDER: &str = "{session_id}";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HeadlessTemplate {
+    /// Program and leading arguments.
+    pub(crate) command: Vec<String>,
+    #[serde(default)]
+    pub(crate) prompt: PromptInput,
+    #[serde(default)]
+    pub(crate) output: OutputFormat,
+    /// JSON pointer to the response text in each JSON value of the output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) text_pointer: Option<String>,
+    /// Flag the spawn's `model` is passed with; without one it is dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) model_flag: Option<String>,
+    /// Arguments that start the worker's conversation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) session_args: Vec<String>,
+    /// Arguments that continue the worker's conversation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) resume_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) env: BTreeMap<String, String>,
+}
+
+/// How the delivery text is passed to the command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PromptInput {
+    /// Substituted for `{prompt}`, or appended after the spawn args when
+    /// `command` has no placeholder.
+    #[default]
+    Arg,
+    /// Written to stdin, which is then closed.
+    Stdin,
+}
+
+/// How the command's stdout is turned into response text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OutputFormat {
+    /// Every line is response text.
+    #[default]
+    Text,
+    /// The whole of stdout is one JSON document.
+    Json,
+    /// Every line is a JSON value; lines without text are skipped.
+    Jsonl,
+}
+
+/// One run of a template for a delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HeadlessInvocation {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+    pub(crate) stdin: Option<String>,
+    pub(crate) env: Vec<(String, String)>,
+}
+
+impl HeadlessTemplate {
+    /// The command for one delivery, with the spawn's `extra_args` after the
+    /// template's own.
+    pub(crate) fn invocation(
+        &self,
+        prompt: &str,
+        extra_args: &[String],
+    ) -> Result<HeadlessInvocation> {
+        let (program, template_args) = self
+            .command
+            .split_first()
+            .context("headless template has an empty command")?;
+        let has_placeholder = template_args
+            .iter()
+            .any(|arg| arg.contains(PROMPT_PLACEHOLDER));
+        let mut args: Vec<String> = template_args
+            .iter()
+            .map(|arg| arg.replace(PROMPT_PLACEHOLDER, prompt))
+            .collect();
+        args.extend(extra_args.iter().cloned());
+        let stdin = match self.prompt {
+            PromptInput::Stdin => Some(prompt.to_string()),
+            PromptInput::Arg if has_placeholder => None,
+            PromptInput::Arg => {
+                args.push(prompt.to_string());
+                None
+            }
+        };
+        Ok(HeadlessInvocation {
+            program: program.clone(),
+            args,
+            stdin,
+            env: self
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        })
+    }
+
+    /// `session_args`, or `resume_args` once the conversation has started,
+    /// for the conversation `session_id`.
+    pub(crate) fn conversation_args(&self, session_id: &str, resume: bool) -> Vec<String> {
+        let args = if resume {
+            &self.resume_args
+        } else {
+            &self.session_args
+        };
+        args.iter()
+            .map(|arg| arg.replace(SESSION_PLACEHOLDER, session_id))
+            .collect()
+    }
+
+    pub(crate) fn output_parser(&self) -> OutputParser {
+        OutputParser {
+            format: self.output,
+            text_pointer: self.text_pointer.clone(),
+            buffer: String::new(),
+        }
+    }
+}
+
+/// Templates from [`HEADLESS_PROVIDERS_ENV`].
+pub(crate) fn templates_from_env() -> Result<BTreeMap<String, HeadlessTemplate>> {
+    match std::env::var(HEADLESS_PROVIDERS_ENV) {
+        Ok(raw) if !raw.trim().is_empty() => {
+            serde_json::from_str(&raw).with_context(|| format!("invalid {HEADLESS_PROVIDERS_ENV}"))
+        }
+        _ => Ok(BTreeMap::new()),
+    }
+}
+
+/// The template the broker handed this worker in [`HEADLESS_TEMPLATE_ENV`].
+pub(crate) fn worker_template_from_env() -> Result<Option<HeadlessTemplate>> {
+    match std::env::var(HEADLESS_TEMPLATE_ENV) {
+        Ok(raw) if !raw.trim().is_empty() => serde_json::from_str(&raw)
+            .map(Some)
+            .with_context(|| format!("invalid {HEADLESS_TEMPLATE_ENV}")),
+        _ => Ok(None),
+    }
+}
+
+/// Turns a headless command's stdout lines into response text.
+#[derive(Debug)]
+pub(crate) struct OutputParser {
+    format: OutputFormat,
+    text_pointer: Option<String>,
+    buffer: String,
+}
+
+impl OutputParser {
+    /// Text to stream for one stdout line, if any yet.
+    pub(crate) fn push_line(&mut self, line: &str) -> Option<String> {
+        match self.format {
+            OutputFormat::Text => Some(line.to_string()),
+            OutputFormat::Json => {
+                self.buffer.push_str(line);
+                self.buffer.push('\n');
+                None
+            }
+            // Lines that are not JSON (warnings, banners) pass through.
+            OutputFormat::Jsonl => match serde_json::from_str::<Value>(line) {
+                Ok(value) => self.text(&value),
+                Err(_) => Some(line.to_string()),
+            },
+        }
+    }
+
+    /// Text left once stdout closes.
+    pub(crate) fn finish(&mut self) -> Option<String> {
+        let buffer = std::mem::take(&mut self.buffer);
+        if buffer.trim().is_empty() {
+            return None;
+        }
+        match serde_json::from_str::<Value>(&buffer) {
+            Ok(value) => self.text(&value),
+            Err(_) => Some(buffer.trim_end().to_string()),
+        }
+    }
+
+    fn text(&self, value: &Value) -> Option<String> {
+        let value = match self.text_pointer.as_deref() {
+            Some(pointer) => value.pointer(pointer)?,
+            None => value,
+        };
+        match value {
+            Value::Null => None,
+            Value::String(text) => Some(text.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn template(value: Value) -> HeadlessTemplate {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn prompt_replaces_placeholder_or_follows_extra_args() {
+        let placeholder = template(json!({ "command": ["aider", "--message={prompt}", "--yes"] }));
+        let invocation = placeholder
+            .invocation("fix it", &["--model".to_string(), "o3".to_string()])
+            .unwrap();
+        assert_eq!(invocation.program, "aider");
+        assert_eq!(
+            invocation.args,
+            ["--message=fix it", "--yes", "--model", "o3"]
+        );
+        assert_eq!(invocation.stdin, None);
+
+        let appended = template(json!({ "command": ["mycli", "run"] }));
+        let invocation = appended.invocation("fix it", &["-v".to_string()]).unwrap();
+        assert_eq!(invocation.args, ["run", "-v", "fix it"]);
+
+        let stdin = template(json!({
+            "command": ["mycli"],
+            "prompt": "stdin",
+            "env": { "MYCLI_YES": "1" },
+        }));
+        let invocation = stdin.invocation("fix it", &[]).unwrap();
+        assert!(invocation.args.is_empty());
+        assert_eq!(invocation.stdin.as_deref(), Some("fix it"));
+        assert_eq!(invocation.env, [("MYCLI_YES".to_string(), "1".to_string())]);
+
+        assert!(template(json!({ "command": [] }))
+            .invocation("x", &[])
+            .is_err());
+    }
+
+    #[test]
+    fn conversation_args_start_then_resume_the_session() {
+        let resumable = template(json!({
+            "command": ["mycli"],
+            "session_args": ["--session-id", "{session_id}"],
+            "resume_args": ["--resume={session_id}"],
+        }));
+        assert_eq!(
+            resumable.conversation_args("s-1", false),
+            ["--session-id", "s-1"]
+        );
+        assert_eq!(resumable.conversation_args("s-1", true), ["--resume=s-1"]);
+        assert!(template(json!({ "command": ["mycli"] }))
+            .conversation_args("s-1", true)
+            .is_empty());
+    }
+
+    #[test]
+    fn jsonl_output_streams_text_at_pointer() {
+        let mut parser = template(json!({
+            "command": ["mycli"],
+            "output": "jsonl",
+            "text_pointer": "/message/text",
+        }))
+        .output_parser();
+
+        assert_eq!(
+            parser.push_line(r#"{"type":"message","message":{"text":"hello"}}"#),
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            parser.push_line(r#"{"type":"tool_use","name":"bash"}"#),
+            None
+        );
+        assert_eq!(
+            parser.push_line("warning: slow"),
+            Some("warning: slow".to_string())
+        );
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn json_output_is_read_when_stdout_closes() {
+        let mut parser = template(json!({
+            "command": ["mycli"],
+            "output": "json",
+            "text_pointer": "/result",
+        }))
+        .output_parser();
+
+        assert_eq!(parser.push_line("{"), None);
+        assert_eq!(parser.push_line(r#"  "result": "done""#), None);
+        assert_eq!(parser.push_line("}"), None);
+        assert_eq!(parser.finish(), Some("done".to_string()));
+    }
+
+    #[test]
+    fn unknown_template_keys_are_rejected() {
+        let error = serde_json::from_value::<HeadlessTemplate>(json!({
+            "command": ["mycli"],
+            "prompt_mode": "stdin",
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("prompt_mode"), "{error}");
+    }
+}
@@ -10,8 +10,11 @@ pub mod fleet_wire;
 pub mod ids;
 pub mod protocol;
 pub mod snippets;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub(crate) mod broker;
+pub(crate) mod broker_config;
 pub(crate) mod cli;
 pub(crate) mod cli_mcp_args;
 pub(crate) mod codex_session;
@@ -25,33 +28,39 @@ pub(crate) mod crash_insights;
 pub(crate) mod dedup;
 #[allow(dead_code)]
 pub(crate) mod events;
+pub(crate) mod headless_template;
 pub(crate) mod listen_api;
 #[allow(dead_code)]
 pub(crate) mod metrics;
 pub(crate) mod node_control;
+#[cfg(feature = "otel")]
+pub(crate) mod otel;
 pub(crate) mod priorities;
 #[allow(dead_code)]
 pub(crate) mod pty;
 pub(crate) mod pty_worker;
 #[allow(dead_code)]
 pub(crate) mod queue;
+pub(crate) mod rate_limit;
 pub(crate) mod readiness;
 #[allow(dead_code)]
 pub(crate) mod redact;
 #[allow(dead_code)]
 pub(crate) mod relaycast;
 pub(crate) mod replay_buffer;
-// Local-target routing helpers, kept for their unit tests but no longer
-// called from production code: the HTTP/sidecar send path (runtime/api.rs)
+// Local-target routing helpers. The HTTP/sidecar send path (runtime/api.rs)
 // no longer resolves local targets and injects directly — it always
 // publishes through Relaycast and lets node delivery (runtime/fleet.rs)
-// redeliver, even to workers attached to this same broker.
+// redeliver, even to workers attached to this same broker. Only the `@team`
+// fan-out, which picks the members to publish to, is still called.
 #[allow(dead_code)]
 pub(crate) mod routing;
 pub(crate) mod runtime;
 #[allow(dead_code)]
 pub(crate) mod scheduler;
+pub(crate) mod scrollback;
 pub(crate) mod snapshot;
+pub(crate) mod spawn_profile;
 pub(crate) mod spawner;
 #[allow(dead_code)]
 pub(crate) mod supervisor;
@@ -5,16 +5,23 @@
 //! sending messages.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use crate::{
-    ids::{ChannelName, MessageTarget, ThreadId, WorkerName, WorkspaceAlias, WorkspaceId},
-    protocol::{MessageInjectionMode, ProtocolEnvelope, ResolvedHarnessConfig},
+    ids::{
+        ChannelName, DeliveryId, MessageTarget, ThreadId, WorkerName, WorkspaceAlias, WorkspaceId,
+    },
+    protocol::{
+        AgentResourceLimits, MessageInjectionMode, Plan, ProtocolEnvelope, ResolvedHarnessConfig,
+        TeamManifest,
+    },
+    rate_limit::{LimitedRoute, RateLimitConfig, RateLimiter},
     relaycast::WorkspaceMembershipSummary,
     replay_buffer::ReplayBuffer,
+    spawn_profile::{spawn_profile, SpawnProfile},
     types::{InboundDeliveryMode, PendingRelayMessage},
 };
 use serde::Deserialize;
@@ -56,6 +63,10 @@ pub enum ListenApiRequest {
         harness_config: Option<ResolvedHarnessConfig>,
         agent_token: Option<String>,
         agent_result_schema: Option<Value>,
+        limits: AgentResourceLimits,
+        image: Option<String>,
+        /// Agents that must report ready before this one is spawned.
+        after: Vec<WorkerName>,
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
     SetModel {
@@ -64,6 +75,40 @@ pub enum ListenApiRequest {
         timeout_ms: Option<u64>,
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
+    /// `POST /api/spawned/{name}/pause` and `/resume` — stop or continue
+    /// the worker's process tree. Deliveries are held while paused.
+    SetAgentPaused {
+        name: WorkerName,
+        paused: bool,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `POST /api/team` — spawn every agent in the manifest, or none.
+    SpawnTeam {
+        manifest: TeamManifest,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `POST /api/plan` — start a plan against running agents.
+    RunPlan {
+        plan: Plan,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `GET /api/dead-letters` — deliveries that ran out of retries.
+    ListDeadLetters {
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `POST /api/dead-letters/{delivery_id}/requeue` — deliver a dead
+    /// letter again.
+    RequeueDeadLetter {
+        delivery_id: DeliveryId,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `POST /api/spawned/{name}/restart` — release and respawn the worker
+    /// from its persisted spec, keeping its pending deliveries.
+    RestartAgent {
+        name: WorkerName,
+        reason: Option<String>,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
     Release {
         name: WorkerName,
         reason: Option<String>,
@@ -126,6 +171,18 @@ pub enum ListenApiRequest {
         agent: Option<WorkerName>,
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
+    /// `GET /metrics` — broker and per-agent metrics in Prometheus text format.
+    GetPrometheusMetrics {
+        reply: tokio::sync::oneshot::Sender<String>,
+    },
+    /// `GET /api/agents/{name}/output` — page through the worker's in-memory
+    /// output scrollback.
+    GetOutput {
+        name: WorkerName,
+        lines: Option<usize>,
+        since: Option<u64>,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
     GetStatus {
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
@@ -146,6 +203,19 @@ pub enum ListenApiRequest {
         channels: Vec<ChannelName>,
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
+    /// `POST /api/spawned/{name}/team` — move the worker into a team, or
+    /// out of its team when `team` is null.
+    SetAgentTeam {
+        name: WorkerName,
+        team: Option<String>,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
+    /// `POST /api/drain` — refuse new work, then exit once pending
+    /// deliveries settle or `timeout_ms` passes.
+    Drain {
+        timeout_ms: Option<u64>,
+        reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
+    },
     Shutdown {
         reply: tokio::sync::oneshot::Sender<Result<Value, String>>,
     },
@@ -335,6 +405,8 @@ struct ListenApiState {
     /// When the broker started
     started_at: std::time::Instant,
     input_serializers: PtyInputSerializers,
+    rate_limiter: Arc<RateLimiter>,
+    spawn_profiles: Arc<BTreeMap<String, SpawnProfile>>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -364,6 +436,9 @@ pub struct ListenApiConfig {
     pub memberships: Vec<WorkspaceMembershipSummary>,
     pub default_workspace_id: Option<WorkspaceId>,
     pub persist: bool,
+    pub(crate) rate_limits: RateLimitConfig,
+    /// Profiles that `POST /api/spawn` can name with `"profile"`.
+    pub(crate) spawn_profiles: Arc<BTreeMap<String, SpawnProfile>>,
 }
 
 pub fn listen_api_router(config: ListenApiConfig) -> axum::Router {
@@ -404,17 +479,42 @@ fn listen_api_router_with_auth(
         persist: config.persist,
         started_at: std::time::Instant::now(),
         input_serializers: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limits)),
+        spawn_profiles: config.spawn_profiles,
     };
 
     let protected = Router::new()
         .route("/api/session", routing::get(listen_api_session))
         .route("/api/session/renew", routing::post(listen_api_renew_lease))
         .route("/api/spawn", routing::post(listen_api_spawn))
+        .route("/api/team", routing::post(listen_api_spawn_team))
+        .route("/api/plan", routing::post(listen_api_run_plan))
+        .route("/api/dead-letters", routing::get(listen_api_dead_letters))
+        .route(
+            "/api/dead-letters/{delivery_id}/requeue",
+            routing::post(listen_api_requeue_dead_letter),
+        )
         .route("/api/spawned", routing::get(listen_api_list))
         .route(
             "/api/spawned/{name}/model",
             routing::post(listen_api_set_model),
         )
+        .route(
+            "/api/spawned/{name}/pause",
+            routing::post(listen_api_pause_agent),
+        )
+        .route(
+            "/api/spawned/{name}/resume",
+            routing::post(listen_api_resume_agent),
+        )
+        .route(
+            "/api/spawned/{name}/restart",
+            routing::post(listen_api_restart_agent),
+        )
+        .route(
+            "/api/spawned/{name}/team",
+            routing::post(listen_api_set_agent_team),
+        )
         .route("/api/threads", routing::get(listen_api_threads))
         .route("/api/events/replay", routing::get(listen_api_replay))
         .route("/api/spawned/{name}", routing::delete(listen_api_release))
@@ -451,6 +551,11 @@ fn listen_api_router_with_auth(
             routing::post(listen_api_flush_pending),
         )
         .route("/api/metrics", routing::get(listen_api_metrics))
+        .route("/metrics", routing::get(listen_api_prometheus_metrics))
+        .route(
+            "/api/agents/{name}/output",
+            routing::get(listen_api_agent_output),
+        )
         .route("/api/status", routing::get(listen_api_status))
         .route(
             "/api/crash-insights",
@@ -458,6 +563,7 @@ fn listen_api_router_with_auth(
         )
         .route("/api/preflight", routing::post(listen_api_preflight))
         .route("/api/shutdown", routing::post(listen_api_shutdown))
+        .route("/api/drain", routing::post(listen_api_drain))
         .route(
             "/api/spawned/{name}/subscribe",
             routing::post(listen_api_subscribe_channels),
@@ -471,6 +577,11 @@ fn listen_api_router_with_auth(
         .route("/api/fleet/ws", routing::get(listen_api_fleet_ws))
         .route("/ws", routing::get(listen_api_ws))
         .with_state(state.clone())
+        // Inside auth so unauthenticated requests never consume quota.
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            listen_api_rate_limit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             listen_api_auth_middleware,
@@ -694,6 +805,64 @@ async fn listen_api_auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// Map a matched route to its rate-limit class.
+fn limited_route(method: &axum::http::Method, matched_path: &str) -> Option<LimitedRoute> {
+    use axum::http::Method;
+    match (method, matched_path) {
+        // A restart re-registers the agent and a team spawns several, so
+        // both share the spawn budget.
+        (&Method::POST, "/api/spawn" | "/api/team" | "/api/spawned/{name}/restart") => {
+            Some(LimitedRoute::Spawn)
+        }
+        (&Method::POST, "/api/send") => Some(LimitedRoute::Send),
+        (&Method::DELETE, "/api/spawned/{name}") => Some(LimitedRoute::Release),
+        _ => None,
+    }
+}
+
+async fn listen_api_rate_limit_middleware(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    request: axum::http::Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let route = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .and_then(|path| limited_route(request.method(), path.as_str()));
+    let Some(route) = route else {
+        return next.run(request).await;
+    };
+
+    match state.rate_limiter.check(route, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::warn!(
+                target = "agent_relay::broker",
+                route = route.as_str(),
+                retry_after_secs,
+                "rate limited HTTP API request"
+            );
+            let mut response = (
+                axum::http::StatusCode::TOO_MANY_REQUESTS,
+                axum::Json(json!({
+                    "code": "rate_limited",
+                    "message": format!("too many {} requests; retry in {retry_after_secs}s", route.as_str()),
+                    "retryAfterSecs": retry_after_secs,
+                })),
+            )
+                .into_response();
+            response.headers_mut().insert(
+                axum::http::header::RETRY_AFTER,
+                axum::http::HeaderValue::from(retry_after_secs),
+            );
+            response
+        }
+    }
+}
+
 fn parse_harness_config_value(value: Value) -> Result<ResolvedHarnessConfig, String> {
     serde_json::from_value::<ResolvedHarnessConfig>(value)
         .map_err(|error| format!("Invalid harnessConfig: {error}"))
@@ -721,12 +890,30 @@ async fn listen_api_spawn(
         .and_then(Value::as_str)
         .unwrap_or_default()
         .to_string();
+    // Fields the request leaves out come from its profile, if it names one.
+    let profile = match body.get("profile").and_then(Value::as_str) {
+        Some(profile) => match spawn_profile(&state.spawn_profiles, profile) {
+            Ok(profile) => profile,
+            Err(error) => {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    axum::Json(json!({ "success": false, "error": error.to_string() })),
+                );
+            }
+        },
+        None => SpawnProfile::default(),
+    };
     let cli = body
         .get("cli")
         .and_then(Value::as_str)
-        .unwrap_or("claude")
-        .to_string();
-    let model = body.get("model").and_then(Value::as_str).map(String::from);
+        .map(String::from)
+        .or_else(|| profile.cli.clone())
+        .unwrap_or_else(|| "claude".to_string());
+    let model = body
+        .get("model")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .or_else(|| profile.model.clone());
     let transport = body
         .get("transport")
         .or_else(|| body.get("runtime"))
@@ -773,7 +960,8 @@ async fn listen_api_spawn(
     let idle_threshold_secs = body
         .get("idle_threshold_secs")
         .or_else(|| body.get("idleThresholdSecs"))
-        .and_then(Value::as_u64);
+        .and_then(Value::as_u64)
+        .or(profile.idle_threshold_secs);
     let spawn_mode = body
         .get("spawn_mode")
         .or_else(|| body.get("spawnMode"))
@@ -806,7 +994,13 @@ async fn listen_api_spawn(
     let restart_policy = Box::new(
         body.get("restart_policy")
             .or_else(|| body.get("restartPolicy"))
-            .cloned(),
+            .cloned()
+            .or_else(|| {
+                profile
+                    .restart_policy
+                    .as_ref()
+                    .and_then(|policy| serde_json::to_value(policy).ok())
+            }),
     );
     if body
         .get("harness_id")
@@ -843,6 +1037,31 @@ async fn listen_api_spawn(
         .or_else(|| body.get("agentResultSchema"))
         .or_else(|| body.get("resultSchema"))
         .cloned();
+    // `max_memory_mb`, `cpu_shares` and `restart_on_limit` sit at the top
+    // level of the body, as they do in an `AgentSpec`.
+    let limits = match AgentResourceLimits::deserialize(&body) {
+        Ok(limits) => limits,
+        Err(error) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::Json(json!({
+                    "success": false,
+                    "error": format!("invalid resource limits: {error}")
+                })),
+            );
+        }
+    };
+    let image = body.get("image").and_then(Value::as_str).map(String::from);
+    let after: Vec<WorkerName> = body
+        .get("after")
+        .and_then(Value::as_array)
+        .map(|arr| {
+            arr.iter()
+                .filter_map(Value::as_str)
+                .map(WorkerName::from)
+                .collect()
+        })
+        .unwrap_or_default();
 
     if name.is_empty() {
         return (
@@ -859,9 +1078,9 @@ async fn listen_api_spawn(
             cli,
             transport,
             model,
-            args,
+            args: profile.args_with(args),
             task,
-            channels: channels.into_iter().map(ChannelName::from).collect(),
+            channels: profile.channels_or(channels.into_iter().map(ChannelName::from).collect()),
             cwd,
             team,
             shadow_of: shadow_of.map(WorkerName::from),
@@ -874,6 +1093,9 @@ async fn listen_api_spawn(
             harness_config,
             agent_token,
             agent_result_schema,
+            limits,
+            image,
+            after,
             reply: reply_tx,
         })
         .await
@@ -887,6 +1109,10 @@ async fn listen_api_spawn(
 
     match reply_rx.await {
         Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) if err.starts_with("broker_draining") => (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            axum::Json(json!({ "success": false, "name": name, "error": err })),
+        ),
         Ok(Err(err)) => (
             axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             axum::Json(json!({ "success": false, "name": name, "error": err })),
@@ -967,6 +1193,48 @@ async fn listen_api_set_model(
     }
 }
 
+async fn listen_api_pause_agent(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    set_agent_paused(&state, name, true).await
+}
+
+async fn listen_api_resume_agent(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    set_agent_paused(&state, name, false).await
+}
+
+async fn set_agent_paused(
+    state: &ListenApiState,
+    name: String,
+    paused: bool,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::SetAgentPaused {
+            name: WorkerName::new(name),
+            paused,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
 async fn listen_api_threads(
     axum::extract::State(state): axum::extract::State<ListenApiState>,
 ) -> axum::Json<Value> {
@@ -1096,6 +1364,164 @@ async fn listen_api_release(
     }
 }
 
+async fn listen_api_restart_agent(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    body: Option<axum::Json<Value>>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let reason = body.and_then(|b| b.get("reason").and_then(|v| v.as_str()).map(String::from));
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::RestartAgent {
+            name: WorkerName::new(name),
+            reason,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
+async fn listen_api_set_agent_team(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::Json(body): axum::Json<Value>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let team = body.get("team").and_then(Value::as_str).map(String::from);
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::SetAgentTeam {
+            name: WorkerName::new(name),
+            team,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
+async fn listen_api_spawn_team(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::Json(manifest): axum::Json<TeamManifest>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::SpawnTeam {
+            manifest,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
+async fn listen_api_run_plan(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::Json(plan): axum::Json<Plan>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::RunPlan {
+            plan,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
+async fn listen_api_dead_letters(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::ListDeadLetters { reply: reply_tx })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
+async fn listen_api_requeue_dead_letter(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(delivery_id): axum::extract::Path<String>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::RequeueDeadLetter {
+            delivery_id: DeliveryId::new(delivery_id),
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
 async fn listen_api_interrupt(
     axum::extract::Path(name): axum::extract::Path<String>,
 ) -> (axum::http::StatusCode, axum::Json<Value>) {
@@ -1470,6 +1896,11 @@ fn classify_error(err: &str) -> (axum::http::StatusCode, &'static str) {
         // Worker died or stalled between accepting the frame and
         // replying. This is a server-side fault, not a bad request.
         (axum::http::StatusCode::GATEWAY_TIMEOUT, "worker_timeout")
+    } else if err.starts_with("broker_draining") {
+        (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            "broker_draining",
+        )
     } else if err.starts_with("internal_error") {
         (
             axum::http::StatusCode::INTERNAL_SERVER_ERROR,
@@ -2097,6 +2528,75 @@ async fn listen_api_metrics(
     {
         return internal_error();
     }
+    match reply_rx.await {
+        Ok(Ok(mut val)) => {
+            val["rate_limits"] = state.rate_limiter.snapshot();
+            (axum::http::StatusCode::OK, axum::Json(val))
+        }
+        Ok(Err(err)) => api_error(axum::http::StatusCode::NOT_FOUND, "agent_not_found", err),
+        Err(_) => internal_error(),
+    }
+}
+
+/// `GET /metrics` → Prometheus text exposition for standard scrapers. Uses the
+/// same auth as the rest of the API (`Authorization: Bearer` works with
+/// Prometheus' `authorization` scrape config).
+async fn listen_api_prometheus_metrics(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::GetPrometheusMetrics { reply: reply_tx })
+        .await
+        .is_err()
+    {
+        return internal_error().into_response();
+    }
+    match reply_rx.await {
+        Ok(mut body) => {
+            body.push_str(&state.rate_limiter.to_prometheus());
+            (
+                [(
+                    axum::http::header::CONTENT_TYPE,
+                    "text/plain; version=0.0.4; charset=utf-8",
+                )],
+                body,
+            )
+                .into_response()
+        }
+        Err(_) => internal_error().into_response(),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct OutputQuery {
+    lines: Option<usize>,
+    since: Option<u64>,
+}
+
+/// `GET /api/agents/{name}/output?lines=N&since=SEQ` → the last `N` lines
+/// (default 100) newer than `SEQ`, plus `next_since` for the next poll.
+async fn listen_api_agent_output(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    axum::extract::Query(query): axum::extract::Query<OutputQuery>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::GetOutput {
+            name: WorkerName::new(name),
+            lines: query.lines,
+            since: query.since,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
     match reply_rx.await {
         Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
         Ok(Err(err)) => api_error(axum::http::StatusCode::NOT_FOUND, "agent_not_found", err),
@@ -2228,6 +2728,33 @@ async fn listen_api_shutdown(
     }
 }
 
+async fn listen_api_drain(
+    axum::extract::State(state): axum::extract::State<ListenApiState>,
+    body: Option<axum::Json<Value>>,
+) -> (axum::http::StatusCode, axum::Json<Value>) {
+    let timeout_ms = body.and_then(|b| b.get("timeout_ms").and_then(Value::as_u64));
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    if state
+        .tx
+        .send(ListenApiRequest::Drain {
+            timeout_ms,
+            reply: reply_tx,
+        })
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+    match reply_rx.await {
+        Ok(Ok(val)) => (axum::http::StatusCode::OK, axum::Json(val)),
+        Ok(Err(err)) => {
+            let (status, code) = classify_error(&err);
+            api_error(status, code, err)
+        }
+        Err(_) => internal_error(),
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Channel subscription
 // ---------------------------------------------------------------------------
@@ -3209,13 +3736,33 @@ mod auth_tests {
         listen_api_router_with_auth, DeliveryRouteError, FleetSidecarFrameResponse,
         ListenApiConfig, ListenApiRequest, PtyInputFrame, SetInboundDeliveryModeOk,
     };
-    use crate::ids::{EventId, MessageTarget, ThreadId, WorkspaceAlias, WorkspaceId};
+    use crate::ids::{
+        ChannelName, EventId, MessageTarget, ThreadId, WorkerName, WorkspaceAlias, WorkspaceId,
+    };
     use crate::protocol::{MessageInjectionMode, ProtocolEnvelope};
+    use crate::rate_limit::{RateLimit, RateLimitConfig};
+    use crate::spawn_profile::SpawnProfile;
     use crate::types::{InboundDeliveryMode, PendingRelayMessage};
     use crate::worker_request::RequestWorkerError;
+    use std::{collections::BTreeMap, sync::Arc};
 
     fn test_router(
         broker_api_key: Option<&str>,
+    ) -> (axum::Router, mpsc::Receiver<ListenApiRequest>) {
+        test_router_with_rate_limits(broker_api_key, RateLimitConfig::default())
+    }
+
+    fn test_router_with_rate_limits(
+        broker_api_key: Option<&str>,
+        rate_limits: RateLimitConfig,
+    ) -> (axum::Router, mpsc::Receiver<ListenApiRequest>) {
+        test_router_with(broker_api_key, rate_limits, BTreeMap::new())
+    }
+
+    fn test_router_with(
+        broker_api_key: Option<&str>,
+        rate_limits: RateLimitConfig,
+        spawn_profiles: BTreeMap<String, SpawnProfile>,
     ) -> (axum::Router, mpsc::Receiver<ListenApiRequest>) {
         let (tx, rx) = mpsc::channel(8);
         let (events_tx, _events_rx) = broadcast::channel(8);
@@ -3231,6 +3778,8 @@ mod auth_tests {
                     memberships: vec![],
                     default_workspace_id: None,
                     persist: false,
+                    rate_limits,
+                    spawn_profiles: Arc::new(spawn_profiles),
                 },
                 broker_api_key.map(ToString::to_string),
             ),
@@ -3262,6 +3811,64 @@ mod auth_tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn release_route_returns_429_with_retry_after_once_limit_is_spent() {
+        let (router, mut rx) = test_router_with_rate_limits(
+            Some("secret"),
+            RateLimitConfig {
+                release: Some(RateLimit::per_minute(1)),
+                ..RateLimitConfig::default()
+            },
+        );
+        let release_replier = tokio::spawn(async move {
+            if let Some(ListenApiRequest::Release { name, reply, .. }) = rx.recv().await {
+                let _ = reply.send(Ok(json!({ "success": true, "name": name })));
+            }
+        });
+        let release = |api_key: &str| {
+            Request::builder()
+                .uri("/api/spawned/worker-a")
+                .method("DELETE")
+                .header("x-api-key", api_key)
+                .body(Body::empty())
+                .expect("request should build")
+        };
+
+        // Unauthenticated requests are rejected before they consume quota.
+        let unauthorized = router
+            .clone()
+            .oneshot(release("wrong"))
+            .await
+            .expect("request should succeed");
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+        let first = router
+            .clone()
+            .oneshot(release("secret"))
+            .await
+            .expect("request should succeed");
+        assert_eq!(first.status(), StatusCode::OK);
+        release_replier
+            .await
+            .expect("release replier should complete");
+
+        let limited = router
+            .oneshot(release("secret"))
+            .await
+            .expect("request should succeed");
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            limited
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok()),
+            Some("60")
+        );
+        let body = response_json(limited).await;
+        assert_eq!(body["code"], "rate_limited");
+        assert_eq!(body["retryAfterSecs"], 60);
+    }
+
     #[tokio::test]
     async fn api_route_rejects_missing_api_key_when_auth_enabled() {
         let (router, _rx) = test_router(Some("secret"));
@@ -3370,6 +3977,9 @@ mod auth_tests {
                     harness_config,
                     agent_token: _,
                     agent_result_schema,
+                    limits,
+                    image,
+                    after,
                     reply,
                 }) => {
                     assert_eq!(name, "worker-a");
@@ -3394,6 +4004,11 @@ mod auth_tests {
                         agent_result_schema,
                         Some(json!({"type": "object", "properties": {"ok": {"type": "boolean"}}}))
                     );
+                    assert_eq!(limits.max_memory_mb, Some(2048));
+                    assert_eq!(limits.cpu_shares, Some(512));
+                    assert!(limits.restart_on_limit);
+                    assert_eq!(image.as_deref(), Some("ghcr.io/acme/agent:latest"));
+                    assert_eq!(after, [WorkerName::from("Lead")]);
                     let _ = reply.send(Ok(
                         json!({ "success": true, "name": "worker-a", "pid": 42 }),
                     ));
@@ -3431,6 +4046,11 @@ mod auth_tests {
                                 "args": ["--fast"]
                             },
                             "resultSchema": {"type": "object", "properties": {"ok": {"type": "boolean"}}},
+                            "maxMemoryMb": 2048,
+                            "cpuShares": 512,
+                            "restartOnLimit": true,
+                            "image": "ghcr.io/acme/agent:latest",
+                            "after": ["Lead"],
                         })
                         .to_string(),
                     ))
@@ -3446,6 +4066,79 @@ mod auth_tests {
         spawn_replier.await.expect("spawn replier should complete");
     }
 
+    #[tokio::test]
+    async fn spawn_route_fills_unset_fields_from_profile() {
+        let profiles = serde_json::from_value(json!({
+            "reviewer": {
+                "cli": "codex",
+                "model": "o3",
+                "args": ["--search"],
+                "channels": ["reviews"],
+                "idle_threshold_secs": 120,
+                "restart_policy": { "max_restarts": 2 },
+            }
+        }))
+        .expect("profiles should parse");
+        let spawn = |body: Value| {
+            Request::builder()
+                .uri("/api/spawn")
+                .method("POST")
+                .header("x-api-key", "secret")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .expect("request should build")
+        };
+
+        let (router, mut rx) =
+            test_router_with(Some("secret"), RateLimitConfig::default(), profiles);
+        let spawn_replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::Spawn {
+                    cli,
+                    model,
+                    args,
+                    channels,
+                    idle_threshold_secs,
+                    restart_policy,
+                    reply,
+                    ..
+                }) => {
+                    assert_eq!(cli, "codex");
+                    assert_eq!(model.as_deref(), Some("gpt-5"));
+                    assert_eq!(args, vec!["--search".to_string(), "--fast".to_string()]);
+                    assert_eq!(channels, vec!["reviews".to_string()]);
+                    assert_eq!(idle_threshold_secs, Some(120));
+                    assert_eq!(
+                        restart_policy.as_ref().as_ref().map(|p| &p["max_restarts"]),
+                        Some(&json!(2))
+                    );
+                    let _ = reply.send(Ok(json!({ "success": true, "name": "worker-a" })));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+        });
+        let response = router
+            .clone()
+            .oneshot(spawn(json!({
+                "name": "worker-a",
+                "profile": "reviewer",
+                "model": "gpt-5",
+                "args": ["--fast"],
+            })))
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+        spawn_replier.await.expect("spawn replier should complete");
+
+        let response = router
+            .oneshot(spawn(json!({ "name": "worker-b", "profile": "nope" })))
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response_json(response).await;
+        assert_eq!(body["error"], "unknown spawn profile 'nope'");
+    }
+
     #[tokio::test]
     async fn spawn_route_rejects_harness_id() {
         let (router, _rx) = test_router(Some("secret"));
@@ -4077,6 +4770,95 @@ mod auth_tests {
         replier.await.expect("replier should complete");
     }
 
+    #[tokio::test]
+    async fn prometheus_route_serves_text_with_rate_limit_counters() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::GetPrometheusMetrics { reply }) => {
+                    let _ = reply.send("relay_broker_active_agents 2\n".to_string());
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+        });
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .method("GET")
+                    .header("authorization", "Bearer secret")
+                    .body(Body::empty())
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("response body should be readable");
+        let body = String::from_utf8(body.to_vec()).expect("body should be utf-8");
+        assert!(body.starts_with("relay_broker_active_agents 2\n"));
+        assert!(body.contains("relay_broker_rate_limit_allowed_total{route=\"spawn\"} 0"));
+        replier.await.expect("replier should complete");
+    }
+
+    #[tokio::test]
+    async fn agent_output_route_forwards_paging_query() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::GetOutput {
+                    name,
+                    lines,
+                    since,
+                    reply,
+                }) => {
+                    assert_eq!(name.as_str(), "worker-a");
+                    assert_eq!(lines, Some(20));
+                    assert_eq!(since, Some(7));
+                    let _ = reply.send(Ok(json!({ "lines": [], "next_since": 7 })));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+            match rx.recv().await {
+                Some(ListenApiRequest::GetOutput { reply, .. }) => {
+                    let _ = reply.send(Err("unknown worker 'ghost'".to_string()));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+        });
+
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .method("GET")
+                .header("x-api-key", "secret")
+                .body(Body::empty())
+                .expect("request should build")
+        };
+        let response = router
+            .clone()
+            .oneshot(request("/api/agents/worker-a/output?lines=20&since=7"))
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_json(response).await["next_since"], 7);
+
+        let response = router
+            .oneshot(request("/api/agents/ghost/output"))
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response_json(response).await["code"], "agent_not_found");
+        replier.await.expect("replier should complete");
+    }
+
     #[tokio::test]
     async fn crash_insights_route_forwards_request() {
         let (router, mut rx) = test_router(Some("secret"));
@@ -4174,6 +4956,96 @@ mod auth_tests {
         replier.await.expect("replier should complete");
     }
 
+    #[tokio::test]
+    async fn drain_route_forwards_timeout() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::Drain { timeout_ms, reply }) => {
+                    assert_eq!(timeout_ms, Some(5_000));
+                    let _ = reply.send(Ok(json!({ "draining": true })));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+        });
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/api/drain")
+                    .method("POST")
+                    .header("x-api-key", "secret")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"timeout_ms":5000}"#))
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response_json(response).await;
+        assert_eq!(body["draining"], json!(true));
+        replier.await.expect("replier should complete");
+    }
+
+    #[tokio::test]
+    async fn team_route_forwards_manifest() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::SpawnTeam { manifest, reply }) => {
+                    assert_eq!(manifest.name, "backend");
+                    assert_eq!(manifest.channels, [ChannelName::from("backend")]);
+                    assert_eq!(manifest.agents.len(), 2);
+                    assert_eq!(
+                        manifest.agents[1].depends_on,
+                        [WorkerName::from("Architect")]
+                    );
+                    let _ = reply.send(Ok(json!({
+                        "success": true,
+                        "team": "backend",
+                        "agents": ["Architect", "Builder"],
+                    })));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+        });
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/api/team")
+                    .method("POST")
+                    .header("x-api-key", "secret")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        json!({
+                            "name": "backend",
+                            "channels": ["backend"],
+                            "agents": [
+                                {"name": "Architect", "runtime": "pty", "cli": "claude"},
+                                {
+                                    "name": "Builder",
+                                    "runtime": "pty",
+                                    "cli": "codex",
+                                    "task": "Build what Architect designs.",
+                                    "depends_on": ["Architect"],
+                                },
+                            ],
+                        })
+                        .to_string(),
+                    ))
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response_json(response).await;
+        assert_eq!(body["agents"], json!(["Architect", "Builder"]));
+        replier.await.expect("replier should complete");
+    }
+
     #[tokio::test]
     async fn resize_pty_route_forwards_dimensions() {
         let (router, mut rx) = test_router(Some("secret"));
@@ -4850,6 +5722,120 @@ mod auth_tests {
         replier.await.expect("replier should complete");
     }
 
+    #[tokio::test]
+    async fn restart_route_forwards_reason_and_maps_missing_agent() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            match rx.recv().await {
+                Some(ListenApiRequest::RestartAgent {
+                    name,
+                    reason,
+                    reply,
+                }) => {
+                    assert_eq!(name, "worker-a");
+                    assert_eq!(reason.as_deref(), Some("stuck"));
+                    let _ = reply.send(Ok(json!({ "success": true, "name": name })));
+                }
+                other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+            }
+            if let Some(ListenApiRequest::RestartAgent { reply, .. }) = rx.recv().await {
+                let _ = reply.send(Err("agent_not_found: no worker named 'ghost'".into()));
+            }
+        });
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/spawned/worker-a/restart")
+                    .method("POST")
+                    .header("x-api-key", "secret")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"reason":"stuck"}"#))
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/api/spawned/ghost/restart")
+                    .method("POST")
+                    .header("x-api-key", "secret")
+                    .body(Body::empty())
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = response_json(response).await;
+        assert_eq!(body["code"], json!("agent_not_found"));
+        replier.await.expect("replier should complete");
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume_routes_forward_requested_state() {
+        let (router, mut rx) = test_router(Some("secret"));
+        let replier = tokio::spawn(async move {
+            for expected in [true, false] {
+                match rx.recv().await {
+                    Some(ListenApiRequest::SetAgentPaused {
+                        name,
+                        paused,
+                        reply,
+                    }) => {
+                        assert_eq!(name, "worker-a");
+                        assert_eq!(paused, expected);
+                        let _ = reply.send(Ok(json!({ "name": name, "paused": paused })));
+                    }
+                    other => panic!("unexpected request: {:?}", other.map(|_| "other")),
+                }
+            }
+            if let Some(ListenApiRequest::SetAgentPaused { reply, .. }) = rx.recv().await {
+                let _ = reply.send(Err("agent_not_found: no worker named 'ghost'".into()));
+            }
+        });
+
+        for (uri, paused) in [
+            ("/api/spawned/worker-a/pause", true),
+            ("/api/spawned/worker-a/resume", false),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .method("POST")
+                        .header("x-api-key", "secret")
+                        .body(Body::empty())
+                        .expect("request should build"),
+                )
+                .await
+                .expect("request should succeed");
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response_json(response).await;
+            assert_eq!(body["paused"], json!(paused));
+        }
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/api/spawned/ghost/pause")
+                    .method("POST")
+                    .header("x-api-key", "secret")
+                    .body(Body::empty())
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = response_json(response).await;
+        assert_eq!(body["code"], json!("agent_not_found"));
+        replier.await.expect("replier should complete");
+    }
+
     #[tokio::test]
     async fn flush_route_returns_flushed_count() {
         let (router, mut rx) = test_router(Some("secret"));
@@ -78,6 +78,14 @@ impl AgentRecord {
     }
 }
 
+/// Per-agent Prometheus family: metric name, help text, type, and value.
+type AgentFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&AgentStats) -> u64,
+);
+
 /// Collects metrics for the broker lifecycle.
 pub struct MetricsCollector {
     broker_start: Instant,
@@ -128,6 +136,7 @@ impl MetricsCollector {
             .or_insert_with(AgentRecord::new);
         record.releases += 1;
         record.status = AgentStatus::Released;
+        record.memory_bytes = 0;
     }
 
     pub fn on_permanent_death(&mut self, name: &str) {
@@ -136,6 +145,7 @@ impl MetricsCollector {
             .entry(name.to_string())
             .or_insert_with(AgentRecord::new);
         record.status = AgentStatus::Dead;
+        record.memory_bytes = 0;
     }
 
     /// Update memory reading for an agent.
@@ -209,21 +219,47 @@ impl MetricsCollector {
             broker.active_agents
         ));
 
-        // Per-agent metrics
-        for (name, record) in &self.agents {
-            let stats = record.to_stats();
-            out.push_str(&format!(
-                "relay_agent_crashes_total{{agent=\"{}\"}} {}\n",
-                name, stats.crashes
-            ));
-            out.push_str(&format!(
-                "relay_agent_restarts_total{{agent=\"{}\"}} {}\n",
-                name, stats.restarts
-            ));
-            out.push_str(&format!(
-                "relay_agent_memory_bytes{{agent=\"{}\"}} {}\n",
-                name, stats.memory_bytes
-            ));
+        // Per-agent metrics. Samples of one family must be contiguous in the
+        // exposition format, so each family is written for all agents at once.
+        let mut agents: Vec<(&String, AgentStats)> = self
+            .agents
+            .iter()
+            .map(|(name, record)| (name, record.to_stats()))
+            .collect();
+        agents.sort_by(|a, b| a.0.cmp(b.0));
+        let families: [AgentFamily; 3] = [
+            (
+                "relay_agent_crashes_total",
+                "Crashes per agent.",
+                "counter",
+                |stats| u64::from(stats.crashes),
+            ),
+            (
+                "relay_agent_restarts_total",
+                "Restarts per agent.",
+                "counter",
+                |stats| u64::from(stats.restarts),
+            ),
+            (
+                "relay_agent_memory_bytes",
+                "Resident memory per agent in bytes.",
+                "gauge",
+                |stats| stats.memory_bytes,
+            ),
+        ];
+        for (metric, help, kind, value) in families {
+            if agents.is_empty() {
+                break;
+            }
+            out.push_str(&format!("# HELP {metric} {help}\n"));
+            out.push_str(&format!("# TYPE {metric} {kind}\n"));
+            for (name, stats) in &agents {
+                out.push_str(&format!(
+                    "{metric}{{agent=\"{}\"}} {}\n",
+                    escape_label_value(name),
+                    value(stats)
+                ));
+            }
         }
 
         out
@@ -245,6 +281,21 @@ impl MetricsCollector {
     }
 }
 
+/// Append a single unlabelled gauge with its `HELP`/`TYPE` header.
+pub fn push_prometheus_gauge(out: &mut String, metric: &str, help: &str, value: u64) {
+    out.push_str(&format!("# HELP {metric} {help}\n"));
+    out.push_str(&format!("# TYPE {metric} gauge\n"));
+    out.push_str(&format!("{metric} {value}\n"));
+}
+
+/// Escape a label value per the Prometheus text exposition format.
+pub fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,6 +406,33 @@ mod tests {
         assert!(prom.contains("relay_agent_crashes_total{agent=\"w1\"} 1"));
     }
 
+    #[test]
+    fn prometheus_per_agent_families_are_grouped_and_escaped() {
+        let mut mc = MetricsCollector::new(Instant::now());
+        mc.on_spawn("w2");
+        mc.on_spawn("w\"1");
+        mc.update_memory("w2", 4096);
+
+        let prom = mc.to_prometheus(2);
+        let crashes = prom
+            .find("# TYPE relay_agent_crashes_total counter")
+            .unwrap();
+        let restarts = prom
+            .find("# TYPE relay_agent_restarts_total counter")
+            .unwrap();
+        let first = prom
+            .find("relay_agent_crashes_total{agent=\"w\\\"1\"} 0")
+            .unwrap();
+        let second = prom
+            .find("relay_agent_crashes_total{agent=\"w2\"} 0")
+            .unwrap();
+        assert!(crashes < first && first < second && second < restarts);
+        assert!(prom.contains("relay_agent_memory_bytes{agent=\"w2\"} 4096"));
+
+        mc.on_release("w2");
+        assert_eq!(mc.agent_stats("w2").unwrap().memory_bytes, 0);
+    }
+
     #[test]
     fn json_export_has_broker_and_agents() {
         let mut mc = MetricsCollector::new(Instant::now());
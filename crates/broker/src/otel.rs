@@ -0,0 +1,93 @@
+//! Optional OpenTelemetry export of broker tracing spans.
+//!
+//! Compiled only with the `otel` cargo feature. When an OTLP endpoint is
+//! configured through the standard `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) variable, the broker's `relay.*`
+//! delivery spans — receipt, routing, `queue_and_try_delivery`, and
+//! `delivery_ack` — are exported over OTLP/HTTP. Every span carries the
+//! message's `event_id`, so one message can be followed across stages even
+//! when it sits in a manual-flush queue between them.
+
+use std::sync::OnceLock;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter as OtlpSpanExporter;
+use opentelemetry_sdk::{
+    trace::{SdkTracerProvider, SpanExporter, Tracer},
+    Resource,
+};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+const DEFAULT_SERVICE_NAME: &str = "agent-relay-broker";
+const ENDPOINT_ENVS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+];
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+pub(crate) fn otlp_endpoint_configured() -> bool {
+    ENDPOINT_ENVS.iter().any(|key| {
+        std::env::var(key)
+            .ok()
+            .is_some_and(|value| !value.trim().is_empty())
+    })
+}
+
+/// Tracing layer exporting spans over OTLP/HTTP, or `None` when no endpoint
+/// is configured or the exporter cannot be built.
+pub(crate) fn otlp_layer<S>() -> Option<OpenTelemetryLayer<S, Tracer>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if !otlp_endpoint_configured() {
+        return None;
+    }
+    // The exporter reads endpoint, headers, and timeout from the standard
+    // `OTEL_EXPORTER_OTLP_*` variables.
+    let exporter = match OtlpSpanExporter::builder().with_http().build() {
+        Ok(exporter) => exporter,
+        Err(error) => {
+            // The global subscriber is not installed yet.
+            eprintln!("[agent-relay] OpenTelemetry exporter disabled: {error}");
+            return None;
+        }
+    };
+    let provider = tracer_provider(exporter);
+    let layer = layer(&provider);
+    let _ = TRACER_PROVIDER.set(provider);
+    Some(layer)
+}
+
+/// Provider batching spans out to `exporter`, named after the broker unless
+/// `OTEL_SERVICE_NAME` says otherwise.
+pub(crate) fn tracer_provider(exporter: impl SpanExporter + 'static) -> SdkTracerProvider {
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(DEFAULT_SERVICE_NAME);
+    }
+    SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build()
+}
+
+/// Tracing layer recording spans through `provider`.
+pub(crate) fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+}
+
+/// Flush buffered spans. Blocks on the exporter, so call it off the async
+/// runtime (e.g. via `spawn_blocking`).
+pub(crate) fn shutdown() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(error) = provider.shutdown() {
+            tracing::debug!(error = %error, "OpenTelemetry shutdown failed");
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{
     de::{self, Deserializer},
@@ -19,6 +19,24 @@ pub const PROTOCOL_VERSION: u32 = 2;
 pub enum AgentRuntime {
     Pty,
     Headless,
+    /// The CLI runs in a PTY inside a `docker run` container.
+    Docker,
+    /// The CLI runs in a Kubernetes pod, attached to through `kubectl`.
+    Kubernetes,
+    /// The CLI runs in a PTY on another machine, over `ssh`.
+    Ssh {
+        host: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+    },
+}
+
+impl AgentRuntime {
+    /// Whether the CLI is a process on this host, rather than inside a
+    /// container or on another machine.
+    pub fn is_local(&self) -> bool {
+        matches!(self, Self::Pty | Self::Headless)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -26,6 +44,11 @@ pub enum AgentRuntime {
 pub enum HeadlessProvider {
     Claude,
     Opencode,
+    Codex,
+    Gemini,
+    /// A provider run from a configured command template, by name.
+    #[serde(untagged)]
+    Template(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -237,6 +260,94 @@ pub struct AgentSpec {
     pub channels: Vec<ChannelName>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub restart_policy: Option<RestartPolicy>,
+    /// Container image for the `docker` runtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(flatten)]
+    pub limits: AgentResourceLimits,
+}
+
+/// Per-agent resource limits. Serialized inline with the rest of
+/// [`AgentSpec`], so the wire fields are `max_memory_mb`, `cpu_shares` and
+/// `restart_on_limit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentResourceLimits {
+    /// Memory ceiling for the agent's process tree.
+    #[serde(
+        default,
+        alias = "maxMemoryMb",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_memory_mb: Option<u64>,
+    /// Relative CPU weight, cgroup v1 style: 1024 is the default share.
+    #[serde(default, alias = "cpuShares", skip_serializing_if = "Option::is_none")]
+    pub cpu_shares: Option<u64>,
+    /// Restart the agent when it is seen over `max_memory_mb`.
+    #[serde(
+        default,
+        alias = "restartOnLimit",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub restart_on_limit: bool,
+}
+
+impl AgentResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_memory_mb.is_none() && self.cpu_shares.is_none()
+    }
+}
+
+/// A team brought up by one `spawn_team` request: the agents, the channels
+/// they all join, and the task each one starts on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamManifest {
+    /// Recorded as every member's `team` unless the member names its own.
+    pub name: String,
+    /// Joined by every member on top of its own channels.
+    #[serde(default)]
+    pub channels: Vec<ChannelName>,
+    pub agents: Vec<TeamMember>,
+    /// How long members have to report ready before the team is torn down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamMember {
+    #[serde(flatten)]
+    pub agent: AgentSpec,
+    /// Spawn profile that fills what `agent` leaves unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// Members whose readiness this member's `task` waits for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<WorkerName>,
+}
+
+/// Tasks the broker hands to running agents in dependency order, passing
+/// each step's reply on to the steps that take it as input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    pub steps: Vec<PlanStep>,
+    /// How long the whole plan may run before it fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub id: String,
+    pub agent: WorkerName,
+    pub task: String,
+    /// Steps that must finish before this one starts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Steps whose replies are appended to `task`. Implies `depends_on`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs_from: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -420,6 +531,12 @@ pub enum SdkToBroker {
         initial_task: Option<String>,
         #[serde(default)]
         skip_relay_prompt: bool,
+        /// Spawn profile that fills what `agent` leaves unset.
+        #[serde(default)]
+        profile: Option<String>,
+        /// Agents that must report ready before this one is spawned.
+        #[serde(default)]
+        after: Vec<String>,
     },
     RegisterNode {
         manifest: NodeManifest,
@@ -454,6 +571,21 @@ pub enum SdkToBroker {
     ReleaseAgent {
         name: WorkerName,
     },
+    /// Stop the worker's process tree (SIGSTOP). Deliveries are held until
+    /// `resume_agent`.
+    PauseAgent {
+        name: WorkerName,
+    },
+    ResumeAgent {
+        name: WorkerName,
+    },
+    /// Release and respawn the worker from its persisted spec, keeping its
+    /// pending deliveries and re-injecting its initial task.
+    RestartAgent {
+        name: WorkerName,
+        #[serde(default)]
+        reason: Option<String>,
+    },
     SubscribeChannels {
         name: WorkerName,
         channels: Vec<ChannelName>,
@@ -462,7 +594,51 @@ pub enum SdkToBroker {
         name: WorkerName,
         channels: Vec<ChannelName>,
     },
+    /// Move the worker into `team`, or out of its team when `team` is null.
+    /// Sends to `@<team>` fan out to the team's members.
+    SetAgentTeam {
+        name: WorkerName,
+        #[serde(default)]
+        team: Option<String>,
+    },
+    /// Read a worker's log file: the last `lines` lines by default, or up to
+    /// `max_bytes` from byte `offset`. `follow: true` then streams appended
+    /// output as `log_chunk` events; `follow: false` stops a running follow.
+    GetLogs {
+        name: WorkerName,
+        #[serde(default)]
+        lines: Option<usize>,
+        #[serde(default)]
+        offset: Option<u64>,
+        #[serde(default)]
+        max_bytes: Option<u64>,
+        #[serde(default)]
+        follow: Option<bool>,
+    },
+    /// Spawn every agent in `manifest`, or none of them. Answers once all
+    /// are spawned; `team_ready` follows when all have reported ready.
+    SpawnTeam {
+        manifest: TeamManifest,
+    },
+    /// Run `plan` against agents that are already running. Answers once the
+    /// plan is accepted; `plan_*` events report its progress.
+    RunPlan {
+        plan: Plan,
+    },
     ListAgents {},
+    /// Deliveries that ran out of retries, oldest first.
+    ListDeadLetters {},
+    /// Deliver a dead letter to its worker again with a fresh retry budget.
+    RequeueDeadLetter {
+        delivery_id: DeliveryId,
+    },
+    /// Stop accepting spawns and node deliveries, tell agents they are about
+    /// to stop, then exit once pending deliveries settle or `timeout_ms`
+    /// passes.
+    Drain {
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
     Shutdown {},
 }
 
@@ -521,11 +697,22 @@ pub enum BrokerEvent {
     },
     AgentReleased {
         name: WorkerName,
+        /// Pids that were still alive after SIGKILL.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        survivors: Vec<u32>,
     },
     AgentExit {
         name: WorkerName,
         reason: String,
     },
+    /// A process carrying a worker's `RELAY_AGENT_NAME` outlived that worker.
+    OrphanProcessDetected {
+        name: WorkerName,
+        pid: u32,
+        #[serde(default)]
+        command: Option<String>,
+        reaped: bool,
+    },
     AgentExited {
         name: WorkerName,
         code: Option<i32>,
@@ -549,12 +736,30 @@ pub enum BrokerEvent {
         stream: String,
         chunk: String,
     },
+    /// Bytes appended to a followed worker log (`get_logs` with `follow`).
+    LogChunk {
+        name: WorkerName,
+        content: String,
+        offset: u64,
+        next_offset: u64,
+        size: u64,
+    },
     DeliveryRetry {
         name: WorkerName,
         delivery_id: DeliveryId,
         event_id: EventId,
         attempts: u32,
     },
+    /// A delivery ran out of retries and was moved to the dead-letter
+    /// queue; `requeue_dead_letter` sends it again.
+    DeliveryDeadLettered {
+        name: WorkerName,
+        delivery_id: DeliveryId,
+        event_id: EventId,
+        from: String,
+        attempts: u32,
+        last_error: String,
+    },
     DeliveryDropped {
         name: WorkerName,
         count: usize,
@@ -671,6 +876,16 @@ pub enum BrokerEvent {
         name: WorkerName,
         reason: String,
     },
+    AgentPaused {
+        name: WorkerName,
+        /// Deliveries held for the agent when it was paused.
+        pending_delivery_count: usize,
+    },
+    AgentResumed {
+        name: WorkerName,
+        paused_ms: u64,
+        pending_delivery_count: usize,
+    },
     ChannelSubscribed {
         name: WorkerName,
         channels: Vec<ChannelName>,
@@ -679,6 +894,114 @@ pub enum BrokerEvent {
         name: WorkerName,
         channels: Vec<ChannelName>,
     },
+    /// A worker joined, left, or moved between teams.
+    AgentTeamChanged {
+        name: WorkerName,
+        #[serde(default)]
+        team: Option<String>,
+        #[serde(default)]
+        previous_team: Option<String>,
+    },
+    /// A spawn past the concurrency cap is waiting for a free slot.
+    SpawnQueued {
+        name: WorkerName,
+        /// 1-based position in the spawn queue.
+        position: usize,
+        running: usize,
+        max_concurrent: usize,
+    },
+    /// A spawn is held until the agents in its `after` report ready. Sent
+    /// when it is held and again each time one of them does.
+    SpawnWaiting {
+        name: WorkerName,
+        /// The agents it is still waiting on.
+        after: Vec<WorkerName>,
+    },
+    SpawnStarted {
+        name: WorkerName,
+        queued_ms: u64,
+        #[serde(default)]
+        pid: Option<u32>,
+    },
+    /// A queued spawn was dequeued but failed to start, or an agent it was
+    /// waiting on exited first.
+    SpawnFailed {
+        name: WorkerName,
+        error: String,
+    },
+    /// An agent went over one of its [`AgentResourceLimits`].
+    AgentLimitExceeded {
+        name: WorkerName,
+        /// The limit that was breached; currently always `memory`.
+        limit: String,
+        max_memory_mb: u64,
+        /// Usage when the breach was seen; `None` when the kernel killed the
+        /// agent first.
+        #[serde(default)]
+        memory_bytes: Option<u64>,
+        /// `restart`, `none`, or `killed` when the cgroup OOM killer ended it.
+        action: String,
+    },
+    /// A `kubernetes` agent's pod changed phase or waiting reason.
+    AgentPodStatus {
+        name: WorkerName,
+        pod: String,
+        /// `Pending`, `Running`, `Succeeded`, `Failed` or `Unknown`.
+        phase: String,
+        /// Why the pod is not running, e.g. `ImagePullBackOff`.
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// Every member of a `spawn_team` team has reported ready.
+    TeamReady {
+        team: String,
+        agents: Vec<WorkerName>,
+        elapsed_ms: u64,
+    },
+    /// A spawned team was torn down: a member exited, or not every member
+    /// was ready within the manifest's `ready_timeout_secs`.
+    TeamFailed {
+        team: String,
+        error: String,
+    },
+    PlanStepStarted {
+        plan: String,
+        step: String,
+        agent: WorkerName,
+    },
+    /// `output` is the agent's `agent_result` data when it submitted one,
+    /// otherwise the text it printed while working on the step.
+    PlanStepCompleted {
+        plan: String,
+        step: String,
+        agent: WorkerName,
+        output: Value,
+    },
+    /// Every step finished; `results` maps step ids to their outputs.
+    PlanCompleted {
+        plan: String,
+        results: BTreeMap<String, Value>,
+        elapsed_ms: u64,
+    },
+    PlanFailed {
+        plan: String,
+        #[serde(default)]
+        step: Option<String>,
+        error: String,
+    },
+    BrokerDraining {
+        reason: String,
+        timeout_ms: u64,
+        agent_count: usize,
+        pending_delivery_count: usize,
+    },
+    BrokerDrained {
+        /// `settled` when every pending delivery finished, `timeout` otherwise.
+        outcome: String,
+        elapsed_ms: u64,
+        /// Deliveries still queued; persisted for the next broker.
+        pending_delivery_count: usize,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -741,11 +1064,11 @@ mod tests {
     use serde_json::{json, Value};
 
     use super::{
-        AgentRuntime, AgentSpec, BrokerEvent, BrokerToSdk, BrokerToWorker, HeadlessHarnessDriver,
-        HeadlessProvider, MessageInjectionMode, ProtocolEnvelope, RelayDelivery,
-        ResolvedHarnessConfig, WorkerToBroker, PROTOCOL_VERSION,
+        AgentResourceLimits, AgentRuntime, AgentSpec, BrokerEvent, BrokerToSdk, BrokerToWorker,
+        HeadlessHarnessDriver, HeadlessProvider, MessageInjectionMode, ProtocolEnvelope,
+        RelayDelivery, ResolvedHarnessConfig, WorkerToBroker, PROTOCOL_VERSION,
     };
-    use crate::ids::RequestId;
+    use crate::ids::{RequestId, WorkerName};
 
     #[test]
     fn sdk_envelope_round_trip() {
@@ -801,6 +1124,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sdk_spawn_agent_accepts_after() {
+        use super::SdkToBroker;
+
+        let decoded: SdkToBroker = serde_json::from_value(json!({
+            "type": "spawn_agent",
+            "payload": {
+                "agent": { "name": "Reviewer", "runtime": "pty", "cli": "codex" },
+                "after": ["Architect", "Builder"]
+            }
+        }))
+        .unwrap();
+        let SdkToBroker::SpawnAgent { after, .. } = decoded else {
+            panic!("expected spawn_agent");
+        };
+        assert_eq!(after, vec!["Architect".to_string(), "Builder".to_string()]);
+
+        let decoded: SdkToBroker = serde_json::from_value(json!({
+            "type": "spawn_agent",
+            "payload": { "agent": { "name": "Solo", "runtime": "pty", "cli": "codex" } }
+        }))
+        .unwrap();
+        let SdkToBroker::SpawnAgent { after, .. } = decoded else {
+            panic!("expected spawn_agent");
+        };
+        assert!(after.is_empty());
+    }
+
     #[test]
     fn sdk_deregister_node_frame_round_trips() {
         use super::SdkToBroker;
@@ -1278,6 +1629,59 @@ mod tests {
         assert_eq!(raw["type"], "unsubscribe_channels");
     }
 
+    #[test]
+    fn sdk_get_logs_defaults_optional_fields() {
+        use super::SdkToBroker;
+        let decoded: SdkToBroker = serde_json::from_value(json!({
+            "type": "get_logs",
+            "payload": { "name": "Worker1", "follow": true }
+        }))
+        .unwrap();
+        assert_eq!(
+            decoded,
+            SdkToBroker::GetLogs {
+                name: "Worker1".into(),
+                lines: None,
+                offset: None,
+                max_bytes: None,
+                follow: Some(true),
+            }
+        );
+    }
+
+    #[test]
+    fn sdk_requeue_dead_letter_decodes() {
+        use super::SdkToBroker;
+        let decoded: SdkToBroker = serde_json::from_value(json!({
+            "type": "requeue_dead_letter",
+            "payload": { "delivery_id": "del_1" }
+        }))
+        .unwrap();
+        assert_eq!(
+            decoded,
+            SdkToBroker::RequeueDeadLetter {
+                delivery_id: "del_1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn sdk_set_agent_team_null_leaves_team() {
+        use super::SdkToBroker;
+        let decoded: SdkToBroker = serde_json::from_value(json!({
+            "type": "set_agent_team",
+            "payload": { "name": "Worker1", "team": null }
+        }))
+        .unwrap();
+        assert_eq!(
+            decoded,
+            SdkToBroker::SetAgentTeam {
+                name: "Worker1".into(),
+                team: None,
+            }
+        );
+    }
+
     #[test]
     fn broker_event_channel_subscribed_round_trip() {
         let event = BrokerToSdk::Event(BrokerEvent::ChannelSubscribed {
@@ -1299,4 +1703,200 @@ mod tests {
         let decoded: BrokerToSdk = serde_json::from_str(&encoded).unwrap();
         assert_eq!(decoded, event);
     }
+
+    #[test]
+    fn pause_and_resume_frames_round_trip() {
+        use super::SdkToBroker;
+
+        let frame: SdkToBroker =
+            serde_json::from_value(json!({"type": "pause_agent", "payload": {"name": "Worker1"}}))
+                .unwrap();
+        assert_eq!(
+            frame,
+            SdkToBroker::PauseAgent {
+                name: "Worker1".into()
+            }
+        );
+
+        let event = BrokerToSdk::Event(BrokerEvent::AgentResumed {
+            name: "Worker1".into(),
+            paused_ms: 1_500,
+            pending_delivery_count: 2,
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "agent_resumed");
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn spawn_queue_events_round_trip() {
+        let event = BrokerToSdk::Event(BrokerEvent::SpawnQueued {
+            name: "Worker9".into(),
+            position: 2,
+            running: 8,
+            max_concurrent: 8,
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "spawn_queued");
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+
+        let event = BrokerToSdk::Event(BrokerEvent::SpawnStarted {
+            name: "Worker9".into(),
+            queued_ms: 4_200,
+            pid: Some(4242),
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "spawn_started");
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn agent_spec_resource_limits_are_inline_and_optional() {
+        let spec: AgentSpec = serde_json::from_value(json!({
+            "name": "Worker1",
+            "runtime": "pty",
+            "cli": "claude",
+            "maxMemoryMb": 2048,
+            "cpu_shares": 512,
+        }))
+        .unwrap();
+        assert_eq!(
+            spec.limits,
+            AgentResourceLimits {
+                max_memory_mb: Some(2048),
+                cpu_shares: Some(512),
+                restart_on_limit: false,
+            }
+        );
+        let encoded = serde_json::to_value(&spec).unwrap();
+        assert_eq!(encoded["max_memory_mb"], 2048);
+        assert_eq!(encoded["cpu_shares"], 512);
+        assert!(encoded.get("restart_on_limit").is_none());
+        assert!(encoded.get("limits").is_none());
+
+        let spec: AgentSpec =
+            serde_json::from_value(json!({"name": "Worker2", "runtime": "pty"})).unwrap();
+        assert!(spec.limits.is_empty());
+    }
+
+    #[test]
+    fn drain_frame_timeout_is_optional() {
+        use super::SdkToBroker;
+
+        let frame: SdkToBroker =
+            serde_json::from_value(json!({"type": "drain", "payload": {}})).unwrap();
+        assert_eq!(frame, SdkToBroker::Drain { timeout_ms: None });
+
+        let frame: SdkToBroker =
+            serde_json::from_value(json!({"type": "drain", "payload": {"timeout_ms": 5000}}))
+                .unwrap();
+        assert_eq!(
+            frame,
+            SdkToBroker::Drain {
+                timeout_ms: Some(5_000)
+            }
+        );
+
+        let event = BrokerToSdk::Event(BrokerEvent::BrokerDrained {
+            outcome: "settled".to_string(),
+            elapsed_ms: 120,
+            pending_delivery_count: 0,
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "broker_drained");
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn spawn_team_frame_reads_member_specs_inline() {
+        use super::SdkToBroker;
+
+        let frame: SdkToBroker = serde_json::from_value(json!({
+            "type": "spawn_team",
+            "payload": {"manifest": {
+                "name": "backend",
+                "channels": ["backend"],
+                "agents": [
+                    {"name": "Architect", "runtime": "pty", "cli": "claude", "task": "Plan it."},
+                    {
+                        "name": "Builder",
+                        "runtime": "headless",
+                        "provider": "codex",
+                        "maxMemoryMb": 1024,
+                        "task": "Build it.",
+                        "depends_on": ["Architect"],
+                    },
+                ],
+            }},
+        }))
+        .unwrap();
+        let SdkToBroker::SpawnTeam { manifest } = frame else {
+            panic!("expected spawn_team");
+        };
+        assert_eq!(manifest.name, "backend");
+        assert_eq!(manifest.ready_timeout_secs, None);
+        let builder = &manifest.agents[1];
+        assert_eq!(builder.agent.name, "Builder");
+        assert_eq!(builder.agent.provider, Some(HeadlessProvider::Codex));
+        assert_eq!(builder.agent.limits.max_memory_mb, Some(1024));
+        assert_eq!(builder.task.as_deref(), Some("Build it."));
+        assert_eq!(builder.depends_on, [WorkerName::from("Architect")]);
+
+        let event = BrokerToSdk::Event(BrokerEvent::TeamReady {
+            team: "backend".to_string(),
+            agents: vec!["Architect".into(), "Builder".into()],
+            elapsed_ms: 900,
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "team_ready");
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn run_plan_frame_round_trips_steps() {
+        use super::SdkToBroker;
+
+        let frame: SdkToBroker = serde_json::from_value(json!({
+            "type": "run_plan",
+            "payload": {"plan": {
+                "name": "release",
+                "steps": [
+                    {"id": "notes", "agent": "Writer", "task": "Draft release notes."},
+                    {
+                        "id": "review",
+                        "agent": "Reviewer",
+                        "task": "Review the notes.",
+                        "inputs_from": ["notes"],
+                    },
+                ],
+            }},
+        }))
+        .unwrap();
+        let SdkToBroker::RunPlan { plan } = frame else {
+            panic!("expected run_plan");
+        };
+        assert_eq!(plan.timeout_secs, None);
+        assert!(plan.steps[0].depends_on.is_empty());
+        assert_eq!(plan.steps[1].agent, "Reviewer");
+        assert_eq!(plan.steps[1].inputs_from, ["notes"]);
+
+        let event = BrokerToSdk::Event(BrokerEvent::PlanCompleted {
+            plan: "release".to_string(),
+            results: [("notes".to_string(), json!("v1.2: faster spawns"))].into(),
+            elapsed_ms: 4200,
+        });
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["payload"]["kind"], "plan_completed");
+        assert_eq!(
+            encoded["payload"]["results"]["notes"],
+            "v1.2: faster spawns"
+        );
+        let decoded: BrokerToSdk = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded, event);
+    }
 }
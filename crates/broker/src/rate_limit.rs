@@ -0,0 +1,361 @@
+//! Per-route rate limiting for the broker HTTP API.
+//!
+//! A runaway dashboard loop can flood `/api/spawn` and burn through the
+//! workspace's Relaycast registration quota. Each limited route class gets a
+//! token bucket: `count` requests are allowed per `window`, refilled
+//! continuously, so a full bucket also permits a burst of `count`.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::broker_config::{env_or, RateLimitsSection};
+
+/// Route classes that are rate limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LimitedRoute {
+    Spawn,
+    Send,
+    Release,
+}
+
+impl LimitedRoute {
+    pub(crate) const ALL: [LimitedRoute; 3] = [Self::Spawn, Self::Send, Self::Release];
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Spawn => "spawn",
+            Self::Send => "send",
+            Self::Release => "release",
+        }
+    }
+
+    pub(crate) fn env_key(self) -> &'static str {
+        match self {
+            Self::Spawn => "AGENT_RELAY_RATE_LIMIT_SPAWN",
+            Self::Send => "AGENT_RELAY_RATE_LIMIT_SEND",
+            Self::Release => "AGENT_RELAY_RATE_LIMIT_RELEASE",
+        }
+    }
+}
+
+/// `count` requests per `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RateLimit {
+    pub(crate) count: u32,
+    pub(crate) window: Duration,
+}
+
+impl RateLimit {
+    pub(crate) const fn per_minute(count: u32) -> Self {
+        Self {
+            count,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    /// Parse `<count>/<window>`, e.g. `30/min`, `5/s`, `100/10m`.
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        let (count, window) = raw.trim().split_once('/')?;
+        let count = count
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|count| *count > 0)?;
+        let window = window.trim().to_ascii_lowercase();
+        let digits_end = window
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(window.len());
+        let (multiplier, unit) = window.split_at(digits_end);
+        let multiplier = if multiplier.is_empty() {
+            1
+        } else {
+            multiplier.parse::<u64>().ok().filter(|value| *value > 0)?
+        };
+        let unit_secs = match unit.trim() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 3_600,
+            _ => return None,
+        };
+        Some(Self {
+            count,
+            window: Duration::from_secs(multiplier.checked_mul(unit_secs)?),
+        })
+    }
+
+    fn refill_per_sec(self) -> f64 {
+        f64::from(self.count) / self.window.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}s", self.count, self.window.as_secs())
+    }
+}
+
+/// Limits per route class; `None` disables limiting for that class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RateLimitConfig {
+    pub(crate) spawn: Option<RateLimit>,
+    pub(crate) send: Option<RateLimit>,
+    pub(crate) release: Option<RateLimit>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            spawn: Some(RateLimit::per_minute(30)),
+            send: Some(RateLimit::per_minute(600)),
+            release: Some(RateLimit::per_minute(60)),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Defaults overridden by `AGENT_RELAY_RATE_LIMIT_{SPAWN,SEND,RELEASE}`,
+    /// else by `[rate_limits]` (`<count>/<window>` or `off`). Unparseable
+    /// values keep the default.
+    pub(crate) fn load(file: &RateLimitsSection) -> Self {
+        let mut config = Self::default();
+        for route in LimitedRoute::ALL {
+            let file_value = match route {
+                LimitedRoute::Spawn => &file.spawn,
+                LimitedRoute::Send => &file.send,
+                LimitedRoute::Release => &file.release,
+            };
+            let Some(raw) = env_or(route.env_key(), file_value.clone()) else {
+                continue;
+            };
+            let slot = config.slot_mut(route);
+            if matches!(
+                raw.trim().to_ascii_lowercase().as_str(),
+                "off" | "0" | "none"
+            ) {
+                *slot = None;
+            } else if let Some(limit) = RateLimit::parse(&raw) {
+                *slot = Some(limit);
+            } else {
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    key = route.env_key(),
+                    value = %raw,
+                    "ignoring invalid rate limit; expected <count>/<window> or off"
+                );
+            }
+        }
+        config
+    }
+
+    pub(crate) fn limit_for(&self, route: LimitedRoute) -> Option<RateLimit> {
+        match route {
+            LimitedRoute::Spawn => self.spawn,
+            LimitedRoute::Send => self.send,
+            LimitedRoute::Release => self.release,
+        }
+    }
+
+    fn slot_mut(&mut self, route: LimitedRoute) -> &mut Option<RateLimit> {
+        match route {
+            LimitedRoute::Spawn => &mut self.spawn,
+            LimitedRoute::Send => &mut self.send,
+            LimitedRoute::Release => &mut self.release,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    allowed: u64,
+    rejected: u64,
+}
+
+/// Shared limiter state for the HTTP API.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<LimitedRoute, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take one token for `route`. On rejection returns how long until the
+    /// next token is available, for the `Retry-After` header.
+    pub(crate) fn check(&self, route: LimitedRoute, now: Instant) -> Result<(), Duration> {
+        let limit = self.config.limit_for(route);
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry(route).or_insert_with(|| Bucket {
+            tokens: limit.map(|limit| f64::from(limit.count)).unwrap_or(0.0),
+            last_refill: now,
+            allowed: 0,
+            rejected: 0,
+        });
+        let Some(limit) = limit else {
+            bucket.allowed += 1;
+            return Ok(());
+        };
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * limit.refill_per_sec())
+            .min(f64::from(limit.count));
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.allowed += 1;
+            Ok(())
+        } else {
+            bucket.rejected += 1;
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.refill_per_sec(),
+            ))
+        }
+    }
+
+    /// Per-route limits and allow/reject counters for `/api/metrics`.
+    pub(crate) fn snapshot(&self) -> Value {
+        let buckets = self.buckets.lock();
+        let routes = LimitedRoute::ALL.iter().map(|route| {
+            let (allowed, rejected) = buckets
+                .get(route)
+                .map(|bucket| (bucket.allowed, bucket.rejected))
+                .unwrap_or((0, 0));
+            (
+                route.as_str().to_string(),
+                json!({
+                    "limit": self.config.limit_for(*route).map(|limit| limit.to_string()),
+                    "allowed": allowed,
+                    "rejected": rejected,
+                }),
+            )
+        });
+        Value::Object(routes.collect())
+    }
+
+    /// Allow/reject counters in Prometheus text format for `/metrics`.
+    pub(crate) fn to_prometheus(&self) -> String {
+        let buckets = self.buckets.lock();
+        let mut out = String::new();
+        for (metric, help, rejected) in [
+            (
+                "relay_broker_rate_limit_allowed_total",
+                "HTTP API requests admitted by the rate limiter.",
+                false,
+            ),
+            (
+                "relay_broker_rate_limit_rejected_total",
+                "HTTP API requests rejected with 429.",
+                true,
+            ),
+        ] {
+            out.push_str(&format!("# HELP {metric} {help}\n"));
+            out.push_str(&format!("# TYPE {metric} counter\n"));
+            for route in LimitedRoute::ALL {
+                let count = buckets
+                    .get(&route)
+                    .map(|bucket| {
+                        if rejected {
+                            bucket.rejected
+                        } else {
+                            bucket.allowed
+                        }
+                    })
+                    .unwrap_or(0);
+                out.push_str(&format!(
+                    "{metric}{{route=\"{}\"}} {count}\n",
+                    route.as_str()
+                ));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_units_and_multipliers() {
+        assert_eq!(RateLimit::parse("30/min"), Some(RateLimit::per_minute(30)));
+        assert_eq!(
+            RateLimit::parse(" 5 / s "),
+            Some(RateLimit {
+                count: 5,
+                window: Duration::from_secs(1),
+            })
+        );
+        assert_eq!(
+            RateLimit::parse("100/10m"),
+            Some(RateLimit {
+                count: 100,
+                window: Duration::from_secs(600),
+            })
+        );
+        assert_eq!(RateLimit::parse("0/min"), None);
+        assert_eq!(RateLimit::parse("10/fortnight"), None);
+        assert_eq!(RateLimit::parse("10"), None);
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_rejects_with_retry_after() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            spawn: Some(RateLimit {
+                count: 2,
+                window: Duration::from_secs(10),
+            }),
+            ..RateLimitConfig::default()
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check(LimitedRoute::Spawn, start).is_ok());
+        assert!(limiter.check(LimitedRoute::Spawn, start).is_ok());
+        let retry_after = limiter
+            .check(LimitedRoute::Spawn, start)
+            .expect_err("third spawn in the burst is limited");
+        assert_eq!(retry_after, Duration::from_secs(5));
+
+        // One token refills every 5s.
+        assert!(limiter
+            .check(LimitedRoute::Spawn, start + Duration::from_secs(6))
+            .is_ok());
+
+        let snapshot = limiter.snapshot();
+        assert_eq!(snapshot["spawn"]["allowed"], 3);
+        assert_eq!(snapshot["spawn"]["rejected"], 1);
+        assert_eq!(snapshot["send"]["allowed"], 0);
+
+        let prom = limiter.to_prometheus();
+        assert!(prom.contains("relay_broker_rate_limit_allowed_total{route=\"spawn\"} 3\n"));
+        assert!(prom.contains("relay_broker_rate_limit_rejected_total{route=\"spawn\"} 1\n"));
+        assert!(prom.contains("relay_broker_rate_limit_rejected_total{route=\"send\"} 0\n"));
+    }
+
+    #[test]
+    fn disabled_route_is_never_limited() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            release: None,
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+        for _ in 0..1_000 {
+            assert!(limiter.check(LimitedRoute::Release, now).is_ok());
+        }
+        assert_eq!(limiter.snapshot()["release"]["limit"], Value::Null);
+    }
+}
@@ -7,6 +7,16 @@ pub(crate) struct RoutingWorker<'a> {
     pub(crate) name: &'a str,
     pub(crate) channels: &'a [crate::ids::ChannelName],
     pub(crate) workspace_id: Option<&'a str>,
+    pub(crate) team: Option<&'a str>,
+}
+
+/// A team name as stored on an [`crate::protocol::AgentSpec`]: trimmed and
+/// without a leading `@`, so `@backend-team` and ` backend-team` are the
+/// same team. Blank names mean no team.
+pub(crate) fn normalize_team(team: &str) -> Option<String> {
+    let team = team.trim();
+    let team = team.strip_prefix('@').unwrap_or(team).trim();
+    (!team.is_empty()).then(|| team.to_string())
 }
 
 /// Returns true if a worker is eligible to receive events from the given workspace.
@@ -76,14 +86,50 @@ pub(crate) fn worker_names_for_direct_target(
         .collect()
 }
 
+/// Members of the team addressed by `@<team>`, matched case-insensitively.
+/// Empty when `target` is not an `@` target or names no team here; a worker
+/// named like the team is addressed directly and takes precedence.
+pub(crate) fn worker_names_for_team_target(
+    workers: &[RoutingWorker<'_>],
+    target: &str,
+    from: &str,
+    workspace_id: Option<&str>,
+) -> Vec<String> {
+    let Some(team) = target.trim().strip_prefix('@').and_then(normalize_team) else {
+        return Vec::new();
+    };
+    if workers
+        .iter()
+        .any(|worker| worker.name.eq_ignore_ascii_case(&team))
+    {
+        return Vec::new();
+    }
+    workers
+        .iter()
+        .filter(|worker| !worker.name.eq_ignore_ascii_case(from))
+        .filter(|worker| worker_matches_workspace(worker, workspace_id))
+        .filter(|worker| {
+            worker
+                .team
+                .and_then(normalize_team)
+                .is_some_and(|worker_team| worker_team.eq_ignore_ascii_case(&team))
+        })
+        .map(|worker| worker.name.to_string())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{worker_names_for_channel_delivery, worker_names_for_direct_target, RoutingWorker};
+    use super::{
+        worker_names_for_channel_delivery, worker_names_for_direct_target,
+        worker_names_for_team_target, RoutingWorker,
+    };
 
     #[derive(Debug)]
     struct WorkerFixture {
         name: String,
         channels: Vec<crate::ids::ChannelName>,
+        team: Option<String>,
     }
 
     impl WorkerFixture {
@@ -94,8 +140,14 @@ mod tests {
                     .iter()
                     .map(|channel| crate::ids::ChannelName::from(*channel))
                     .collect(),
+                team: None,
             }
         }
+
+        fn in_team(mut self, team: &str) -> Self {
+            self.team = Some(team.to_string());
+            self
+        }
     }
 
     fn routing_workers<'a>(workers: &'a [WorkerFixture]) -> Vec<RoutingWorker<'a>> {
@@ -105,6 +157,7 @@ mod tests {
                 name: &worker.name,
                 channels: &worker.channels,
                 workspace_id: None,
+                team: worker.team.as_deref(),
             })
             .collect()
     }
@@ -142,6 +195,37 @@ mod tests {
         assert_eq!(targets, vec!["AgentOne".to_string()]);
     }
 
+    #[test]
+    fn team_target_fans_out_to_members_except_sender() {
+        let workers = vec![
+            WorkerFixture::new("Lead", &["general"]).in_team("backend-team"),
+            WorkerFixture::new("Api", &["general"]).in_team("Backend-Team"),
+            WorkerFixture::new("Db", &["general"]).in_team(" @backend-team"),
+            WorkerFixture::new("Web", &["general"]).in_team("frontend"),
+            WorkerFixture::new("Solo", &["general"]),
+        ];
+        let team_workers = routing_workers(&workers);
+
+        let targets = worker_names_for_team_target(&team_workers, "@backend-team", "Lead", None);
+        assert_eq!(targets, vec!["Api".to_string(), "Db".to_string()]);
+
+        // Only `@` targets address teams, and a worker name wins over a team.
+        assert!(
+            worker_names_for_team_target(&team_workers, "backend-team", "Lead", None).is_empty()
+        );
+        let workers = vec![
+            WorkerFixture::new("frontend", &["general"]),
+            WorkerFixture::new("Web", &["general"]).in_team("frontend"),
+        ];
+        assert!(worker_names_for_team_target(
+            &routing_workers(&workers),
+            "@frontend",
+            "Lead",
+            None
+        )
+        .is_empty());
+    }
+
     #[test]
     fn channel_delivery_filters_by_workspace_id() {
         let workers = [
@@ -154,11 +238,13 @@ mod tests {
                 name: &workers[0].name,
                 channels: &workers[0].channels,
                 workspace_id: Some("ws_a"),
+                team: None,
             },
             RoutingWorker {
                 name: &workers[1].name,
                 channels: &workers[1].channels,
                 workspace_id: Some("ws_b"),
+                team: None,
             },
         ];
 
@@ -193,11 +279,13 @@ mod tests {
                 name: &workers[0].name,
                 channels: &workers[0].channels,
                 workspace_id: None,
+                team: None,
             },
             RoutingWorker {
                 name: &workers[1].name,
                 channels: &workers[1].channels,
                 workspace_id: Some("ws_b"),
+                team: None,
             },
         ];
 
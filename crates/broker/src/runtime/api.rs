@@ -40,6 +40,51 @@ impl BrokerRuntime {
                 let _ = reply.send(result);
                 return;
             }
+            ListenApiRequest::RestartAgent {
+                name,
+                reason,
+                reply,
+            } => {
+                let result = self.handle_restart_agent(name, reason).await;
+                let _ = reply.send(result);
+                return;
+            }
+            ListenApiRequest::SpawnTeam { manifest, reply } => {
+                let result = self.spawn_team(manifest, false).await;
+                let _ = reply.send(result);
+                return;
+            }
+            ListenApiRequest::RunPlan { plan, reply } => {
+                let result = self.run_plan(plan).await;
+                let _ = reply.send(result);
+                return;
+            }
+            ListenApiRequest::ListDeadLetters { reply } => {
+                let _ = reply.send(Ok(self.list_dead_letters()));
+                return;
+            }
+            ListenApiRequest::RequeueDeadLetter { delivery_id, reply } => {
+                let result = self.requeue_dead_letter(&delivery_id).await;
+                let _ = reply.send(result);
+                return;
+            }
+            ListenApiRequest::Drain { timeout_ms, reply } => {
+                let result = self
+                    .begin_drain(timeout_ms.map(Duration::from_millis), "request")
+                    .await;
+                let _ = reply.send(Ok(result));
+                return;
+            }
+            // Waiting on `after`, or past the concurrency cap; draining spawns
+            // fall through to be refused.
+            request @ ListenApiRequest::Spawn { .. }
+                if !self.workers.draining
+                    && (!self.workers.has_capacity()
+                        || !self.spawn_dependencies_ready(&request)) =>
+            {
+                self.enqueue_spawn(request).await;
+                return;
+            }
             other => other,
         };
         let paths = &self.paths;
@@ -57,6 +102,7 @@ impl BrokerRuntime {
         let fleet_node_name = self.fleet_node_name.as_str();
         let node_delivery_token_present = self.node_delivery_token_present;
         let node_delivery_connected = self.node_delivery_connected;
+        let fleet_sidecar_connected = self.fleet_sidecar_out_tx.is_some();
         let fleet_inventory = &mut self.fleet_inventory;
         let fleet_delivery_book = &mut self.fleet_delivery_book;
         let fleet_max_agents = self.fleet_max_agents;
@@ -75,6 +121,8 @@ impl BrokerRuntime {
         let persist = self.persist;
         let shutdown = &mut self.shutdown;
         let crash_insights = &self.crash_insights;
+        let spawn_queue = &self.spawn_queue;
+        let config = &self.config;
 
         match req {
             ListenApiRequest::Spawn {
@@ -97,10 +145,20 @@ impl BrokerRuntime {
                 harness_config,
                 agent_token,
                 agent_result_schema,
+                limits,
+                image,
+                after: _,
                 reply,
             } => {
+                // Refuse before registering a token the spawn would never use.
+                if workers.draining {
+                    let _ = reply.send(Err(format!(
+                        "broker_draining: not accepting new agents while the broker drains (requested '{name}')"
+                    )));
+                    return;
+                }
                 let effective_channels = if channels.is_empty() {
-                    default_spawn_channels()
+                    default_spawn_channels(config)
                 } else {
                     channels.clone()
                 };
@@ -117,8 +175,13 @@ impl BrokerRuntime {
                     shadow_mode,
                     *restart_policy,
                     harness_config,
+                    &config.headless,
                 ) {
-                    Ok(spec) => spec,
+                    Ok(spec) => AgentSpec {
+                        limits,
+                        image,
+                        ..spec
+                    },
                     Err(error) => {
                         let _ = reply.send(Err(error.to_string()));
                         return;
@@ -217,90 +280,15 @@ impl BrokerRuntime {
                     normalize_initial_task(task)
                 };
                 if let Some(ref continue_from) = continue_from {
-                    let continuity_dir = continuity_dir(&paths.state);
-                    let continuity_file = continuity_dir.join(format!("{}.json", continue_from));
-                    if continuity_file.exists() {
-                        match std::fs::read_to_string(&continuity_file) {
-                            Ok(contents) => {
-                                if let Ok(ctx) = serde_json::from_str::<Value>(&contents) {
-                                    let prev_task = ctx
-                                        .get("initial_task")
-                                        .and_then(Value::as_str)
-                                        .unwrap_or("unknown");
-                                    let summary = ctx
-                                        .get("summary")
-                                        .and_then(Value::as_str)
-                                        .unwrap_or("no summary available");
-                                    let messages = ctx
-                                        .get("message_history")
-                                        .and_then(Value::as_array)
-                                        .map(|msgs| {
-                                            msgs.iter()
-                                                .filter_map(|m| {
-                                                    let from = m
-                                                        .get("from")
-                                                        .and_then(Value::as_str)
-                                                        .unwrap_or("?");
-                                                    let text = m
-                                                        .get("text")
-                                                        .and_then(Value::as_str)
-                                                        .unwrap_or("");
-                                                    if text.is_empty() {
-                                                        None
-                                                    } else {
-                                                        Some(format!("  {}: {}", from, text))
-                                                    }
-                                                })
-                                                .collect::<Vec<_>>()
-                                                .join("\n")
-                                        })
-                                        .unwrap_or_default();
-
-                                    let continuity_block = format!(
-                                        "## Continuity Context (from previous session as '{}')\n\
-                                                     Previous task: {}\n\
-                                                     Session summary: {}\n{}",
-                                        continue_from,
-                                        prev_task,
-                                        summary,
-                                        if messages.is_empty() {
-                                            String::new()
-                                        } else {
-                                            format!("Recent messages:\n{}\n", messages)
-                                        }
-                                    );
-
-                                    effective_task = Some(match effective_task {
-                                        Some(new_task) => {
-                                            format!(
-                                                "{}\n\n## Current Task\n{}",
-                                                continuity_block, new_task
-                                            )
-                                        }
-                                        None => continuity_block,
-                                    });
-                                    tracing::info!(
-                                        agent = %name,
-                                        continue_from = %continue_from,
-                                        "injected continuity context from previous session for HTTP API spawn"
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                tracing::warn!(
-                                    agent = %name,
-                                    continue_from = %continue_from,
-                                    error = %e,
-                                    "failed to read continuity file for HTTP API spawn"
-                                );
-                            }
-                        }
-                    } else {
-                        tracing::warn!(
+                    if let Some(continuity_block) =
+                        read_continuity_block(&paths.state, continue_from)
+                    {
+                        effective_task =
+                            Some(with_continuity_block(continuity_block, effective_task));
+                        tracing::info!(
                             agent = %name,
                             continue_from = %continue_from,
-                            "no continuity file found at {}",
-                            continuity_file.display()
+                            "injected continuity context from previous session for HTTP API spawn"
                         );
                     }
                 }
@@ -334,6 +322,7 @@ impl BrokerRuntime {
                     .await
                 {
                     Ok(effective_spec) => {
+                        workers.metrics.on_spawn(&name);
                         // Prepend relay skill text for small-tier models and CLI harnesses that
                         // need explicit tool guidance to reliably call add_agent / remove_agent.
                         // Skip when relay prompt injection is opted out — relay tools are absent.
@@ -477,6 +466,9 @@ impl BrokerRuntime {
                     "name": agent_name,
                     "result_id": result_id,
                 })));
+                if final_result {
+                    self.handle_plan_step_done(&agent_name, Some(data)).await;
+                }
             }
             ListenApiRequest::SetModel {
                 name,
@@ -527,6 +519,62 @@ impl BrokerRuntime {
                     }
                 }
             }
+            ListenApiRequest::SetAgentPaused {
+                name,
+                paused,
+                reply,
+            } => {
+                let paused_at = workers.workers.get(&name).and_then(|h| h.paused_at);
+                match workers.set_paused(&name, paused) {
+                    Ok(changed) => {
+                        let pending_delivery_count = pending_deliveries
+                            .values()
+                            .filter(|pending| pending.worker_name == name)
+                            .count();
+                        if changed {
+                            let (event, transition, reason) = if paused {
+                                (
+                                    BrokerEvent::AgentPaused {
+                                        name: name.clone(),
+                                        pending_delivery_count,
+                                    },
+                                    "paused",
+                                    "pause_agent",
+                                )
+                            } else {
+                                (
+                                    BrokerEvent::AgentResumed {
+                                        name: name.clone(),
+                                        paused_ms: paused_at
+                                            .map(|at| at.elapsed().as_millis() as u64)
+                                            .unwrap_or(0),
+                                        pending_delivery_count,
+                                    },
+                                    "working",
+                                    "resume_agent",
+                                )
+                            };
+                            let _ = send_broker_event(sdk_out_tx, event).await;
+                            publish_agent_state_transition(
+                                ws_control_tx,
+                                &name,
+                                transition,
+                                Some(reason),
+                            )
+                            .await;
+                        }
+                        let _ = reply.send(Ok(json!({
+                            "name": name,
+                            "paused": paused,
+                            "changed": changed,
+                            "pending_delivery_count": pending_delivery_count,
+                        })));
+                    }
+                    Err(error) => {
+                        let _ = reply.send(Err(error.to_string()));
+                    }
+                }
+            }
             ListenApiRequest::Release {
                 name,
                 reason,
@@ -540,7 +588,7 @@ impl BrokerRuntime {
                 workers.supervisor.unregister(&name);
                 workers.metrics.on_release(&name);
                 match workers.release(&name).await {
-                    Ok(()) => {
+                    Ok(report) => {
                         if let Err(error) = relaycast_http.mark_agent_offline(&name).await {
                             tracing::warn!(
                                 worker = %name,
@@ -584,7 +632,7 @@ impl BrokerRuntime {
                         )
                         .await;
                         let _ =
-                            send_event(sdk_out_tx, json!({"kind":"agent_released","name":&name}))
+                            send_event(sdk_out_tx, agent_released_event(&name, &report.survivors))
                                 .await;
                         publish_agent_state_transition(
                             ws_control_tx,
@@ -593,7 +641,9 @@ impl BrokerRuntime {
                             Some("http_api_release"),
                         )
                         .await;
-                        let _ = reply.send(Ok(json!({ "success": true, "name": name })));
+                        let _ = reply.send(Ok(
+                            json!({ "success": true, "name": name, "survivors": report.survivors }),
+                        ));
                     }
                     Err(e) => {
                         let message = e.to_string();
@@ -770,108 +820,111 @@ impl BrokerRuntime {
                         "thread_id is not a Relaycast message id; publishing without a thread reply"
                     );
                 }
+                // `@<team>` fans out as one DM per local member of the team.
+                let team_members = workers.team_members_for_target(
+                    &normalized_to,
+                    &delivery_from,
+                    &selected_workspace_id,
+                );
+                let publish_targets = if team_members.is_empty() {
+                    vec![normalized_to.clone()]
+                } else {
+                    team_members.clone()
+                };
+                // Each target is published and timed out on its own, so a
+                // failing team member does not hide which members already
+                // have the message; the caller retries only the failed ones.
                 let relaycast_start = Instant::now();
-                match timeout(
-                    relaycast_timeout,
-                    selected_workspace.http_client.send_with_mode(
-                        &normalized_to,
-                        &text,
-                        mode.clone(),
-                        publish_from,
-                        reply_thread_id,
-                    ),
-                )
-                .await
-                {
-                    Ok(Ok(())) => {
-                        tracing::info!(
-                            target = "relay_broker::http_api",
-
-                            event_id = %event_id,
-                            to = %normalized_to,
-                            relaycast_ms = %relaycast_start.elapsed().as_millis(),
-                            "relaycast publish succeeded"
+                let outcomes: Vec<(String, Result<(), String>)> =
+                    futures_util::future::join_all(publish_targets.iter().map(|target| {
+                        let publish = selected_workspace.http_client.send_with_mode(
+                            target,
+                            &text,
+                            mode.clone(),
+                            publish_from,
+                            reply_thread_id,
                         );
-                        emit_http_api_event_with_timeout(
-                            sdk_out_tx,
-                            json!({
-                                "kind": "relay_inbound",
-                                "event_id": event_id,
-                                "from": ui_from,
-                                "target": normalized_to,
-                                "body": text,
-                                "thread_id": thread_id.clone(),
-                                "workspace_id": selected_workspace_id.clone(),
-                                "workspace_alias": selected_workspace_alias.clone(),
-                            }),
-                            event_emit_timeout,
-                        )
-                        .await;
-                        if reply
-                            .send(Ok(json!({
-                                "success": true,
-                                "event_id": event_id,
-                                "relaycast_published": true,
-                                "local": false,
-                                "workspace_id": selected_workspace_id,
-                                "workspace_alias": selected_workspace_alias,
-                            })))
-                            .is_err()
-                        {
-                            tracing::warn!(
-                                target = "relay_broker::http_api",
-
-                                event_id = %event_id,
-                                "broker HTTP API reply channel closed before relaycast response"
-                            );
+                        async move {
+                            let outcome = match timeout(relaycast_timeout, publish).await {
+                                Ok(Ok(_)) => Ok(()),
+                                Ok(Err(error)) => Err(format!("Relaycast publish failed: {error}")),
+                                Err(_) => Err(format!(
+                                    "Relaycast publish timed out after {}ms",
+                                    relaycast_timeout.as_millis()
+                                )),
+                            };
+                            (target.clone(), outcome)
                         }
+                    }))
+                    .await;
+                let first_error = outcomes
+                    .iter()
+                    .find_map(|(_, outcome)| outcome.as_ref().err().cloned());
+                let any_published = outcomes.iter().any(|(_, outcome)| outcome.is_ok());
+                if any_published {
+                    tracing::info!(
+                        target = "relay_broker::http_api",
+
+                        event_id = %event_id,
+                        to = %normalized_to,
+                        relaycast_ms = %relaycast_start.elapsed().as_millis(),
+                        failed = ?first_error,
+                        "relaycast publish succeeded"
+                    );
+                    emit_http_api_event_with_timeout(
+                        sdk_out_tx,
+                        json!({
+                            "kind": "relay_inbound",
+                            "event_id": event_id,
+                            "from": ui_from,
+                            "target": normalized_to,
+                            "body": text,
+                            "thread_id": thread_id.clone(),
+                            "workspace_id": selected_workspace_id.clone(),
+                            "workspace_alias": selected_workspace_alias.clone(),
+                        }),
+                        event_emit_timeout,
+                    )
+                    .await;
+                    let mut response = json!({
+                        "success": first_error.is_none(),
+                        "event_id": event_id,
+                        "relaycast_published": true,
+                        "local": false,
+                        "workspace_id": selected_workspace_id,
+                        "workspace_alias": selected_workspace_alias,
+                    });
+                    if !team_members.is_empty() {
+                        response["team_members"] = json!(team_members);
+                        response["team_results"] = team_publish_results(&outcomes);
                     }
-                    Ok(Err(error)) => {
+                    if reply.send(Ok(response)).is_err() {
                         tracing::warn!(
                             target = "relay_broker::http_api",
 
                             event_id = %event_id,
-                            to = %normalized_to,
-                            relaycast_ms = %relaycast_start.elapsed().as_millis(),
-                            error = %error,
-                            "relaycast publish failed"
+                            "broker HTTP API reply channel closed before relaycast response"
                         );
-                        if reply
-                            .send(Err(format!("Relaycast publish failed: {error}")))
-                            .is_err()
-                        {
-                            tracing::warn!(
-                                target = "relay_broker::http_api",
-
-                                event_id = %event_id,
-                                "broker HTTP API reply channel closed before relaycast failure response"
-                            );
-                        }
                     }
-                    Err(_) => {
+                } else {
+                    let error = first_error
+                        .unwrap_or_else(|| "Relaycast publish failed: no targets".to_string());
+                    tracing::warn!(
+                        target = "relay_broker::http_api",
+
+                        event_id = %event_id,
+                        to = %normalized_to,
+                        relaycast_ms = %relaycast_start.elapsed().as_millis(),
+                        error = %error,
+                        "relaycast publish failed"
+                    );
+                    if reply.send(Err(error)).is_err() {
                         tracing::warn!(
                             target = "relay_broker::http_api",
 
                             event_id = %event_id,
-                            to = %normalized_to,
-                            relaycast_timeout_ms = %relaycast_timeout.as_millis(),
-                            relaycast_ms = %relaycast_start.elapsed().as_millis(),
-                            "relaycast publish timed out"
+                            "broker HTTP API reply channel closed before relaycast failure response"
                         );
-                        if reply
-                            .send(Err(format!(
-                                "Relaycast publish timed out after {}ms",
-                                relaycast_timeout.as_millis()
-                            )))
-                            .is_err()
-                        {
-                            tracing::warn!(
-                                target = "relay_broker::http_api",
-
-                                event_id = %event_id,
-                                "broker HTTP API reply channel closed before relaycast timeout response"
-                            );
-                        }
                     }
                 }
                 tracing::info!(
@@ -1004,7 +1057,7 @@ impl BrokerRuntime {
                             "unsupported_runtime: worker '{name}' is headless; pty input is only supported on PTY workers"
                         )));
                     }
-                    Some(AgentRuntime::Pty) => {
+                    Some(_) => {
                         if let Err(err) = workers
                             .send_to_worker(
                                 &name,
@@ -1039,7 +1092,7 @@ impl BrokerRuntime {
                             "unsupported_runtime: worker '{name}' is headless; pty input streams are only supported on PTY workers"
                         )));
                     }
-                    Some(AgentRuntime::Pty) => {
+                    Some(_) => {
                         let _ = reply.send(Ok(json!({
                             "name": name,
                             "runtime": "pty",
@@ -1071,7 +1124,7 @@ impl BrokerRuntime {
                                 "unsupported_runtime: worker '{name}' is headless; resize_pty is only supported on PTY workers"
                             )));
                         }
-                        Some(AgentRuntime::Pty) => {
+                        Some(_) => {
                             if let Err(err) = workers
                                 .send_to_worker(
                                     &name,
@@ -1134,7 +1187,7 @@ impl BrokerRuntime {
                                         ),
                                     ));
                     }
-                    Some(AgentRuntime::Pty) => {
+                    Some(_) => {
                         let request_id = RequestId::new(format!("req_{}", Uuid::new_v4().simple()));
                         if let Err(err) = workers
                             .send_to_worker(&name, &kind, Some(request_id.clone()), payload)
@@ -1175,6 +1228,54 @@ impl BrokerRuntime {
                     })));
                 }
             }
+            ListenApiRequest::GetPrometheusMetrics { reply } => {
+                for handle in workers.workers.values() {
+                    let sample = build_agent_metrics(handle);
+                    workers
+                        .metrics
+                        .update_memory(sample.name.as_str(), sample.memory_bytes);
+                }
+                let mut body = workers.metrics.to_prometheus(workers.workers.len());
+                push_prometheus_gauge(
+                    &mut body,
+                    "relay_broker_pending_deliveries",
+                    "Deliveries injected but not yet acknowledged by the worker.",
+                    pending_deliveries.len() as u64,
+                );
+                push_prometheus_gauge(
+                    &mut body,
+                    "relay_broker_node_ws_connected",
+                    "Whether the Relaycast node websocket is connected (1) or not (0).",
+                    u64::from(node_delivery_connected),
+                );
+                push_prometheus_gauge(
+                    &mut body,
+                    "relay_broker_fleet_sidecar_connected",
+                    "Whether a fleet sidecar is attached (1) or not (0).",
+                    u64::from(fleet_sidecar_connected),
+                );
+                let _ = reply.send(body);
+            }
+            ListenApiRequest::GetOutput {
+                name,
+                lines,
+                since,
+                reply,
+            } => {
+                let result = match workers.workers.get(&name) {
+                    Some(handle) => {
+                        let page = handle.scrollback.page(lines, since);
+                        Ok(json!({
+                            "name": name,
+                            "lines": page.lines,
+                            "next_since": page.next_since,
+                            "truncated": page.truncated,
+                        }))
+                    }
+                    None => Err(format!("unknown worker '{}'", name)),
+                };
+                let _ = reply.send(result);
+            }
             ListenApiRequest::GetStatus { reply } => {
                 let pending: Vec<Value> = pending_deliveries
                     .values()
@@ -1211,6 +1312,9 @@ impl BrokerRuntime {
                     "agent_count": workers.workers.len(),
                     "agents": workers.list(),
                     "pending_delivery_count": pending.len(),
+                    "draining": workers.draining,
+                    "max_concurrent_agents": workers.max_concurrent,
+                    "queued_spawns": spawn_queue.iter().map(QueuedSpawn::name).collect::<Vec<_>>(),
                     "pending_deliveries": pending,
                     "node_connected": node_delivery_connected,
                     "node_delivery": {
@@ -1413,6 +1517,46 @@ impl BrokerRuntime {
                     "channels": remaining,
                 })));
             }
+            ListenApiRequest::SetAgentTeam { name, team, reply } => {
+                let team = team.as_deref().and_then(normalize_team);
+                let Some(handle) = workers.workers.get_mut(&name) else {
+                    let _ = reply.send(Err(format!("agent_not_found: no worker named '{name}'")));
+                    return;
+                };
+                let previous_team = std::mem::replace(&mut handle.spec.team, team.clone());
+                if let Some(spec) = state
+                    .agents
+                    .get_mut(&name)
+                    .and_then(|agent| agent.spec.as_mut())
+                {
+                    spec.team = team.clone();
+                    if paths.persist {
+                        if let Err(error) = state.save(&paths.state) {
+                            tracing::warn!(
+                                path = %paths.state.display(),
+                                worker = %name,
+                                error = %error,
+                                "failed to persist team membership"
+                            );
+                        }
+                    }
+                }
+                if previous_team != team {
+                    let _ = send_broker_event(
+                        sdk_out_tx,
+                        BrokerEvent::AgentTeamChanged {
+                            name: name.clone(),
+                            team: team.clone(),
+                            previous_team,
+                        },
+                    )
+                    .await;
+                }
+                let _ = reply.send(Ok(json!({
+                    "name": name,
+                    "team": team,
+                })));
+            }
             ListenApiRequest::GetInboundDeliveryMode { name, reply } => {
                 if !workers.has_worker(&name) {
                     let _ = reply.send(Err(DeliveryRouteError::WorkerNotFound(name)));
@@ -1561,8 +1705,14 @@ impl BrokerRuntime {
             }
             ListenApiRequest::FleetSidecarConnect { .. }
             | ListenApiRequest::FleetSidecarDisconnect
-            | ListenApiRequest::FleetSidecarFrame { .. } => {
-                unreachable!("fleet sidecar API requests are handled before runtime borrows")
+            | ListenApiRequest::FleetSidecarFrame { .. }
+            | ListenApiRequest::RestartAgent { .. }
+            | ListenApiRequest::SpawnTeam { .. }
+            | ListenApiRequest::RunPlan { .. }
+            | ListenApiRequest::ListDeadLetters { .. }
+            | ListenApiRequest::RequeueDeadLetter { .. }
+            | ListenApiRequest::Drain { .. } => {
+                unreachable!("handled before runtime borrows")
             }
         }
     }
@@ -1725,6 +1875,20 @@ pub(super) fn relay_skill_prefix(cli: &str, model: Option<&str>) -> Option<Strin
     }
 }
 
+/// Per-member outcome of an `@team` fan-out, reported as `team_results` so
+/// a caller can retry only the members that did not get the message.
+pub(crate) fn team_publish_results(outcomes: &[(String, Result<(), String>)]) -> Value {
+    Value::Array(
+        outcomes
+            .iter()
+            .map(|(target, outcome)| match outcome {
+                Ok(()) => json!({ "target": target, "published": true }),
+                Err(error) => json!({ "target": target, "published": false, "error": error }),
+            })
+            .collect(),
+    )
+}
+
 #[cfg(test)]
 mod skill_injection_tests {
     use super::{
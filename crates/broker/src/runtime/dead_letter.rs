@@ -0,0 +1,197 @@
+use super::*;
+
+/// The oldest dead letters are dropped past this many.
+const MAX_DEAD_LETTERS: usize = 1000;
+
+/// A delivery dropped after [`MAX_DELIVERY_RETRIES`] attempts, kept so an
+/// operator can inspect it and send it again with `requeue_dead_letter`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DeadLetter {
+    pub(super) worker_name: WorkerName,
+    pub(super) delivery: RelayDelivery,
+    pub(super) attempts: u32,
+    pub(super) last_error: String,
+    pub(super) queued_at_ms: u64,
+    pub(super) dead_at_ms: u64,
+}
+
+impl DeadLetter {
+    pub(crate) fn new(pending: &PendingDelivery, last_error: &str) -> Self {
+        Self {
+            worker_name: pending.worker_name.clone(),
+            delivery: pending.delivery.clone(),
+            attempts: pending.attempts,
+            last_error: last_error.to_string(),
+            queued_at_ms: pending.queued_at_ms,
+            dead_at_ms: unix_timestamp_millis(),
+        }
+    }
+
+    /// A fresh pending delivery for the same worker, with its retries reset.
+    pub(crate) fn into_pending(self) -> PendingDelivery {
+        PendingDelivery {
+            worker_name: self.worker_name,
+            delivery: self.delivery,
+            attempts: 0,
+            next_retry_at: Instant::now(),
+            queued_at_ms: unix_timestamp_millis(),
+            last_error: None,
+        }
+    }
+}
+
+/// Dead letters, oldest first, written back to `path` on every change.
+#[derive(Debug)]
+pub(crate) struct DeadLetterQueue {
+    path: PathBuf,
+    entries: Vec<DeadLetter>,
+}
+
+impl DeadLetterQueue {
+    /// Load `path`; a missing or unreadable file starts an empty queue.
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|error| {
+                tracing::warn!(
+                    path = %path.display(),
+                    error = %error,
+                    "ignoring unreadable dead-letter file"
+                );
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { path, entries }
+    }
+
+    pub(crate) fn entries(&self) -> &[DeadLetter] {
+        &self.entries
+    }
+
+    pub(crate) fn push(&mut self, entry: DeadLetter) {
+        self.entries
+            .retain(|existing| existing.delivery.delivery_id != entry.delivery.delivery_id);
+        self.entries.push(entry);
+        if self.entries.len() > MAX_DEAD_LETTERS {
+            let excess = self.entries.len() - MAX_DEAD_LETTERS;
+            self.entries.drain(..excess);
+        }
+        self.save();
+    }
+
+    pub(crate) fn get(&self, delivery_id: &str) -> Option<&DeadLetter> {
+        self.entries
+            .iter()
+            .find(|entry| entry.delivery.delivery_id == delivery_id)
+    }
+
+    /// Remove and return the dead letter for `delivery_id`.
+    pub(crate) fn take(&mut self, delivery_id: &str) -> Option<DeadLetter> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.delivery.delivery_id == delivery_id)?;
+        let entry = self.entries.remove(index);
+        self.save();
+        Some(entry)
+    }
+
+    fn save(&self) {
+        if let Err(error) = self.try_save() {
+            tracing::warn!(
+                path = %self.path.display(),
+                error = %error,
+                "failed to persist dead letters"
+            );
+        }
+    }
+
+    fn try_save(&self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(&self.path);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("failed creating temp file in {}", dir.display()))?;
+        std::io::Write::write_all(&mut tmp, json.as_bytes())?;
+        tmp.persist(&self.path).with_context(|| {
+            format!("failed persisting dead letters to {}", self.path.display())
+        })?;
+        Ok(())
+    }
+}
+
+/// Keep a delivery that ran out of retries and tell the SDK about it.
+pub(crate) async fn record_dead_letter(
+    dead_letters: &mut DeadLetterQueue,
+    sdk_out_tx: &mpsc::Sender<ProtocolEnvelope<Value>>,
+    dead_letter: DeadLetter,
+) {
+    tracing::warn!(
+        target = "agent_relay::broker",
+        worker = %dead_letter.worker_name,
+        delivery_id = %dead_letter.delivery.delivery_id,
+        attempts = dead_letter.attempts,
+        "delivery exhausted its retries; moved to the dead-letter queue"
+    );
+    let event = BrokerEvent::DeliveryDeadLettered {
+        name: dead_letter.worker_name.clone(),
+        delivery_id: dead_letter.delivery.delivery_id.clone(),
+        event_id: dead_letter.delivery.event_id.clone(),
+        from: dead_letter.delivery.from.clone(),
+        attempts: dead_letter.attempts,
+        last_error: dead_letter.last_error.clone(),
+    };
+    dead_letters.push(dead_letter);
+    let _ = send_broker_event(sdk_out_tx, event).await;
+}
+
+impl BrokerRuntime {
+    pub(super) fn list_dead_letters(&self) -> Value {
+        json!({ "dead_letters": self.dead_letters.entries() })
+    }
+
+    /// Send a dead letter to its worker again, with a fresh retry budget.
+    pub(super) async fn requeue_dead_letter(&mut self, delivery_id: &str) -> Result<Value, String> {
+        let not_found =
+            || format!("invalid_dead_letter: no dead letter with delivery id '{delivery_id}'");
+        let worker_name = self
+            .dead_letters
+            .get(delivery_id)
+            .map(|entry| entry.worker_name.clone())
+            .ok_or_else(not_found)?;
+        if !self.workers.has_worker(&worker_name) {
+            return Err(format!("agent_not_found: no worker named '{worker_name}'"));
+        }
+        let entry = self.dead_letters.take(delivery_id).ok_or_else(not_found)?;
+        let delivery_id = entry.delivery.delivery_id.clone();
+        self.pending_deliveries
+            .insert(delivery_id.clone(), entry.into_pending());
+        match retry_pending_delivery(
+            &delivery_id,
+            &mut self.workers,
+            &mut self.pending_deliveries,
+            self.delivery_retry_interval,
+        )
+        .await
+        {
+            Ok(outcome) => {
+                let _ =
+                    emit_delivery_attempt_outcome(&self.sdk_out_tx, &delivery_id, false, outcome)
+                        .await;
+            }
+            // Still pending; the maintenance tick retries it.
+            Err(error) => tracing::warn!(
+                worker = %worker_name,
+                delivery_id = %delivery_id,
+                error = %error,
+                "requeued dead letter failed its first attempt"
+            ),
+        }
+        Ok(json!({
+            "success": true,
+            "name": worker_name,
+            "delivery_id": delivery_id,
+        }))
+    }
+}
@@ -37,6 +37,9 @@ pub(crate) enum DeliveryAttemptOutcome {
         to: MessageTarget,
         attempts: u32,
         last_error: String,
+        /// Set when the delivery ran out of retries rather than losing its
+        /// recipient.
+        dead_letter: Option<Box<DeadLetter>>,
     },
     Noop,
 }
@@ -179,6 +182,11 @@ pub(crate) struct AgentMetrics {
     pub(super) pid: u32,
     pub(super) memory_bytes: u64,
     pub(super) uptime_secs: u64,
+    /// Cumulative user + system CPU time.
+    pub(super) cpu_time_ms: u64,
+    /// CPU use since the previous sample (or since spawn, for the first),
+    /// as a percentage of one core.
+    pub(super) cpu_percent: f64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -207,6 +215,9 @@ pub(crate) fn synthetic_delivery_read_ack_reason(event_id: &EventId) -> Option<&
     if event_id.starts_with("flush_") {
         return Some("manual_flush_synthetic_event_id");
     }
+    if event_id.starts_with("drain_") {
+        return Some("drain_notice_synthetic_event_id");
+    }
     None
 }
 
@@ -460,6 +471,12 @@ pub(crate) struct InboundContext<'a> {
 /// `drive` client needs to intercept. Internal broker-driven injections
 /// (`worker_ready` initial task, continuity restore) bypass this queue by
 /// not calling this helper.
+#[tracing::instrument(
+    name = "relay.route",
+    target = "agent_relay::delivery",
+    skip_all,
+    fields(event_id = ctx.event_id.unwrap_or(""), worker = %worker_name)
+)]
 pub(crate) fn queue_inbound_for_delivery_mode(
     delivery_states: &mut HashMap<WorkerName, InboundDeliveryState>,
     workers: &WorkerRegistry,
@@ -614,6 +631,12 @@ pub(crate) async fn inject_pending_relay_message(
 }
 
 #[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "relay.queue_and_try_delivery",
+    target = "agent_relay::delivery",
+    skip_all,
+    fields(event_id = %event_id, worker = %worker_name, delivery_id = tracing::field::Empty)
+)]
 pub(crate) async fn queue_and_try_delivery_raw(
     workers: &mut WorkerRegistry,
     pending_deliveries: &mut HashMap<DeliveryId, PendingDelivery>,
@@ -642,6 +665,7 @@ pub(crate) async fn queue_and_try_delivery_raw(
         injection_mode,
     };
     let delivery_id = delivery.delivery_id.clone();
+    tracing::Span::current().record("delivery_id", delivery_id.as_str());
     pending_deliveries.insert(
         delivery_id.clone(),
         PendingDelivery {
@@ -675,17 +699,7 @@ pub(crate) async fn retry_pending_delivery(
 
     if pending.attempts >= MAX_DELIVERY_RETRIES {
         let removed = pending_deliveries.remove(delivery_id).unwrap_or(pending);
-        return Ok(DeliveryAttemptOutcome::Failed {
-            worker_name: removed.worker_name,
-            delivery_id: removed.delivery.delivery_id,
-            event_id: removed.delivery.event_id,
-            from: removed.delivery.from,
-            to: removed.delivery.target,
-            attempts: removed.attempts,
-            last_error: removed
-                .last_error
-                .unwrap_or_else(|| "max delivery retries exceeded".to_string()),
-        });
+        return Ok(exhausted_delivery_outcome(removed));
     }
 
     if !workers.has_worker(&pending.worker_name) {
@@ -698,9 +712,16 @@ pub(crate) async fn retry_pending_delivery(
             to: removed.delivery.target,
             attempts: removed.attempts,
             last_error: "recipient gone".to_string(),
+            dead_letter: None,
         });
     }
 
+    // A paused worker's stdin is not being read, and a starting one has no
+    // process yet; hold the delivery instead of spending retries on it.
+    if workers.is_paused(&pending.worker_name) || workers.is_starting(&pending.worker_name) {
+        return Ok(DeliveryAttemptOutcome::Noop);
+    }
+
     match workers
         .deliver(&pending.worker_name, pending.delivery.clone())
         .await
@@ -730,17 +751,7 @@ pub(crate) async fn retry_pending_delivery(
 
             if should_fail {
                 if let Some(removed) = pending_deliveries.remove(delivery_id) {
-                    return Ok(DeliveryAttemptOutcome::Failed {
-                        worker_name: removed.worker_name,
-                        delivery_id: removed.delivery.delivery_id,
-                        event_id: removed.delivery.event_id,
-                        from: removed.delivery.from,
-                        to: removed.delivery.target,
-                        attempts: removed.attempts,
-                        last_error: removed
-                            .last_error
-                            .unwrap_or_else(|| "max delivery retries exceeded".to_string()),
-                    });
+                    return Ok(exhausted_delivery_outcome(removed));
                 }
                 return Ok(DeliveryAttemptOutcome::Noop);
             }
@@ -749,6 +760,23 @@ pub(crate) async fn retry_pending_delivery(
     }
 }
 
+fn exhausted_delivery_outcome(removed: PendingDelivery) -> DeliveryAttemptOutcome {
+    let last_error = removed
+        .last_error
+        .clone()
+        .unwrap_or_else(|| "max delivery retries exceeded".to_string());
+    DeliveryAttemptOutcome::Failed {
+        dead_letter: Some(Box::new(DeadLetter::new(&removed, &last_error))),
+        worker_name: removed.worker_name,
+        delivery_id: removed.delivery.delivery_id,
+        event_id: removed.delivery.event_id,
+        from: removed.delivery.from,
+        to: removed.delivery.target,
+        attempts: removed.attempts,
+        last_error,
+    }
+}
+
 pub(crate) async fn emit_delivery_attempt_outcome(
     sdk_out_tx: &mpsc::Sender<ProtocolEnvelope<Value>>,
     delivery_id: &DeliveryId,
@@ -782,6 +810,7 @@ pub(crate) async fn emit_delivery_attempt_outcome(
             to,
             attempts,
             last_error,
+            dead_letter: _,
         } => {
             send_broker_event(
                 sdk_out_tx,
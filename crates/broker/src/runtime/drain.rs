@@ -0,0 +1,193 @@
+use super::*;
+use crate::worker::AgentWorkState;
+
+/// An in-progress drain. While set, the worker registry refuses spawns and
+/// node deliveries are left unacked so the engine redelivers them to the
+/// next broker.
+pub(crate) struct DrainState {
+    started_at: Instant,
+    deadline: Instant,
+    /// The drain never settles before this, so agents get time to act on
+    /// the notice even when nothing else is in flight.
+    grace_until: Instant,
+    /// Drain notices the worker has not acked yet.
+    unacked_notices: HashMap<DeliveryId, WorkerName>,
+}
+
+impl DrainState {
+    pub(crate) fn new(now: Instant, timeout: Duration) -> Self {
+        Self {
+            started_at: now,
+            deadline: now + timeout,
+            grace_until: now + timeout.min(Duration::from_secs(MIN_DRAIN_GRACE_SECS)),
+            unacked_notices: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn notice_sent(&mut self, delivery_id: DeliveryId, worker: WorkerName) {
+        self.unacked_notices.insert(delivery_id, worker);
+    }
+
+    /// Returns true when `delivery_id` was one of this drain's notices.
+    pub(crate) fn notice_acked(&mut self, delivery_id: &str) -> bool {
+        self.unacked_notices.remove(delivery_id).is_some()
+    }
+
+    /// `Some(outcome)` once the drain should end: `settled` when the grace
+    /// period is over, nothing is left to deliver, every notice was acked
+    /// and every running agent is idle; `timeout` when the deadline passed
+    /// first.
+    pub(crate) fn outcome(
+        &mut self,
+        now: Instant,
+        pending_delivery_count: usize,
+        workers: &WorkerRegistry,
+    ) -> Option<&'static str> {
+        // An agent that exited will never ack its notice.
+        self.unacked_notices
+            .retain(|_, worker| workers.has_worker(worker));
+        if now >= self.deadline {
+            return Some("timeout");
+        }
+        let busy_workers = workers
+            .workers
+            .iter()
+            .filter(|(name, handle)| {
+                handle.state != AgentWorkState::Idle && !workers.is_paused(name)
+            })
+            .count();
+        (now >= self.grace_until
+            && pending_delivery_count == 0
+            && self.unacked_notices.is_empty()
+            && busy_workers == 0)
+            .then_some("settled")
+    }
+}
+
+/// Message delivered to every agent when a drain starts.
+pub(crate) fn drain_notice_body(timeout: Duration) -> String {
+    format!(
+        "The relay broker is draining for a restart. This agent will be stopped in about {} seconds. \
+         Finish or checkpoint your current step and save any progress you need to keep.",
+        timeout.as_secs().max(1)
+    )
+}
+
+impl BrokerRuntime {
+    /// Start draining. Calling it again while a drain runs is a no-op that
+    /// reports the existing drain.
+    pub(super) async fn begin_drain(&mut self, timeout: Option<Duration>, reason: &str) -> Value {
+        let now = Instant::now();
+        if let Some(drain) = &self.drain {
+            return json!({
+                "draining": true,
+                "already_draining": true,
+                "remaining_ms": drain.deadline.saturating_duration_since(now).as_millis() as u64,
+                "pending_delivery_count": self.pending_deliveries.len(),
+            });
+        }
+
+        let timeout = timeout.unwrap_or_else(|| drain_timeout(&self.config));
+        let agent_count = self.workers.workers.len();
+        let pending_delivery_count = self.pending_deliveries.len();
+        tracing::info!(
+            target = "agent_relay::broker",
+            reason = %reason,
+            timeout_ms = timeout.as_millis() as u64,
+            agent_count,
+            pending_delivery_count,
+            "draining broker"
+        );
+        self.workers.draining = true;
+        self.cancel_queued_spawns("broker_draining: queued spawn cancelled by drain")
+            .await;
+        let mut drain = DrainState::new(now, timeout);
+
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::BrokerDraining {
+                reason: reason.to_string(),
+                timeout_ms: timeout.as_millis() as u64,
+                agent_count,
+                pending_delivery_count,
+            },
+        )
+        .await;
+
+        // Sent straight to the worker rather than queued: a notice that
+        // outlived this broker would be replayed to the agent's successor.
+        // The drain tracks the acks itself instead.
+        let body = drain_notice_body(timeout);
+        let names: Vec<WorkerName> = self.workers.workers.keys().cloned().collect();
+        for name in names {
+            if self.workers.is_paused(&name) {
+                continue;
+            }
+            let delivery = RelayDelivery {
+                delivery_id: DeliveryId::new(format!("del_{}", Uuid::new_v4().simple())),
+                event_id: EventId::new(format!("drain_{}", Uuid::new_v4().simple())),
+                workspace_id: None,
+                workspace_alias: None,
+                from: "broker".to_string(),
+                target: MessageTarget::new(name.as_str()),
+                body: body.clone(),
+                thread_id: None,
+                priority: Some(1),
+                injection_mode: MessageInjectionMode::Steer,
+            };
+            let delivery_id = delivery.delivery_id.clone();
+            match self.workers.deliver(&name, delivery).await {
+                Ok(()) => drain.notice_sent(delivery_id, name),
+                Err(error) => tracing::warn!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    error = %error,
+                    "failed to deliver drain notice"
+                ),
+            }
+        }
+        self.drain = Some(drain);
+
+        json!({
+            "draining": true,
+            "already_draining": false,
+            "timeout_ms": timeout.as_millis() as u64,
+            "agent_count": agent_count,
+            "pending_delivery_count": pending_delivery_count,
+        })
+    }
+
+    /// Called every maintenance tick; ends the run loop once the drain has
+    /// settled or timed out. `shutdown_runtime` persists whatever is left.
+    pub(super) async fn handle_drain_tick(&mut self) {
+        if self.shutdown {
+            return;
+        }
+        let Some(drain) = self.drain.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let pending_delivery_count = self.pending_deliveries.len();
+        let Some(outcome) = drain.outcome(now, pending_delivery_count, &self.workers) else {
+            return;
+        };
+        let elapsed_ms = now.duration_since(drain.started_at).as_millis() as u64;
+        tracing::info!(
+            target = "agent_relay::broker",
+            outcome,
+            elapsed_ms,
+            pending_delivery_count,
+            "drain complete, shutting down"
+        );
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::BrokerDrained {
+                outcome: outcome.to_string(),
+                elapsed_ms,
+                pending_delivery_count,
+            },
+        )
+        .await;
+        self.shutdown = true;
+    }
+}
@@ -5,6 +5,9 @@ pub(crate) struct BrokerRuntime {
     pub(super) broker_start: Instant,
     pub(super) agent_spawn_count: u32,
     pub(super) paths: RuntimePaths,
+    /// The broker config file, loaded once at startup. Settings read from it
+    /// still defer to their environment variables.
+    pub(super) config: Arc<BrokerConfigFile>,
     pub(super) state: broker::BrokerState,
     pub(super) workspaces: Vec<RelayWorkspace>,
     pub(super) workspace_lookup: HashMap<WorkspaceId, RelayWorkspace>,
@@ -34,6 +37,7 @@ pub(crate) struct BrokerRuntime {
     pub(super) fleet_sidecar_restart: fleet::FleetSidecarRestartState,
     pub(super) fleet_max_agents: u32,
     pub(super) fleet_inventory: HashMap<WorkerName, InventoryAgent>,
+    pub(super) log_follows: HashMap<WorkerName, LogFollow>,
     pub(super) sdk_out_tx: mpsc::Sender<ProtocolEnvelope<Value>>,
     pub(super) worker_event_rx: mpsc::Receiver<WorkerEvent>,
     pub(super) worker_events_open: bool,
@@ -43,15 +47,29 @@ pub(crate) struct BrokerRuntime {
     pub(super) sdk_lines: tokio::io::Lines<BufReader<tokio::io::Stdin>>,
     pub(super) stdin_open: bool,
     pub(super) reap_tick: tokio::time::Interval,
+    pub(super) orphan_audit: OrphanAudit,
+    pub(super) pod_watch: PodWatch,
     pub(super) dedup: DedupCache,
     pub(super) delivery_retry_interval: Duration,
     pub(super) pending_deliveries: PendingDeliveryStore,
+    /// Deliveries that ran out of retries, kept for `requeue_dead_letter`.
+    pub(super) dead_letters: DeadLetterQueue,
     pub(super) terminal_failed_deliveries: HashSet<DeliveryId>,
     pub(super) pending_requests: HashMap<String, worker_request::PendingRequest>,
     pub(super) delivery_states: HashMap<WorkerName, InboundDeliveryState>,
     pub(super) agent_result_tokens: HashMap<String, WorkerName>,
     pub(super) recent_thread_messages: VecDeque<Value>,
     pub(super) shutdown: bool,
+    /// Spawns waiting for a slot under the registry's concurrency cap.
+    pub(super) spawn_queue: VecDeque<QueuedSpawn>,
+    /// Teams from `spawn_team` waiting for every member to report ready.
+    pub(super) pending_teams: Vec<PendingTeam>,
+    /// Plans from `run_plan` with steps still to finish.
+    pub(super) running_plans: Vec<RunningPlan>,
+    /// Set once a drain starts; see [`BrokerRuntime::begin_drain`].
+    pub(super) drain: Option<DrainState>,
+    /// `--drain`: treat the first SIGTERM as a drain request.
+    pub(super) drain_on_sigterm: bool,
     pub(super) lease_duration: Option<Duration>,
     pub(super) last_lease_renewal: Instant,
     pub(super) lease_check: tokio::time::Interval,
@@ -101,8 +119,14 @@ impl BrokerRuntime {
                     self.handle_lease_tick();
                 }
                 RuntimeEvent::Sigterm => {
-                    tracing::info!("received SIGTERM, shutting down");
-                    self.shutdown = true;
+                    if self.drain_on_sigterm && self.drain.is_none() {
+                        tracing::info!("received SIGTERM, draining");
+                        self.begin_drain(None, "sigterm").await;
+                    } else {
+                        // A second SIGTERM during a drain exits right away.
+                        tracing::info!("received SIGTERM, shutting down");
+                        self.shutdown = true;
+                    }
                 }
                 RuntimeEvent::Api(request) => {
                     self.handle_api_request(*request).await;
@@ -135,9 +159,18 @@ impl BrokerRuntime {
                 }
                 RuntimeEvent::MaintenanceTick => {
                     self.handle_maintenance_tick().await;
+                    self.sample_worker_cpu();
+                    self.handle_memory_limit_tick().await;
+                    self.handle_drain_tick().await;
+                    self.handle_team_tick().await;
+                    self.handle_plan_tick().await;
+                    self.handle_orphan_audit().await;
+                    self.handle_pod_status_tick().await;
+                    self.pump_log_follows().await;
                 }
             }
 
+            self.start_queued_spawns().await;
             self.flush_pending_deliveries();
         }
 
@@ -233,6 +266,9 @@ impl BrokerRuntime {
         let connection_path = self.paths.state.parent().unwrap().join("connection.json");
         let _ = std::fs::remove_file(&connection_path);
 
+        #[cfg(feature = "otel")]
+        let _ = tokio::task::spawn_blocking(crate::otel::shutdown).await;
+
         Ok(())
     }
 }
@@ -7,6 +7,7 @@ use crate::{
     },
     node_control::{delivery_ack, HandlerDispatchDecision},
     protocol::{BrokerToSdk, SdkToBroker},
+    spawn_profile::spawn_profile,
 };
 
 const FLEET_AGENT_REGISTER_TIMEOUT: Duration = Duration::from_secs(30);
@@ -67,6 +68,7 @@ impl BrokerRuntime {
         outbound: mpsc::Sender<ProtocolEnvelope<Value>>,
     ) -> Result<Value, String> {
         self.fleet_sidecar_out_tx = Some(outbound);
+        self.log_follows.clear();
         self.fleet_handlers.connect_sidecar();
         self.publish_fleet_load(true).await;
         Ok(json!({"connected": true}))
@@ -74,6 +76,7 @@ impl BrokerRuntime {
 
     pub(super) async fn handle_fleet_sidecar_disconnect(&mut self) {
         self.fleet_sidecar_out_tx = None;
+        self.log_follows.clear();
         self.fleet_handlers.disconnect_sidecar();
         for result in self.fleet_handlers.drain_in_flight_unavailable() {
             self.send_fleet_action_result(result).await;
@@ -86,6 +89,7 @@ impl BrokerRuntime {
 
     async fn handle_fleet_sidecar_deregister(&mut self) -> Result<(), String> {
         self.fleet_sidecar_out_tx = None;
+        self.log_follows.clear();
         self.fleet_handlers.disconnect_sidecar();
         for result in self.fleet_handlers.drain_in_flight_unavailable() {
             self.send_fleet_action_result(result).await;
@@ -184,11 +188,22 @@ impl BrokerRuntime {
                 )))
             }
             SdkToBroker::SpawnAgent {
-                agent,
+                mut agent,
                 invocation_id,
                 initial_task,
                 skip_relay_prompt,
+                profile,
+                after,
             } => {
+                let idle_threshold_secs = match profile {
+                    Some(profile) => {
+                        let profile = spawn_profile(&self.config.profiles, &profile)
+                            .map_err(|error| error.to_string())?;
+                        profile.apply_to_spec(&mut agent);
+                        profile.idle_threshold_secs
+                    }
+                    None => None,
+                };
                 if invocation_id.is_none() && self.fleet_handlers.has_in_flight() {
                     tracing::debug!(
                         target = "relay_broker::fleet",
@@ -202,12 +217,26 @@ impl BrokerRuntime {
                         invocation_id,
                         initial_task,
                         skip_relay_prompt,
+                        idle_threshold_secs,
+                        after.into_iter().map(WorkerName::from).collect(),
                     )
                     .await?;
                 Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
                     request_id, result,
                 )))
             }
+            SdkToBroker::SpawnTeam { manifest } => {
+                let result = self.spawn_team(manifest, true).await?;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id, result,
+                )))
+            }
+            SdkToBroker::RunPlan { plan } => {
+                let result = self.run_plan(plan).await?;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id, result,
+                )))
+            }
             SdkToBroker::SendInput { name, data } => {
                 let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
                 Box::pin(self.handle_api_request(ListenApiRequest::SendInput {
@@ -261,6 +290,45 @@ impl BrokerRuntime {
                     reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
                 )))
             }
+            SdkToBroker::PauseAgent { name } => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                Box::pin(self.handle_api_request(ListenApiRequest::SetAgentPaused {
+                    name,
+                    paused: true,
+                    reply: reply_tx,
+                }))
+                .await;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id,
+                    reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
+                )))
+            }
+            SdkToBroker::ResumeAgent { name } => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                Box::pin(self.handle_api_request(ListenApiRequest::SetAgentPaused {
+                    name,
+                    paused: false,
+                    reply: reply_tx,
+                }))
+                .await;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id,
+                    reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
+                )))
+            }
+            SdkToBroker::RestartAgent { name, reason } => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                Box::pin(self.handle_api_request(ListenApiRequest::RestartAgent {
+                    name,
+                    reason,
+                    reply: reply_tx,
+                }))
+                .await;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id,
+                    reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
+                )))
+            }
             SdkToBroker::SubscribeChannels { name, channels } => {
                 let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
                 Box::pin(
@@ -291,6 +359,31 @@ impl BrokerRuntime {
                     reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
                 )))
             }
+            SdkToBroker::SetAgentTeam { name, team } => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                Box::pin(self.handle_api_request(ListenApiRequest::SetAgentTeam {
+                    name,
+                    team,
+                    reply: reply_tx,
+                }))
+                .await;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id,
+                    reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
+                )))
+            }
+            SdkToBroker::GetLogs {
+                name,
+                lines,
+                offset,
+                max_bytes,
+                follow,
+            } => Ok(FleetSidecarFrameResponse::frame(
+                match self.handle_get_logs(&name, lines, offset, max_bytes, follow) {
+                    Ok(result) => ok_protocol_frame(request_id, result),
+                    Err(message) => error_protocol_frame(request_id, "log_unavailable", &message),
+                },
+            )),
             SdkToBroker::ListAgents {} => {
                 let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
                 Box::pin(self.handle_api_request(ListenApiRequest::List { reply: reply_tx })).await;
@@ -299,6 +392,27 @@ impl BrokerRuntime {
                     reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
                 )))
             }
+            SdkToBroker::ListDeadLetters {} => Ok(FleetSidecarFrameResponse::frame(
+                ok_protocol_frame(request_id, self.list_dead_letters()),
+            )),
+            SdkToBroker::RequeueDeadLetter { delivery_id } => {
+                let result = self.requeue_dead_letter(&delivery_id).await?;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id, result,
+                )))
+            }
+            SdkToBroker::Drain { timeout_ms } => {
+                let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+                Box::pin(self.handle_api_request(ListenApiRequest::Drain {
+                    timeout_ms,
+                    reply: reply_tx,
+                }))
+                .await;
+                Ok(FleetSidecarFrameResponse::frame(ok_protocol_frame(
+                    request_id,
+                    reply_rx.await.map_err(|_| "reply_dropped".to_string())??,
+                )))
+            }
             SdkToBroker::Shutdown {} => {
                 let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
                 Box::pin(self.handle_api_request(ListenApiRequest::Shutdown { reply: reply_tx }))
@@ -343,7 +457,27 @@ impl BrokerRuntime {
         }
     }
 
+    #[tracing::instrument(
+        name = "relay.receive",
+        target = "agent_relay::delivery",
+        skip_all,
+        fields(
+            event_id = %deliver.msg_id,
+            agent = %deliver.agent,
+            node_delivery_id = %deliver.delivery_id,
+        )
+    )]
     async fn handle_fleet_deliver(&mut self, deliver: Deliver) {
+        if self.drain.is_some() {
+            // Unacked, so the engine redelivers to the broker that replaces us.
+            tracing::debug!(
+                target = "relay_broker::fleet",
+                agent = %deliver.agent,
+                delivery_id = %deliver.delivery_id,
+                "broker draining; withholding fleet delivery ack"
+            );
+            return;
+        }
         let decision = self.fleet_delivery_book.observe(&deliver);
         let up_to_seq = match decision {
             crate::node_control::DeliveryDecision::Deliver { up_to_seq: _ } => {
@@ -666,11 +800,6 @@ impl BrokerRuntime {
                 .await;
             return;
         };
-        let workspace_state = self
-            .workspace_lookup
-            .get(&workspace_id)
-            .cloned()
-            .unwrap_or_else(|| self.default_workspace.clone());
 
         // Forward the invocation id and the harness session ref into the node
         // `agent.register` the spawn emits, mirroring the sidecar path
@@ -678,6 +807,54 @@ impl BrokerRuntime {
         // not correlated to the agent and a resumable `spawn:<harness>` (when
         // `harnessConfig.session_id` is set) silently becomes a fresh spawn.
         let session_ref = super::relaycast_events::relaycast_spawn_session_ref(&ws_value);
+        let spawn = NodeSpawn {
+            name,
+            cli,
+            task,
+            channel,
+            model,
+            ws_value,
+            workspace_id,
+            invocation_id: invoke.invocation_id.clone(),
+            session_ref,
+        };
+
+        // Past the concurrency cap the spawn waits in the same queue as
+        // `/api/spawn`; the invocation resolves now with its position.
+        let result = if !self.workers.draining && !self.workers.has_capacity() {
+            self.enqueue_node_spawn(spawn).await
+        } else {
+            self.run_node_spawn(spawn).await
+        };
+        match result {
+            Ok(output) => {
+                self.reply_action_output(&invoke.invocation_id, output)
+                    .await;
+            }
+            Err(error) => {
+                self.reply_action_error(&invoke.invocation_id, &error).await;
+            }
+        }
+    }
+
+    /// Spawn the agent a node action asked for, binding it to this node.
+    pub(super) async fn run_node_spawn(&mut self, spawn: NodeSpawn) -> Result<Value, String> {
+        let NodeSpawn {
+            name,
+            cli,
+            task,
+            channel,
+            model,
+            ws_value,
+            workspace_id,
+            invocation_id,
+            session_ref,
+        } = spawn;
+        let workspace_state = self
+            .workspace_lookup
+            .get(&workspace_id)
+            .cloned()
+            .unwrap_or_else(|| self.default_workspace.clone());
 
         super::relaycast_events::spawn_worker_from_request(
             name.clone(),
@@ -692,13 +869,14 @@ impl BrokerRuntime {
             &mut self.workers,
             &mut self.state,
             &self.paths,
+            &self.config,
             &self.telemetry,
             &self.sdk_out_tx,
             &mut self.dedup,
             &mut self.agent_spawn_count,
             &self.fleet_control_tx,
             &self.fleet_node_name,
-            Some(invoke.invocation_id.clone()),
+            Some(invocation_id),
             session_ref,
         )
         .await;
@@ -707,15 +885,14 @@ impl BrokerRuntime {
 
         // `spawn_worker_from_request` does not return a result; treat presence of
         // the worker as success so the engine's invocation resolves.
-        if self.workers.workers.contains_key(&name) {
-            self.reply_action_output(
-                &invoke.invocation_id,
-                json!({ "spawned": true, "name": name.as_str() }),
-            )
-            .await;
+        if self.workers.has_worker(&name) {
+            Ok(json!({
+                "spawned": true,
+                "name": name.as_str(),
+                "pid": self.workers.harness_pid(&name),
+            }))
         } else {
-            self.reply_action_error(&invoke.invocation_id, "spawn_failed")
-                .await;
+            Err("spawn_failed".to_string())
         }
     }
 
@@ -796,12 +973,14 @@ impl BrokerRuntime {
         .await;
     }
 
-    async fn handle_fleet_spawn_agent(
+    pub(super) async fn handle_fleet_spawn_agent(
         &mut self,
         spec: AgentSpec,
         invocation_id: Option<String>,
         initial_task: Option<String>,
         skip_relay_prompt: bool,
+        idle_threshold_secs: Option<u64>,
+        after: Vec<WorkerName>,
     ) -> Result<Value, String> {
         let initial_session_ref = fleet_initial_session_ref(&spec);
         let token = self
@@ -810,7 +989,14 @@ impl BrokerRuntime {
         let name = spec.name.clone();
         let agent_id = token.agent_id.clone();
         let result = match self
-            .spawn_from_agent_spec(spec, initial_task, skip_relay_prompt, Some(token.token))
+            .spawn_from_agent_spec(
+                spec,
+                initial_task,
+                skip_relay_prompt,
+                idle_threshold_secs,
+                Some(token.token),
+                after,
+            )
             .await
         {
             Ok(result) => result,
@@ -860,15 +1046,17 @@ impl BrokerRuntime {
         .await
     }
 
-    async fn spawn_from_agent_spec(
+    pub(super) async fn spawn_from_agent_spec(
         &mut self,
         spec: AgentSpec,
         initial_task: Option<String>,
         skip_relay_prompt: bool,
+        idle_threshold_secs: Option<u64>,
         agent_token: Option<String>,
+        after: Vec<WorkerName>,
     ) -> Result<Value, String> {
         let cli = cli_for_agent_spec(&spec)?;
-        let transport = Some(runtime_label(&spec.runtime).to_string());
+        let transport = Some(runtime_transport(&spec.runtime));
         let restart_policy = spec
             .restart_policy
             .as_ref()
@@ -889,13 +1077,16 @@ impl BrokerRuntime {
             shadow_of: spec.shadow_of,
             shadow_mode: spec.shadow_mode,
             continue_from: None,
-            idle_threshold_secs: None,
+            idle_threshold_secs,
             skip_relay_prompt,
             restart_policy: Box::new(restart_policy),
             harness_config: spec.harness_config,
             agent_token,
             agent_result_schema: None,
             exit_after_task: false,
+            limits: spec.limits,
+            image: spec.image,
+            after,
             reply: reply_tx,
         }))
         .await;
@@ -1177,6 +1368,9 @@ fn cli_for_agent_spec(spec: &AgentSpec) -> Result<String, String> {
     match spec.provider {
         Some(ProtocolHeadlessProvider::Claude) => Ok("claude".to_string()),
         Some(ProtocolHeadlessProvider::Opencode) => Ok("opencode".to_string()),
+        Some(ProtocolHeadlessProvider::Codex) => Ok("codex".to_string()),
+        Some(ProtocolHeadlessProvider::Gemini) => Ok("gemini".to_string()),
+        Some(ProtocolHeadlessProvider::Template(ref name)) => Ok(name.clone()),
         None => Err("agent spec requires cli or provider".to_string()),
     }
 }
@@ -1539,6 +1733,8 @@ mod tests {
             args: Vec::new(),
             channels: Vec::new(),
             restart_policy: None,
+            image: None,
+            limits: AgentResourceLimits::default(),
         }
     }
 
@@ -1,48 +1,174 @@
 use super::*;
 
-pub(crate) fn headless_provider_cli_name(provider: &ProtocolHeadlessProvider) -> &'static str {
+use crate::headless_template::{
+    worker_template_from_env, HeadlessTemplate, OutputFormat, PromptInput,
+};
+
+pub(crate) fn headless_provider_cli_name(provider: &ProtocolHeadlessProvider) -> &str {
     match provider {
         ProtocolHeadlessProvider::Claude => "claude",
         ProtocolHeadlessProvider::Opencode => "opencode",
+        ProtocolHeadlessProvider::Codex => "codex",
+        ProtocolHeadlessProvider::Gemini => "gemini",
+        ProtocolHeadlessProvider::Template(name) => name,
     }
 }
 
-pub(crate) fn headless_provider_command(
+/// The command template a provider runs; the built-in providers are
+/// templates too, and the rest are looked up in `templates`.
+pub(crate) fn headless_provider_template(
     provider: &ProtocolHeadlessProvider,
-    task: &str,
-    extra_args: &[String],
-) -> (String, Vec<String>) {
-    match provider {
-        ProtocolHeadlessProvider::Claude => {
-            let mut args = vec![
-                "-p".to_string(),
-                "--dangerously-skip-permissions".to_string(),
-            ];
-            args.extend(extra_args.iter().cloned());
-            args.push(task.to_string());
-            ("claude".to_string(), args)
-        }
-        ProtocolHeadlessProvider::Opencode => {
-            let mut args = vec!["run".to_string()];
-            args.extend(extra_args.iter().cloned());
-            args.push(task.to_string());
-            ("opencode".to_string(), args)
-        }
+    templates: &BTreeMap<String, HeadlessTemplate>,
+) -> Result<HeadlessTemplate> {
+    let command = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+    Ok(match provider {
+        ProtocolHeadlessProvider::Claude => HeadlessTemplate {
+            command: command(&[
+                "claude",
+                "-p",
+                "--dangerously-skip-permissions",
+                "--output-format",
+                "stream-json",
+                "--verbose",
+            ]),
+            prompt: Default::default(),
+            // One assistant message per line, so text streams as it is
+            // written; tool calls and the closing result carry no text here.
+            output: OutputFormat::Jsonl,
+            text_pointer: Some("/message/content/0/text".to_string()),
+            model_flag: Some("--model".to_string()),
+            session_args: command(&["--session-id", "{session_id}"]),
+            resume_args: command(&["--resume", "{session_id}"]),
+            env: Default::default(),
+        },
+        ProtocolHeadlessProvider::Opencode => HeadlessTemplate {
+            command: command(&["opencode", "run"]),
+            prompt: Default::default(),
+            output: Default::default(),
+            text_pointer: None,
+            model_flag: Some("--model".to_string()),
+            session_args: Vec::new(),
+            resume_args: Vec::new(),
+            // Auto-approve tool permissions for opencode in headless mode.
+            env: [(
+                "OPENCODE_PERMISSION".to_string(),
+                r#"{"*":"allow","external_directory":{"*":"allow"}}"#.to_string(),
+            )]
+            .into(),
+        },
+        ProtocolHeadlessProvider::Codex => HeadlessTemplate {
+            // `codex exec` reads the prompt from stdin when none is given,
+            // which keeps prompts that start with `-` out of its flag parser.
+            command: command(&[
+                "codex",
+                "exec",
+                "--dangerously-bypass-approvals-and-sandbox",
+                "--skip-git-repo-check",
+            ]),
+            prompt: PromptInput::Stdin,
+            output: Default::default(),
+            text_pointer: None,
+            model_flag: Some("--model".to_string()),
+            session_args: Vec::new(),
+            resume_args: Vec::new(),
+            env: Default::default(),
+        },
+        ProtocolHeadlessProvider::Gemini => HeadlessTemplate {
+            command: command(&["gemini", "--yolo", "--prompt={prompt}"]),
+            prompt: Default::default(),
+            output: Default::default(),
+            text_pointer: None,
+            model_flag: Some("--model".to_string()),
+            session_args: Vec::new(),
+            resume_args: Vec::new(),
+            env: Default::default(),
+        },
+        ProtocolHeadlessProvider::Template(name) => templates
+            .get(name)
+            .cloned()
+            .with_context(|| format!("no headless provider template named '{name}'"))?,
+    })
+}
+
+/// A built-in provider, or one of `templates`, named `value`.
+pub(crate) fn headless_provider_from_cli(
+    value: &str,
+    templates: &BTreeMap<String, HeadlessTemplate>,
+) -> Option<ProtocolHeadlessProvider> {
+    let value = value.trim();
+    match value.to_ascii_lowercase().as_str() {
+        "claude" => return Some(ProtocolHeadlessProvider::Claude),
+        "opencode" => return Some(ProtocolHeadlessProvider::Opencode),
+        "codex" => return Some(ProtocolHeadlessProvider::Codex),
+        "gemini" => return Some(ProtocolHeadlessProvider::Gemini),
+        _ => {}
     }
+    templates
+        .contains_key(value)
+        .then(|| ProtocolHeadlessProvider::Template(value.to_string()))
 }
 
-pub(crate) fn headless_provider_from_cli(value: &str) -> Option<ProtocolHeadlessProvider> {
-    match value.trim().to_ascii_lowercase().as_str() {
-        "claude" => Some(ProtocolHeadlessProvider::Claude),
-        "opencode" => Some(ProtocolHeadlessProvider::Opencode),
-        _ => None,
+/// How often a running delivery re-sends `delivery_active`, which holds off
+/// the broker's retry while the CLI is still responding.
+const HEADLESS_ACTIVE_INTERVAL: Duration = Duration::from_secs(2);
+
+async fn parse_worker_frame(
+    out_tx: &mpsc::Sender<ProtocolEnvelope<Value>>,
+    line: &str,
+) -> Option<ProtocolEnvelope<Value>> {
+    match serde_json::from_str(line) {
+        Ok(frame) => Some(frame),
+        Err(error) => {
+            let _ = send_frame(
+                out_tx,
+                "worker_error",
+                None,
+                json!({
+                    "code":"invalid_frame",
+                    "message": error.to_string(),
+                    "retryable": false,
+                }),
+            )
+            .await;
+            None
+        }
     }
 }
 
+fn requests_exit(chunk: &str) -> bool {
+    chunk.lines().any(|line| line.trim() == "/exit")
+}
+
+async fn send_pong(out_tx: &mpsc::Sender<ProtocolEnvelope<Value>>, frame: ProtocolEnvelope<Value>) {
+    let ts = frame
+        .payload
+        .get("ts_ms")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    let _ = send_frame(out_tx, "pong", frame.request_id, json!({"ts_ms": ts})).await;
+}
+
+/// Runs the provider's CLI once per delivery, in one conversation when the
+/// template supports it. A delivery is acked only after the CLI exits
+/// successfully; while it runs, its response streams as `worker_stream` and
+/// `delivery_active` is repeated so the broker does not retry it.
 pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
-    let provider: ProtocolHeadlessProvider = cmd.provider.into();
+    let (provider, template) = match worker_template_from_env()? {
+        Some(template) => (
+            ProtocolHeadlessProvider::Template(cmd.provider.clone()),
+            template,
+        ),
+        None => {
+            let provider = headless_provider_from_cli(&cmd.provider, &BTreeMap::new())
+                .with_context(|| format!("unknown headless provider '{}'", cmd.provider))?;
+            let template = headless_provider_template(&provider, &BTreeMap::new())?;
+            (provider, template)
+        }
+    };
     let provider_name = headless_provider_cli_name(&provider);
     let provider_args = cmd.args.clone();
+    let session_id = Uuid::new_v4().to_string();
+    let mut session_started = false;
 
     let (out_tx, mut out_rx) = mpsc::channel::<ProtocolEnvelope<Value>>(512);
     let writer_task = tokio::spawn(async move {
@@ -61,30 +187,38 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
         }
     });
 
-    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    // Frames are read on their own task so pings and shutdowns are answered
+    // while a delivery is running.
+    let (line_tx, mut line_rx) = mpsc::channel::<String>(64);
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line_tx.send(line).await.is_err() {
+                break;
+            }
+        }
+    });
+
     let mut worker_name = cmd
         .agent_name
         .clone()
         .unwrap_or_else(|| format!("headless-{provider_name}"));
     let mut final_exit_code: Option<i32> = None;
-    let mut final_exit_signal: Option<String> = None;
+    // Frames that arrived while a delivery was running.
+    let mut backlog: VecDeque<ProtocolEnvelope<Value>> = VecDeque::new();
+    let mut completed: HashSet<DeliveryId> = HashSet::new();
 
-    while let Ok(Some(line)) = lines.next_line().await {
-        let frame: ProtocolEnvelope<Value> = match serde_json::from_str(&line) {
-            Ok(frame) => frame,
-            Err(error) => {
-                let _ = send_frame(
-                    &out_tx,
-                    "worker_error",
-                    None,
-                    json!({
-                        "code":"invalid_frame",
-                        "message": error.to_string(),
-                        "retryable": false,
-                    }),
-                )
-                .await;
-                continue;
+    loop {
+        let frame = match backlog.pop_front() {
+            Some(frame) => frame,
+            None => {
+                let Some(line) = line_rx.recv().await else {
+                    break;
+                };
+                match parse_worker_frame(&out_tx, &line).await {
+                    Some(frame) => frame,
+                    None => continue,
+                }
             }
         };
 
@@ -138,6 +272,22 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                 let delivery_id = delivery.delivery_id;
                 let event_id = delivery.event_id;
 
+                // A retry of a delivery the CLI already answered: ack it
+                // again rather than running the task twice.
+                if completed.contains(&delivery_id) {
+                    let _ = send_frame(
+                        &out_tx,
+                        "delivery_ack",
+                        request_id,
+                        json!({
+                            "delivery_id": delivery_id,
+                            "event_id": event_id,
+                        }),
+                    )
+                    .await;
+                    continue;
+                }
+
                 let _ = send_frame(
                     &out_tx,
                     "delivery_queued",
@@ -164,36 +314,46 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                 )
                 .await;
 
-                let _ = send_frame(
-                    &out_tx,
-                    "delivery_active",
-                    None,
-                    json!({
-                        "delivery_id": delivery_id,
-                        "event_id": event_id,
-                        "pattern": format!("headless:{}", provider_name),
-                    }),
-                )
-                .await;
+                let active_frame = json!({
+                    "delivery_id": delivery_id,
+                    "event_id": event_id,
+                    "pattern": format!("headless:{}", provider_name),
+                });
 
-                let task_text = delivery.body.clone();
-                let (binary, args) =
-                    headless_provider_command(&provider, &task_text, &provider_args);
+                let mut args = template.conversation_args(&session_id, session_started);
+                args.extend(provider_args.iter().cloned());
+                let invocation = match template.invocation(&delivery.body, &args) {
+                    Ok(invocation) => invocation,
+                    Err(error) => {
+                        let _ = send_frame(
+                            &out_tx,
+                            "delivery_failed",
+                            None,
+                            json!({
+                                "delivery_id": delivery_id,
+                                "event_id": event_id,
+                                "reason": error.to_string(),
+                            }),
+                        )
+                        .await;
+                        final_exit_code = Some(1);
+                        break;
+                    }
+                };
+                let binary = invocation.program;
 
                 let mut child_cmd = tokio::process::Command::new(&binary);
                 child_cmd
-                    .args(&args)
-                    .stdin(Stdio::null())
+                    .args(&invocation.args)
+                    .envs(invocation.env)
+                    .stdin(if invocation.stdin.is_some() {
+                        Stdio::piped()
+                    } else {
+                        Stdio::null()
+                    })
                     .stdout(Stdio::piped())
-                    .stderr(Stdio::piped());
-
-                // Auto-approve tool permissions for opencode in headless mode.
-                if matches!(provider, ProtocolHeadlessProvider::Opencode) {
-                    child_cmd.env(
-                        "OPENCODE_PERMISSION",
-                        r#"{"*":"allow","external_directory":{"*":"allow"}}"#,
-                    );
-                }
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true);
 
                 let mut child = match child_cmd.spawn() {
                     Ok(child) => child,
@@ -225,26 +385,31 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                     }
                 };
 
-                let _ = send_frame(
-                    &out_tx,
-                    "delivery_ack",
-                    request_id.clone(),
-                    json!({
-                        "delivery_id": delivery_id,
-                        "event_id": event_id,
-                    }),
-                )
-                .await;
+                // Written from its own task so a command that prints before
+                // reading all of stdin cannot deadlock against the reader.
+                if let (Some(prompt), Some(mut stdin)) = (invocation.stdin, child.stdin.take()) {
+                    tokio::spawn(async move {
+                        let _ = stdin.write_all(prompt.as_bytes()).await;
+                    });
+                }
 
                 let stdout = child.stdout.take();
                 let stderr = child.stderr.take();
 
+                // Resolves to whether the response asked to end the session
+                // with `/exit`, as the PTY worker does.
                 let stream_stdout = {
                     let out_tx = out_tx.clone();
+                    let mut parser = template.output_parser();
                     async move {
+                        let mut exit_requested = false;
                         if let Some(stdout) = stdout {
                             let mut lines = BufReader::new(stdout).lines();
-                            while let Ok(Some(chunk)) = lines.next_line().await {
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                let Some(chunk) = parser.push_line(&line) else {
+                                    continue;
+                                };
+                                exit_requested |= requests_exit(&chunk);
                                 let _ = send_frame(
                                     &out_tx,
                                     "worker_stream",
@@ -257,6 +422,20 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                                 .await;
                             }
                         }
+                        if let Some(chunk) = parser.finish() {
+                            exit_requested |= requests_exit(&chunk);
+                            let _ = send_frame(
+                                &out_tx,
+                                "worker_stream",
+                                None,
+                                json!({
+                                    "stream": "stdout",
+                                    "chunk": chunk,
+                                }),
+                            )
+                            .await;
+                        }
+                        exit_requested
                     }
                 };
 
@@ -281,41 +460,94 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                     }
                 };
 
-                let (status, _, _) = tokio::join!(child.wait(), stream_stdout, stream_stderr);
+                // Owns the child, so dropping it on shutdown kills the CLI.
+                let run = async move {
+                    let (status, exit_requested, _) =
+                        tokio::join!(child.wait(), stream_stdout, stream_stderr);
+                    (status, exit_requested)
+                };
+                tokio::pin!(run);
 
-                match status {
-                    Ok(exit_status) => {
-                        final_exit_code = exit_status.code();
-                        final_exit_signal = None;
-                        if exit_status.success() {
+                let mut heartbeat = tokio::time::interval(HEADLESS_ACTIVE_INTERVAL);
+                heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                let outcome = loop {
+                    tokio::select! {
+                        outcome = &mut run => break Some(outcome),
+                        _ = heartbeat.tick() => {
                             let _ = send_frame(
                                 &out_tx,
-                                "delivery_verified",
+                                "delivery_active",
                                 None,
-                                json!({
-                                    "delivery_id": delivery_id,
-                                    "event_id": event_id,
-                                }),
+                                active_frame.clone(),
                             )
                             .await;
-                        } else {
-                            let reason = match exit_status.code() {
-                                Some(code) => format!("{} exited with code {}", binary, code),
-                                None => format!("{} exited without an exit code", binary),
+                        }
+                        line = line_rx.recv() => {
+                            let Some(line) = line else {
+                                break None;
                             };
-                            let _ = send_frame(
-                                &out_tx,
-                                "delivery_failed",
-                                None,
-                                json!({
-                                    "delivery_id": delivery_id,
-                                    "event_id": event_id,
-                                    "reason": reason,
-                                }),
-                            )
-                            .await;
+                            let Some(frame) = parse_worker_frame(&out_tx, &line).await else {
+                                continue;
+                            };
+                            match frame.msg_type.as_str() {
+                                "ping" => send_pong(&out_tx, frame).await,
+                                "shutdown_worker" => break None,
+                                _ => backlog.push_back(frame),
+                            }
                         }
                     }
+                };
+                let Some((status, exit_requested)) = outcome else {
+                    tracing::info!(
+                        target: "agent_relay::worker::headless",
+                        delivery_id = %delivery_id,
+                        "worker stopping; abandoning running delivery"
+                    );
+                    break;
+                };
+
+                match status {
+                    Ok(exit_status) if exit_status.success() => {
+                        completed.insert(delivery_id.clone());
+                        session_started = true;
+                        let _ = send_frame(
+                            &out_tx,
+                            "delivery_ack",
+                            request_id,
+                            json!({
+                                "delivery_id": delivery_id,
+                                "event_id": event_id,
+                            }),
+                        )
+                        .await;
+                        let _ = send_frame(
+                            &out_tx,
+                            "delivery_verified",
+                            None,
+                            json!({
+                                "delivery_id": delivery_id,
+                                "event_id": event_id,
+                            }),
+                        )
+                        .await;
+                    }
+                    Ok(exit_status) => {
+                        let reason = match exit_status.code() {
+                            Some(code) => format!("{} exited with code {}", binary, code),
+                            None => format!("{} exited without an exit code", binary),
+                        };
+                        let _ = send_frame(
+                            &out_tx,
+                            "delivery_failed",
+                            None,
+                            json!({
+                                "delivery_id": delivery_id,
+                                "event_id": event_id,
+                                "reason": reason,
+                            }),
+                        )
+                        .await;
+                    }
                     Err(error) => {
                         let reason = format!("failed waiting for {}: {}", binary, error);
                         let _ = send_frame(
@@ -341,19 +573,28 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
                         )
                         .await;
                         final_exit_code = Some(1);
+                        break;
                     }
                 }
 
-                break;
-            }
-            "ping" => {
-                let ts = frame
-                    .payload
-                    .get("ts_ms")
-                    .and_then(Value::as_u64)
-                    .unwrap_or_default();
-                let _ = send_frame(&out_tx, "pong", frame.request_id, json!({"ts_ms": ts})).await;
+                if exit_requested {
+                    tracing::info!(
+                        target: "agent_relay::worker::headless",
+                        "agent issued /exit — shutting down"
+                    );
+                    let _ = send_frame(
+                        &out_tx,
+                        "agent_exit",
+                        None,
+                        json!({
+                            "reason": "agent_requested",
+                        }),
+                    )
+                    .await;
+                    break;
+                }
             }
+            "ping" => send_pong(&out_tx, frame).await,
             "shutdown_worker" => {
                 break;
             }
@@ -377,7 +618,7 @@ pub(crate) async fn run_headless_worker(cmd: HeadlessCommand) -> Result<()> {
         &out_tx,
         "worker_exited",
         None,
-        json!({"code": final_exit_code, "signal": final_exit_signal}),
+        json!({"code": final_exit_code, "signal": Value::Null}),
     )
     .await;
     drop(out_tx);
@@ -1,7 +1,11 @@
 use super::*;
 use std::net::{IpAddr, SocketAddr};
 
-pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Result<()> {
+pub(crate) async fn run_init(
+    cmd: InitCommand,
+    config: Arc<BrokerConfigFile>,
+    telemetry: TelemetryClient,
+) -> Result<()> {
     let broker_start = Instant::now();
     let startup_debug = startup_debug_enabled();
     let agent_spawn_count: u32 = 0;
@@ -127,7 +131,12 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
     let relay_ready = Arc::new(Notify::new());
     let relay_ready_state: Arc<RwLock<Option<RelayReadyState>>> = Arc::new(RwLock::new(None));
     let (api_tx, api_rx) = mpsc::channel::<ListenApiRequest>(32);
-    let bind_addr = format!("{}:{}", cmd.api_bind, cmd.api_port);
+    // `--api-port` wins; otherwise AGENT_RELAY_API_PORT (or the config file).
+    let api_port = match cmd.api_port {
+        0 => configured_api_port(&config).unwrap_or(0),
+        port => port,
+    };
+    let bind_addr = format!("{}:{}", cmd.api_bind, api_port);
     log_startup_phase(
         startup_debug,
         broker_start,
@@ -373,6 +382,8 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
         memberships: workspace_memberships.clone(),
         default_workspace_id: default_workspace_id.clone(),
         persist: cmd.persist,
+        rate_limits: crate::rate_limit::RateLimitConfig::load(&config.rate_limits),
+        spawn_profiles: Arc::new(config.profiles.clone()),
     });
     {
         let mut ready = relay_ready_state.write().await;
@@ -477,6 +488,7 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
             "RELAY_WORKSPACES_JSON".to_string(),
             relay_workspaces_json.clone(),
         ),
+        (BROKER_PID_ENV.to_string(), std::process::id().to_string()),
     ];
     // Pass RELAY_BASE_URL to workers only when an override is configured; when
     // unset, workers inherit the SDK default.
@@ -528,7 +540,14 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
         .expect("state path should always have a parent")
         .join("team")
         .join("worker-logs");
-    let workers = WorkerRegistry::new(worker_event_tx, worker_env, worker_logs_dir, broker_start);
+    let mut workers = WorkerRegistry::new(
+        worker_event_tx,
+        worker_env,
+        worker_logs_dir,
+        broker_start,
+        config.clone(),
+    );
+    workers.broker_scope = broker_scope(&runtime_cwd, &resolved_name);
 
     // Load crash insights from previous session
     let crash_insights_path = paths.state.parent().unwrap().join("crash-insights.json");
@@ -539,8 +558,9 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
     let mut reap_tick = tokio::time::interval(Duration::from_millis(500));
     reap_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
     let dedup = DedupCache::new(Duration::from_secs(300), 8192);
-    let delivery_retry_interval = delivery_retry_interval();
+    let delivery_retry_interval = delivery_retry_interval(&config);
     let pending_deliveries = PendingDeliveryStore::new(load_pending_deliveries(&paths.pending));
+    let dead_letters = DeadLetterQueue::load(paths.dead_letter.clone());
     let terminal_failed_deliveries: HashSet<DeliveryId> = HashSet::new();
     // Outstanding worker-bound RPC requests waiting on a `*_response`
     // frame from the wrapped worker. Keyed by the `request_id` we put on
@@ -596,6 +616,7 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
         broker_start,
         agent_spawn_count,
         paths,
+        config: config.clone(),
         state,
         workspaces,
         workspace_lookup,
@@ -623,6 +644,7 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
         fleet_sidecar_restart: fleet::FleetSidecarRestartState::default(),
         fleet_max_agents: 0,
         fleet_inventory: HashMap::new(),
+        log_follows: HashMap::new(),
         sdk_out_tx,
         worker_event_rx,
         worker_events_open: true,
@@ -632,15 +654,23 @@ pub(crate) async fn run_init(cmd: InitCommand, telemetry: TelemetryClient) -> Re
         sdk_lines,
         stdin_open,
         reap_tick,
+        orphan_audit: OrphanAudit::new(&config, Instant::now()),
+        pod_watch: PodWatch::new(&config, Instant::now()),
         dedup,
         delivery_retry_interval,
         pending_deliveries,
+        dead_letters,
         terminal_failed_deliveries,
         pending_requests,
         delivery_states,
         agent_result_tokens,
         recent_thread_messages,
         shutdown,
+        spawn_queue: VecDeque::new(),
+        pending_teams: Vec::new(),
+        running_plans: Vec::new(),
+        drain: None,
+        drain_on_sigterm: cmd.drain,
         lease_duration,
         last_lease_renewal,
         lease_check,
@@ -0,0 +1,146 @@
+use super::*;
+
+use crate::worker::kubernetes::{namespace, pod_name, pod_statuses, PodStatus};
+
+const DEFAULT_POD_POLL_SECS: u64 = 5;
+
+/// Pod phase tracking for `kubernetes` agents.
+pub(crate) struct PodWatch {
+    interval: Duration,
+    next_at: Instant,
+    /// A `kubectl get pods` is running in the background.
+    in_flight: bool,
+    /// Last status reported per agent, so only changes become events.
+    reported: HashMap<WorkerName, PodStatus>,
+}
+
+/// `AGENT_RELAY_K8S_POLL_SECS` or `[kubernetes] poll_secs`, how often pod
+/// phases are checked.
+pub(crate) fn pod_poll_interval(config: &BrokerConfigFile) -> Duration {
+    let secs = env_or("AGENT_RELAY_K8S_POLL_SECS", config.kubernetes.poll_secs)
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_POD_POLL_SECS);
+    Duration::from_secs(secs)
+}
+
+impl PodWatch {
+    pub(crate) fn new(config: &BrokerConfigFile, now: Instant) -> Self {
+        let interval = pod_poll_interval(config);
+        Self {
+            interval,
+            next_at: now + interval,
+            in_flight: false,
+            reported: HashMap::new(),
+        }
+    }
+}
+
+impl BrokerRuntime {
+    /// Runs every maintenance tick; every [`pod_poll_interval`] it starts a
+    /// background `kubectl get pods`, whose result comes back as a
+    /// [`WorkerEvent::PodStatuses`].
+    pub(super) async fn handle_pod_status_tick(&mut self) {
+        let agents: Vec<WorkerName> = self
+            .workers
+            .workers
+            .iter()
+            .filter(|(_, handle)| handle.spec.runtime == AgentRuntime::Kubernetes)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let watch = &mut self.pod_watch;
+        watch.reported.retain(|name, _| agents.contains(name));
+        let now = Instant::now();
+        if agents.is_empty() || self.shutdown || watch.in_flight || now < watch.next_at {
+            return;
+        }
+        watch.next_at = now + watch.interval;
+        watch.in_flight = true;
+        let event_tx = self.workers.event_sender();
+        let broker_scope = self.workers.broker_scope.clone();
+        let namespace = namespace(&self.config.kubernetes);
+        tokio::spawn(async move {
+            let result = pod_statuses(namespace.as_deref(), &broker_scope)
+                .await
+                .map_err(|error| format!("{error:#}"));
+            let _ = event_tx.send(WorkerEvent::PodStatuses(result)).await;
+        });
+    }
+
+    /// Reports pod changes as `agent_pod_status` events. An agent whose pod
+    /// failed, or cannot start without a new spec, is stopped with a
+    /// `pod_failed` exit reason rather than left waiting on `kubectl attach`.
+    pub(super) async fn handle_pod_statuses(
+        &mut self,
+        result: Result<HashMap<String, PodStatus>, String>,
+    ) {
+        self.pod_watch.in_flight = false;
+        let statuses = match result {
+            Ok(statuses) => statuses,
+            Err(error) => {
+                tracing::debug!(
+                    target = "agent_relay::broker",
+                    error = %error,
+                    "failed to read agent pod statuses"
+                );
+                return;
+            }
+        };
+        let agents: Vec<WorkerName> = self
+            .workers
+            .workers
+            .iter()
+            .filter(|(_, handle)| handle.spec.runtime == AgentRuntime::Kubernetes)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in agents {
+            let pod = pod_name(&self.workers.broker_scope, name.as_str());
+            let Some(status) = statuses.get(&pod) else {
+                continue;
+            };
+            if self.pod_watch.reported.get(&name) == Some(status) {
+                continue;
+            }
+            self.pod_watch.reported.insert(name.clone(), status.clone());
+            tracing::info!(
+                target = "agent_relay::broker",
+                worker = %name,
+                pod = %pod,
+                phase = %status.phase,
+                reason = ?status.reason,
+                "agent pod status changed"
+            );
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::AgentPodStatus {
+                    name: name.clone(),
+                    pod: pod.clone(),
+                    phase: status.phase.clone(),
+                    reason: status.reason.clone(),
+                },
+            )
+            .await;
+
+            if !status.is_fatal() {
+                continue;
+            }
+            if let Some(handle) = self.workers.workers.get_mut(&name) {
+                if handle.exit_reason.is_none() {
+                    handle.exit_reason = Some(format!(
+                        "pod_failed: {}",
+                        status.reason.as_deref().unwrap_or(&status.phase)
+                    ));
+                }
+                // The reap pass reports the exit and deletes the pod.
+                if let Err(error) = handle.child.start_kill() {
+                    tracing::warn!(
+                        target = "agent_relay::broker",
+                        worker = %name,
+                        error = %error,
+                        "failed to stop worker after pod failure"
+                    );
+                }
+            }
+        }
+    }
+}
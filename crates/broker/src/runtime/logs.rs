@@ -0,0 +1,196 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::*;
+
+/// Lines returned by `get_logs` when neither `lines` nor `offset` is given.
+pub(crate) const DEFAULT_LOG_TAIL_LINES: usize = 100;
+/// Upper bound on bytes returned by one `get_logs` reply or `log_chunk` event.
+pub(crate) const MAX_LOG_READ_BYTES: u64 = 1024 * 1024;
+
+/// A slice of a worker log file. Offsets are byte positions, so a client can
+/// resume with `offset = next_offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LogSlice {
+    pub(crate) content: String,
+    pub(crate) offset: u64,
+    pub(crate) next_offset: u64,
+    pub(crate) size: u64,
+}
+
+/// Read up to `max_bytes` starting at `offset` (clamped to the file size).
+pub(crate) fn read_log_range(
+    path: &Path,
+    offset: u64,
+    max_bytes: u64,
+) -> std::io::Result<LogSlice> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let offset = offset.min(size);
+    let len = (size - offset).min(max_bytes.min(MAX_LOG_READ_BYTES));
+    let mut buf = Vec::with_capacity(len as usize);
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(LogSlice {
+        content: String::from_utf8_lossy(&buf).into_owned(),
+        offset,
+        next_offset: offset + buf.len() as u64,
+        size,
+    })
+}
+
+/// Read the last `lines` complete lines, looking back at most
+/// [`MAX_LOG_READ_BYTES`] from the end of the file.
+pub(crate) fn read_log_tail(path: &Path, lines: usize) -> std::io::Result<LogSlice> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let window_start = size.saturating_sub(MAX_LOG_READ_BYTES);
+    let mut buf = Vec::with_capacity((size - window_start) as usize);
+    file.seek(SeekFrom::Start(window_start))?;
+    file.read_to_end(&mut buf)?;
+
+    let body = buf.strip_suffix(b"\n").unwrap_or(&buf);
+    let mut newlines = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, byte)| **byte == b'\n')
+        .map(|(index, _)| index + 1);
+    let cut = if lines == 0 {
+        buf.len()
+    } else if let Some(cut) = newlines.nth(lines - 1) {
+        cut
+    } else if window_start > 0 {
+        // The window begins mid-line; drop that fragment.
+        body.iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(0, |index| index + 1)
+    } else {
+        0
+    };
+    Ok(LogSlice {
+        content: String::from_utf8_lossy(&buf[cut..]).into_owned(),
+        offset: window_start + cut as u64,
+        next_offset: window_start + buf.len() as u64,
+        size,
+    })
+}
+
+fn log_slice_json(name: &str, slice: &LogSlice) -> Value {
+    json!({
+        "name": name,
+        "content": slice.content,
+        "offset": slice.offset,
+        "next_offset": slice.next_offset,
+        "size": slice.size,
+    })
+}
+
+/// A `get_logs` follow subscription from the fleet sidecar: new bytes past
+/// `offset` are pushed as `log_chunk` events on each maintenance tick.
+pub(crate) struct LogFollow {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl BrokerRuntime {
+    pub(super) fn handle_get_logs(
+        &mut self,
+        name: &WorkerName,
+        lines: Option<usize>,
+        offset: Option<u64>,
+        max_bytes: Option<u64>,
+        follow: Option<bool>,
+    ) -> Result<Value, String> {
+        if follow == Some(false) {
+            self.log_follows.remove(name);
+        }
+        let path = self
+            .workers
+            .worker_log_path(name)
+            .ok_or_else(|| format!("invalid worker name '{name}'"))?;
+        let slice = match offset {
+            Some(offset) => read_log_range(&path, offset, max_bytes.unwrap_or(MAX_LOG_READ_BYTES)),
+            None => read_log_tail(&path, lines.unwrap_or(DEFAULT_LOG_TAIL_LINES)),
+        }
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => format!("no log file for worker '{name}'"),
+            _ => format!("failed to read log for worker '{name}': {error}"),
+        })?;
+
+        if follow == Some(true) {
+            // Follow from the end of what this reply covered; a byte-range
+            // read that stopped short still streams the remainder.
+            self.log_follows.insert(
+                name.clone(),
+                LogFollow {
+                    path,
+                    offset: slice.next_offset,
+                },
+            );
+        }
+        let mut result = log_slice_json(name.as_str(), &slice);
+        result["following"] = json!(self.log_follows.contains_key(name));
+        Ok(result)
+    }
+
+    /// Push new log bytes for every followed worker to the fleet sidecar.
+    /// Follows end when the sidecar goes away or, after a final read, when
+    /// the worker is no longer registered.
+    pub(super) async fn pump_log_follows(&mut self) {
+        if self.log_follows.is_empty() {
+            return;
+        }
+        let Some(tx) = self.fleet_sidecar_out_tx.clone() else {
+            self.log_follows.clear();
+            return;
+        };
+
+        let mut finished = Vec::new();
+        let mut sidecar_gone = false;
+        for (name, follow) in &mut self.log_follows {
+            let slice = match read_log_range(&follow.path, follow.offset, MAX_LOG_READ_BYTES) {
+                Ok(slice) if slice.size < follow.offset => {
+                    // Truncated or replaced: restart from the beginning.
+                    follow.offset = 0;
+                    continue;
+                }
+                Ok(slice) => slice,
+                Err(error) => {
+                    tracing::debug!(
+                        target = "agent_relay::broker",
+                        worker = %name,
+                        error = %error,
+                        "stopping log follow"
+                    );
+                    finished.push(name.clone());
+                    continue;
+                }
+            };
+            if !slice.content.is_empty() {
+                follow.offset = slice.next_offset;
+                let mut payload = log_slice_json(name.as_str(), &slice);
+                payload["kind"] = json!("log_chunk");
+                let frame = ProtocolEnvelope {
+                    v: PROTOCOL_VERSION,
+                    msg_type: "event".to_string(),
+                    request_id: None,
+                    payload,
+                };
+                if tx.send(frame).await.is_err() {
+                    sidecar_gone = true;
+                    break;
+                }
+            }
+            if slice.next_offset >= slice.size && !self.workers.has_worker(name) {
+                finished.push(name.clone());
+            }
+        }
+        if sidecar_gone {
+            self.log_follows.clear();
+            return;
+        }
+        for name in finished {
+            self.log_follows.remove(&name);
+        }
+    }
+}
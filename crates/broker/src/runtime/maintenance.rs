@@ -18,6 +18,7 @@ impl BrokerRuntime {
         let telemetry = &self.telemetry;
         let crash_insights = &mut self.crash_insights;
         let pending_deliveries = &mut self.pending_deliveries;
+        let dead_letters = &mut self.dead_letters;
         let pending_requests = &mut self.pending_requests;
         let delivery_states = &mut self.delivery_states;
         let agent_result_tokens = &mut self.agent_result_tokens;
@@ -43,7 +44,7 @@ impl BrokerRuntime {
         let due_ids: Vec<DeliveryId> = pending_deliveries
             .iter()
             .filter_map(|(delivery_id, pending)| {
-                if pending.next_retry_at <= now {
+                if pending.next_retry_at <= now && !workers.is_paused(&pending.worker_name) {
                     Some(delivery_id.clone())
                 } else {
                     None
@@ -65,10 +66,17 @@ impl BrokerRuntime {
             )
             .await
             {
-                Ok(outcome) => {
+                Ok(mut outcome) => {
+                    let dead_letter = match &mut outcome {
+                        DeliveryAttemptOutcome::Failed { dead_letter, .. } => dead_letter.take(),
+                        _ => None,
+                    };
                     let _ =
                         emit_delivery_attempt_outcome(sdk_out_tx, &delivery_id, was_retry, outcome)
                             .await;
+                    if let Some(dead_letter) = dead_letter {
+                        record_dead_letter(dead_letters, sdk_out_tx, *dead_letter).await;
+                    }
                 }
                 Err(error) => {
                     let _ = send_error(
@@ -94,6 +102,25 @@ impl BrokerRuntime {
         let mut fleet_load_changed = !exited.is_empty();
         for (name, code, signal, exit_reason) in &exited {
             let lifecycle_reason = exit_reason.as_deref().unwrap_or("worker_exited");
+            if lifecycle_reason == crate::worker::limits::MEMORY_LIMIT_EXIT_REASON {
+                let max_memory_mb = state
+                    .agents
+                    .get(name)
+                    .and_then(|agent| agent.spec.as_ref())
+                    .and_then(|spec| spec.limits.max_memory_mb)
+                    .unwrap_or_default();
+                let _ = send_broker_event(
+                    sdk_out_tx,
+                    BrokerEvent::AgentLimitExceeded {
+                        name: name.clone(),
+                        limit: "memory".to_string(),
+                        max_memory_mb,
+                        memory_bytes: None,
+                        action: "killed".to_string(),
+                    },
+                )
+                .await;
+            }
             // Record crash in insights
             let (category, description) =
                 crate::crash_insights::CrashInsights::analyze(*code, signal.as_deref());
@@ -285,6 +312,11 @@ impl BrokerRuntime {
             let pending_restarts = workers.supervisor.pending_restarts();
             for (name, rst) in pending_restarts {
                 let name = WorkerName::from(name);
+                // Stays pending until a worker slot frees up.
+                if !workers.has_capacity() {
+                    tracing::debug!(worker = %name, "restart waiting for a worker slot");
+                    continue;
+                }
                 if let Some(remaining) = relaycast_http.registration_block_remaining(&name) {
                     tracing::debug!(
                         worker = %name,
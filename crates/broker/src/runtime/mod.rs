@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
     process::Stdio,
     sync::{Arc, OnceLock},
@@ -23,18 +23,21 @@ use tokio::{
 use uuid::Uuid;
 
 use crate::{
+    broker_config::{env_or, BrokerConfigFile},
     dedup::DedupCache,
     fleet_wire::InventoryAgent,
+    headless_template::HeadlessTemplate,
     ids::{
         AgentId, ChannelName, DeliveryId, EventId, MessageTarget, RequestId, ThreadId, WorkerName,
         WorkspaceAlias, WorkspaceId,
     },
+    metrics::push_prometheus_gauge,
     node_control::{
         FleetControlCommand, FleetControlEvent, FleetDeliveryBook, FleetLoadSnapshot,
         HandlerDispatchState,
     },
     protocol::{
-        AgentRuntime, AgentSpec, BrokerEvent, DeliveryReadAckStatus,
+        AgentResourceLimits, AgentRuntime, AgentSpec, BrokerEvent, DeliveryReadAckStatus,
         HeadlessProvider as ProtocolHeadlessProvider, MessageInjectionMode, NodeManifest,
         NodeSupervision, ProtocolEnvelope, RelayDelivery, ResolvedHarnessConfig, PROTOCOL_VERSION,
     },
@@ -44,6 +47,7 @@ use crate::{
         RelaycastHttpClient, WorkspaceInboundMessage, WorkspaceMembershipSummary, WsControl,
     },
     replay_buffer::{ReplayBuffer, DEFAULT_REPLAY_CAPACITY},
+    routing::normalize_team,
     supervisor::{RestartDecision, RestartPolicy},
     telemetry::{ActionSource, TelemetryClient, TelemetryEvent},
     types::{
@@ -55,7 +59,7 @@ use crate::{
 use crate::cli::{
     DumpPtyCommand, DumpPtyFormat, HeadlessAppServerCommand, HeadlessCommand, InitCommand,
 };
-use crate::worker::{WorkerEvent, WorkerHandle, WorkerRegistry};
+use crate::worker::{broker_scope, WorkerEvent, WorkerHandle, WorkerRegistry};
 use crate::{broker, listen_api, worker_request};
 
 const DEFAULT_DELIVERY_RETRY_MS: u64 = 1_000;
@@ -66,41 +70,62 @@ const DEFAULT_HTTP_API_LOCAL_DELIVERY_TIMEOUT_MS: u64 = 3_000;
 const DEFAULT_HTTP_API_RELAYCAST_SEND_TIMEOUT_MS: u64 = 20_000;
 const DEFAULT_HTTP_API_OBSERVER_TOKEN_TIMEOUT_MS: u64 = 20_000;
 const DEFAULT_HTTP_API_EVENT_EMIT_TIMEOUT_MS: u64 = 200;
+const DEFAULT_ORPHAN_AUDIT_SECS: u64 = 60;
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+const MIN_DRAIN_GRACE_SECS: u64 = 5;
 static TRACING_GUARD: OnceLock<tracing_appender::non_blocking::WorkerGuard> = OnceLock::new();
 
 mod api;
 mod app_server;
 mod connection;
+mod dead_letter;
 mod delivery;
+mod drain;
 mod event_loop;
 mod fleet;
 mod headless;
 mod init;
 mod io;
+mod kubernetes;
+mod logs;
 mod maintenance;
 mod messages;
+mod orphans;
 mod paths;
+mod plan;
 mod relaycast_events;
+mod resource_limits;
+mod restart;
 mod session;
+mod spawn_queue;
 mod spawn_spec;
 mod system;
+mod team;
 #[cfg(test)]
 mod tests;
 mod util;
 mod worker_events;
 
 #[cfg(test)]
-pub(crate) use api::{default_observer_token_scopes, resolve_workspace};
+pub(crate) use api::{default_observer_token_scopes, resolve_workspace, team_publish_results};
 pub(crate) use app_server::*;
 pub(crate) use connection::*;
+pub(crate) use dead_letter::*;
 pub(crate) use delivery::*;
+pub(crate) use drain::*;
 pub(crate) use event_loop::*;
 pub(crate) use headless::*;
 pub(crate) use init::*;
 pub(crate) use io::*;
+pub(crate) use kubernetes::*;
+pub(crate) use logs::*;
 pub(crate) use messages::*;
+pub(crate) use orphans::*;
 pub(crate) use paths::*;
+pub(crate) use plan::*;
 pub(crate) use session::*;
+pub(crate) use spawn_queue::*;
 pub(crate) use spawn_spec::*;
 pub(crate) use system::*;
+pub(crate) use team::*;
 pub(crate) use util::*;
@@ -0,0 +1,143 @@
+use super::*;
+
+use crate::util::process::{processes_with_env_marker, MarkedProcess};
+
+/// Stamped on every worker so the orphan audit only considers processes
+/// spawned by this broker, not agents of a sibling or parent broker.
+pub(crate) const BROKER_PID_ENV: &str = "AGENT_RELAY_BROKER_PID";
+const AGENT_NAME_ENV: &str = "RELAY_AGENT_NAME";
+
+/// Periodic scan for processes left behind by workers that are no longer
+/// registered (crashed harnesses, MCP servers that detached from the worker's
+/// process group, ...).
+pub(crate) struct OrphanAudit {
+    interval: Option<Duration>,
+    next_at: Instant,
+    reap: bool,
+    /// Pids already reported, so each orphan is announced once. A reported
+    /// orphan that is still alive on the next audit is sent SIGKILL when
+    /// reaping is enabled.
+    reported: HashSet<u32>,
+}
+
+impl OrphanAudit {
+    pub(crate) fn new(config: &BrokerConfigFile, now: Instant) -> Self {
+        let interval = orphan_audit_interval(config);
+        Self {
+            interval,
+            next_at: now + interval.unwrap_or_default(),
+            reap: env_flag_or("AGENT_RELAY_REAP_ORPHANS", config.orphans.reap),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn due(&mut self, now: Instant) -> bool {
+        let Some(interval) = self.interval else {
+            return false;
+        };
+        if now < self.next_at {
+            return false;
+        }
+        self.next_at = now + interval;
+        true
+    }
+}
+
+/// Keep processes whose agent marker names no registered worker.
+///
+/// The broker's own pid is never an orphan, and neither is a process whose
+/// marker equals the name the broker itself inherited — workers that don't
+/// get a per-agent `RELAY_AGENT_NAME` (headless runtimes) carry that one.
+pub(crate) fn select_orphans(
+    candidates: Vec<MarkedProcess>,
+    is_registered: impl Fn(&str) -> bool,
+    own_pid: u32,
+    inherited_name: Option<&str>,
+) -> Vec<MarkedProcess> {
+    candidates
+        .into_iter()
+        .filter(|process| process.pid != own_pid)
+        .filter(|process| !process.marker.trim().is_empty())
+        .filter(|process| inherited_name != Some(process.marker.as_str()))
+        .filter(|process| !is_registered(&process.marker))
+        .collect()
+}
+
+#[cfg(unix)]
+fn signal_orphan(pid: u32, force: bool) -> bool {
+    use nix::sys::signal::{kill, Signal};
+    let signal = if force {
+        Signal::SIGKILL
+    } else {
+        Signal::SIGTERM
+    };
+    kill(nix::unistd::Pid::from_raw(pid as i32), signal).is_ok()
+}
+
+#[cfg(not(unix))]
+fn signal_orphan(_pid: u32, _force: bool) -> bool {
+    false
+}
+
+impl BrokerRuntime {
+    pub(super) async fn handle_orphan_audit(&mut self) {
+        if self.shutdown || !self.orphan_audit.due(Instant::now()) {
+            return;
+        }
+
+        let own_pid = std::process::id();
+        let candidates = tokio::task::spawn_blocking(move || {
+            processes_with_env_marker(AGENT_NAME_ENV, BROKER_PID_ENV, &own_pid.to_string())
+        })
+        .await
+        .unwrap_or_default();
+        let inherited_name = std::env::var(AGENT_NAME_ENV).ok();
+        let workers = &self.workers;
+        let orphans = select_orphans(
+            candidates,
+            |name| workers.has_worker(name),
+            own_pid,
+            inherited_name.as_deref(),
+        );
+
+        let audit = &mut self.orphan_audit;
+        audit
+            .reported
+            .retain(|pid| orphans.iter().any(|orphan| orphan.pid == *pid));
+
+        for orphan in orphans {
+            if !audit.reported.insert(orphan.pid) {
+                if audit.reap && signal_orphan(orphan.pid, true) {
+                    tracing::warn!(
+                        target = "agent_relay::broker",
+                        pid = orphan.pid,
+                        name = %orphan.marker,
+                        "orphan process survived SIGTERM; sent SIGKILL"
+                    );
+                }
+                continue;
+            }
+
+            let reaped = audit.reap && signal_orphan(orphan.pid, false);
+            tracing::warn!(
+                target = "agent_relay::broker",
+                pid = orphan.pid,
+                name = %orphan.marker,
+                command = ?orphan.command,
+                reaped,
+                "orphan process detected"
+            );
+            let _ = send_event(
+                &self.sdk_out_tx,
+                json!({
+                    "kind": "orphan_process_detected",
+                    "name": orphan.marker,
+                    "pid": orphan.pid,
+                    "command": orphan.command,
+                    "reaped": reaped,
+                }),
+            )
+            .await;
+        }
+    }
+}
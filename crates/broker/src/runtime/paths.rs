@@ -5,6 +5,8 @@ pub(crate) struct RuntimePaths {
     pub(super) persist: bool,
     pub(super) state: PathBuf,
     pub(super) pending: PathBuf,
+    /// Deliveries that ran out of retries; see [`DeadLetterQueue`].
+    pub(super) dead_letter: PathBuf,
     /// Held for process lifetime to prevent concurrent broker instances (persist mode only).
     #[allow(dead_code)]
     pub(super) _lock: Option<std::fs::File>,
@@ -19,6 +21,80 @@ pub(crate) fn continuity_dir(state_path: &Path) -> PathBuf {
         .join("continuity")
 }
 
+/// Render the continuity file saved for `continue_from` as a task preamble.
+/// Returns `None` (with a warning) when the file is missing or unreadable.
+pub(crate) fn read_continuity_block(state_path: &Path, continue_from: &str) -> Option<String> {
+    let continuity_file = continuity_dir(state_path).join(format!("{continue_from}.json"));
+    if !continuity_file.exists() {
+        tracing::warn!(
+            continue_from = %continue_from,
+            "no continuity file found at {}",
+            continuity_file.display()
+        );
+        return None;
+    }
+    let contents = match std::fs::read_to_string(&continuity_file) {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::warn!(
+                continue_from = %continue_from,
+                error = %e,
+                "failed to read continuity file"
+            );
+            return None;
+        }
+    };
+    let ctx = serde_json::from_str::<Value>(&contents).ok()?;
+    let prev_task = ctx
+        .get("initial_task")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown");
+    let summary = ctx
+        .get("summary")
+        .and_then(Value::as_str)
+        .unwrap_or("no summary available");
+    let messages = ctx
+        .get("message_history")
+        .and_then(Value::as_array)
+        .map(|msgs| {
+            msgs.iter()
+                .filter_map(|m| {
+                    let from = m.get("from").and_then(Value::as_str).unwrap_or("?");
+                    let text = m.get("text").and_then(Value::as_str).unwrap_or("");
+                    if text.is_empty() {
+                        None
+                    } else {
+                        Some(format!("  {}: {}", from, text))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+
+    Some(format!(
+        "## Continuity Context (from previous session as '{}')\n\
+         Previous task: {}\n\
+         Session summary: {}\n{}",
+        continue_from,
+        prev_task,
+        summary,
+        if messages.is_empty() {
+            String::new()
+        } else {
+            format!("Recent messages:\n{}\n", messages)
+        }
+    ))
+}
+
+/// Prepend a continuity block to the task, if any.
+pub(crate) fn with_continuity_block(continuity_block: String, task: Option<String>) -> String {
+    match task {
+        Some(new_task) => format!("{}\n\n## Current Task\n{}", continuity_block, new_task),
+        None => continuity_block,
+    }
+}
+
 /// Create ephemeral runtime paths in the system temp directory.
 ///
 /// Unlike `ensure_runtime_paths`, this function:
@@ -63,6 +139,7 @@ pub(crate) fn ensure_ephemeral_paths(_cwd: &Path, broker_name: &str) -> Result<R
         persist: false,
         state: root.join("state.json"),
         pending: root.join("pending.json"),
+        dead_letter: root.join("dead-letter.json"),
         _lock: None,
     })
 }
@@ -139,6 +216,7 @@ pub(crate) fn ensure_runtime_paths(
                         persist: true,
                         state: root.join(format!("state-{safe_name}.json")),
                         pending: root.join(format!("pending-{safe_name}.json")),
+                        dead_letter: root.join(format!("dead-letter-{safe_name}.json")),
                         _lock: Some(lock_file),
                     });
                 } else {
@@ -175,6 +253,7 @@ pub(crate) fn ensure_runtime_paths(
                 persist: true,
                 state: root.join(format!("state-{safe_name}.json")),
                 pending: root.join(format!("pending-{safe_name}.json")),
+                dead_letter: root.join(format!("dead-letter-{safe_name}.json")),
                 _lock: Some(lock_file),
             });
         }
@@ -186,6 +265,7 @@ pub(crate) fn ensure_runtime_paths(
         persist: true,
         state: root.join(format!("state-{safe_name}.json")),
         pending: root.join(format!("pending-{safe_name}.json")),
+        dead_letter: root.join(format!("dead-letter-{safe_name}.json")),
         _lock: Some(lock_file),
     })
 }
@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::{
+    protocol::{Plan, PlanStep},
+    util::ansi::strip_ansi,
+};
+
+/// How much of an agent's output a running step keeps; the tail wins.
+const MAX_STEP_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Check that `plan` names its steps uniquely, only refers to its own
+/// steps, and has no dependency cycle.
+pub(crate) fn validate_plan(plan: &Plan) -> Result<(), String> {
+    if plan.name.trim().is_empty() {
+        return Err("invalid_plan: plan name is required".to_string());
+    }
+    if plan.steps.is_empty() {
+        return Err(format!("invalid_plan: plan '{}' has no steps", plan.name));
+    }
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, step) in plan.steps.iter().enumerate() {
+        if step.id.trim().is_empty() {
+            return Err(format!("invalid_plan: step {} has no id", i + 1));
+        }
+        if index.insert(step.id.as_str(), i).is_some() {
+            return Err(format!("invalid_plan: step '{}' is listed twice", step.id));
+        }
+    }
+    for step in &plan.steps {
+        if let Some(unknown) = step_prerequisites(step).find(|id| !index.contains_key(id)) {
+            return Err(format!(
+                "invalid_plan: step '{}' refers to '{unknown}', which is not in the plan",
+                step.id
+            ));
+        }
+    }
+
+    let mut placed = vec![false; plan.steps.len()];
+    let mut remaining = plan.steps.len();
+    while remaining > 0 {
+        let next = (0..plan.steps.len()).find(|&i| {
+            !placed[i] && step_prerequisites(&plan.steps[i]).all(|id| placed[index[id]])
+        });
+        let Some(next) = next else {
+            let cycle: Vec<&str> = plan
+                .steps
+                .iter()
+                .zip(&placed)
+                .filter(|(_, placed)| !**placed)
+                .map(|(step, _)| step.id.as_str())
+                .collect();
+            return Err(format!(
+                "invalid_plan: dependency cycle between {}",
+                cycle.join(", ")
+            ));
+        };
+        placed[next] = true;
+        remaining -= 1;
+    }
+    Ok(())
+}
+
+/// The steps that must finish before `step` starts.
+fn step_prerequisites(step: &PlanStep) -> impl Iterator<Item = &str> {
+    step.depends_on
+        .iter()
+        .chain(&step.inputs_from)
+        .map(String::as_str)
+}
+
+/// A step's output as text to hand to the steps that take it as input.
+fn output_text(output: &Value) -> String {
+    match output {
+        Value::String(text) => text.clone(),
+        other => serde_json::to_string_pretty(other).unwrap_or_else(|_| other.to_string()),
+    }
+}
+
+enum StepState {
+    Waiting,
+    /// Delivered as `event_id`; `acked` once the agent has it.
+    Running {
+        event_id: String,
+        acked: bool,
+        output: String,
+    },
+    Done(Value),
+}
+
+/// A plan the broker is working through.
+pub(crate) struct RunningPlan {
+    name: String,
+    steps: Vec<PlanStep>,
+    states: Vec<StepState>,
+    started_at: Instant,
+    deadline: Option<Instant>,
+}
+
+impl RunningPlan {
+    pub(crate) fn new(plan: Plan, now: Instant) -> Self {
+        Self {
+            name: plan.name,
+            states: plan.steps.iter().map(|_| StepState::Waiting).collect(),
+            steps: plan.steps,
+            started_at: now,
+            deadline: plan
+                .timeout_secs
+                .map(|secs| now + Duration::from_secs(secs)),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn step(&self, index: usize) -> &PlanStep {
+        &self.steps[index]
+    }
+
+    fn is_done(&self, id: &str) -> bool {
+        self.steps
+            .iter()
+            .zip(&self.states)
+            .any(|(step, state)| step.id == id && matches!(state, StepState::Done(_)))
+    }
+
+    fn running_step(&self, agent: &WorkerName) -> Option<usize> {
+        self.steps
+            .iter()
+            .zip(&self.states)
+            .position(|(step, state)| {
+                &step.agent == agent && matches!(state, StepState::Running { .. })
+            })
+    }
+
+    pub(crate) fn has_running_step(&self, agent: &WorkerName) -> bool {
+        self.running_step(agent).is_some()
+    }
+
+    /// Waiting steps whose prerequisites are done, at most one per agent and
+    /// none for an agent already running a step here or `busy` in another
+    /// plan.
+    pub(crate) fn startable_steps(&self, busy: impl Fn(&WorkerName) -> bool) -> Vec<usize> {
+        let mut claimed: Vec<&WorkerName> = Vec::new();
+        let mut startable = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            if !matches!(self.states[i], StepState::Waiting)
+                || claimed.contains(&&step.agent)
+                || self.has_running_step(&step.agent)
+                || busy(&step.agent)
+                || !step_prerequisites(step).all(|id| self.is_done(id))
+            {
+                continue;
+            }
+            claimed.push(&step.agent);
+            startable.push(i);
+        }
+        startable
+    }
+
+    /// Mark step `index` as delivered under `event_id` and return the text
+    /// to deliver: its task followed by the outputs it takes as input.
+    pub(crate) fn start_step(&mut self, index: usize, event_id: String) -> String {
+        let step = &self.steps[index];
+        let mut text = step.task.clone();
+        for input in &step.inputs_from {
+            let Some((source, StepState::Done(output))) = self
+                .steps
+                .iter()
+                .zip(&self.states)
+                .find(|(source, _)| &source.id == input)
+            else {
+                continue;
+            };
+            text.push_str(&format!(
+                "\n\nOutput of step '{}' ({}):\n{}",
+                source.id,
+                source.agent,
+                output_text(output)
+            ));
+        }
+        self.states[index] = StepState::Running {
+            event_id,
+            acked: false,
+            output: String::new(),
+        };
+        text
+    }
+
+    /// Keep `chunk` of `agent`'s output for its running step.
+    pub(crate) fn record_output(&mut self, agent: &WorkerName, chunk: &str) {
+        let Some(index) = self.running_step(agent) else {
+            return;
+        };
+        if let StepState::Running { output, .. } = &mut self.states[index] {
+            output.push_str(chunk);
+            if output.len() > MAX_STEP_OUTPUT_BYTES {
+                let start = floor_char_boundary(output, output.len() - MAX_STEP_OUTPUT_BYTES);
+                output.drain(..start);
+            }
+        }
+    }
+
+    /// Record that `agent` acknowledged `event_id`; false when that is not
+    /// its running step's delivery.
+    pub(crate) fn mark_acked(&mut self, agent: &WorkerName, acked_event_id: &str) -> bool {
+        let Some(index) = self.running_step(agent) else {
+            return false;
+        };
+        match &mut self.states[index] {
+            StepState::Running {
+                event_id, acked, ..
+            } if *event_id == acked_event_id => {
+                *acked = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Finish `agent`'s running step with `result`, or with the output it
+    /// printed once the step's delivery was acked. Returns the step and its
+    /// output.
+    pub(crate) fn finish_step(
+        &mut self,
+        agent: &WorkerName,
+        result: Option<Value>,
+    ) -> Option<(usize, Value)> {
+        let index = self.running_step(agent)?;
+        let StepState::Running { acked, output, .. } = &mut self.states[index] else {
+            return None;
+        };
+        let output = match result {
+            Some(result) => result,
+            None if *acked => Value::String(strip_ansi(output).trim().to_string()),
+            None => return None,
+        };
+        self.states[index] = StepState::Done(output.clone());
+        Some((index, output))
+    }
+
+    /// The step delivered as `event_id`, if it is still running.
+    pub(crate) fn step_for_event(&self, event_id: &str) -> Option<usize> {
+        self.states.iter().position(|state| {
+            matches!(state, StepState::Running { event_id: running, .. } if running == event_id)
+        })
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.states
+            .iter()
+            .all(|state| matches!(state, StepState::Done(_)))
+    }
+
+    /// Step outputs keyed by step id.
+    pub(crate) fn results(&self) -> BTreeMap<String, Value> {
+        self.steps
+            .iter()
+            .zip(&self.states)
+            .filter_map(|(step, state)| match state {
+                StepState::Done(output) => Some((step.id.clone(), output.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Why the plan has failed, if it has, and the step it failed on: an
+    /// agent with unfinished steps stopped, a delivery was dropped before the
+    /// agent acked it, or the deadline passed.
+    pub(crate) fn failure(
+        &self,
+        now: Instant,
+        is_running: impl Fn(&WorkerName) -> bool,
+        is_pending: impl Fn(&str) -> bool,
+    ) -> Option<(Option<String>, String)> {
+        for (step, state) in self.steps.iter().zip(&self.states) {
+            match state {
+                StepState::Done(_) => continue,
+                _ if !is_running(&step.agent) => {
+                    return Some((
+                        Some(step.id.clone()),
+                        format!("agent '{}' is no longer running", step.agent),
+                    ));
+                }
+                StepState::Running {
+                    event_id,
+                    acked: false,
+                    ..
+                } if !is_pending(event_id) => {
+                    return Some((
+                        Some(step.id.clone()),
+                        format!("delivery to '{}' was dropped", step.agent),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let deadline = self.deadline?;
+        if now < deadline {
+            return None;
+        }
+        let running = self
+            .steps
+            .iter()
+            .zip(&self.states)
+            .find(|(_, state)| matches!(state, StepState::Running { .. }))
+            .map(|(step, _)| step.id.clone());
+        Some((
+            running,
+            format!(
+                "plan '{}' did not finish within {}s",
+                self.name,
+                deadline.duration_since(self.started_at).as_secs()
+            ),
+        ))
+    }
+}
+
+impl BrokerRuntime {
+    /// Start `plan`. Its agents must already be running; steps are delivered
+    /// as their prerequisites finish.
+    pub(super) async fn run_plan(&mut self, plan: Plan) -> Result<Value, String> {
+        validate_plan(&plan)?;
+        if self.workers.draining {
+            return Err(format!(
+                "broker_draining: not starting plans while the broker drains (requested plan '{}')",
+                plan.name
+            ));
+        }
+        if self
+            .running_plans
+            .iter()
+            .any(|running| running.name() == plan.name)
+        {
+            return Err(format!("plan '{}' is already running", plan.name));
+        }
+        if let Some(step) = plan
+            .steps
+            .iter()
+            .find(|step| !self.workers.has_worker(&step.agent))
+        {
+            return Err(format!(
+                "agent_not_found: step '{}' names unknown agent '{}'",
+                step.id, step.agent
+            ));
+        }
+
+        let name = plan.name.clone();
+        let steps = plan.steps.len();
+        tracing::info!(
+            target = "agent_relay::broker",
+            plan = %name,
+            steps,
+            "plan started"
+        );
+        self.running_plans
+            .push(RunningPlan::new(plan, Instant::now()));
+        self.advance_plans().await;
+        Ok(json!({
+            "success": true,
+            "plan": name,
+            "steps": steps,
+        }))
+    }
+
+    /// Deliver every step that can start, and wrap up finished plans.
+    async fn advance_plans(&mut self) {
+        let mut index = 0;
+        while index < self.running_plans.len() {
+            let startable = {
+                let plans = &self.running_plans;
+                plans[index]
+                    .startable_steps(|agent| plans.iter().any(|plan| plan.has_running_step(agent)))
+            };
+            let mut failed = None;
+            for step_index in startable {
+                let event_id = format!("plan_{}", Uuid::new_v4().simple());
+                let plan = &mut self.running_plans[index];
+                let text = plan.start_step(step_index, event_id.clone());
+                let step = plan.step(step_index).clone();
+                if let Err(error) = queue_and_try_delivery_raw(
+                    &mut self.workers,
+                    &mut self.pending_deliveries,
+                    &step.agent,
+                    &event_id,
+                    "broker",
+                    &step.agent,
+                    &text,
+                    None,
+                    None,
+                    None,
+                    2,
+                    MessageInjectionMode::Wait,
+                    self.delivery_retry_interval,
+                )
+                .await
+                {
+                    failed = Some((Some(step.id), error.to_string()));
+                    break;
+                }
+                let _ = send_broker_event(
+                    &self.sdk_out_tx,
+                    BrokerEvent::PlanStepStarted {
+                        plan: self.running_plans[index].name.clone(),
+                        step: step.id,
+                        agent: step.agent,
+                    },
+                )
+                .await;
+            }
+            if let Some((step, error)) = failed {
+                let plan = self.running_plans.remove(index);
+                self.fail_plan(plan, step, error).await;
+                continue;
+            }
+            if !self.running_plans[index].is_complete() {
+                index += 1;
+                continue;
+            }
+
+            let plan = self.running_plans.remove(index);
+            let elapsed_ms = plan.started_at.elapsed().as_millis() as u64;
+            tracing::info!(
+                target = "agent_relay::broker",
+                plan = %plan.name,
+                elapsed_ms,
+                "plan completed"
+            );
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::PlanCompleted {
+                    results: plan.results(),
+                    plan: plan.name,
+                    elapsed_ms,
+                },
+            )
+            .await;
+        }
+    }
+
+    async fn fail_plan(&mut self, plan: RunningPlan, step: Option<String>, error: String) {
+        tracing::warn!(
+            target = "agent_relay::broker",
+            plan = %plan.name,
+            step = step.as_deref().unwrap_or(""),
+            error = %error,
+            "plan failed"
+        );
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::PlanFailed {
+                plan: plan.name,
+                step,
+                error,
+            },
+        )
+        .await;
+    }
+
+    /// Called on every `worker_stream` chunk.
+    pub(super) fn handle_plan_output(&mut self, agent: &WorkerName, chunk: &str) {
+        for plan in &mut self.running_plans {
+            plan.record_output(agent, chunk);
+        }
+    }
+
+    /// Called on every `delivery_ack`. A headless agent is done with a step
+    /// once its run for the step is acked.
+    pub(super) async fn handle_plan_delivery_ack(&mut self, agent: &WorkerName, event_id: &str) {
+        let headless = self
+            .workers
+            .workers
+            .get(agent)
+            .is_some_and(|handle| handle.spec.runtime == AgentRuntime::Headless);
+        let acked = self
+            .running_plans
+            .iter_mut()
+            .any(|plan| plan.mark_acked(agent, event_id));
+        if acked && headless {
+            self.handle_plan_step_done(agent, None).await;
+        }
+    }
+
+    /// Called when `agent` goes idle or submits an `agent_result`: finishes
+    /// its running step with `result`, or with what it printed.
+    pub(super) async fn handle_plan_step_done(
+        &mut self,
+        agent: &WorkerName,
+        result: Option<Value>,
+    ) {
+        let mut finished = None;
+        for plan in &mut self.running_plans {
+            if let Some((index, output)) = plan.finish_step(agent, result.clone()) {
+                finished = Some((plan.name.clone(), plan.step(index).id.clone(), output));
+                break;
+            }
+        }
+        let Some((plan, step, output)) = finished else {
+            return;
+        };
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::PlanStepCompleted {
+                plan,
+                step,
+                agent: agent.clone(),
+                output,
+            },
+        )
+        .await;
+        self.advance_plans().await;
+    }
+
+    /// Called on every `delivery_failed`: the plan whose step was delivered
+    /// as `event_id` fails.
+    pub(super) async fn handle_plan_delivery_failed(&mut self, event_id: &str, reason: &str) {
+        let Some((index, step)) = self
+            .running_plans
+            .iter()
+            .enumerate()
+            .find_map(|(index, plan)| Some((index, plan.step_for_event(event_id)?)))
+        else {
+            return;
+        };
+        let plan = self.running_plans.remove(index);
+        let step = plan.step(step).clone();
+        let error = format!("delivery to '{}' failed: {reason}", step.agent);
+        self.fail_plan(plan, Some(step.id), error).await;
+    }
+
+    /// Fail plans whose agents stopped, whose deliveries were dropped, or
+    /// that ran out of time.
+    pub(super) async fn handle_plan_tick(&mut self) {
+        let now = Instant::now();
+        let mut index = 0;
+        while index < self.running_plans.len() {
+            let failure = {
+                let pending = &self.pending_deliveries;
+                self.running_plans[index].failure(
+                    now,
+                    |agent| self.workers.has_worker(agent),
+                    |event_id| {
+                        pending
+                            .values()
+                            .any(|pending| pending.delivery.event_id == event_id)
+                    },
+                )
+            };
+            let Some((step, error)) = failure else {
+                index += 1;
+                continue;
+            };
+            let plan = self.running_plans.remove(index);
+            self.fail_plan(plan, step, error).await;
+        }
+    }
+}
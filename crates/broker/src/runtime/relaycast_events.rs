@@ -185,7 +185,7 @@ pub(super) async fn release_worker_locally(
     workers.supervisor.unregister(&name);
     workers.metrics.on_release(&name);
     match workers.release(&name).await {
-        Ok(()) => {
+        Ok(report) => {
             workspace_http.forget_agent_registration(&name);
             let dropped = take_pending_for_worker(pending_deliveries, &name);
             if !dropped.is_empty() {
@@ -211,7 +211,7 @@ pub(super) async fn release_worker_locally(
                     tracing::warn!(path = %paths.state.display(), error = %error, "failed to persist broker state");
                 }
             }
-            let _ = send_event(sdk_out_tx, json!({"kind":"agent_released","name":name})).await;
+            let _ = send_event(sdk_out_tx, agent_released_event(&name, &report.survivors)).await;
             publish_agent_state_transition(
                 &workspace_state.ws_control_tx,
                 &name,
@@ -275,6 +275,7 @@ pub(super) async fn spawn_worker_from_request(
     workers: &mut WorkerRegistry,
     state: &mut broker::BrokerState,
     paths: &RuntimePaths,
+    config: &BrokerConfigFile,
     telemetry: &TelemetryClient,
     sdk_out_tx: &mpsc::Sender<ProtocolEnvelope<Value>>,
     dedup: &mut DedupCache,
@@ -352,14 +353,14 @@ pub(super) async fn spawn_worker_from_request(
     let channels = channel
         .as_deref()
         .map(|ch| {
-            let mut chs = default_spawn_channels();
+            let mut chs = default_spawn_channels(config);
             let candidate = ChannelName::from(ch);
             if !chs.contains(&candidate) {
                 chs.push(candidate);
             }
             chs
         })
-        .unwrap_or_else(default_spawn_channels);
+        .unwrap_or_else(|| default_spawn_channels(config));
     let spec = AgentSpec {
         name: name.clone(),
         runtime: runtime.clone(),
@@ -375,6 +376,8 @@ pub(super) async fn spawn_worker_from_request(
         args: vec![],
         channels: channels.clone(),
         restart_policy: None,
+        image: None,
+        limits: AgentResourceLimits::default(),
     };
     let mut effective_task = normalize_initial_task(task.clone());
 
@@ -473,6 +476,7 @@ pub(super) async fn spawn_worker_from_request(
         .await
     {
         Ok(effective_spec) => {
+            workers.metrics.on_spawn(&name);
             if let Some(prefix) = super::api::relay_skill_prefix(
                 effective_spec.cli.as_deref().unwrap_or(&cli),
                 effective_spec.model.as_deref(),
@@ -0,0 +1,78 @@
+use super::*;
+
+use crate::worker::limits::{memory_limit_bytes, MEMORY_LIMIT_EXIT_REASON};
+
+/// Resident memory of a worker and, for PTY workers, the harness under it.
+fn agent_memory_bytes(handle: &WorkerHandle) -> u64 {
+    let pid = handle.child.id().unwrap_or_default();
+    let mut pids = vec![pid];
+    pids.extend(handle.harness_pid.filter(|harness| *harness != pid));
+    pids.into_iter()
+        .filter(|pid| *pid != 0)
+        .map(memory_bytes_for_pid)
+        .sum()
+}
+
+impl BrokerRuntime {
+    /// Runs every maintenance tick: compares each limited agent's memory
+    /// with its `max_memory_mb`. A breach is reported once until usage drops
+    /// back under the limit; agents with `restart_on_limit` are restarted.
+    /// Containerised agents are left to their container's own limit.
+    pub(super) async fn handle_memory_limit_tick(&mut self) {
+        let mut breaches = Vec::new();
+        for (name, handle) in self.workers.workers.iter_mut() {
+            if !handle.spec.runtime.is_local() {
+                continue;
+            }
+            let Some(max_memory_mb) = handle.spec.limits.max_memory_mb else {
+                continue;
+            };
+            let memory_bytes = agent_memory_bytes(handle);
+            let over = memory_bytes > memory_limit_bytes(max_memory_mb);
+            if over && !handle.over_memory_limit {
+                breaches.push((
+                    name.clone(),
+                    max_memory_mb,
+                    memory_bytes,
+                    handle.spec.limits.restart_on_limit,
+                ));
+            }
+            handle.over_memory_limit = over;
+        }
+
+        for (name, max_memory_mb, memory_bytes, restart) in breaches {
+            tracing::warn!(
+                target = "agent_relay::broker",
+                worker = %name,
+                max_memory_mb,
+                memory_bytes,
+                restart,
+                "agent over its memory limit"
+            );
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::AgentLimitExceeded {
+                    name: name.clone(),
+                    limit: "memory".to_string(),
+                    max_memory_mb,
+                    memory_bytes: Some(memory_bytes),
+                    action: if restart { "restart" } else { "none" }.to_string(),
+                },
+            )
+            .await;
+            if restart {
+                if let Err(error) = self
+                    .handle_restart_agent(name.clone(), Some(MEMORY_LIMIT_EXIT_REASON.to_string()))
+                    .await
+                {
+                    tracing::warn!(
+                        target = "agent_relay::broker",
+                        worker = %name,
+                        error = %error,
+                        "restart after memory limit breach failed"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use super::*;
+
+impl BrokerRuntime {
+    /// Release `name` and spawn it again from its persisted [`AgentSpec`].
+    ///
+    /// Unlike a release, pending deliveries stay queued for the new process
+    /// and the manual-flush queue is kept. The initial task is re-injected on
+    /// `worker_ready`, preceded by the agent's saved continuity context when
+    /// it has one.
+    pub(super) async fn handle_restart_agent(
+        &mut self,
+        name: WorkerName,
+        reason: Option<String>,
+    ) -> Result<Value, String> {
+        let Some(handle) = self.workers.workers.get(&name) else {
+            return Err(format!("agent_not_found: no worker named '{name}'"));
+        };
+        if self.workers.draining {
+            // The release would succeed but the respawn would be refused.
+            return Err(format!(
+                "broker_draining: cannot restart '{name}' while the broker drains"
+            ));
+        }
+        let persisted = self.state.agents.get(&name);
+        let spec = persisted
+            .and_then(|agent| agent.spec.clone())
+            .unwrap_or_else(|| handle.spec.clone());
+        let initial_task = persisted.and_then(|agent| agent.initial_task.clone());
+        let parent = handle.parent.clone();
+        let workspace_id = handle.workspace_id.clone();
+        let idle_threshold_secs = handle.idle_threshold_secs;
+        let skip_relay_prompt = handle.skip_relay_prompt;
+        let agent_result = handle.agent_result.clone();
+        let reason = reason.unwrap_or_else(|| "restart_agent".to_string());
+        let restart_count = self
+            .workers
+            .metrics
+            .agent_stats(&name)
+            .map(|stats| stats.restarts)
+            .unwrap_or(0)
+            + 1;
+
+        // Register before tearing anything down, so a Relaycast failure
+        // leaves the running worker untouched.
+        let worker_relay_key = if skip_relay_prompt {
+            None
+        } else {
+            let token = self
+                .relaycast_http
+                .register_agent_token(&name, spec.cli.as_deref())
+                .await
+                .map_err(|error| {
+                    format!("internal_error: failed to register '{name}' for restart: {error}")
+                })?;
+            Some(token)
+        };
+
+        tracing::info!(
+            target = "agent_relay::broker",
+            worker = %name,
+            reason = %reason,
+            "restarting agent on request"
+        );
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::AgentRestarting {
+                name: name.clone(),
+                exit_code: None,
+                signal: None,
+                restart_count,
+                delay_ms: 0,
+            },
+        )
+        .await;
+
+        // The old process can no longer answer outstanding worker requests.
+        fail_pending_requests_for_worker(&mut self.pending_requests, &name, "agent_restarted");
+        let survivors = match self.workers.release(&name).await {
+            Ok(report) => report.survivors,
+            Err(error) => {
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    error = %error,
+                    "release before restart failed; spawning anyway"
+                );
+                Vec::new()
+            }
+        };
+
+        let effective_spec = match self
+            .workers
+            .spawn(
+                spec,
+                parent,
+                idle_threshold_secs,
+                worker_relay_key,
+                skip_relay_prompt,
+                workspace_id,
+                agent_result,
+            )
+            .await
+        {
+            Ok(effective_spec) => effective_spec,
+            Err(error) => {
+                tracing::error!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    error = %error,
+                    "restart failed"
+                );
+                let dropped = take_pending_for_worker(&mut self.pending_deliveries, &name);
+                if !dropped.is_empty() {
+                    let _ = emit_dropped_delivery_failures(
+                        &self.sdk_out_tx,
+                        &dropped,
+                        "restart_failed",
+                    )
+                    .await;
+                }
+                self.delivery_states.remove(&name);
+                self.agent_result_tokens.retain(|_, agent| agent != &name);
+                self.state.agents.remove(&name);
+                if self.paths.persist {
+                    let _ = self.state.save(&self.paths.state);
+                }
+                publish_agent_state_transition(
+                    &self.ws_control_tx,
+                    &name,
+                    "exited",
+                    Some("restart_failed"),
+                )
+                .await;
+                return Err(format!(
+                    "internal_error: restart of '{name}' failed: {error}"
+                ));
+            }
+        };
+        self.workers.metrics.on_restart(&name);
+
+        // Only the agent's own saved context; a missing file is the norm.
+        let continuity = continuity_dir(&self.paths.state)
+            .join(format!("{name}.json"))
+            .exists()
+            .then(|| read_continuity_block(&self.paths.state, &name))
+            .flatten();
+        let continuity_restored = continuity.is_some();
+        let task = match continuity {
+            Some(block) => Some(with_continuity_block(block, initial_task)),
+            None => initial_task,
+        };
+        if let Some(task) = &task {
+            self.workers
+                .initial_tasks
+                .insert(name.clone(), task.clone());
+        }
+
+        // Queued deliveries were waiting on the old process; give them a
+        // fresh retry budget against the new one.
+        let now = Instant::now();
+        let mut preserved_deliveries = 0;
+        for pending in self
+            .pending_deliveries
+            .values_mut()
+            .filter(|pending| pending.worker_name == name)
+        {
+            pending.attempts = 0;
+            pending.next_retry_at = now;
+            pending.last_error = None;
+            preserved_deliveries += 1;
+        }
+
+        let pid = self.workers.worker_pid(&name);
+        if let Some(agent) = self.state.agents.get_mut(&name) {
+            agent.runtime = effective_spec.runtime.clone();
+            agent.channels = effective_spec.channels.clone();
+            agent.pid = pid;
+            agent.started_at = Some(unix_timestamp_secs());
+            agent.spec = Some(effective_spec.clone());
+        }
+        if self.paths.persist {
+            if let Err(error) = self.state.save(&self.paths.state) {
+                tracing::warn!(
+                    path = %self.paths.state.display(),
+                    worker = %name,
+                    error = %error,
+                    "failed to persist restarted worker state"
+                );
+            }
+        }
+
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::AgentRestarted {
+                name: name.clone(),
+                restart_count,
+            },
+        )
+        .await;
+        publish_agent_state_transition(&self.ws_control_tx, &name, "spawned", Some("restarted"))
+            .await;
+
+        Ok(json!({
+            "success": true,
+            "name": name,
+            "pid": pid,
+            "restart_count": restart_count,
+            "pending_delivery_count": preserved_deliveries,
+            "task_reinjected": task.is_some(),
+            "continuity_restored": continuity_restored,
+            "survivors": survivors,
+        }))
+    }
+}
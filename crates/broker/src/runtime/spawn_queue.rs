@@ -0,0 +1,385 @@
+use super::*;
+
+/// A spawn held back by [`WorkerRegistry::max_concurrent`] or by the agents
+/// named in its `after`. The original caller has already been answered.
+pub(crate) struct QueuedSpawn {
+    name: WorkerName,
+    queued_at: Instant,
+    /// Agents in `after` that have not reported ready yet.
+    waiting_on: Vec<WorkerName>,
+    request: QueuedRequest,
+}
+
+enum QueuedRequest {
+    /// A `ListenApiRequest::Spawn`; `result_rx` receives the outcome once
+    /// the request is finally dispatched.
+    Api {
+        request: Box<ListenApiRequest>,
+        result_rx: tokio::sync::oneshot::Receiver<Result<Value, String>>,
+    },
+    Node(Box<NodeSpawn>),
+}
+
+/// A node-control `spawn` action, as [`BrokerRuntime::run_node_spawn`]
+/// takes it.
+pub(crate) struct NodeSpawn {
+    pub(crate) name: WorkerName,
+    pub(crate) cli: String,
+    pub(crate) task: Option<String>,
+    pub(crate) channel: Option<String>,
+    pub(crate) model: Option<String>,
+    pub(crate) ws_value: Value,
+    pub(crate) workspace_id: WorkspaceId,
+    pub(crate) invocation_id: String,
+    pub(crate) session_ref: Option<String>,
+}
+
+impl QueuedSpawn {
+    pub(crate) fn name(&self) -> &WorkerName {
+        &self.name
+    }
+
+    pub(crate) fn node(spawn: NodeSpawn) -> Self {
+        Self {
+            name: spawn.name.clone(),
+            queued_at: Instant::now(),
+            waiting_on: Vec::new(),
+            request: QueuedRequest::Node(Box::new(spawn)),
+        }
+    }
+}
+
+/// Take the oldest queued spawn whose dependencies are ready, if `workers`
+/// has a free slot for it.
+pub(crate) fn next_queued_spawn(
+    queue: &mut VecDeque<QueuedSpawn>,
+    workers: &WorkerRegistry,
+) -> Option<QueuedSpawn> {
+    if !workers.has_capacity() {
+        return None;
+    }
+    let index = queue
+        .iter()
+        .position(|queued| queued.waiting_on.is_empty())?;
+    queue.remove(index)
+}
+
+/// The agents in a spawn's `after` that have not reported ready yet.
+pub(crate) fn unready_spawn_dependencies(
+    after: &[WorkerName],
+    is_ready: impl Fn(&WorkerName) -> bool,
+) -> Vec<WorkerName> {
+    let mut waiting_on: Vec<WorkerName> = Vec::new();
+    for dependency in after {
+        if !is_ready(dependency) && !waiting_on.contains(dependency) {
+            waiting_on.push(dependency.clone());
+        }
+    }
+    waiting_on
+}
+
+impl BrokerRuntime {
+    /// Whether every agent in a `Spawn` request's `after` has reported ready.
+    pub(super) fn spawn_dependencies_ready(&self, request: &ListenApiRequest) -> bool {
+        let ListenApiRequest::Spawn { after, .. } = request else {
+            return true;
+        };
+        after.iter().all(|name| self.is_worker_ready(name))
+    }
+
+    fn is_worker_ready(&self, name: &WorkerName) -> bool {
+        self.workers
+            .workers
+            .get(name)
+            .is_some_and(|handle| handle.ready)
+    }
+
+    /// Park a `ListenApiRequest::Spawn` until the agents in its `after` are
+    /// ready and a worker slot frees up, and answer the caller with what it
+    /// is waiting for.
+    pub(super) async fn enqueue_spawn(&mut self, mut request: ListenApiRequest) {
+        let ListenApiRequest::Spawn {
+            name,
+            cli,
+            transport,
+            model,
+            args,
+            channels,
+            cwd,
+            team,
+            shadow_of,
+            shadow_mode,
+            restart_policy,
+            harness_config,
+            after,
+            reply,
+            ..
+        } = &mut request
+        else {
+            return;
+        };
+        let name = name.clone();
+        let waiting_on =
+            unready_spawn_dependencies(after, |dependency| self.is_worker_ready(dependency));
+        // Validate now so a malformed request fails the caller instead of
+        // surfacing later as a `spawn_failed` event.
+        let spec = build_http_api_spawn_spec(
+            name.clone(),
+            cli.clone(),
+            transport.clone(),
+            model.clone(),
+            args.clone(),
+            channels.clone(),
+            cwd.clone(),
+            team.clone(),
+            shadow_of.clone(),
+            shadow_mode.clone(),
+            (**restart_policy).clone(),
+            harness_config.clone(),
+            &self.config.headless,
+        );
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        let caller = std::mem::replace(reply, result_tx);
+
+        let spec = match spec {
+            Ok(spec) => spec,
+            Err(error) => {
+                let _ = caller.send(Err(error.to_string()));
+                return;
+            }
+        };
+        if self.workers.has_worker(&name) || self.spawn_queue.iter().any(|q| q.name == name) {
+            let _ = caller.send(Err(format!("agent '{name}' already exists")));
+            return;
+        }
+        if waiting_on.contains(&name) {
+            let _ = caller.send(Err(format!("agent '{name}' cannot wait for itself")));
+            return;
+        }
+        if let Some(unknown) = waiting_on.iter().find(|dependency| {
+            !self.workers.has_worker(dependency)
+                && !self.spawn_queue.iter().any(|q| &q.name == *dependency)
+        }) {
+            let _ = caller.send(Err(format!(
+                "agent '{name}' waits for unknown agent '{unknown}'"
+            )));
+            return;
+        }
+
+        self.spawn_queue.push_back(QueuedSpawn {
+            name: name.clone(),
+            queued_at: Instant::now(),
+            waiting_on: waiting_on.clone(),
+            request: QueuedRequest::Api {
+                request: Box::new(request),
+                result_rx,
+            },
+        });
+        if !waiting_on.is_empty() {
+            tracing::info!(
+                target = "agent_relay::broker",
+                worker = %name,
+                after = ?waiting_on,
+                "spawn waiting for dependencies"
+            );
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::SpawnWaiting {
+                    name: name.clone(),
+                    after: waiting_on.clone(),
+                },
+            )
+            .await;
+            let _ = caller.send(Ok(json!({
+                "success": true,
+                "name": name,
+                "runtime": runtime_label(&spec.runtime),
+                "queued": true,
+                "waiting_on": waiting_on,
+            })));
+            return;
+        }
+        let position = self.announce_queued_spawn(&name).await;
+        let _ = caller.send(Ok(json!({
+            "success": true,
+            "name": name,
+            "runtime": runtime_label(&spec.runtime),
+            "queued": true,
+            "position": position,
+        })));
+    }
+
+    /// Park a node-control spawn until a worker slot frees up.
+    pub(super) async fn enqueue_node_spawn(&mut self, spawn: NodeSpawn) -> Result<Value, String> {
+        let name = spawn.name.clone();
+        if self.workers.has_worker(&name) || self.spawn_queue.iter().any(|q| q.name == name) {
+            return Err(format!("agent '{name}' already exists"));
+        }
+        self.spawn_queue.push_back(QueuedSpawn::node(spawn));
+        let position = self.announce_queued_spawn(&name).await;
+        Ok(json!({
+            "spawned": false,
+            "name": name.as_str(),
+            "queued": true,
+            "position": position,
+        }))
+    }
+
+    /// Log and emit `spawn_queued` for the spawn just pushed onto the queue,
+    /// returning its position.
+    async fn announce_queued_spawn(&self, name: &WorkerName) -> usize {
+        let position = self.spawn_queue.len();
+        let running = self.workers.occupied_slots();
+        let max_concurrent = self.workers.max_concurrent.unwrap_or(running);
+        tracing::info!(
+            target = "agent_relay::broker",
+            worker = %name,
+            position,
+            running,
+            max_concurrent,
+            "spawn queued at concurrency cap"
+        );
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::SpawnQueued {
+                name: name.clone(),
+                position,
+                running,
+                max_concurrent,
+            },
+        )
+        .await;
+        position
+    }
+
+    /// Dispatch queued spawns whose dependencies are ready, oldest first,
+    /// while the registry has room. Runs after every runtime event, so a
+    /// slot freed by an exit or release is taken before the next event is
+    /// handled.
+    pub(super) async fn start_queued_spawns(&mut self) {
+        self.update_spawn_dependencies().await;
+        while let Some(QueuedSpawn {
+            name,
+            queued_at,
+            request,
+            ..
+        }) = next_queued_spawn(&mut self.spawn_queue, &self.workers)
+        {
+            let result = match request {
+                QueuedRequest::Api {
+                    mut request,
+                    mut result_rx,
+                } => {
+                    // Its dependencies were ready; they need not still be.
+                    if let ListenApiRequest::Spawn { after, .. } = request.as_mut() {
+                        after.clear();
+                    }
+                    self.handle_api_request(*request).await;
+                    result_rx
+                        .try_recv()
+                        .unwrap_or_else(|_| Err("internal_error: spawn reply dropped".to_string()))
+                }
+                QueuedRequest::Node(spawn) => self.run_node_spawn(*spawn).await,
+            };
+
+            let queued_ms = queued_at.elapsed().as_millis() as u64;
+            let event = match result {
+                Ok(result) => BrokerEvent::SpawnStarted {
+                    name: name.clone(),
+                    queued_ms,
+                    pid: result
+                        .get("pid")
+                        .and_then(Value::as_u64)
+                        .and_then(|pid| u32::try_from(pid).ok()),
+                },
+                Err(error) => BrokerEvent::SpawnFailed {
+                    name: name.clone(),
+                    error,
+                },
+            };
+            if let BrokerEvent::SpawnFailed { error, .. } = &event {
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    queued_ms,
+                    error = %error,
+                    "queued spawn failed to start"
+                );
+            }
+            let _ = send_broker_event(&self.sdk_out_tx, event).await;
+        }
+    }
+
+    /// Drop dependencies that have reported ready, telling callers what each
+    /// spawn still waits on, and fail spawns waiting on an agent that is
+    /// gone.
+    async fn update_spawn_dependencies(&mut self) {
+        let mut index = 0;
+        while index < self.spawn_queue.len() {
+            let queued = &self.spawn_queue[index];
+            if queued.waiting_on.is_empty() {
+                index += 1;
+                continue;
+            }
+            let gone = queued
+                .waiting_on
+                .iter()
+                .find(|dependency| {
+                    !self.workers.has_worker(dependency)
+                        && !self.spawn_queue.iter().any(|q| &q.name == *dependency)
+                })
+                .cloned();
+            if let Some(gone) = gone {
+                let Some(QueuedSpawn { name, .. }) = self.spawn_queue.remove(index) else {
+                    return;
+                };
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    dependency = %gone,
+                    "dependency exited before it was ready; dropping waiting spawn"
+                );
+                let _ = send_broker_event(
+                    &self.sdk_out_tx,
+                    BrokerEvent::SpawnFailed {
+                        name,
+                        error: format!("agent '{gone}' exited before it was ready"),
+                    },
+                )
+                .await;
+                // A spawn waiting on this one may be gone too.
+                index = 0;
+                continue;
+            }
+            let waiting_on = unready_spawn_dependencies(&queued.waiting_on, |dependency| {
+                self.is_worker_ready(dependency)
+            });
+            if waiting_on.len() < queued.waiting_on.len() && !waiting_on.is_empty() {
+                let _ = send_broker_event(
+                    &self.sdk_out_tx,
+                    BrokerEvent::SpawnWaiting {
+                        name: queued.name.clone(),
+                        after: waiting_on.clone(),
+                    },
+                )
+                .await;
+            }
+            self.spawn_queue[index].waiting_on = waiting_on;
+            index += 1;
+        }
+    }
+
+    /// Fail every queued spawn, e.g. when the broker starts draining.
+    pub(super) async fn cancel_queued_spawns(&mut self, reason: &str) {
+        while let Some(QueuedSpawn { name, .. }) = self.spawn_queue.pop_front() {
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::SpawnFailed {
+                    name,
+                    error: reason.to_string(),
+                },
+            )
+            .await;
+        }
+    }
+}
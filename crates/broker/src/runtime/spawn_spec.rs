@@ -4,9 +4,57 @@ pub(crate) fn runtime_label(runtime: &AgentRuntime) -> &'static str {
     match runtime {
         AgentRuntime::Pty => "pty",
         AgentRuntime::Headless => "headless",
+        AgentRuntime::Docker => "docker",
+        AgentRuntime::Kubernetes => "kubernetes",
+        AgentRuntime::Ssh { .. } => "ssh",
     }
 }
 
+/// The transport string that [`build_http_api_spawn_spec`] parses back into
+/// `runtime`; unlike [`runtime_label`] it keeps the ssh target.
+pub(crate) fn runtime_transport(runtime: &AgentRuntime) -> String {
+    match runtime {
+        AgentRuntime::Ssh {
+            host,
+            user: Some(user),
+        } => format!("ssh://{user}@{host}"),
+        AgentRuntime::Ssh { host, user: None } => format!("ssh://{host}"),
+        other => runtime_label(other).to_string(),
+    }
+}
+
+/// Parses a spawn `transport`: `pty`, `headless`, `docker`, `kubernetes` or
+/// `ssh://[user@]host`.
+fn parse_transport(value: &str) -> Result<AgentRuntime> {
+    if let Some(target) = value
+        .get(..6)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("ssh://"))
+        .map(|_| &value[6..])
+    {
+        let (user, host) = match target.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, target),
+        };
+        if host.is_empty() || user.is_some_and(str::is_empty) {
+            anyhow::bail!("invalid ssh transport '{value}' (expected 'ssh://[user@]host')");
+        }
+        return Ok(AgentRuntime::Ssh {
+            host: host.to_string(),
+            user: user.map(ToOwned::to_owned),
+        });
+    }
+    Ok(match value.to_ascii_lowercase().as_str() {
+        "pty" => AgentRuntime::Pty,
+        "headless" => AgentRuntime::Headless,
+        "docker" => AgentRuntime::Docker,
+        "kubernetes" => AgentRuntime::Kubernetes,
+        "ssh" => anyhow::bail!("ssh transport requires a host ('ssh://[user@]host')"),
+        other => anyhow::bail!(
+            "unsupported transport '{other}' (expected 'pty', 'headless', 'docker', 'kubernetes' or 'ssh://[user@]host')"
+        ),
+    })
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn build_http_api_spawn_spec(
     name: WorkerName,
@@ -21,19 +69,15 @@ pub(crate) fn build_http_api_spawn_spec(
     shadow_mode: Option<String>,
     restart_policy: Option<Value>,
     harness_config: Option<ResolvedHarnessConfig>,
+    headless_templates: &BTreeMap<String, HeadlessTemplate>,
 ) -> Result<AgentSpec> {
     let requested_runtime = match transport
         .as_deref()
         .map(str::trim)
         .filter(|value| !value.is_empty())
-        .map(|value| value.to_ascii_lowercase())
     {
         None => AgentRuntime::Pty,
-        Some(value) if value == "pty" => AgentRuntime::Pty,
-        Some(value) if value == "headless" => AgentRuntime::Headless,
-        Some(other) => {
-            anyhow::bail!("unsupported transport '{other}' (expected 'pty' or 'headless')")
-        }
+        Some(value) => parse_transport(value)?,
     };
     let harness_runtime = harness_config.as_ref().map(ResolvedHarnessConfig::runtime);
     let runtime = match (
@@ -60,13 +104,16 @@ pub(crate) fn build_http_api_spawn_spec(
     };
 
     let (provider, cli_command, model) = match runtime {
-        AgentRuntime::Pty => (None, Some(cli), model),
+        AgentRuntime::Pty
+        | AgentRuntime::Docker
+        | AgentRuntime::Kubernetes
+        | AgentRuntime::Ssh { .. } => (None, Some(cli), model),
         AgentRuntime::Headless => match harness_config.as_ref() {
             Some(ResolvedHarnessConfig::Headless(_)) => (None, Some(cli), model),
             _ => {
-                let provider = headless_provider_from_cli(&cli).with_context(|| {
+                let provider = headless_provider_from_cli(&cli, headless_templates).with_context(|| {
                     format!(
-                        "provider '{cli}' does not support headless transport (supported: claude, opencode)"
+                        "provider '{cli}' does not support headless transport (supported: claude, opencode, codex, gemini, or a configured headless template)"
                     )
                 })?;
                 (Some(provider), None, model)
@@ -87,11 +134,13 @@ pub(crate) fn build_http_api_spawn_spec(
         harness_config,
         model,
         cwd,
-        team,
+        team: team.as_deref().and_then(normalize_team),
         shadow_of,
         shadow_mode,
         args,
         channels,
         restart_policy: parsed_restart_policy,
+        image: None,
+        limits: AgentResourceLimits::default(),
     })
 }
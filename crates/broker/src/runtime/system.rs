@@ -1,5 +1,7 @@
 use super::*;
 
+use crate::util::process::{cpu_time_for_pid, CpuSample};
+
 /// Get terminal rows from TIOCGWINSZ.
 #[cfg(unix)]
 pub(crate) fn terminal_rows() -> Option<u16> {
@@ -74,11 +76,71 @@ pub(crate) fn memory_bytes_for_pid(pid: u32) -> u64 {
     rss_pages.saturating_mul(page_size as u64)
 }
 
-#[cfg(not(target_os = "linux"))]
+/// Resident set size, matching what `/proc/<pid>/statm` reports on Linux.
+#[cfg(target_os = "macos")]
+pub(crate) fn memory_bytes_for_pid(pid: u32) -> u64 {
+    use nix::libc;
+    let Ok(pid) = libc::c_int::try_from(pid) else {
+        return 0;
+    };
+    let mut info = std::mem::MaybeUninit::<libc::rusage_info_v2>::zeroed();
+    let status = unsafe {
+        libc::proc_pid_rusage(
+            pid,
+            libc::RUSAGE_INFO_V2,
+            info.as_mut_ptr().cast::<libc::rusage_info_t>(),
+        )
+    };
+    if status != 0 {
+        return 0;
+    }
+    unsafe { info.assume_init() }.ri_resident_size
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
 pub(crate) fn memory_bytes_for_pid(_pid: u32) -> u64 {
     0
 }
 
+/// How far apart the maintenance tick takes CPU samples. `cpu_percent` is
+/// the utilization over the most recent window of this length.
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// CPU time of the worker process and, for PTY workers, the harness running
+/// inside it.
+fn agent_cpu_time(handle: &WorkerHandle) -> Duration {
+    let pid = handle.child.id().unwrap_or_default();
+    let mut pids = vec![pid];
+    pids.extend(handle.harness_pid.filter(|harness| *harness != pid));
+    pids.into_iter()
+        .filter(|pid| *pid != 0)
+        .filter_map(cpu_time_for_pid)
+        .sum()
+}
+
+/// Take a CPU sample for `handle` once the previous one is at least
+/// [`CPU_SAMPLE_INTERVAL`] old and update its `cpu_percent`. The first
+/// sample is measured against the spawn time.
+pub(crate) fn sample_agent_cpu(handle: &mut WorkerHandle, now: Instant) {
+    let baseline = handle.cpu_sample.unwrap_or(CpuSample {
+        cpu_time: Duration::ZERO,
+        at: handle.spawned_at,
+    });
+    if handle.cpu_sample.is_some()
+        && now.saturating_duration_since(baseline.at) < CPU_SAMPLE_INTERVAL
+    {
+        return;
+    }
+    let current = CpuSample {
+        cpu_time: agent_cpu_time(handle),
+        at: now,
+    };
+    handle.cpu_percent = (current.utilization_since(&baseline) * 10.0).round() / 10.0;
+    handle.cpu_sample = Some(current);
+}
+
+/// Report memory and CPU for a worker. `cpu_percent` comes from the samples
+/// taken on the maintenance tick, so reads do not disturb each other.
 pub(crate) fn build_agent_metrics(handle: &WorkerHandle) -> AgentMetrics {
     let pid = handle.child.id().unwrap_or_default();
     AgentMetrics {
@@ -90,5 +152,17 @@ pub(crate) fn build_agent_metrics(handle: &WorkerHandle) -> AgentMetrics {
             memory_bytes_for_pid(pid)
         },
         uptime_secs: handle.spawned_at.elapsed().as_secs(),
+        cpu_time_ms: u64::try_from(agent_cpu_time(handle).as_millis()).unwrap_or(u64::MAX),
+        cpu_percent: handle.cpu_percent,
+    }
+}
+
+impl BrokerRuntime {
+    /// Runs every maintenance tick; see [`sample_agent_cpu`].
+    pub(super) fn sample_worker_cpu(&mut self) {
+        let now = Instant::now();
+        for handle in self.workers.workers.values_mut() {
+            sample_agent_cpu(handle, now);
+        }
     }
 }
@@ -0,0 +1,419 @@
+use super::*;
+use crate::{
+    protocol::{TeamManifest, TeamMember},
+    spawn_profile::spawn_profile,
+};
+
+/// How long a team's members have to report ready when the manifest does
+/// not say.
+const DEFAULT_TEAM_READY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The order to spawn `manifest`'s agents in, as indexes into `agents`:
+/// each agent after the ones it depends on, otherwise in manifest order.
+pub(crate) fn team_spawn_order(manifest: &TeamManifest) -> Result<Vec<usize>, String> {
+    if manifest.name.trim().is_empty() {
+        return Err("invalid_team_manifest: team name is required".to_string());
+    }
+    if manifest.agents.is_empty() {
+        return Err(format!(
+            "invalid_team_manifest: team '{}' has no agents",
+            manifest.name
+        ));
+    }
+    let mut index: HashMap<&WorkerName, usize> = HashMap::new();
+    for (i, member) in manifest.agents.iter().enumerate() {
+        if index.insert(&member.agent.name, i).is_some() {
+            return Err(format!(
+                "invalid_team_manifest: agent '{}' is listed twice",
+                member.agent.name
+            ));
+        }
+    }
+    for member in &manifest.agents {
+        if let Some(unknown) = member
+            .depends_on
+            .iter()
+            .find(|dependency| !index.contains_key(dependency))
+        {
+            return Err(format!(
+                "invalid_team_manifest: '{}' depends on '{unknown}', which is not in the team",
+                member.agent.name
+            ));
+        }
+    }
+
+    let mut placed = vec![false; manifest.agents.len()];
+    let mut order = Vec::with_capacity(manifest.agents.len());
+    while order.len() < manifest.agents.len() {
+        let next = (0..manifest.agents.len()).find(|&i| {
+            !placed[i]
+                && manifest.agents[i]
+                    .depends_on
+                    .iter()
+                    .all(|dependency| placed[index[dependency]])
+        });
+        let Some(next) = next else {
+            let cycle: Vec<&str> = manifest
+                .agents
+                .iter()
+                .zip(&placed)
+                .filter(|(_, placed)| !**placed)
+                .map(|(member, _)| member.agent.name.as_str())
+                .collect();
+            return Err(format!(
+                "invalid_team_manifest: dependency cycle between {}",
+                cycle.join(", ")
+            ));
+        };
+        placed[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+/// A spawned team whose members have not all reported ready. The broker
+/// emits `team_ready` once they have, and tears the team down if one exits
+/// or the deadline passes first.
+pub(crate) struct PendingTeam {
+    name: String,
+    /// In spawn order.
+    members: Vec<WorkerName>,
+    ready: HashSet<WorkerName>,
+    /// Tasks of members with `depends_on`, held until the member and its
+    /// dependencies are ready.
+    held_tasks: Vec<HeldTask>,
+    started_at: Instant,
+    deadline: Instant,
+}
+
+struct HeldTask {
+    name: WorkerName,
+    depends_on: Vec<WorkerName>,
+    task: String,
+}
+
+impl PendingTeam {
+    pub(crate) fn new(manifest: &TeamManifest, order: &[usize], now: Instant) -> Self {
+        let ready_timeout = manifest
+            .ready_timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TEAM_READY_TIMEOUT);
+        Self {
+            name: manifest.name.clone(),
+            members: order
+                .iter()
+                .map(|&i| manifest.agents[i].agent.name.clone())
+                .collect(),
+            ready: HashSet::new(),
+            held_tasks: manifest
+                .agents
+                .iter()
+                .filter(|member| !member.depends_on.is_empty())
+                .filter_map(|member| {
+                    Some(HeldTask {
+                        name: member.agent.name.clone(),
+                        depends_on: member.depends_on.clone(),
+                        task: member.task.clone()?,
+                    })
+                })
+                .collect(),
+            started_at: now,
+            deadline: now + ready_timeout,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn members(&self) -> &[WorkerName] {
+        &self.members
+    }
+
+    pub(crate) fn has_member(&self, name: &WorkerName) -> bool {
+        self.members.contains(name)
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.ready.len() == self.members.len()
+    }
+
+    /// Record `name` as ready and return the held tasks that can now be
+    /// delivered, as `(worker, task)`.
+    pub(crate) fn mark_ready(&mut self, name: &WorkerName) -> Vec<(WorkerName, String)> {
+        if !self.has_member(name) {
+            return Vec::new();
+        }
+        self.ready.insert(name.clone());
+        let ready = &self.ready;
+        let (released, held) = std::mem::take(&mut self.held_tasks)
+            .into_iter()
+            .partition::<Vec<_>, _>(|held| {
+                ready.contains(&held.name) && held.depends_on.iter().all(|d| ready.contains(d))
+            });
+        self.held_tasks = held;
+        released
+            .into_iter()
+            .map(|held| (held.name, held.task))
+            .collect()
+    }
+
+    /// Why the team has failed, if it has: a member is no longer running,
+    /// or the deadline passed before every member was ready.
+    pub(crate) fn failure(
+        &self,
+        now: Instant,
+        is_running: impl Fn(&WorkerName) -> bool,
+    ) -> Option<String> {
+        if let Some(gone) = self.members.iter().find(|name| !is_running(name)) {
+            return Some(format!(
+                "agent '{gone}' exited before team '{}' was ready",
+                self.name
+            ));
+        }
+        if now < self.deadline {
+            return None;
+        }
+        let waiting: Vec<&str> = self
+            .members
+            .iter()
+            .filter(|name| !self.ready.contains(*name))
+            .map(WorkerName::as_str)
+            .collect();
+        Some(format!(
+            "team '{}' was not ready within {}s; still waiting on {}",
+            self.name,
+            self.deadline.duration_since(self.started_at).as_secs(),
+            waiting.join(", ")
+        ))
+    }
+}
+
+impl BrokerRuntime {
+    /// Spawn every agent in `manifest`, or none: when one fails, the ones
+    /// already spawned are released. With `fleet`, each agent is registered
+    /// with the node the way an SDK `spawn_agent` is.
+    pub(super) async fn spawn_team(
+        &mut self,
+        manifest: TeamManifest,
+        fleet: bool,
+    ) -> Result<Value, String> {
+        let order = team_spawn_order(&manifest)?;
+        if self.workers.draining {
+            return Err(format!(
+                "broker_draining: not accepting new agents while the broker drains (requested team '{}')",
+                manifest.name
+            ));
+        }
+        if self
+            .pending_teams
+            .iter()
+            .any(|team| team.name() == manifest.name)
+        {
+            return Err(format!("team '{}' is already starting", manifest.name));
+        }
+        if let Some(member) = manifest.agents.iter().find(|member| {
+            self.workers.has_worker(&member.agent.name)
+                || self
+                    .spawn_queue
+                    .iter()
+                    .any(|queued| queued.name() == &member.agent.name)
+        }) {
+            return Err(format!("agent '{}' already exists", member.agent.name));
+        }
+        // Spawns past the cap would queue, and a queued member may never start.
+        if let Some(free) = self.workers.free_slots() {
+            let free = free.saturating_sub(self.spawn_queue.len());
+            if manifest.agents.len() > free {
+                return Err(format!(
+                    "team '{}' needs {} worker slots but {free} are free",
+                    manifest.name,
+                    manifest.agents.len()
+                ));
+            }
+        }
+
+        let team = PendingTeam::new(&manifest, &order, Instant::now());
+        let mut spawned = Vec::with_capacity(order.len());
+        for i in order {
+            let member = manifest.agents[i].clone();
+            let name = member.agent.name.clone();
+            if let Err(error) = self.spawn_team_member(&manifest, member, fleet).await {
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    team = %manifest.name,
+                    worker = %name,
+                    error = %error,
+                    "team member failed to spawn; releasing the team"
+                );
+                self.release_team_members(&spawned, "team_spawn_failed")
+                    .await;
+                return Err(format!(
+                    "team '{}': agent '{name}' failed to spawn: {error}",
+                    manifest.name
+                ));
+            }
+            spawned.push(name);
+        }
+        tracing::info!(
+            target = "agent_relay::broker",
+            team = %manifest.name,
+            agents = spawned.len(),
+            "team spawned"
+        );
+        self.pending_teams.push(team);
+        Ok(json!({
+            "success": true,
+            "team": manifest.name,
+            "agents": spawned,
+        }))
+    }
+
+    async fn spawn_team_member(
+        &mut self,
+        manifest: &TeamManifest,
+        member: TeamMember,
+        fleet: bool,
+    ) -> Result<Value, String> {
+        let TeamMember {
+            mut agent,
+            profile,
+            task,
+            depends_on,
+        } = member;
+        let idle_threshold_secs = match profile {
+            Some(profile) => {
+                let profile = spawn_profile(&self.config.profiles, &profile)
+                    .map_err(|error| error.to_string())?;
+                profile.apply_to_spec(&mut agent);
+                profile.idle_threshold_secs
+            }
+            None => None,
+        };
+        for channel in &manifest.channels {
+            if !agent.channels.contains(channel) {
+                agent.channels.push(channel.clone());
+            }
+        }
+        agent.team.get_or_insert_with(|| manifest.name.clone());
+        // Held by the `PendingTeam` until the dependencies are ready.
+        let task = if depends_on.is_empty() { task } else { None };
+        if fleet {
+            self.handle_fleet_spawn_agent(agent, None, task, false, idle_threshold_secs, Vec::new())
+                .await
+        } else {
+            self.spawn_from_agent_spec(agent, task, false, idle_threshold_secs, None, Vec::new())
+                .await
+        }
+    }
+
+    async fn release_team_members(&mut self, names: &[WorkerName], reason: &str) {
+        for name in names {
+            if !self.workers.has_worker(name) {
+                continue;
+            }
+            let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+            Box::pin(self.handle_api_request(ListenApiRequest::Release {
+                name: name.clone(),
+                reason: Some(reason.to_string()),
+                reply: reply_tx,
+            }))
+            .await;
+            if let Ok(Err(error)) = reply_rx.await {
+                tracing::warn!(
+                    target = "agent_relay::broker",
+                    worker = %name,
+                    error = %error,
+                    "failed to release team member"
+                );
+            }
+        }
+    }
+
+    /// Called on every `worker_ready`: delivers held tasks that were waiting
+    /// on `name` and emits `team_ready` once the whole team is up.
+    pub(super) async fn handle_team_member_ready(&mut self, name: &WorkerName) {
+        let Some(index) = self
+            .pending_teams
+            .iter()
+            .position(|team| team.has_member(name))
+        else {
+            return;
+        };
+        for (worker, task) in self.pending_teams[index].mark_ready(name) {
+            let event_id = format!("init_{}", Uuid::new_v4().simple());
+            if let Err(error) = queue_and_try_delivery_raw(
+                &mut self.workers,
+                &mut self.pending_deliveries,
+                &worker,
+                &event_id,
+                "broker",
+                &worker,
+                &task,
+                None,
+                None,
+                None,
+                2,
+                MessageInjectionMode::Wait,
+                self.delivery_retry_interval,
+            )
+            .await
+            {
+                tracing::warn!(worker = %worker, error = %error, "failed to deliver held team task");
+            }
+        }
+        if !self.pending_teams[index].is_ready() {
+            return;
+        }
+
+        let team = self.pending_teams.remove(index);
+        let elapsed_ms = team.started_at.elapsed().as_millis() as u64;
+        tracing::info!(
+            target = "agent_relay::broker",
+            team = %team.name,
+            agents = team.members.len(),
+            elapsed_ms,
+            "team ready"
+        );
+        let _ = send_broker_event(
+            &self.sdk_out_tx,
+            BrokerEvent::TeamReady {
+                team: team.name,
+                agents: team.members,
+                elapsed_ms,
+            },
+        )
+        .await;
+    }
+
+    /// Tear down starting teams that lost a member or ran out of time.
+    pub(super) async fn handle_team_tick(&mut self) {
+        let now = Instant::now();
+        let mut index = 0;
+        while index < self.pending_teams.len() {
+            let Some(error) =
+                self.pending_teams[index].failure(now, |name| self.workers.has_worker(name))
+            else {
+                index += 1;
+                continue;
+            };
+            let team = self.pending_teams.remove(index);
+            tracing::warn!(
+                target = "agent_relay::broker",
+                team = %team.name,
+                error = %error,
+                "team failed to come up; releasing its agents"
+            );
+            self.release_team_members(team.members(), "team_failed")
+                .await;
+            let _ = send_broker_event(
+                &self.sdk_out_tx,
+                BrokerEvent::TeamFailed {
+                    team: team.name,
+                    error,
+                },
+            )
+            .await;
+        }
+    }
+}
@@ -1,18 +1,21 @@
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     path::PathBuf,
     process::Stdio,
-    sync::{Mutex, OnceLock},
+    sync::{Arc, Mutex, OnceLock},
     time::{Duration, Instant},
 };
 
+use crate::broker_config::BrokerConfigFile;
+use crate::headless_template::HeadlessTemplate;
 use crate::ids::{
     AgentId, ChannelName, DeliveryId, EventId, MessageTarget, WorkerName, WorkspaceAlias,
     WorkspaceId,
 };
 use crate::protocol::{
-    AgentSpec, BrokerEvent, DeliveryReadAckStatus, HarnessReleasePolicy, HeadlessHarnessConfig,
-    HeadlessHarnessDriver, MessageInjectionMode, RelayDelivery, ResolvedHarnessConfig,
+    AgentResourceLimits, AgentSpec, BrokerEvent, DeliveryReadAckStatus, HarnessReleasePolicy,
+    HeadlessHarnessConfig, HeadlessHarnessDriver, MessageInjectionMode, Plan, RelayDelivery,
+    ResolvedHarnessConfig, TeamManifest,
 };
 use crate::worker::{AgentWorkState, WorkerEvent, WorkerHandle, WorkerRegistry};
 use crate::{
@@ -29,29 +32,35 @@ use serde_json::{json, Value};
 use tokio::sync::mpsc;
 
 use super::{
-    apply_exit_after_task_instruction, build_agent_state_transition_event,
-    build_http_api_spawn_spec, build_thread_infos, channels_from_csv,
-    clear_pending_delivery_if_event_matches, continuity_dir, default_observer_token_scopes,
-    delivery_read_ack_is_relaycast_message, delivery_retry_interval, drop_pending_for_worker,
-    emit_delivery_attempt_outcome, emit_dropped_delivery_failures, ensure_ephemeral_paths,
+    agent_released_event, apply_exit_after_task_instruction, build_agent_metrics,
+    build_agent_state_transition_event, build_http_api_spawn_spec, build_thread_infos,
+    channels_from_csv, clear_pending_delivery_if_event_matches, configured_api_port,
+    continuity_dir, default_observer_token_scopes, delivery_read_ack_is_relaycast_message,
+    delivery_retry_interval, drain_timeout, drop_pending_for_worker, emit_delivery_attempt_outcome,
+    emit_dropped_delivery_failures, ensure_ephemeral_paths, ensure_runtime_paths,
     extract_mcp_message_ids, http_api_event_emit_timeout, http_api_local_delivery_timeout,
     http_api_relaycast_send_timeout, is_relaycast_self_control_target,
-    is_unknown_worker_error_message, load_pending_deliveries, mark_delivery_read_ack,
-    mark_delivery_read_ack_with_timeout, normalize_channel, normalize_initial_task,
-    normalize_sender, parse_sort_key_from_raw_timestamp, persist_pending_on_shutdown,
-    queue_inbound_for_delivery_mode, relaycast_spawn_control_dedup_key,
-    relaycast_ws_should_apply_local_spawn_echo_dedup, relaycast_ws_spawn_token, resolve_workspace,
-    retry_pending_delivery, seed_supplied_agent_token, send_broker_event,
-    sender_is_dashboard_label, should_clear_pending_delivery_for_event,
-    synthetic_delivery_read_ack_reason, AgentRuntime, DeliveryAttemptOutcome, InboundContext,
-    InboundQueueOutcome, PendingDelivery, PendingDeliveryStore, ProtocolHeadlessProvider,
-    RelayWorkspace, MAX_DELIVERY_RETRIES,
+    is_unknown_worker_error_message, load_pending_deliveries, log_retention_days,
+    mark_delivery_read_ack, mark_delivery_read_ack_with_timeout, next_queued_spawn,
+    normalize_channel, normalize_initial_task, normalize_sender, orphan_audit_interval,
+    parse_sort_key_from_raw_timestamp, persist_pending_on_shutdown, pod_poll_interval,
+    queue_inbound_for_delivery_mode, read_continuity_block, read_log_range, read_log_tail,
+    relaycast_spawn_control_dedup_key, relaycast_ws_should_apply_local_spawn_echo_dedup,
+    relaycast_ws_spawn_token, resolve_workspace, retry_pending_delivery, runtime_label,
+    runtime_transport, sample_agent_cpu, seed_supplied_agent_token, select_orphans,
+    send_broker_event, sender_is_dashboard_label, should_clear_pending_delivery_for_event,
+    synthetic_delivery_read_ack_reason, team_publish_results, team_spawn_order,
+    unready_spawn_dependencies, validate_plan, with_continuity_block, AgentRuntime, DeadLetter,
+    DeadLetterQueue, DeliveryAttemptOutcome, DrainState, InboundContext, InboundQueueOutcome,
+    NodeSpawn, PendingDelivery, PendingDeliveryStore, PendingTeam, ProtocolHeadlessProvider,
+    QueuedSpawn, RelayWorkspace, RunningPlan, MAX_DELIVERY_RETRIES, MIN_DRAIN_GRACE_SECS,
 };
 use crate::dedup::DedupCache;
 use crate::relaycast::{
     format_worker_preregistration_error, RelaycastHttpClient, RelaycastRegistrationError, WsControl,
 };
 use crate::types::{InboundDeliveryMode, InboundDeliveryState};
+use crate::util::process::MarkedProcess;
 use relaycast::ObserverScope;
 
 fn env_test_lock() -> &'static Mutex<()> {
@@ -66,6 +75,7 @@ async fn make_worker_registry_with_worker(name: &str) -> WorkerRegistry {
         Vec::new(),
         PathBuf::from("/tmp/agent-relay-broker-tests"),
         Instant::now(),
+        Arc::default(),
     );
     let mut child = tokio::process::Command::new("cat")
         .stdin(Stdio::piped())
@@ -92,6 +102,8 @@ async fn make_worker_registry_with_worker(name: &str) -> WorkerRegistry {
                 args: Vec::new(),
                 channels: Vec::new(),
                 restart_policy: None,
+                image: None,
+                limits: AgentResourceLimits::default(),
             },
             parent: None,
             workspace_id: Some(WorkspaceId::new("ws_demo")),
@@ -99,10 +111,20 @@ async fn make_worker_registry_with_worker(name: &str) -> WorkerRegistry {
             stdin,
             harness_pid: None,
             spawned_at: Instant::now(),
+            ready: false,
             last_activity_at: Instant::now(),
             context_budget_pct: None,
             state: AgentWorkState::Working,
             exit_reason: None,
+            scrollback: crate::scrollback::OutputScrollback::new(16),
+            cpu_sample: None,
+            cpu_percent: 0.0,
+            paused_at: None,
+            idle_threshold_secs: None,
+            skip_relay_prompt: false,
+            agent_result: None,
+            cgroup: None,
+            over_memory_limit: false,
         },
     );
     registry
@@ -230,6 +252,7 @@ async fn inbound_queue_worker_missing_does_not_create_state() {
         Vec::new(),
         PathBuf::from("/tmp/agent-relay-broker-tests"),
         Instant::now(),
+        Arc::default(),
     );
     let mut delivery_states = HashMap::new();
 
@@ -245,6 +268,47 @@ async fn inbound_queue_worker_missing_does_not_create_state() {
     assert!(delivery_states.is_empty());
 }
 
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn inbound_route_span_reaches_the_otel_exporter() {
+    use opentelemetry::Value as OtelValue;
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let worker_name = "worker-a";
+    let workers = make_worker_registry_with_worker(worker_name).await;
+    let exporter = InMemorySpanExporter::default();
+    let provider = crate::otel::tracer_provider(exporter.clone());
+    let subscriber = tracing_subscriber::registry().with(crate::otel::layer(&provider));
+
+    tracing::subscriber::with_default(subscriber, || {
+        queue_inbound_for_delivery_mode(
+            &mut HashMap::new(),
+            &workers,
+            worker_name,
+            inbound_ctx("evt_traced"),
+        );
+    });
+    provider.force_flush().expect("flush spans");
+
+    let spans = exporter.get_finished_spans().expect("exported spans");
+    let route = spans
+        .iter()
+        .find(|span| span.name == "relay.route")
+        .expect("relay.route span should be exported");
+    let attribute = |key: &str| {
+        route
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    };
+    assert_eq!(attribute("event_id"), Some(OtelValue::from("evt_traced")));
+    assert_eq!(attribute("worker"), Some(OtelValue::from(worker_name)));
+
+    cleanup_worker_registry(workers).await;
+}
+
 #[tokio::test]
 async fn inbound_queue_eviction_surfaces_dropped_message() {
     let worker_name = "worker-a";
@@ -396,6 +460,7 @@ async fn delivery_retry_fails_promptly_when_recipient_is_gone() {
         Vec::new(),
         PathBuf::from("/tmp/agent-relay-broker-tests"),
         Instant::now(),
+        Arc::default(),
     );
     let mut pending_deliveries = HashMap::from([(
         DeliveryId::new("del_gone"),
@@ -439,6 +504,7 @@ async fn delivery_retry_fails_promptly_when_recipient_is_gone() {
             to: MessageTarget::new("Worker"),
             attempts: 3,
             last_error: "recipient gone".to_string(),
+            dead_letter: None,
         }
     );
     assert!(
@@ -447,6 +513,189 @@ async fn delivery_retry_fails_promptly_when_recipient_is_gone() {
     );
 }
 
+#[test]
+fn dead_letters_persist_and_requeue_with_fresh_retries() {
+    let dir = tempfile::tempdir().expect("tempdir should create");
+    let path = dir.path().join(".agent-relay/dead-letter.json");
+    let pending = |id: &str| PendingDelivery {
+        worker_name: WorkerName::from("Worker"),
+        delivery: RelayDelivery {
+            delivery_id: DeliveryId::new(id),
+            event_id: EventId::new(format!("evt_{id}")),
+            workspace_id: None,
+            workspace_alias: None,
+            from: "Lead".to_string(),
+            target: MessageTarget::new("Worker"),
+            body: format!("instructions {id}"),
+            thread_id: None,
+            priority: Some(2),
+            injection_mode: MessageInjectionMode::Wait,
+        },
+        attempts: MAX_DELIVERY_RETRIES,
+        next_retry_at: Instant::now(),
+        queued_at_ms: 1,
+        last_error: None,
+    };
+
+    let mut queue = DeadLetterQueue::load(path.clone());
+    assert!(queue.entries().is_empty());
+    queue.push(DeadLetter::new(&pending("del_a"), "write failed"));
+    queue.push(DeadLetter::new(&pending("del_b"), "write failed"));
+
+    let mut reloaded = DeadLetterQueue::load(path.clone());
+    assert_eq!(reloaded.entries(), queue.entries());
+    assert!(reloaded.take("del_missing").is_none());
+    let requeued = reloaded
+        .take("del_a")
+        .expect("dead letter present")
+        .into_pending();
+    assert_eq!(requeued.attempts, 0);
+    assert_eq!(requeued.last_error, None);
+    assert_eq!(requeued.delivery.body, "instructions del_a");
+
+    let remaining = DeadLetterQueue::load(path);
+    assert_eq!(remaining.entries().len(), 1);
+    assert_eq!(remaining.entries()[0].delivery.delivery_id, "del_b");
+}
+
+#[test]
+fn brokers_sharing_a_state_dir_keep_separate_dead_letters() {
+    let dir = tempfile::tempdir().expect("tempdir should create");
+    let alpha = ensure_runtime_paths(dir.path(), "alpha", Some(dir.path())).expect("alpha paths");
+    let beta = ensure_runtime_paths(dir.path(), "beta", Some(dir.path())).expect("beta paths");
+    assert_ne!(alpha.dead_letter, beta.dead_letter);
+
+    let dead = |id: &str| {
+        DeadLetter::new(
+            &PendingDelivery {
+                worker_name: WorkerName::from("Worker"),
+                delivery: RelayDelivery {
+                    delivery_id: DeliveryId::new(id),
+                    event_id: EventId::new(format!("evt_{id}")),
+                    workspace_id: None,
+                    workspace_alias: None,
+                    from: "Lead".to_string(),
+                    target: MessageTarget::new("Worker"),
+                    body: format!("instructions {id}"),
+                    thread_id: None,
+                    priority: None,
+                    injection_mode: MessageInjectionMode::Wait,
+                },
+                attempts: MAX_DELIVERY_RETRIES,
+                next_retry_at: Instant::now(),
+                queued_at_ms: 1,
+                last_error: None,
+            },
+            "write failed",
+        )
+    };
+
+    let mut alpha_queue = DeadLetterQueue::load(alpha.dead_letter.clone());
+    let mut beta_queue = DeadLetterQueue::load(beta.dead_letter.clone());
+    alpha_queue.push(dead("del_alpha"));
+    beta_queue.push(dead("del_beta"));
+    alpha_queue.push(dead("del_alpha_2"));
+
+    let alpha_ids: Vec<_> = DeadLetterQueue::load(alpha.dead_letter)
+        .entries()
+        .iter()
+        .map(|entry| entry.delivery.delivery_id.to_string())
+        .collect();
+    assert_eq!(alpha_ids, ["del_alpha", "del_alpha_2"]);
+    let beta_entries = DeadLetterQueue::load(beta.dead_letter);
+    assert_eq!(beta_entries.entries().len(), 1);
+    assert_eq!(beta_entries.entries()[0].delivery.delivery_id, "del_beta");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn paused_worker_holds_deliveries_until_resumed() {
+    let worker_name = "worker-paused";
+    let mut workers = make_worker_registry_with_worker(worker_name).await;
+    assert!(workers.set_paused(worker_name, true).expect("pause worker"));
+    assert!(!workers
+        .set_paused(worker_name, true)
+        .expect("pausing twice is a no-op"));
+    assert!(workers.is_paused(worker_name));
+    assert_eq!(workers.list()[0]["current_state"], "paused");
+
+    let delivery_id = DeliveryId::new("del_paused");
+    let mut pending_deliveries = HashMap::from([(
+        delivery_id.clone(),
+        PendingDelivery {
+            worker_name: WorkerName::from(worker_name),
+            delivery: RelayDelivery {
+                delivery_id: delivery_id.clone(),
+                event_id: EventId::new("evt_paused"),
+                workspace_id: None,
+                workspace_alias: None,
+                from: "Lead".to_string(),
+                target: MessageTarget::new(worker_name),
+                body: "held while paused".to_string(),
+                thread_id: None,
+                priority: None,
+                injection_mode: MessageInjectionMode::Wait,
+            },
+            attempts: 0,
+            next_retry_at: Instant::now(),
+            queued_at_ms: super::unix_timestamp_millis(),
+            last_error: None,
+        },
+    )]);
+
+    let outcome = retry_pending_delivery(
+        &delivery_id,
+        &mut workers,
+        &mut pending_deliveries,
+        Duration::from_millis(1),
+    )
+    .await
+    .expect("held delivery");
+    assert_eq!(outcome, DeliveryAttemptOutcome::Noop);
+    assert_eq!(pending_deliveries[&delivery_id].attempts, 0);
+
+    assert!(workers
+        .set_paused(worker_name, false)
+        .expect("resume worker"));
+    let outcome = retry_pending_delivery(
+        &delivery_id,
+        &mut workers,
+        &mut pending_deliveries,
+        Duration::from_millis(1),
+    )
+    .await
+    .expect("delivery after resume");
+    assert!(matches!(
+        outcome,
+        DeliveryAttemptOutcome::Attempted { attempts: 1, .. }
+    ));
+
+    let error = workers
+        .set_paused("ghost", true)
+        .expect_err("unknown worker");
+    assert!(error.to_string().starts_with("agent_not_found"));
+    cleanup_worker_registry(workers).await;
+}
+
+#[tokio::test]
+async fn non_local_workers_cannot_be_paused() {
+    let worker_name = "worker-remote";
+    let mut workers = make_worker_registry_with_worker(worker_name).await;
+    workers
+        .workers
+        .get_mut(worker_name)
+        .expect("present worker handle")
+        .spec
+        .runtime = AgentRuntime::Docker;
+
+    let error = workers
+        .set_paused(worker_name, true)
+        .expect_err("docker agent cannot be paused");
+    assert!(error.to_string().starts_with("unsupported_operation"));
+    assert!(!workers.is_paused(worker_name));
+    cleanup_worker_registry(workers).await;
+}
+
 #[tokio::test]
 async fn delivery_retry_transient_blip_emits_failed_event_for_present_worker() {
     let worker_name = "worker-blip";
@@ -542,6 +791,15 @@ async fn delivery_retry_transient_blip_emits_failed_event_for_present_worker() {
         pending_deliveries.is_empty(),
         "terminal failed deliveries are removed so they cannot stall silently"
     );
+    let DeliveryAttemptOutcome::Failed {
+        dead_letter: Some(dead_letter),
+        ..
+    } = &outcome
+    else {
+        panic!("exhausted deliveries must carry a dead letter: {outcome:?}");
+    };
+    assert_eq!(dead_letter.delivery.body, "transient auth blip");
+    assert_eq!(dead_letter.attempts, MAX_DELIVERY_RETRIES);
 
     let (sdk_out_tx, mut sdk_out_rx) = mpsc::channel(4);
     emit_delivery_attempt_outcome(&sdk_out_tx, &DeliveryId::new("del_blip"), true, outcome)
@@ -1131,6 +1389,20 @@ fn build_agent_state_transition_event_has_expected_shape() {
     assert!(no_reason.get("reason").is_none());
 }
 
+#[test]
+fn agent_released_event_lists_survivors_only_when_present() {
+    let clean = agent_released_event("worker-a", &[]);
+    assert_eq!(clean, json!({"kind": "agent_released", "name": "worker-a"}));
+
+    let leaked = agent_released_event("worker-a", &[4242]);
+    assert_eq!(leaked["survivors"], json!([4242]));
+    let parsed: BrokerEvent = serde_json::from_value(leaked).expect("typed agent_released");
+    assert!(matches!(
+        parsed,
+        BrokerEvent::AgentReleased { survivors, .. } if survivors == vec![4242]
+    ));
+}
+
 #[test]
 fn preregistration_error_message_dedupes_retry_after_for_rate_limit() {
     let error = RelaycastRegistrationError::RateLimited {
@@ -1207,20 +1479,491 @@ fn sender_is_dashboard_label_accepts_legacy_dashboard_senders() {
 }
 
 #[test]
-fn delivery_retry_interval_uses_default_and_env_override() {
+fn delivery_retry_interval_uses_default_config_and_env_override() {
     let _guard = env_test_lock().lock().expect("env test lock");
+    let mut config = BrokerConfigFile::default();
     std::env::remove_var("AGENT_RELAY_DELIVERY_RETRY_MS");
-    assert_eq!(delivery_retry_interval().as_millis(), 1_000);
+    assert_eq!(delivery_retry_interval(&config).as_millis(), 1_000);
+
+    config.delivery.retry_ms = Some(2_000);
+    assert_eq!(delivery_retry_interval(&config).as_millis(), 2_000);
 
     std::env::set_var("AGENT_RELAY_DELIVERY_RETRY_MS", "250");
-    assert_eq!(delivery_retry_interval().as_millis(), 250);
+    assert_eq!(delivery_retry_interval(&config).as_millis(), 250);
 
     std::env::set_var("AGENT_RELAY_DELIVERY_RETRY_MS", "1");
-    assert_eq!(delivery_retry_interval().as_millis(), 50);
+    assert_eq!(delivery_retry_interval(&config).as_millis(), 50);
 
     std::env::remove_var("AGENT_RELAY_DELIVERY_RETRY_MS");
 }
 
+#[test]
+fn orphan_audit_interval_uses_default_config_and_env_override() {
+    let _guard = env_test_lock().lock().expect("env test lock");
+    let mut config = BrokerConfigFile::default();
+    std::env::remove_var("AGENT_RELAY_ORPHAN_AUDIT_SECS");
+    assert_eq!(
+        orphan_audit_interval(&config),
+        Some(Duration::from_secs(60))
+    );
+
+    config.orphans.audit_secs = Some(0);
+    assert_eq!(orphan_audit_interval(&config), None);
+
+    std::env::set_var("AGENT_RELAY_ORPHAN_AUDIT_SECS", "5");
+    assert_eq!(orphan_audit_interval(&config), Some(Duration::from_secs(5)));
+
+    std::env::remove_var("AGENT_RELAY_ORPHAN_AUDIT_SECS");
+}
+
+#[test]
+fn pod_poll_interval_uses_default_config_and_env_override() {
+    let _guard = env_test_lock().lock().expect("env test lock");
+    let mut config = BrokerConfigFile::default();
+    std::env::remove_var("AGENT_RELAY_K8S_POLL_SECS");
+    assert_eq!(pod_poll_interval(&config), Duration::from_secs(5));
+
+    config.kubernetes.poll_secs = Some(10);
+    assert_eq!(pod_poll_interval(&config), Duration::from_secs(10));
+
+    std::env::set_var("AGENT_RELAY_K8S_POLL_SECS", "30");
+    assert_eq!(pod_poll_interval(&config), Duration::from_secs(30));
+
+    std::env::set_var("AGENT_RELAY_K8S_POLL_SECS", "0");
+    assert_eq!(pod_poll_interval(&config), Duration::from_secs(5));
+
+    std::env::remove_var("AGENT_RELAY_K8S_POLL_SECS");
+}
+
+#[test]
+fn config_backed_helpers_prefer_env_and_ignore_invalid_values() {
+    let _guard = env_test_lock().lock().expect("env test lock");
+    let mut config = BrokerConfigFile::default();
+    std::env::remove_var("AGENT_RELAY_API_PORT");
+    std::env::remove_var("AGENT_RELAY_LOG_RETENTION_DAYS");
+    assert_eq!(configured_api_port(&config), None);
+    assert_eq!(log_retention_days(&config), None);
+
+    config.api.port = Some(4000);
+    config.logs.retention_days = Some(3);
+    assert_eq!(configured_api_port(&config), Some(4000));
+    assert_eq!(log_retention_days(&config), Some(3));
+
+    std::env::set_var("AGENT_RELAY_API_PORT", "3889");
+    std::env::set_var("AGENT_RELAY_LOG_RETENTION_DAYS", "7");
+    assert_eq!(configured_api_port(&config), Some(3889));
+    assert_eq!(log_retention_days(&config), Some(7));
+
+    // A set but invalid env value is not replaced by the file's.
+    std::env::set_var("AGENT_RELAY_API_PORT", "99999");
+    std::env::set_var("AGENT_RELAY_LOG_RETENTION_DAYS", "0");
+    assert_eq!(configured_api_port(&config), None);
+    assert_eq!(log_retention_days(&config), None);
+
+    std::env::remove_var("AGENT_RELAY_API_PORT");
+    std::env::remove_var("AGENT_RELAY_LOG_RETENTION_DAYS");
+}
+
+#[test]
+fn drain_timeout_uses_default_config_and_env_override() {
+    let _guard = env_test_lock().lock().expect("env test lock");
+    let mut config = BrokerConfigFile::default();
+    std::env::remove_var("AGENT_RELAY_DRAIN_TIMEOUT_SECS");
+    assert_eq!(drain_timeout(&config), Duration::from_secs(30));
+
+    config.delivery.drain_timeout_secs = Some(10);
+    assert_eq!(drain_timeout(&config), Duration::from_secs(10));
+
+    std::env::set_var("AGENT_RELAY_DRAIN_TIMEOUT_SECS", "5");
+    assert_eq!(drain_timeout(&config), Duration::from_secs(5));
+
+    std::env::remove_var("AGENT_RELAY_DRAIN_TIMEOUT_SECS");
+}
+
+#[tokio::test]
+async fn drain_waits_for_grace_notice_ack_and_idle_workers() {
+    let mut registry = make_worker_registry_with_worker("alice").await;
+    let now = Instant::now();
+    let grace = Duration::from_secs(MIN_DRAIN_GRACE_SECS);
+    let mut drain = DrainState::new(now, Duration::from_secs(30));
+    drain.notice_sent(DeliveryId::new("del_notice"), WorkerName::from("alice"));
+
+    // Nothing pending, but the grace period has not passed.
+    registry.workers.get_mut("alice").unwrap().state = AgentWorkState::Idle;
+    assert_eq!(drain.outcome(now, 0, &registry), None);
+    // Past the grace period the unacked notice still holds the drain open.
+    assert_eq!(drain.outcome(now + grace, 0, &registry), None);
+
+    assert!(drain.notice_acked("del_notice"));
+    assert!(!drain.notice_acked("del_notice"));
+    registry.workers.get_mut("alice").unwrap().state = AgentWorkState::Working;
+    assert_eq!(drain.outcome(now + grace, 0, &registry), None);
+
+    registry.workers.get_mut("alice").unwrap().state = AgentWorkState::Idle;
+    assert_eq!(drain.outcome(now + grace, 2, &registry), None);
+    assert_eq!(drain.outcome(now + grace, 0, &registry), Some("settled"));
+
+    registry.workers.get_mut("alice").unwrap().state = AgentWorkState::Working;
+    assert_eq!(
+        drain.outcome(now + Duration::from_secs(30), 2, &registry),
+        Some("timeout")
+    );
+
+    cleanup_worker_registry(registry).await;
+}
+
+#[tokio::test]
+async fn drain_forgets_notices_for_exited_workers_and_caps_grace_at_timeout() {
+    let mut registry = make_worker_registry_with_worker("alice").await;
+    let now = Instant::now();
+    let mut drain = DrainState::new(now, Duration::from_secs(2));
+    drain.notice_sent(DeliveryId::new("del_gone"), WorkerName::from("bob"));
+    registry.workers.get_mut("alice").unwrap().state = AgentWorkState::Idle;
+
+    assert_eq!(
+        drain.outcome(now + Duration::from_secs(1), 0, &registry),
+        None
+    );
+    assert_eq!(
+        drain.outcome(now + Duration::from_secs(2), 0, &registry),
+        Some("timeout")
+    );
+    let mut drain = DrainState::new(now, Duration::from_secs(30));
+    drain.notice_sent(DeliveryId::new("del_gone"), WorkerName::from("bob"));
+    assert_eq!(
+        drain.outcome(
+            now + Duration::from_secs(MIN_DRAIN_GRACE_SECS),
+            0,
+            &registry
+        ),
+        Some("settled")
+    );
+
+    cleanup_worker_registry(registry).await;
+}
+
+#[tokio::test]
+async fn cpu_samples_are_taken_per_interval_and_not_by_metrics_reads() {
+    let mut registry = make_worker_registry_with_worker("alice").await;
+    let now = Instant::now();
+    let handle = registry.workers.get_mut("alice").unwrap();
+
+    sample_agent_cpu(handle, now);
+    let first = handle.cpu_sample.expect("first tick samples");
+    sample_agent_cpu(handle, now + Duration::from_secs(1));
+    assert_eq!(handle.cpu_sample, Some(first));
+
+    handle.cpu_percent = 42.5;
+    let _ = build_agent_metrics(handle);
+    assert_eq!(build_agent_metrics(handle).cpu_percent, 42.5);
+    assert_eq!(handle.cpu_sample, Some(first));
+
+    sample_agent_cpu(handle, now + Duration::from_secs(5));
+    assert_eq!(handle.cpu_sample.unwrap().at, now + Duration::from_secs(5));
+
+    cleanup_worker_registry(registry).await;
+}
+
+#[tokio::test]
+async fn spawn_queued_at_cap_runs_once_a_worker_is_released() {
+    let mut registry = make_worker_registry_with_worker("alice").await;
+    registry.max_concurrent = Some(1);
+    let node_spawn = |name: &str| NodeSpawn {
+        name: WorkerName::from(name),
+        cli: "claude".to_string(),
+        task: None,
+        channel: None,
+        model: None,
+        ws_value: json!({}),
+        workspace_id: WorkspaceId::new("ws_demo"),
+        invocation_id: format!("inv_{name}"),
+        session_ref: None,
+    };
+    let mut queue = VecDeque::from([QueuedSpawn::node(node_spawn("bob"))]);
+
+    // A spawn that skipped the queue is still refused by the registry.
+    let spec = build_http_api_spawn_spec(
+        WorkerName::from("carol"),
+        "claude".to_string(),
+        None,
+        None,
+        Vec::new(),
+        Vec::new(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        &BTreeMap::new(),
+    )
+    .expect("spec should build");
+    let error = registry
+        .spawn(spec, None, None, None, false, None, None)
+        .await
+        .expect_err("spawn past the cap must be refused");
+    assert!(error.to_string().starts_with("at_capacity"), "{error}");
+    assert!(next_queued_spawn(&mut queue, &registry).is_none());
+    assert_eq!(queue.len(), 1);
+
+    registry.release("alice").await.expect("release alice");
+    let next = next_queued_spawn(&mut queue, &registry).expect("bob runs once alice is gone");
+    assert_eq!(next.name(), "bob");
+    assert!(queue.is_empty());
+
+    cleanup_worker_registry(registry).await;
+}
+
+fn team_manifest(agents: Value) -> TeamManifest {
+    serde_json::from_value(json!({ "name": "backend", "agents": agents })).unwrap()
+}
+
+#[test]
+fn team_spawns_dependencies_first_and_rejects_cycles() {
+    let manifest = team_manifest(json!([
+        {"name": "Builder", "runtime": "pty", "depends_on": ["Architect"]},
+        {"name": "Reviewer", "runtime": "pty", "depends_on": ["Builder", "Architect"]},
+        {"name": "Architect", "runtime": "pty"},
+        {"name": "Scribe", "runtime": "pty"},
+    ]));
+    assert_eq!(team_spawn_order(&manifest).unwrap(), [2, 0, 1, 3]);
+
+    let cycle = team_manifest(json!([
+        {"name": "A", "runtime": "pty", "depends_on": ["B"]},
+        {"name": "B", "runtime": "pty", "depends_on": ["A"]},
+        {"name": "C", "runtime": "pty"},
+    ]));
+    let error = team_spawn_order(&cycle).unwrap_err();
+    assert!(error.contains("dependency cycle between A, B"), "{error}");
+
+    let unknown = team_manifest(json!([
+        {"name": "A", "runtime": "pty", "depends_on": ["Ghost"]},
+    ]));
+    let error = team_spawn_order(&unknown).unwrap_err();
+    assert!(error.starts_with("invalid_team_manifest"), "{error}");
+    assert!(error.contains("Ghost"), "{error}");
+
+    let duplicate = team_manifest(json!([
+        {"name": "A", "runtime": "pty"},
+        {"name": "A", "runtime": "pty"},
+    ]));
+    assert!(team_spawn_order(&duplicate)
+        .unwrap_err()
+        .contains("listed twice"));
+    assert!(team_spawn_order(&team_manifest(json!([]))).is_err());
+}
+
+#[test]
+fn pending_team_holds_tasks_until_dependencies_are_ready() {
+    let manifest = team_manifest(json!([
+        {"name": "Architect", "runtime": "pty", "task": "Design it."},
+        {"name": "Builder", "runtime": "pty", "task": "Build it.", "depends_on": ["Architect"]},
+    ]));
+    let order = team_spawn_order(&manifest).unwrap();
+    let mut team = PendingTeam::new(&manifest, &order, Instant::now());
+    let architect = WorkerName::from("Architect");
+    let builder = WorkerName::from("Builder");
+
+    assert!(team.mark_ready(&builder).is_empty());
+    assert!(!team.is_ready());
+    assert_eq!(
+        team.mark_ready(&architect),
+        [(builder.clone(), "Build it.".to_string())]
+    );
+    assert!(team.is_ready());
+    assert!(team.mark_ready(&architect).is_empty());
+    assert!(team.mark_ready(&WorkerName::from("Stranger")).is_empty());
+}
+
+#[test]
+fn pending_team_fails_when_a_member_exits_or_the_deadline_passes() {
+    let manifest: TeamManifest = serde_json::from_value(json!({
+        "name": "backend",
+        "ready_timeout_secs": 30,
+        "agents": [
+            {"name": "Architect", "runtime": "pty"},
+            {"name": "Builder", "runtime": "pty"},
+        ],
+    }))
+    .unwrap();
+    let now = Instant::now();
+    let mut team = PendingTeam::new(&manifest, &[0, 1], now);
+    team.mark_ready(&WorkerName::from("Architect"));
+
+    assert_eq!(team.failure(now, |_| true), None);
+    let error = team.failure(now, |name| name != "Builder").unwrap();
+    assert!(error.contains("'Builder' exited"), "{error}");
+    let error = team
+        .failure(now + Duration::from_secs(30), |_| true)
+        .unwrap();
+    assert!(error.contains("within 30s"), "{error}");
+    assert!(error.ends_with("still waiting on Builder"), "{error}");
+}
+
+#[test]
+fn spawn_waits_on_each_unready_dependency_once() {
+    let after: Vec<WorkerName> = ["Architect", "Builder", "Architect"]
+        .into_iter()
+        .map(WorkerName::from)
+        .collect();
+
+    assert_eq!(
+        unready_spawn_dependencies(&after, |_| false),
+        [WorkerName::from("Architect"), WorkerName::from("Builder")]
+    );
+    assert_eq!(
+        unready_spawn_dependencies(&after, |name| name == "Architect"),
+        [WorkerName::from("Builder")]
+    );
+    assert!(unready_spawn_dependencies(&after, |_| true).is_empty());
+    assert!(unready_spawn_dependencies(&[], |_| false).is_empty());
+}
+
+fn plan(steps: Value) -> Plan {
+    serde_json::from_value(json!({ "name": "release", "steps": steps })).unwrap()
+}
+
+#[test]
+fn plan_validation_rejects_unknown_steps_and_cycles() {
+    assert!(validate_plan(&plan(json!([
+        {"id": "notes", "agent": "Writer", "task": "Draft."},
+        {"id": "review", "agent": "Reviewer", "task": "Review.", "inputs_from": ["notes"]},
+    ])))
+    .is_ok());
+
+    let error = validate_plan(&plan(json!([
+        {"id": "review", "agent": "Reviewer", "task": "Review.", "depends_on": ["notes"]},
+    ])))
+    .unwrap_err();
+    assert!(error.starts_with("invalid_plan"), "{error}");
+    assert!(error.contains("'notes'"), "{error}");
+
+    let error = validate_plan(&plan(json!([
+        {"id": "a", "agent": "Writer", "task": "A.", "depends_on": ["b"]},
+        {"id": "b", "agent": "Writer", "task": "B.", "inputs_from": ["a"]},
+        {"id": "c", "agent": "Writer", "task": "C."},
+    ])))
+    .unwrap_err();
+    assert!(error.ends_with("cycle between a, b"), "{error}");
+
+    assert!(validate_plan(&plan(json!([
+        {"id": "a", "agent": "Writer", "task": "A."},
+        {"id": "a", "agent": "Reviewer", "task": "A again."},
+    ])))
+    .is_err());
+    assert!(validate_plan(&plan(json!([]))).is_err());
+}
+
+#[test]
+fn running_plan_passes_step_outputs_to_later_steps() {
+    let mut running = RunningPlan::new(
+        plan(json!([
+            {"id": "notes", "agent": "Writer", "task": "Draft the notes."},
+            {"id": "summary", "agent": "Writer", "task": "Summarize."},
+            {"id": "review", "agent": "Reviewer", "task": "Review.", "inputs_from": ["notes"]},
+        ])),
+        Instant::now(),
+    );
+    let writer = WorkerName::from("Writer");
+    let reviewer = WorkerName::from("Reviewer");
+
+    // One step per agent at a time; `review` waits on `notes`.
+    assert_eq!(running.startable_steps(|_| false), [0]);
+    assert_eq!(
+        running.start_step(0, "plan_1".to_string()),
+        "Draft the notes."
+    );
+    assert!(running.startable_steps(|_| false).is_empty());
+
+    // Idling before the delivery is acked does not finish the step.
+    running.record_output(&writer, "\x1b[1mv1.2\x1b[0m: faster spawns\n");
+    assert_eq!(running.finish_step(&writer, None), None);
+    assert!(!running.mark_acked(&writer, "plan_other"));
+    assert!(running.mark_acked(&writer, "plan_1"));
+    assert_eq!(
+        running.finish_step(&writer, None),
+        Some((0, json!("v1.2: faster spawns")))
+    );
+
+    assert_eq!(running.startable_steps(|_| false), [1, 2]);
+    assert_eq!(running.startable_steps(|agent| agent == "Writer"), [2]);
+    running.start_step(1, "plan_2".to_string());
+    assert_eq!(
+        running.start_step(2, "plan_3".to_string()),
+        "Review.\n\nOutput of step 'notes' (Writer):\nv1.2: faster spawns"
+    );
+    assert_eq!(running.step_for_event("plan_3"), Some(2));
+
+    // A submitted result finishes the step without waiting for the ack.
+    assert_eq!(
+        running.finish_step(&reviewer, Some(json!({"approved": true}))),
+        Some((2, json!({"approved": true})))
+    );
+    assert!(!running.is_complete());
+    running.mark_acked(&writer, "plan_2");
+    running.finish_step(&writer, None);
+    assert!(running.is_complete());
+    let results = running.results();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results["review"], json!({"approved": true}));
+}
+
+#[test]
+fn running_plan_fails_on_exit_dropped_delivery_or_deadline() {
+    let now = Instant::now();
+    let mut running = RunningPlan::new(
+        serde_json::from_value(json!({
+            "name": "release",
+            "timeout_secs": 60,
+            "steps": [
+                {"id": "notes", "agent": "Writer", "task": "Draft."},
+                {"id": "review", "agent": "Reviewer", "task": "Review.", "depends_on": ["notes"]},
+            ],
+        }))
+        .unwrap(),
+        now,
+    );
+    running.start_step(0, "plan_1".to_string());
+
+    assert_eq!(running.failure(now, |_| true, |_| true), None);
+    let (step, error) = running
+        .failure(now, |agent| agent != "Reviewer", |_| true)
+        .unwrap();
+    assert_eq!(step.as_deref(), Some("review"));
+    assert!(error.contains("'Reviewer'"), "{error}");
+    let (step, error) = running.failure(now, |_| true, |_| false).unwrap();
+    assert_eq!(step.as_deref(), Some("notes"));
+    assert!(error.contains("dropped"), "{error}");
+
+    running.mark_acked(&WorkerName::from("Writer"), "plan_1");
+    assert_eq!(running.failure(now, |_| true, |_| false), None);
+    let (step, error) = running
+        .failure(now + Duration::from_secs(60), |_| true, |_| true)
+        .unwrap();
+    assert_eq!(step.as_deref(), Some("notes"));
+    assert!(error.contains("within 60s"), "{error}");
+}
+
+#[test]
+fn select_orphans_skips_registered_self_and_inherited_names() {
+    let process = |pid: u32, marker: &str| MarkedProcess {
+        pid,
+        marker: marker.to_string(),
+        command: Some("node".to_string()),
+    };
+    let candidates = vec![
+        process(10, "Live"),
+        process(11, "Gone"),
+        process(12, "Parent"),
+        process(13, ""),
+        process(99, "Gone"),
+    ];
+
+    let orphans = select_orphans(candidates, |name| name == "Live", 99, Some("Parent"));
+
+    assert_eq!(orphans, vec![process(11, "Gone")]);
+}
+
 #[test]
 fn http_api_timeout_windows_use_default_and_env_override() {
     let _guard = env_test_lock().lock().expect("env test lock");
@@ -1427,6 +2170,7 @@ fn delivery_read_ack_classification_skips_synthetic_event_ids() {
         ("init_123", Some("initial_task_synthetic_event_id")),
         ("cont_load_123", Some("continuity_synthetic_event_id")),
         ("flush_123", Some("manual_flush_synthetic_event_id")),
+        ("drain_123", Some("drain_notice_synthetic_event_id")),
         ("msg_123", None),
         ("1780911342_317109", None),
     ];
@@ -2373,6 +3117,39 @@ fn continuity_dir_preserves_relative_paths() {
     );
 }
 
+#[test]
+fn continuity_block_renders_saved_context_ahead_of_task() {
+    let dir = tempfile::tempdir().expect("tempdir should create");
+    let state_path = dir.path().join("state.json");
+    assert_eq!(read_continuity_block(&state_path, "Worker"), None);
+
+    std::fs::create_dir_all(continuity_dir(&state_path)).expect("continuity dir");
+    std::fs::write(
+        continuity_dir(&state_path).join("Worker.json"),
+        json!({
+            "initial_task": "ship it",
+            "summary": "tests pass",
+            "message_history": [{"from": "Lead", "text": "status?"}],
+        })
+        .to_string(),
+    )
+    .expect("continuity file");
+
+    let block = read_continuity_block(&state_path, "Worker").expect("block");
+    assert_eq!(
+        block,
+        "## Continuity Context (from previous session as 'Worker')\n\
+         Previous task: ship it\n\
+         Session summary: tests pass\n\
+         Recent messages:\n  Lead: status?\n"
+    );
+    assert_eq!(
+        with_continuity_block(block.clone(), Some("next".to_string())),
+        format!("{block}\n\n## Current Task\nnext")
+    );
+    assert_eq!(with_continuity_block(block.clone(), None), block);
+}
+
 #[test]
 fn ephemeral_paths_are_unique_per_broker_instance() {
     let cwd = PathBuf::from("/tmp/agent-relay-test-project");
@@ -2400,6 +3177,7 @@ fn http_api_spawn_spec_defaults_to_pty_runtime() {
         Some("subagent".to_string()),
         None,
         None,
+        &BTreeMap::new(),
     )
     .expect("spec should build");
 
@@ -2409,6 +3187,144 @@ fn http_api_spawn_spec_defaults_to_pty_runtime() {
     assert_eq!(spec.model.as_deref(), Some("o3"));
 }
 
+#[test]
+fn http_api_spawn_spec_strips_at_prefix_from_team() {
+    let spec = build_http_api_spawn_spec(
+        WorkerName::from("worker-a"),
+        "codex".to_string(),
+        None,
+        None,
+        vec![],
+        vec![ChannelName::from("general")],
+        None,
+        Some(" @core".to_string()),
+        None,
+        None,
+        None,
+        None,
+        &BTreeMap::new(),
+    )
+    .expect("spec should build");
+
+    assert_eq!(spec.team.as_deref(), Some("core"));
+}
+
+#[test]
+fn team_publish_results_report_each_member() {
+    let results = team_publish_results(&[
+        ("alice".to_string(), Ok(())),
+        (
+            "bob".to_string(),
+            Err("Relaycast publish timed out after 5000ms".to_string()),
+        ),
+    ]);
+    assert_eq!(
+        results,
+        json!([
+            { "target": "alice", "published": true },
+            {
+                "target": "bob",
+                "published": false,
+                "error": "Relaycast publish timed out after 5000ms",
+            },
+        ])
+    );
+}
+
+#[test]
+fn http_api_spawn_spec_accepts_docker_transport() {
+    let spec = build_http_api_spawn_spec(
+        WorkerName::from("worker-a"),
+        "claude".to_string(),
+        Some("docker".to_string()),
+        None,
+        vec![],
+        vec![ChannelName::from("general")],
+        Some("/tmp/project".to_string()),
+        None,
+        None,
+        None,
+        None,
+        None,
+        &BTreeMap::new(),
+    )
+    .expect("docker spec should build");
+
+    assert!(matches!(spec.runtime, AgentRuntime::Docker));
+    assert!(!spec.runtime.is_local());
+    assert!(spec.provider.is_none());
+    assert_eq!(spec.cli.as_deref(), Some("claude"));
+    assert_eq!(runtime_label(&spec.runtime), "docker");
+}
+
+#[test]
+fn http_api_spawn_spec_accepts_kubernetes_transport() {
+    let spec = build_http_api_spawn_spec(
+        WorkerName::from("worker-a"),
+        "codex".to_string(),
+        Some("kubernetes".to_string()),
+        None,
+        vec![],
+        vec![ChannelName::from("general")],
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        &BTreeMap::new(),
+    )
+    .expect("kubernetes spec should build");
+
+    assert!(matches!(spec.runtime, AgentRuntime::Kubernetes));
+    assert!(!spec.runtime.is_local());
+    assert_eq!(spec.cli.as_deref(), Some("codex"));
+    assert_eq!(runtime_label(&spec.runtime), "kubernetes");
+}
+
+#[test]
+fn http_api_spawn_spec_parses_ssh_transport() {
+    let build = |transport: &str| {
+        build_http_api_spawn_spec(
+            WorkerName::from("worker-a"),
+            "claude".to_string(),
+            Some(transport.to_string()),
+            None,
+            vec![],
+            vec![ChannelName::from("general")],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            &BTreeMap::new(),
+        )
+    };
+
+    let spec = build("ssh://CI@Build-01.internal").expect("ssh spec should build");
+    assert_eq!(
+        spec.runtime,
+        AgentRuntime::Ssh {
+            host: "Build-01.internal".to_string(),
+            user: Some("CI".to_string()),
+        }
+    );
+    assert!(!spec.runtime.is_local());
+    assert_eq!(runtime_label(&spec.runtime), "ssh");
+    assert_eq!(
+        runtime_transport(&spec.runtime),
+        "ssh://CI@Build-01.internal"
+    );
+
+    let spec = build("SSH://build-01").expect("ssh spec without user should build");
+    assert_eq!(runtime_transport(&spec.runtime), "ssh://build-01");
+
+    for invalid in ["ssh", "ssh://", "ssh://ci@"] {
+        assert!(build(invalid).is_err(), "{invalid} should be rejected");
+    }
+}
+
 #[test]
 fn http_api_spawn_spec_uses_headless_runtime_for_supported_providers() {
     let spec = build_http_api_spawn_spec(
@@ -2424,6 +3340,7 @@ fn http_api_spawn_spec_uses_headless_runtime_for_supported_providers() {
         None,
         None,
         None,
+        &BTreeMap::new(),
     )
     .expect("headless spec should build");
 
@@ -2462,6 +3379,7 @@ fn http_api_spawn_spec_uses_headless_runtime_for_app_server_harness_config() {
         None,
         None,
         Some(harness_config),
+        &BTreeMap::new(),
     )
     .expect("headless app-server harness spec should build");
 
@@ -2490,6 +3408,7 @@ fn http_api_spawn_spec_rejects_unknown_headless_provider_without_harness_config(
         None,
         None,
         None,
+        &BTreeMap::new(),
     )
     .expect_err("custom headless provider without harness config should fail");
 
@@ -2503,14 +3422,18 @@ fn http_api_spawn_spec_rejects_unknown_headless_provider_without_harness_config(
 
 #[test]
 fn headless_provider_command_claude_places_flags_before_task() {
-    let (bin, args) = super::headless_provider_command(
-        &ProtocolHeadlessProvider::Claude,
-        "hello world",
-        &[
-            "--mcp-config".to_string(),
-            "{\"mcpServers\":{}}".to_string(),
-        ],
-    );
+    let invocation =
+        super::headless_provider_template(&ProtocolHeadlessProvider::Claude, &BTreeMap::new())
+            .unwrap()
+            .invocation(
+                "hello world",
+                &[
+                    "--mcp-config".to_string(),
+                    "{\"mcpServers\":{}}".to_string(),
+                ],
+            )
+            .unwrap();
+    let (bin, args) = (invocation.program, invocation.args);
 
     assert_eq!(bin, "claude");
     assert_eq!(args.last().map(String::as_str), Some("hello world"));
@@ -2520,12 +3443,39 @@ fn headless_provider_command_claude_places_flags_before_task() {
 }
 
 #[test]
-fn headless_provider_command_opencode_places_flags_before_task() {
-    let (bin, args) = super::headless_provider_command(
-        &ProtocolHeadlessProvider::Opencode,
-        "hello world",
-        &["--agent".to_string(), "agent-relay".to_string()],
+fn headless_claude_streams_json_in_one_session() {
+    let template =
+        super::headless_provider_template(&ProtocolHeadlessProvider::Claude, &BTreeMap::new())
+            .unwrap();
+    let mut parser = template.output_parser();
+    assert_eq!(
+        parser.push_line(
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"On it."}]}}"#
+        ),
+        Some("On it.".to_string())
     );
+    assert_eq!(
+        parser.push_line(r#"{"type":"result","subtype":"success","result":"On it."}"#),
+        None
+    );
+
+    let first = template.conversation_args("s-1", false);
+    let later = template.conversation_args("s-1", true);
+    assert_eq!(first, ["--session-id", "s-1"]);
+    assert_eq!(later, ["--resume", "s-1"]);
+}
+
+#[test]
+fn headless_provider_command_opencode_places_flags_before_task() {
+    let invocation =
+        super::headless_provider_template(&ProtocolHeadlessProvider::Opencode, &BTreeMap::new())
+            .unwrap()
+            .invocation(
+                "hello world",
+                &["--agent".to_string(), "agent-relay".to_string()],
+            )
+            .unwrap();
+    let (bin, args) = (invocation.program, invocation.args);
 
     assert_eq!(bin, "opencode");
     assert_eq!(args.first().map(String::as_str), Some("run"));
@@ -2535,11 +3485,96 @@ fn headless_provider_command_opencode_places_flags_before_task() {
     assert!(agent_pos < task_pos, "--agent must precede task");
 }
 
+#[test]
+fn headless_codex_and_gemini_run_their_print_modes() {
+    for (cli, provider) in [
+        ("codex", ProtocolHeadlessProvider::Codex),
+        ("gemini", ProtocolHeadlessProvider::Gemini),
+    ] {
+        let spec = build_http_api_spawn_spec(
+            WorkerName::from("worker-a"),
+            cli.to_string(),
+            Some("headless".to_string()),
+            None,
+            vec![],
+            vec![ChannelName::from("general")],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            &BTreeMap::new(),
+        )
+        .expect("headless spec should build");
+        assert_eq!(spec.provider.as_ref(), Some(&provider));
+    }
+
+    let mcp_args = vec![
+        "--config".to_string(),
+        "check_for_update_on_startup=false".to_string(),
+    ];
+    let codex =
+        super::headless_provider_template(&ProtocolHeadlessProvider::Codex, &BTreeMap::new())
+            .unwrap()
+            .invocation("-v means verbose", &mcp_args)
+            .unwrap();
+    assert_eq!(codex.program, "codex");
+    assert_eq!(codex.args.first().map(String::as_str), Some("exec"));
+    assert!(codex.args.ends_with(&mcp_args));
+    assert_eq!(codex.stdin.as_deref(), Some("-v means verbose"));
+
+    let gemini =
+        super::headless_provider_template(&ProtocolHeadlessProvider::Gemini, &BTreeMap::new())
+            .unwrap()
+            .invocation("hello world", &["--model".to_string(), "pro".to_string()])
+            .unwrap();
+    assert_eq!(gemini.program, "gemini");
+    assert_eq!(
+        gemini.args,
+        ["--yolo", "--prompt=hello world", "--model", "pro"]
+    );
+    assert_eq!(gemini.stdin, None);
+}
+
+#[test]
+fn http_api_spawn_spec_accepts_configured_headless_template() {
+    let templates: BTreeMap<String, HeadlessTemplate> = serde_json::from_value(
+        json!({ "aider": { "command": ["aider", "--message", "{prompt}"] } }),
+    )
+    .unwrap();
+    let spec = build_http_api_spawn_spec(
+        WorkerName::from("worker-a"),
+        "aider".to_string(),
+        Some("headless".to_string()),
+        None,
+        vec![],
+        vec![ChannelName::from("general")],
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        &templates,
+    );
+
+    let spec = spec.expect("configured template should be a headless provider");
+    assert_eq!(
+        spec.provider,
+        Some(ProtocolHeadlessProvider::Template("aider".to_string()))
+    );
+    assert_eq!(
+        serde_json::to_value(&spec.provider).unwrap(),
+        json!("aider")
+    );
+}
+
 #[test]
 fn http_api_spawn_spec_rejects_unknown_headless_providers() {
     let error = build_http_api_spawn_spec(
         WorkerName::from("worker-a"),
-        "codex".to_string(),
+        "droid".to_string(),
         Some("headless".to_string()),
         None,
         vec![],
@@ -2550,6 +3585,7 @@ fn http_api_spawn_spec_rejects_unknown_headless_providers() {
         None,
         None,
         None,
+        &BTreeMap::new(),
     )
     .expect_err("unsupported headless provider should fail");
 
@@ -2807,3 +3843,53 @@ fn default_observer_token_scopes_are_read_only_and_exclude_unneeded_scopes() {
         "default observer token scopes must be exactly the minimal read-only set"
     );
 }
+
+#[test]
+fn read_log_tail_returns_last_complete_lines_with_offsets() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("worker.log");
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+    let tail = read_log_tail(&path, 2).unwrap();
+    assert_eq!(tail.content, "two\nthree\n");
+    assert_eq!(tail.offset, 4);
+    assert_eq!(tail.next_offset, 14);
+    assert_eq!(tail.size, 14);
+
+    let all = read_log_tail(&path, 10).unwrap();
+    assert_eq!(all.content, "one\ntwo\nthree\n");
+    assert_eq!(all.offset, 0);
+
+    let none = read_log_tail(&path, 0).unwrap();
+    assert!(none.content.is_empty());
+    assert_eq!(none.offset, 14);
+}
+
+#[test]
+fn read_log_range_clamps_to_file_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("worker.log");
+    std::fs::write(&path, "one\ntwo\n").unwrap();
+
+    let slice = read_log_range(&path, 4, 2).unwrap();
+    assert_eq!(slice.content, "tw");
+    assert_eq!((slice.offset, slice.next_offset), (4, 6));
+
+    let past_end = read_log_range(&path, 100, 10).unwrap();
+    assert!(past_end.content.is_empty());
+    assert_eq!((past_end.offset, past_end.next_offset), (8, 8));
+
+    assert_eq!(
+        read_log_range(&dir.path().join("missing.log"), 0, 10)
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[test]
+fn memory_bytes_for_pid_reads_own_resident_size() {
+    assert!(super::memory_bytes_for_pid(std::process::id()) > 0);
+    assert_eq!(super::memory_bytes_for_pid(u32::MAX), 0);
+}
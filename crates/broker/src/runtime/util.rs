@@ -114,7 +114,7 @@ pub(crate) fn broker_log_dir() -> Option<PathBuf> {
 
 /// Filename prefix used for this broker's rolling log file.
 ///
-/// `tracing_appender::rolling::daily` appends a `.YYYY-MM-DD` suffix, so files
+/// The daily rolling appender appends a `.YYYY-MM-DD` suffix, so files
 /// land as `{broker_id}.log.YYYY-MM-DD` in the log directory.
 pub(crate) fn broker_log_file_prefix(broker_id: &str) -> String {
     format!("{}.log", sanitize_filename_segment(broker_id))
@@ -140,8 +140,10 @@ pub(crate) fn unix_timestamp_secs() -> u64 {
 /// Initialise the global tracing subscriber for this broker process.
 ///
 /// Destination is controlled by `AGENT_RELAY_BROKER_LOG`; level filter by
-/// `RUST_LOG`. See [`tracing_destination`] for accepted env values.
-pub(crate) fn init_tracing(broker_id: &str) {
+/// `RUST_LOG`; file retention by [`log_retention_days`]. See
+/// [`tracing_destination`] for accepted env values. Builds with the `otel`
+/// feature also export spans when an OTLP endpoint is set.
+pub(crate) fn init_tracing(broker_id: &str, config: &BrokerConfigFile) {
     let rust_log = std::env::var("RUST_LOG").ok();
     let broker_log = std::env::var(BROKER_LOG_ENV).ok();
     let destination = tracing_destination(broker_log.as_deref());
@@ -161,8 +163,15 @@ pub(crate) fn init_tracing(broker_id: &str) {
             if std::fs::create_dir_all(&log_dir).is_err() {
                 return;
             }
-            let appender =
-                tracing_appender::rolling::daily(&log_dir, broker_log_file_prefix(broker_id));
+            let mut builder = tracing_appender::rolling::Builder::new()
+                .rotation(tracing_appender::rolling::Rotation::DAILY)
+                .filename_prefix(broker_log_file_prefix(broker_id));
+            if let Some(days) = log_retention_days(config) {
+                builder = builder.max_log_files(days);
+            }
+            let Ok(appender) = builder.build(&log_dir) else {
+                return;
+            };
             tracing_appender::non_blocking(appender)
         }
         TracingDestination::Off => unreachable!(),
@@ -176,6 +185,11 @@ pub(crate) fn init_tracing(broker_id: &str) {
         .with_target(true)
         .with_writer(writer)
         .finish();
+    #[cfg(feature = "otel")]
+    let subscriber = {
+        use tracing_subscriber::layer::SubscriberExt;
+        subscriber.with(crate::otel::otlp_layer())
+    };
     if tracing::subscriber::set_global_default(subscriber).is_ok() {
         let _ = TRACING_GUARD.set(guard);
     }
@@ -190,15 +204,17 @@ pub(crate) fn channels_from_csv(raw: &str) -> Vec<String> {
 }
 
 /// Default channels for freshly spawned agents.
-/// Reads RELAY_DEFAULT_CHANNELS (comma-separated) or falls back to the
-/// broker's default channels: vec!["general", "engineering"] — both created
-/// at startup by ensure_default_channels().
-pub(crate) fn default_spawn_channels() -> Vec<ChannelName> {
-    if let Ok(raw) = std::env::var("RELAY_DEFAULT_CHANNELS") {
-        let parsed = channels_from_csv(&raw);
-        if !parsed.is_empty() {
-            return parsed.into_iter().map(ChannelName::from).collect();
-        }
+/// Reads RELAY_DEFAULT_CHANNELS (comma-separated), then `[agents]
+/// default_channels`, or falls back to the broker's default channels:
+/// vec!["general", "engineering"] — both created at startup by
+/// ensure_default_channels().
+pub(crate) fn default_spawn_channels(config: &BrokerConfigFile) -> Vec<ChannelName> {
+    let configured = match std::env::var("RELAY_DEFAULT_CHANNELS") {
+        Ok(raw) => channels_from_csv(&raw),
+        Err(_) => config.agents.default_channels.clone().unwrap_or_default(),
+    };
+    if !configured.is_empty() {
+        return configured.into_iter().map(ChannelName::from).collect();
     }
     // channels: ["general", "engineering"] (must match ensure_default_channels)
     vec![ChannelName::new("general"), ChannelName::new("engineering")]
@@ -229,14 +245,52 @@ pub(crate) fn env_flag_enabled(name: &str) -> bool {
         .is_some_and(|value| env_flag_value_enabled(&value))
 }
 
-pub(crate) fn delivery_retry_interval() -> Duration {
-    let ms = std::env::var("AGENT_RELAY_DELIVERY_RETRY_MS")
-        .ok()
-        .and_then(|raw| raw.trim().parse::<u64>().ok())
+/// [`env_flag_enabled`] when `name` is set, else the config file's value.
+pub(crate) fn env_flag_or(name: &str, file: Option<bool>) -> bool {
+    match std::env::var(name) {
+        Ok(value) => env_flag_value_enabled(&value),
+        Err(_) => file.unwrap_or(false),
+    }
+}
+
+pub(crate) fn delivery_retry_interval(config: &BrokerConfigFile) -> Duration {
+    let ms = env_or("AGENT_RELAY_DELIVERY_RETRY_MS", config.delivery.retry_ms)
         .unwrap_or(DEFAULT_DELIVERY_RETRY_MS);
     Duration::from_millis(ms.max(50))
 }
 
+/// Interval between orphan process audits. `AGENT_RELAY_ORPHAN_AUDIT_SECS=0`
+/// disables the audit.
+pub(crate) fn orphan_audit_interval(config: &BrokerConfigFile) -> Option<Duration> {
+    let secs = env_or("AGENT_RELAY_ORPHAN_AUDIT_SECS", config.orphans.audit_secs)
+        .unwrap_or(DEFAULT_ORPHAN_AUDIT_SECS);
+    (secs > 0).then_some(Duration::from_secs(secs))
+}
+
+/// HTTP API port from `AGENT_RELAY_API_PORT` or `[api] port`, used when
+/// `--api-port` is not passed.
+pub(crate) fn configured_api_port(config: &BrokerConfigFile) -> Option<u16> {
+    env_or("AGENT_RELAY_API_PORT", config.api.port)
+}
+
+/// Daily broker log files kept by [`init_tracing`], from
+/// `AGENT_RELAY_LOG_RETENTION_DAYS` or `[logs] retention_days`. Unset or `0`
+/// keeps every file.
+pub(crate) fn log_retention_days(config: &BrokerConfigFile) -> Option<usize> {
+    env_or("AGENT_RELAY_LOG_RETENTION_DAYS", config.logs.retention_days).filter(|days| *days > 0)
+}
+
+/// How long a drain waits for pending deliveries before the broker exits
+/// anyway. Remaining deliveries are persisted for the next broker.
+pub(crate) fn drain_timeout(config: &BrokerConfigFile) -> Duration {
+    let secs = env_or(
+        "AGENT_RELAY_DRAIN_TIMEOUT_SECS",
+        config.delivery.drain_timeout_secs,
+    )
+    .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
 // No longer called from production code — the HTTP/sidecar send path
 // (runtime/api.rs) no longer attempts direct local delivery, so there's
 // nothing left to bound with a "local delivery" timeout. Kept (with its
@@ -287,6 +341,16 @@ pub(crate) fn normalize_channel(raw: &str) -> String {
     }
 }
 
+/// `agent_released` event payload. `survivors` lists pids that outlived
+/// SIGKILL and is omitted when the process tree went down cleanly.
+pub(crate) fn agent_released_event(name: &str, survivors: &[u32]) -> Value {
+    let mut event = json!({"kind": "agent_released", "name": name});
+    if !survivors.is_empty() {
+        event["survivors"] = json!(survivors);
+    }
+    event
+}
+
 pub(crate) fn build_agent_state_transition_event(
     name: &str,
     state: &str,
@@ -44,14 +44,31 @@ impl BrokerRuntime {
                             let pending_for_confirmation = if let Ok(ack) =
                                 serde_json::from_value::<DeliveryAckPayload>(payload.clone())
                             {
-                                let pending = clear_pending_delivery_if_event_matches(
-                                    pending_deliveries,
-                                    &ack.delivery_id,
-                                    Some(&ack.event_id),
-                                    &name,
-                                    "delivery_ack",
+                                // `latency_ms` covers queue_and_try_delivery
+                                // through the worker's ack.
+                                let span = tracing::info_span!(
+                                    target: "agent_relay::delivery",
+                                    "relay.delivery_ack",
+                                    event_id = %ack.event_id,
+                                    delivery_id = %ack.delivery_id,
+                                    worker = %name,
+                                    latency_ms = tracing::field::Empty,
                                 );
-                                if pending.is_some() {
+                                let pending = span.in_scope(|| {
+                                    clear_pending_delivery_if_event_matches(
+                                        pending_deliveries,
+                                        &ack.delivery_id,
+                                        Some(&ack.event_id),
+                                        &name,
+                                        "delivery_ack",
+                                    )
+                                });
+                                if let Some(pending) = &pending {
+                                    span.record(
+                                        "latency_ms",
+                                        unix_timestamp_millis()
+                                            .saturating_sub(pending.queued_at_ms),
+                                    );
                                     terminal_failed_deliveries.remove(&ack.delivery_id);
                                 }
                                 pending
@@ -102,6 +119,21 @@ impl BrokerRuntime {
                                     &read_ack_event_id,
                                 );
                             }
+                            // An acked drain notice means the agent is now
+                            // acting on it; the drain waits for it to go idle.
+                            if self
+                                .drain
+                                .as_mut()
+                                .is_some_and(|drain| drain.notice_acked(delivery_id))
+                            {
+                                if let Some(handle) = self.workers.workers.get_mut(&name) {
+                                    handle.state = AgentWorkState::Working;
+                                }
+                            }
+                            if let Some(event_id) = payload.get("event_id").and_then(Value::as_str)
+                            {
+                                self.handle_plan_delivery_ack(&name, event_id).await;
+                            }
                         }
                     } else if msg_type == "delivery_queued" || msg_type == "delivery_injected" {
                         if let Some(payload) = value.get("payload") {
@@ -208,6 +240,19 @@ impl BrokerRuntime {
                         }
                     } else if msg_type == "delivery_active" {
                         if let Some(payload) = value.get("payload") {
+                            // A worker still working on a delivery (a headless
+                            // CLI mid-response) is not retried.
+                            let delivery_id = payload
+                                .get("delivery_id")
+                                .and_then(Value::as_str)
+                                .unwrap_or("");
+                            if let Some(pending) = pending_deliveries.get_mut(delivery_id) {
+                                pending.next_retry_at = Instant::now()
+                                    + std::cmp::max(
+                                        delivery_retry_interval,
+                                        crate::broker::delivery_verification::VERIFICATION_WINDOW,
+                                    );
+                            }
                             if let Some(handle) = workers.workers.get_mut(&name) {
                                 handle.last_activity_at = Instant::now();
                                 handle.state = AgentWorkState::Working;
@@ -286,6 +331,7 @@ impl BrokerRuntime {
                                 )
                                 .await;
                             }
+                            self.handle_plan_delivery_failed(event_id, reason).await;
                         }
                     } else if msg_type == "worker_error" {
                         let _ = send_event(
@@ -324,6 +370,20 @@ impl BrokerRuntime {
                         if let Some(handle) = workers.workers.get_mut(&name) {
                             handle.last_activity_at = Instant::now();
                             handle.state = AgentWorkState::Working;
+                            let payload = value.get("payload");
+                            if let Some(chunk) =
+                                payload.and_then(|p| p.get("chunk")).and_then(Value::as_str)
+                            {
+                                let stream = payload
+                                    .and_then(|p| p.get("stream"))
+                                    .and_then(Value::as_str)
+                                    .unwrap_or("stdout");
+                                handle.scrollback.push_chunk(
+                                    stream,
+                                    chunk,
+                                    unix_timestamp_millis(),
+                                );
+                            }
                         }
                         let _ = send_event(sdk_out_tx, json!({
                                         "kind": "worker_stream",
@@ -331,6 +391,13 @@ impl BrokerRuntime {
                                         "stream": value.get("payload").and_then(|p| p.get("stream")).cloned().unwrap_or(Value::String("stdout".to_string())),
                                         "chunk": value.get("payload").and_then(|p| p.get("chunk")).cloned().unwrap_or(Value::String(String::new())),
                                     })).await;
+                        if let Some(chunk) = value
+                            .get("payload")
+                            .and_then(|p| p.get("chunk"))
+                            .and_then(Value::as_str)
+                        {
+                            self.handle_plan_output(&name, chunk);
+                        }
                     } else if msg_type == "worker_ready" {
                         if let Some(task_text) = workers.initial_tasks.remove(&name) {
                             let event_id = format!("init_{}", Uuid::new_v4().simple());
@@ -369,6 +436,7 @@ impl BrokerRuntime {
                             .workers
                             .get_mut(&name)
                             .map(|h| {
+                                h.ready = true;
                                 if let Some(pid) = payload_pid {
                                     h.harness_pid = Some(pid);
                                 }
@@ -404,6 +472,7 @@ impl BrokerRuntime {
                             }),
                         )
                         .await;
+                        self.handle_team_member_ready(&name).await;
                     } else if msg_type == "agent_idle" {
                         let idle_secs = value
                             .get("payload")
@@ -432,6 +501,7 @@ impl BrokerRuntime {
                             Some("idle_threshold"),
                         )
                         .await;
+                        self.handle_plan_step_done(&name, None).await;
                     } else if msg_type == "agent_blocked_on_send" {
                         let blocked_secs = value
                             .get("payload")
@@ -691,6 +761,18 @@ impl BrokerRuntime {
                     }
                 }
             }
+            WorkerEvent::RemoteSetup { name, result } => {
+                // The persisted pid was unknown while the setup ran.
+                if let Some(pid) = workers.finish_start(&name, result).await {
+                    if let Some(agent) = state.agents.get_mut(&name) {
+                        agent.pid = Some(pid);
+                        if paths.persist {
+                            let _ = state.save(&paths.state);
+                        }
+                    }
+                }
+            }
+            WorkerEvent::PodStatuses(result) => self.handle_pod_statuses(result).await,
         }
     }
 }
@@ -0,0 +1,189 @@
+//! Bounded per-worker output scrollback.
+//!
+//! `worker_stream` chunks are reassembled into lines, stripped of ANSI escape
+//! sequences, and kept in a ring so `GET /api/agents/{name}/output` can show
+//! recent agent activity without tailing the worker log file. Every line gets
+//! a monotonic `seq`, which clients pass back as `?since=` to poll for new
+//! output only.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::{
+    broker_config::{env_or, AgentsSection},
+    util::ansi::strip_ansi,
+};
+
+/// Default number of lines retained per worker.
+pub(crate) const DEFAULT_SCROLLBACK_LINES: usize = 1000;
+/// Lines returned when the caller does not pass `lines`.
+pub(crate) const DEFAULT_OUTPUT_PAGE_LINES: usize = 100;
+/// A partial line longer than this is flushed as-is, so a worker that never
+/// prints a newline (progress bars, full-screen TUIs) cannot grow the buffer
+/// without bound.
+const MAX_PARTIAL_LINE_BYTES: usize = 16 * 1024;
+
+/// Per-worker capacity from `AGENT_RELAY_SCROLLBACK_LINES` or `[agents]
+/// scrollback_lines` (`0` disables).
+pub(crate) fn scrollback_lines(config: &AgentsSection) -> usize {
+    env_or("AGENT_RELAY_SCROLLBACK_LINES", config.scrollback_lines)
+        .unwrap_or(DEFAULT_SCROLLBACK_LINES)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct ScrollbackLine {
+    pub(crate) seq: u64,
+    pub(crate) stream: String,
+    pub(crate) text: String,
+    /// Unix epoch milliseconds when the line was completed.
+    pub(crate) at_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct ScrollbackPage {
+    pub(crate) lines: Vec<ScrollbackLine>,
+    /// Cursor to pass as `since` on the next poll.
+    pub(crate) next_since: u64,
+    /// True when some lines after `since` are missing from this page, either
+    /// evicted from the ring or cut by the `lines` limit.
+    pub(crate) truncated: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct OutputScrollback {
+    capacity: usize,
+    lines: VecDeque<ScrollbackLine>,
+    /// Unterminated tail per stream, kept raw until its newline arrives so
+    /// escape sequences split across chunks are stripped correctly.
+    partial: HashMap<String, String>,
+    last_seq: u64,
+}
+
+impl OutputScrollback {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: VecDeque::new(),
+            partial: HashMap::new(),
+            last_seq: 0,
+        }
+    }
+
+    pub(crate) fn push_chunk(&mut self, stream: &str, chunk: &str, at_ms: u64) {
+        if self.capacity == 0 || chunk.is_empty() {
+            return;
+        }
+        let mut pending = self.partial.remove(stream).unwrap_or_default();
+        pending.push_str(chunk);
+
+        let mut rest = pending.as_str();
+        while let Some(newline) = rest.find('\n') {
+            self.push_line(stream, &rest[..newline], at_ms);
+            rest = &rest[newline + 1..];
+        }
+        if rest.len() > MAX_PARTIAL_LINE_BYTES {
+            self.push_line(stream, rest, at_ms);
+        } else if !rest.is_empty() {
+            self.partial.insert(stream.to_string(), rest.to_string());
+        }
+    }
+
+    fn push_line(&mut self, stream: &str, raw: &str, at_ms: u64) {
+        // A carriage return redraws the line in place (spinners, progress
+        // bars); only the final rendering is worth keeping.
+        let raw = raw.trim_end_matches('\r');
+        let raw = raw.rsplit('\r').next().unwrap_or(raw);
+        let text = strip_ansi(raw);
+        self.last_seq += 1;
+        self.lines.push_back(ScrollbackLine {
+            seq: self.last_seq,
+            stream: stream.to_string(),
+            text,
+            at_ms,
+        });
+        while self.lines.len() > self.capacity {
+            self.lines.pop_front();
+        }
+    }
+
+    /// The last `lines` lines with `seq > since`, oldest first.
+    pub(crate) fn page(&self, lines: Option<usize>, since: Option<u64>) -> ScrollbackPage {
+        let limit = lines
+            .unwrap_or(DEFAULT_OUTPUT_PAGE_LINES)
+            .min(self.capacity.max(1));
+        let since = since.unwrap_or(0);
+        let oldest_retained = self.lines.front().map(|line| line.seq);
+        let newer = self.lines.iter().filter(|line| line.seq > since);
+        let matched = newer.clone().count();
+        let lines: Vec<ScrollbackLine> =
+            newer.skip(matched.saturating_sub(limit)).cloned().collect();
+        ScrollbackPage {
+            lines,
+            next_since: self.last_seq.max(since),
+            truncated: oldest_retained.is_some_and(|oldest| oldest > since + 1) || matched > limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(page: &ScrollbackPage) -> Vec<&str> {
+        page.lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn chunks_are_joined_into_clean_lines() {
+        let mut scrollback = OutputScrollback::new(10);
+        scrollback.push_chunk("stdout", "\x1b[32mhel", 1);
+        scrollback.push_chunk("stdout", "lo\x1b[0m\r\nwor", 2);
+        scrollback.push_chunk("stderr", "oops\n", 3);
+        scrollback.push_chunk("stdout", "king 10%\rworking 100%\n", 4);
+
+        let page = scrollback.page(None, None);
+        assert_eq!(texts(&page), vec!["hello", "oops", "working 100%"]);
+        assert_eq!(page.lines[1].stream, "stderr");
+        assert_eq!(page.lines[2].at_ms, 4);
+        assert_eq!(page.next_since, 3);
+        assert!(!page.truncated);
+    }
+
+    #[test]
+    fn ring_evicts_oldest_and_since_reports_gaps() {
+        let mut scrollback = OutputScrollback::new(3);
+        for n in 1..=5 {
+            scrollback.push_chunk("stdout", &format!("line {n}\n"), n);
+        }
+
+        let page = scrollback.page(None, None);
+        assert_eq!(texts(&page), vec!["line 3", "line 4", "line 5"]);
+        assert!(page.truncated, "lines 1-2 were evicted");
+
+        let page = scrollback.page(None, Some(3));
+        assert_eq!(texts(&page), vec!["line 4", "line 5"]);
+        assert!(!page.truncated);
+
+        let page = scrollback.page(Some(1), Some(3));
+        assert_eq!(texts(&page), vec!["line 5"]);
+        assert!(page.truncated);
+
+        let page = scrollback.page(None, Some(5));
+        assert!(page.lines.is_empty());
+        assert_eq!(page.next_since, 5);
+    }
+
+    #[test]
+    fn oversized_partial_lines_are_flushed_and_zero_capacity_disables() {
+        let mut scrollback = OutputScrollback::new(2);
+        scrollback.push_chunk("stdout", &"x".repeat(MAX_PARTIAL_LINE_BYTES + 1), 1);
+        assert_eq!(scrollback.page(None, None).lines.len(), 1);
+
+        let mut disabled = OutputScrollback::new(0);
+        disabled.push_chunk("stdout", "ignored\n", 1);
+        let page = disabled.page(None, None);
+        assert!(page.lines.is_empty());
+        assert_eq!(page.next_since, 0);
+    }
+}
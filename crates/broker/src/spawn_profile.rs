@@ -0,0 +1,162 @@
+//! Named spawn profiles.
+//!
+//! A profile bundles the parts of a spawn request that orchestrators repeat
+//! for every agent of a kind. Spawns name one with `"profile": "reviewer"`
+//! and fill in the rest. Profiles come from `AGENT_RELAY_SPAWN_PROFILES` (a
+//! JSON object keyed by profile name), or `[profiles.<name>]` in the broker
+//! config:
+//!
+//! ```toml
+//! [profiles.reviewer]
+//! cli = "claude"
+//! model = "opus"
+//! args = ["--append-system-prompt", "Review only; do not edit files."]
+//! channels = ["reviews"]
+//! idle_threshold_secs = 120
+//! restart_policy = { max_restarts = 2 }
+//! ```
+//!
+//! Whatever the request sets wins. The exception is `args`: the request's
+//! follow the profile's, so a spawn can add flags without restating them.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{ids::ChannelName, protocol::AgentSpec, supervisor::RestartPolicy};
+
+pub(crate) const SPAWN_PROFILES_ENV: &str = "AGENT_RELAY_SPAWN_PROFILES";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SpawnProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cli: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) model: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) args: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) channels: Vec<ChannelName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) idle_threshold_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) restart_policy: Option<RestartPolicy>,
+}
+
+impl SpawnProfile {
+    /// The profile's args followed by the request's.
+    pub(crate) fn args_with(&self, request_args: Vec<String>) -> Vec<String> {
+        self.args.iter().cloned().chain(request_args).collect()
+    }
+
+    /// The request's channels, or the profile's when it named none.
+    pub(crate) fn channels_or(&self, request_channels: Vec<ChannelName>) -> Vec<ChannelName> {
+        if request_channels.is_empty() {
+            self.channels.clone()
+        } else {
+            request_channels
+        }
+    }
+
+    /// Fill in what an SDK `spawn_agent` spec left unset. The idle threshold
+    /// is not part of an [`AgentSpec`]; callers read it from the profile.
+    pub(crate) fn apply_to_spec(&self, spec: &mut AgentSpec) {
+        if spec.cli.is_none() && spec.provider.is_none() {
+            spec.cli = self.cli.clone();
+        }
+        if spec.model.is_none() {
+            spec.model = self.model.clone();
+        }
+        spec.args = self.args_with(std::mem::take(&mut spec.args));
+        spec.channels = self.channels_or(std::mem::take(&mut spec.channels));
+        if spec.restart_policy.is_none() {
+            spec.restart_policy = self.restart_policy.clone();
+        }
+    }
+}
+
+/// Profiles from [`SPAWN_PROFILES_ENV`].
+pub(crate) fn spawn_profiles_from_env() -> Result<BTreeMap<String, SpawnProfile>> {
+    match std::env::var(SPAWN_PROFILES_ENV) {
+        Ok(raw) if !raw.trim().is_empty() => {
+            serde_json::from_str(&raw).with_context(|| format!("invalid {SPAWN_PROFILES_ENV}"))
+        }
+        _ => Ok(BTreeMap::new()),
+    }
+}
+
+/// The profile named `name` in `profiles`; an unknown name is an error.
+pub(crate) fn spawn_profile(
+    profiles: &BTreeMap<String, SpawnProfile>,
+    name: &str,
+) -> Result<SpawnProfile> {
+    profiles
+        .get(name.trim())
+        .cloned()
+        .with_context(|| format!("unknown spawn profile '{}'", name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn request_args_follow_profile_args_and_channels_fall_back() {
+        let profile: SpawnProfile = serde_json::from_value(json!({
+            "cli": "claude",
+            "args": ["--append-system-prompt", "review only"],
+            "channels": ["reviews"],
+            "restart_policy": { "max_restarts": 2 },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            profile.args_with(vec!["--verbose".to_string()]),
+            ["--append-system-prompt", "review only", "--verbose"]
+        );
+        assert_eq!(
+            profile.channels_or(Vec::new()),
+            [ChannelName::from("reviews")]
+        );
+        assert_eq!(
+            profile.channels_or(vec![ChannelName::from("general")]),
+            [ChannelName::from("general")]
+        );
+        let policy = profile.restart_policy.unwrap();
+        assert_eq!(policy.max_restarts, 2);
+        assert!(policy.enabled);
+    }
+
+    #[test]
+    fn profile_fills_sdk_spawn_spec() {
+        let profile: SpawnProfile = serde_json::from_value(json!({
+            "cli": "codex",
+            "model": "o3",
+            "channels": ["reviews"],
+        }))
+        .unwrap();
+        let mut spec: AgentSpec = serde_json::from_value(json!({
+            "name": "Reviewer1",
+            "runtime": "pty",
+            "model": "gpt-5",
+        }))
+        .unwrap();
+
+        profile.apply_to_spec(&mut spec);
+        assert_eq!(spec.cli.as_deref(), Some("codex"));
+        assert_eq!(spec.model.as_deref(), Some("gpt-5"));
+        assert_eq!(spec.channels, [ChannelName::from("reviews")]);
+        assert_eq!(spec.restart_policy, None);
+    }
+
+    #[test]
+    fn unknown_profile_keys_are_rejected() {
+        let error =
+            serde_json::from_value::<SpawnProfile>(json!({ "command": "claude" })).unwrap_err();
+        assert!(error.to_string().contains("command"), "{error}");
+    }
+}
@@ -11,7 +11,7 @@ use crate::cli::command_parse::parse_cli_command;
 
 #[cfg(unix)]
 use nix::{
-    sys::signal::{kill, Signal},
+    sys::signal::{kill, killpg, Signal},
     unistd::Pid,
 };
 
@@ -183,36 +183,183 @@ impl Spawner {
     }
 }
 
+/// Grace used after SIGKILL before giving up on a process tree.
+pub const DEFAULT_KILL_GRACE: Duration = Duration::from_secs(1);
+
+const TERMINATION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Signal schedule used when tearing down a child and its process group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KillEscalation {
+    /// How long to wait after SIGTERM before escalating to SIGKILL.
+    pub term_grace: Duration,
+    /// How long to wait after SIGKILL before reporting survivors.
+    pub kill_grace: Duration,
+}
+
+impl KillEscalation {
+    pub fn new(term_grace: Duration) -> Self {
+        Self {
+            term_grace,
+            kill_grace: DEFAULT_KILL_GRACE,
+        }
+    }
+}
+
+/// Outcome of [`terminate_child_tree`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TerminationReport {
+    /// SIGTERM did not finish the job and SIGKILL was sent.
+    pub escalated: bool,
+    /// Pids still alive after the kill grace elapsed.
+    pub survivors: Vec<u32>,
+}
+
 pub async fn terminate_child(child: &mut Child, timeout_duration: Duration) -> Result<()> {
+    terminate_child_tree(child, &[], KillEscalation::new(timeout_duration)).await?;
+    Ok(())
+}
+
+/// Terminate `child` together with every process in its process group.
+///
+/// When the child leads its own group (workers, wrap-mode children and the
+/// sidecar are all spawned that way) the signals go to the whole group so
+/// grandchildren such as MCP servers and shells do not outlive it.
+/// `extra_groups` names further group leaders to take down alongside it —
+/// the PTY harness runs in its own session below the worker process.
+pub async fn terminate_child_tree(
+    child: &mut Child,
+    extra_groups: &[u32],
+    escalation: KillEscalation,
+) -> Result<TerminationReport> {
+    // Resolve groups up front: once the leader exits, getpgid no longer
+    // answers for it even though its group may still be populated.
+    let mut groups: Vec<u32> = child
+        .id()
+        .and_then(crate::util::process::own_process_group)
+        .into_iter()
+        .chain(
+            extra_groups
+                .iter()
+                .copied()
+                .filter_map(crate::util::process::own_process_group),
+        )
+        .collect();
+    groups.sort_unstable();
+    groups.dedup();
+
+    signal_tree(child, &groups, TreeSignal::Term).await;
+    if wait_for_tree(child, &groups, escalation.term_grace).await {
+        return Ok(TerminationReport::default());
+    }
+
+    signal_tree(child, &groups, TreeSignal::Kill).await;
+    wait_for_tree(child, &groups, escalation.kill_grace).await;
+
+    let mut survivors: Vec<u32> = groups
+        .iter()
+        .flat_map(|pgid| crate::util::process::process_group_members(*pgid))
+        .collect();
+    if !matches!(child.try_wait(), Ok(Some(_))) {
+        survivors.extend(child.id());
+    }
+    survivors.sort_unstable();
+    survivors.dedup();
+
+    Ok(TerminationReport {
+        escalated: true,
+        survivors,
+    })
+}
+
+/// Freeze (`SIGSTOP`) or thaw (`SIGCONT`) `child` and its process group,
+/// plus the `extra_groups` leaders, without tearing anything down.
+#[cfg(unix)]
+pub fn suspend_child_tree(child: &Child, extra_groups: &[u32], suspend: bool) -> Result<()> {
+    let pid = child.id().context("worker process has already exited")?;
+    let signal = if suspend {
+        Signal::SIGSTOP
+    } else {
+        Signal::SIGCONT
+    };
+    let mut groups: Vec<u32> = std::iter::once(pid)
+        .chain(extra_groups.iter().copied())
+        .filter_map(crate::util::process::own_process_group)
+        .collect();
+    groups.sort_unstable();
+    groups.dedup();
+    for pgid in &groups {
+        let _ = killpg(Pid::from_raw(*pgid as i32), signal);
+    }
+    kill(Pid::from_raw(pid as i32), signal)
+        .with_context(|| format!("failed to send {signal} to worker pid {pid}"))
+}
+
+#[cfg(not(unix))]
+pub fn suspend_child_tree(_child: &Child, _extra_groups: &[u32], _suspend: bool) -> Result<()> {
+    anyhow::bail!("unsupported_operation: pausing agents requires a unix host")
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TreeSignal {
+    Term,
+    Kill,
+}
+
+async fn signal_tree(child: &mut Child, groups: &[u32], signal: TreeSignal) {
     #[cfg(unix)]
     {
+        let signal = match signal {
+            TreeSignal::Term => Signal::SIGTERM,
+            TreeSignal::Kill => Signal::SIGKILL,
+        };
+        for pgid in groups {
+            let _ = killpg(Pid::from_raw(*pgid as i32), signal);
+        }
         if let Some(pid) = child.id() {
-            let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+            let _ = kill(Pid::from_raw(pid as i32), signal);
+        }
+        if signal == Signal::SIGTERM {
+            // A paused (SIGSTOPped) tree only acts on SIGTERM once resumed.
+            for pgid in groups {
+                let _ = killpg(Pid::from_raw(*pgid as i32), Signal::SIGCONT);
+            }
+            if let Some(pid) = child.id() {
+                let _ = kill(Pid::from_raw(pid as i32), Signal::SIGCONT);
+            }
         }
     }
 
     #[cfg(not(unix))]
     {
+        let _ = (groups, signal);
         let _ = child.kill().await;
     }
+}
 
-    if timeout(timeout_duration, child.wait()).await.is_err() {
-        #[cfg(unix)]
+/// Wait until the child has exited and every group is empty. Returns `false`
+/// if `grace` elapsed first.
+async fn wait_for_tree(child: &mut Child, groups: &[u32], grace: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + grace;
+    loop {
+        let child_gone = !matches!(child.try_wait(), Ok(None));
+        if child_gone
+            && groups
+                .iter()
+                .all(|pgid| crate::util::process::process_group_members(*pgid).is_empty())
         {
-            if let Some(pid) = child.id() {
-                let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
-            }
+            return true;
         }
-
-        #[cfg(not(unix))]
-        {
-            let _ = child.kill().await;
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        if child_gone {
+            tokio::time::sleep(TERMINATION_POLL_INTERVAL.min(deadline - now)).await;
+        } else {
+            let _ = timeout(TERMINATION_POLL_INTERVAL.min(deadline - now), child.wait()).await;
         }
-
-        let _ = child.wait().await;
     }
-
-    Ok(())
 }
 
 pub fn spawn_env_vars(
@@ -285,7 +432,10 @@ mod tests {
     use nix::unistd::{getsid, Pid};
     use tokio::process::Command;
 
-    use super::{spawn_env_vars, terminate_child, Spawner};
+    use super::{
+        spawn_env_vars, suspend_child_tree, terminate_child, terminate_child_tree, KillEscalation,
+        Spawner,
+    };
 
     #[test]
     fn spawn_env_vars_sets_origin_actor_path_when_harness_present() {
@@ -406,4 +556,94 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn terminate_child_tree_reaps_grandchildren_in_group() {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut line = String::new();
+        tokio::io::BufReader::new(stdout)
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        let grandchild: u32 = line.trim().parse().unwrap();
+        let pgid = child.id().unwrap();
+        assert!(crate::util::process::process_group_members(pgid).contains(&grandchild));
+
+        let report =
+            terminate_child_tree(&mut child, &[], KillEscalation::new(Duration::from_secs(2)))
+                .await
+                .unwrap();
+
+        assert!(report.survivors.is_empty());
+        assert!(crate::util::process::process_group_members(pgid).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn terminate_child_tree_escalates_when_term_is_ignored() {
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 30 & wait"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        // Give the shell a moment to install the trap before signalling.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let report = terminate_child_tree(
+            &mut child,
+            &[],
+            KillEscalation {
+                term_grace: Duration::from_millis(200),
+                kill_grace: Duration::from_secs(2),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(report.escalated);
+        assert!(report.survivors.is_empty());
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn suspended_tree_resumes_and_still_terminates_on_sigterm() {
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+        let state = || {
+            std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                .ok()
+                .and_then(|stat| crate::util::process::parse_stat_pgrp(&stat))
+                .map(|(state, _)| state)
+        };
+
+        suspend_child_tree(&child, &[], true).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(state(), Some('T'));
+        suspend_child_tree(&child, &[], false).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(state(), Some('S'));
+
+        // A tree left stopped must still go down on SIGTERM alone.
+        suspend_child_tree(&child, &[], true).unwrap();
+        let report =
+            terminate_child_tree(&mut child, &[], KillEscalation::new(Duration::from_secs(2)))
+                .await
+                .unwrap();
+        assert!(!report.escalated);
+        assert!(child.try_wait().unwrap().is_some());
+    }
 }
@@ -222,7 +222,7 @@ impl Supervisor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::AgentRuntime;
+    use crate::protocol::{AgentResourceLimits, AgentRuntime};
 
     fn test_spec(name: &str) -> AgentSpec {
         AgentSpec {
@@ -240,6 +240,8 @@ mod tests {
             args: vec![],
             channels: vec![crate::ids::ChannelName::from("general")],
             restart_policy: None,
+            image: None,
+            limits: AgentResourceLimits::default(),
         }
     }
 
@@ -0,0 +1,959 @@
+//! In-process fake Relaycast server for integration tests.
+//!
+//! [`FakeRelaycast`] serves the subset of the Relaycast REST API the broker
+//! talks to — workspace creation, agent registration and token rotation,
+//! channels, DMs, read receipts, node minting — plus the node-control
+//! websocket (`/v1/node/ws`). Responses use the live `{"ok", "data" | "error"}`
+//! envelope, so the real clients can be pointed at [`FakeRelaycast::base_url`]
+//! and spawn/delivery/reconnect flows run under `cargo test` with no network.
+//!
+//! Message echo: a channel post or DM is delivered back as a `deliver` frame
+//! to every recipient agent bound to a connected node. Channels broadcast to
+//! all node-bound agents except the sender; there is no membership model.
+//!
+//! Failure simulation: [`FakeRelaycast::rate_limit`] makes the next matching
+//! requests fail with `429` + `Retry-After`, and
+//! [`FakeRelaycast::disconnect_nodes`] drops every node socket so the broker's
+//! reconnect path runs.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::fleet_wire::{
+    Deliver, DeliveryMode, NodeToServer, Reply, ServerToNode, FLEET_WIRE_VERSION,
+};
+
+const FAKE_CREATED_AT: &str = "2026-01-01T00:00:00Z";
+
+/// An agent registered with the fake server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeAgent {
+    pub id: String,
+    pub name: String,
+    pub token: String,
+    /// `online` once bound to a connected node, otherwise `offline`.
+    pub status: String,
+}
+
+/// A message accepted by the fake server, in arrival order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeMessage {
+    pub id: String,
+    pub from: String,
+    /// Channel name without the leading `#`, for channel posts.
+    pub channel: Option<String>,
+    /// Recipient agent name, for DMs.
+    pub to: Option<String>,
+    pub text: String,
+}
+
+struct NodeConnection {
+    tx: mpsc::UnboundedSender<Option<ServerToNode>>,
+    agents: HashSet<String>,
+}
+
+struct InjectedRateLimit {
+    path_prefix: String,
+    remaining: u32,
+    retry_after_secs: u64,
+}
+
+#[derive(Default)]
+struct FakeState {
+    next_id: u64,
+    workspace_id: String,
+    workspace_key: String,
+    agents: BTreeMap<String, FakeAgent>,
+    channels: BTreeMap<String, Value>,
+    messages: Vec<FakeMessage>,
+    node_tokens: HashSet<String>,
+    nodes: HashMap<u64, NodeConnection>,
+    delivery_seq: HashMap<String, u64>,
+    acked_seq: HashMap<String, u64>,
+    rate_limits: Vec<InjectedRateLimit>,
+}
+
+impl FakeState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}_{}", self.next_id)
+    }
+
+    fn token_is_known(&self, token: &str) -> bool {
+        token == self.workspace_key
+            || self.node_tokens.contains(token)
+            || self.agents.values().any(|agent| agent.token == token)
+    }
+
+    fn agent_for_token(&self, token: &str) -> Option<&FakeAgent> {
+        self.agents.values().find(|agent| agent.token == token)
+    }
+
+    fn register_agent(&mut self, name: &str) -> FakeAgent {
+        let id = self.next_id("agent");
+        let token = format!("at_live_{}", self.next_id("fake"));
+        let agent = FakeAgent {
+            id,
+            name: name.to_string(),
+            token,
+            status: "offline".to_string(),
+        };
+        self.agents.insert(name.to_string(), agent.clone());
+        agent
+    }
+
+    fn take_rate_limit(&mut self, path: &str) -> Option<u64> {
+        let injected = self
+            .rate_limits
+            .iter_mut()
+            .find(|limit| limit.remaining > 0 && path.starts_with(&limit.path_prefix))?;
+        injected.remaining -= 1;
+        let retry_after_secs = injected.retry_after_secs;
+        self.rate_limits.retain(|limit| limit.remaining > 0);
+        Some(retry_after_secs)
+    }
+
+    /// Push a `deliver` frame to the node `recipient` is bound to, if any.
+    fn deliver(&mut self, recipient: &str, msg_id: &str, payload: Value) -> bool {
+        let Some(agent_id) = self.agents.get(recipient).map(|agent| agent.id.clone()) else {
+            return false;
+        };
+        let Some(node) = self
+            .nodes
+            .values()
+            .find(|node| node.agents.contains(recipient))
+        else {
+            return false;
+        };
+        let tx = node.tx.clone();
+        let seq = {
+            let seq = self.delivery_seq.entry(recipient.to_string()).or_insert(0);
+            *seq += 1;
+            *seq
+        };
+        let delivery_id = self.next_id("dlv");
+        tx.send(Some(ServerToNode::Deliver(Deliver {
+            v: FLEET_WIRE_VERSION,
+            agent: recipient.to_string(),
+            agent_id,
+            delivery_id,
+            msg_id: msg_id.to_string(),
+            seq,
+            mode: DeliveryMode::Wait,
+            payload,
+        })))
+        .is_ok()
+    }
+
+    fn node_bound_agents(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .nodes
+            .values()
+            .flat_map(|node| node.agents.iter().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The sender's agent id; senders the fake never registered get an
+    /// empty one.
+    fn agent_id(&self, name: &str) -> String {
+        self.agents
+            .get(name)
+            .map(|agent| agent.id.clone())
+            .unwrap_or_default()
+    }
+
+    fn post_channel_message(&mut self, from: &str, channel: &str, text: &str) -> Value {
+        let channel = channel.trim_start_matches('#').to_string();
+        self.channels
+            .entry(channel.clone())
+            .or_insert_with(|| json!({"name": channel, "topic": null}));
+        let id = self.next_id("msg");
+        self.messages.push(FakeMessage {
+            id: id.clone(),
+            from: from.to_string(),
+            channel: Some(channel.clone()),
+            to: None,
+            text: text.to_string(),
+        });
+        let data = json!({
+            "id": id,
+            "agent_id": self.agent_id(from),
+            "agent_name": from,
+            "channel_name": channel,
+            "text": text,
+            "created_at": FAKE_CREATED_AT,
+        });
+        for recipient in self.node_bound_agents() {
+            if recipient != from {
+                self.deliver(
+                    &recipient,
+                    &id,
+                    json!({"type": "message.created", "data": data}),
+                );
+            }
+        }
+        data
+    }
+
+    fn send_dm(&mut self, from: &str, to: &str, text: &str) -> Value {
+        let id = self.next_id("msg");
+        self.messages.push(FakeMessage {
+            id: id.clone(),
+            from: from.to_string(),
+            channel: None,
+            to: Some(to.to_string()),
+            text: text.to_string(),
+        });
+        let data = json!({
+            "id": id,
+            "agent_id": self.agent_id(from),
+            "agent_name": from,
+            "from_name": from,
+            "text": text,
+            "created_at": FAKE_CREATED_AT,
+        });
+        self.deliver(to, &id, json!({"type": "dm.received", "data": data}));
+        json!({
+            "conversation_id": format!("dm_{from}_{to}"),
+            "message": data,
+            "created_at": FAKE_CREATED_AT,
+        })
+    }
+
+    fn refresh_status(&mut self) {
+        let bound: HashSet<String> = self.node_bound_agents().into_iter().collect();
+        for agent in self.agents.values_mut() {
+            agent.status = if bound.contains(&agent.name) {
+                "online"
+            } else {
+                "offline"
+            }
+            .to_string();
+        }
+    }
+}
+
+type SharedState = Arc<Mutex<FakeState>>;
+
+/// Handle to a running fake Relaycast server. The server stops on drop.
+pub struct FakeRelaycast {
+    addr: SocketAddr,
+    state: SharedState,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeRelaycast {
+    /// Bind an ephemeral loopback port and start serving.
+    pub async fn start() -> std::io::Result<Self> {
+        let state: SharedState = Arc::new(Mutex::new(FakeState {
+            workspace_id: "ws_fake".to_string(),
+            workspace_key: "rk_live_fake".to_string(),
+            ..FakeState::default()
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let router = fake_router(state.clone());
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+        });
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(shutdown_tx),
+            task,
+        })
+    }
+
+    /// HTTP base URL, suitable for `RELAY_BASE_URL`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Node-control websocket URL.
+    pub fn node_ws_url(&self) -> String {
+        format!("ws://{}/v1/node/ws", self.addr)
+    }
+
+    /// The workspace key every workspace-scoped request must present.
+    pub fn workspace_key(&self) -> String {
+        self.state.lock().workspace_key.clone()
+    }
+
+    pub fn workspace_id(&self) -> String {
+        self.state.lock().workspace_id.clone()
+    }
+
+    pub fn agents(&self) -> Vec<FakeAgent> {
+        self.state.lock().agents.values().cloned().collect()
+    }
+
+    pub fn agent(&self, name: &str) -> Option<FakeAgent> {
+        self.state.lock().agents.get(name).cloned()
+    }
+
+    pub fn channels(&self) -> Vec<String> {
+        self.state.lock().channels.keys().cloned().collect()
+    }
+
+    pub fn messages(&self) -> Vec<FakeMessage> {
+        self.state.lock().messages.clone()
+    }
+
+    /// Highest `delivery.ack` sequence received for `agent`.
+    pub fn acked_seq(&self, agent: &str) -> Option<u64> {
+        self.state.lock().acked_seq.get(agent).copied()
+    }
+
+    /// Mint a node token without going through `POST /v1/nodes`.
+    pub fn mint_node_token(&self) -> String {
+        let mut state = self.state.lock();
+        let token = format!("nt_live_{}", state.next_id("fake"));
+        state.node_tokens.insert(token.clone());
+        token
+    }
+
+    pub fn connected_nodes(&self) -> usize {
+        self.state.lock().nodes.len()
+    }
+
+    /// Post to a channel as `from`, as if another workspace member did.
+    pub fn post_message(&self, from: &str, channel: &str, text: &str) -> String {
+        let data = self.state.lock().post_channel_message(from, channel, text);
+        data["id"].as_str().unwrap_or_default().to_string()
+    }
+
+    /// DM `to` as `from`. Returns whether a node socket took the delivery.
+    pub fn send_dm(&self, from: &str, to: &str, text: &str) -> bool {
+        let mut state = self.state.lock();
+        let bound = state.node_bound_agents().iter().any(|name| name == to);
+        state.send_dm(from, to, text);
+        bound
+    }
+
+    /// Fail the next `times` requests whose path starts with `path_prefix`
+    /// with `429 Too Many Requests` and `Retry-After: retry_after_secs`.
+    pub fn rate_limit(&self, path_prefix: &str, times: u32, retry_after_secs: u64) {
+        self.state.lock().rate_limits.push(InjectedRateLimit {
+            path_prefix: path_prefix.to_string(),
+            remaining: times,
+            retry_after_secs,
+        });
+    }
+
+    /// Close every node-control socket, unbinding their agents.
+    pub fn disconnect_nodes(&self) {
+        let mut state = self.state.lock();
+        for (_, node) in state.nodes.drain() {
+            let _ = node.tx.send(None);
+        }
+        state.refresh_status();
+    }
+}
+
+impl Drop for FakeRelaycast {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        self.task.abort();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// REST
+// ---------------------------------------------------------------------------
+
+fn fake_router(state: SharedState) -> Router {
+    Router::new()
+        .route("/v1/workspaces", routing::post(create_workspace))
+        .route("/v1/agents", routing::get(list_agents).post(register_agent))
+        .route("/v1/agents/{name}", routing::get(get_agent))
+        .route(
+            "/v1/agents/{name}/rotate-token",
+            routing::post(rotate_token),
+        )
+        .route(
+            "/v1/channels",
+            routing::get(list_channels).post(create_channel),
+        )
+        .route(
+            "/v1/channels/{name}/messages",
+            routing::post(post_channel_message),
+        )
+        .route("/v1/dm", routing::post(send_dm))
+        .route("/v1/messages/{id}/read", routing::post(mark_read))
+        .route("/v1/nodes", routing::post(create_node))
+        .route("/v1/node/ws", routing::get(node_ws))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            fake_gate,
+        ))
+        .with_state(state)
+}
+
+fn ok(data: Value) -> Response {
+    Json(json!({"ok": true, "data": data})).into_response()
+}
+
+fn fail(status: StatusCode, code: &str, message: &str) -> Response {
+    (
+        status,
+        Json(json!({"ok": false, "error": {"code": code, "message": message}})),
+    )
+        .into_response()
+}
+
+fn bearer(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+/// Injected rate limits first, then bearer auth for everything except
+/// workspace creation.
+async fn fake_gate(
+    State(state): State<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let rejection = {
+        let mut state = state.lock();
+        if let Some(retry_after_secs) = state.take_rate_limit(&path) {
+            let mut response = fail(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "rate limit exceeded",
+            );
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+            Some(response)
+        } else if (request.method() == Method::POST && path == "/v1/workspaces")
+            || bearer(request.headers()).is_some_and(|token| state.token_is_known(&token))
+        {
+            None
+        } else {
+            Some(fail(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "unauthorized",
+            ))
+        }
+    };
+    match rejection {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+async fn create_workspace(State(state): State<SharedState>) -> Response {
+    let state = state.lock();
+    ok(json!({
+        "workspace_id": state.workspace_id,
+        "api_key": state.workspace_key,
+        "created_at": FAKE_CREATED_AT,
+    }))
+}
+
+fn agent_json(agent: &FakeAgent, workspace_id: &str, include_token: bool) -> Value {
+    let mut value = json!({
+        "id": agent.id,
+        "workspace_id": workspace_id,
+        "name": agent.name,
+        "type": "agent",
+        "status": agent.status,
+        "created_at": FAKE_CREATED_AT,
+        "channels": [],
+    });
+    if include_token {
+        value["token"] = json!(agent.token);
+    }
+    value
+}
+
+async fn list_agents(State(state): State<SharedState>) -> Response {
+    let state = state.lock();
+    ok(Value::Array(
+        state
+            .agents
+            .values()
+            .map(|agent| agent_json(agent, &state.workspace_id, false))
+            .collect(),
+    ))
+}
+
+async fn register_agent(State(state): State<SharedState>, Json(body): Json<Value>) -> Response {
+    let Some(name) = body["name"].as_str().filter(|name| !name.trim().is_empty()) else {
+        return fail(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "name is required",
+        );
+    };
+    let mut state = state.lock();
+    if state.agents.contains_key(name) {
+        return fail(StatusCode::CONFLICT, "agent_already_exists", "name_taken");
+    }
+    let agent = state.register_agent(name);
+    ok(agent_json(&agent, &state.workspace_id, true))
+}
+
+async fn get_agent(State(state): State<SharedState>, Path(name): Path<String>) -> Response {
+    let state = state.lock();
+    match state.agents.get(&name) {
+        Some(agent) => ok(agent_json(agent, &state.workspace_id, false)),
+        None => fail(StatusCode::NOT_FOUND, "agent_not_found", "agent not found"),
+    }
+}
+
+async fn rotate_token(State(state): State<SharedState>, Path(name): Path<String>) -> Response {
+    let mut state = state.lock();
+    let token = format!("at_live_{}", state.next_id("rotated"));
+    match state.agents.get_mut(&name) {
+        Some(agent) => {
+            agent.token = token.clone();
+            ok(json!({"name": name, "token": token}))
+        }
+        None => fail(StatusCode::NOT_FOUND, "agent_not_found", "agent not found"),
+    }
+}
+
+async fn list_channels(State(state): State<SharedState>) -> Response {
+    ok(Value::Array(
+        state.lock().channels.values().cloned().collect(),
+    ))
+}
+
+async fn create_channel(State(state): State<SharedState>, Json(body): Json<Value>) -> Response {
+    let Some(name) = body["name"]
+        .as_str()
+        .map(|name| name.trim_start_matches('#'))
+        .filter(|name| !name.is_empty())
+    else {
+        return fail(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "name is required",
+        );
+    };
+    let channel = json!({"name": name, "topic": body.get("topic").cloned().unwrap_or(Value::Null)});
+    let mut state = state.lock();
+    if state.channels.contains_key(name) {
+        return fail(
+            StatusCode::CONFLICT,
+            "channel_already_exists",
+            "channel exists",
+        );
+    }
+    state.channels.insert(name.to_string(), channel.clone());
+    ok(channel)
+}
+
+/// Sender for a message: the agent owning the bearer token, otherwise the
+/// workspace itself.
+fn sender_name(state: &FakeState, headers: &HeaderMap) -> String {
+    bearer(headers)
+        .and_then(|token| {
+            state
+                .agent_for_token(&token)
+                .map(|agent| agent.name.clone())
+        })
+        .unwrap_or_else(|| "workspace".to_string())
+}
+
+async fn post_channel_message(
+    State(state): State<SharedState>,
+    Path(channel): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let text = body["text"].as_str().unwrap_or_default();
+    let mut state = state.lock();
+    let from = sender_name(&state, &headers);
+    ok(state.post_channel_message(&from, &channel, text))
+}
+
+async fn send_dm(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let Some(to) = body["to"].as_str() else {
+        return fail(StatusCode::BAD_REQUEST, "invalid_request", "to is required");
+    };
+    let text = body["text"].as_str().unwrap_or_default();
+    let mut state = state.lock();
+    if !state.agents.contains_key(to) {
+        return fail(StatusCode::NOT_FOUND, "agent_not_found", "agent not found");
+    }
+    let from = sender_name(&state, &headers);
+    ok(state.send_dm(&from, to, text))
+}
+
+async fn mark_read(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let state = state.lock();
+    if !state.messages.iter().any(|message| message.id == id) {
+        return fail(
+            StatusCode::NOT_FOUND,
+            "message_not_found",
+            "message not found",
+        );
+    }
+    let agent_id = bearer(&headers)
+        .and_then(|token| state.agent_for_token(&token).map(|agent| agent.id.clone()));
+    ok(json!({"message_id": id, "agent_id": agent_id, "read_at": FAKE_CREATED_AT}))
+}
+
+async fn create_node(State(state): State<SharedState>, Json(body): Json<Value>) -> Response {
+    let mut state = state.lock();
+    let id = body["node_id"]
+        .as_str()
+        .or_else(|| body["id"].as_str())
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| state.next_id("node"));
+    let token = format!("nt_live_{}", state.next_id("fake"));
+    state.node_tokens.insert(token.clone());
+    ok(json!({
+        "id": id,
+        "name": body.get("name").cloned().unwrap_or(Value::Null),
+        "kind": "ws",
+        "role": "broker",
+        "status": "offline",
+        "live": false,
+        "handlers_live": false,
+        "load": 0.0,
+        "active_agents": 0,
+        "max_agents": 0,
+        "created_at": FAKE_CREATED_AT,
+        "token": token,
+    }))
+}
+
+// ---------------------------------------------------------------------------
+// Node-control websocket
+// ---------------------------------------------------------------------------
+
+async fn node_ws(State(state): State<SharedState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| serve_node(state, socket))
+}
+
+async fn serve_node(state: SharedState, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<Option<ServerToNode>>();
+    let conn_id = {
+        let mut state = state.lock();
+        state.next_id += 1;
+        let conn_id = state.next_id;
+        state.nodes.insert(
+            conn_id,
+            NodeConnection {
+                tx: tx.clone(),
+                agents: HashSet::new(),
+            },
+        );
+        conn_id
+    };
+
+    let writer = tokio::spawn(async move {
+        // `None` asks the writer to close the socket (see `disconnect_nodes`).
+        while let Some(Some(frame)) = rx.recv().await {
+            let Ok(text) = serde_json::to_string(&frame) else {
+                continue;
+            };
+            if sink.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+        }
+        let _ = sink.send(Message::Close(None)).await;
+    });
+
+    while let Some(Ok(message)) = stream.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(frame) = serde_json::from_str::<NodeToServer>(text.as_str()) else {
+            continue;
+        };
+        let mut state = state.lock();
+        if !state.nodes.contains_key(&conn_id) {
+            break;
+        }
+        if let Some(reply) = handle_node_frame(&mut state, conn_id, frame) {
+            let _ = tx.send(Some(ServerToNode::Reply(reply)));
+        }
+    }
+
+    {
+        let mut state = state.lock();
+        state.nodes.remove(&conn_id);
+        state.refresh_status();
+    }
+    writer.abort();
+}
+
+fn reply(id: Option<String>, data: Value) -> Option<Reply> {
+    Some(Reply {
+        v: FLEET_WIRE_VERSION,
+        id: id?,
+        ok: true,
+        data,
+    })
+}
+
+fn handle_node_frame(state: &mut FakeState, conn_id: u64, frame: NodeToServer) -> Option<Reply> {
+    match frame {
+        NodeToServer::NodeRegister(register) => {
+            reply(register.id, json!({"node_id": register.node_id}))
+        }
+        NodeToServer::AgentRegister(register) => {
+            let agent = match state.agents.get(&register.name) {
+                Some(agent) => agent.clone(),
+                None => state.register_agent(&register.name),
+            };
+            if let Some(node) = state.nodes.get_mut(&conn_id) {
+                node.agents.insert(agent.name.clone());
+            }
+            state.refresh_status();
+            reply(
+                register.id,
+                json!({"agent_id": agent.id, "token": agent.token, "name": agent.name}),
+            )
+        }
+        NodeToServer::AgentDeregister(deregister) => {
+            let name = deregister.name.or_else(|| {
+                state
+                    .agents
+                    .values()
+                    .find(|agent| agent.id == deregister.agent_id)
+                    .map(|agent| agent.name.clone())
+            });
+            if let (Some(name), Some(node)) = (name, state.nodes.get_mut(&conn_id)) {
+                node.agents.remove(&name);
+            }
+            state.refresh_status();
+            reply(deregister.id, json!({}))
+        }
+        NodeToServer::InventorySync(sync) => {
+            if let Some(node) = state.nodes.get_mut(&conn_id) {
+                node.agents = sync.agents.into_iter().map(|agent| agent.name).collect();
+            }
+            state.refresh_status();
+            reply(sync.id, json!({}))
+        }
+        NodeToServer::DeliveryAck(ack) => {
+            let acked = state.acked_seq.entry(ack.agent).or_insert(0);
+            *acked = (*acked).max(ack.up_to_seq);
+            reply(ack.id, json!({}))
+        }
+        NodeToServer::NodeHeartbeat(heartbeat) => reply(heartbeat.id, json!({})),
+        NodeToServer::NodeDeregister(deregister) => reply(deregister.id, json!({})),
+        NodeToServer::ActionResult(result) => reply(result.id, json!({})),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message as WsMessage};
+
+    use super::FakeRelaycast;
+    use crate::relaycast::RelaycastHttpClient;
+
+    async fn next_json<S>(stream: &mut S) -> Value
+    where
+        S: futures_util::Stream<Item = Result<WsMessage, tokio_tungstenite::tungstenite::Error>>
+            + Unpin,
+    {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(2), stream.next())
+                .await
+                .expect("frame should arrive")
+                .expect("socket open")
+                .expect("frame should decode");
+            if let WsMessage::Text(text) = message {
+                return serde_json::from_str(&text).expect("frame is json");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn registration_conflicts_and_injected_rate_limits_match_relaycast() {
+        let fake = FakeRelaycast::start().await.expect("fake starts");
+        let http = reqwest::Client::new();
+        let register = || {
+            http.post(format!("{}/v1/agents", fake.base_url()))
+                .bearer_auth(fake.workspace_key())
+                .json(&json!({"name": "lead", "type": "agent"}))
+        };
+
+        let created: Value = register().send().await.unwrap().json().await.unwrap();
+        assert_eq!(created["ok"], true);
+        assert_eq!(created["data"]["name"], "lead");
+        assert!(created["data"]["token"]
+            .as_str()
+            .unwrap()
+            .starts_with("at_live_"));
+
+        let conflict = register().send().await.unwrap();
+        assert_eq!(conflict.status(), 409);
+
+        fake.rate_limit("/v1/agents", 1, 7);
+        let limited = register().send().await.unwrap();
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.headers()["retry-after"], "7");
+
+        let unauthorized = http
+            .get(format!("{}/v1/channels", fake.base_url()))
+            .bearer_auth("rk_live_wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn node_socket_registers_agents_and_receives_echoed_messages() {
+        let fake = FakeRelaycast::start().await.expect("fake starts");
+        let mut request = fake.node_ws_url().into_client_request().unwrap();
+        request.headers_mut().insert(
+            "authorization",
+            format!("Bearer {}", fake.mint_node_token())
+                .parse()
+                .unwrap(),
+        );
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .expect("node socket connects");
+        let (mut sink, mut stream) = socket.split();
+
+        sink.send(WsMessage::Text(
+            json!({"type": "agent.register", "v": 1, "id": "req_1", "name": "worker"}).to_string(),
+        ))
+        .await
+        .unwrap();
+        let reply = next_json(&mut stream).await;
+        assert_eq!(reply["type"], "reply");
+        assert_eq!(reply["id"], "req_1");
+        assert_eq!(reply["data"]["name"], "worker");
+        assert_eq!(fake.agent("worker").unwrap().status, "online");
+
+        assert!(fake.send_dm("lead", "worker", "hello"));
+        let deliver = next_json(&mut stream).await;
+        assert_eq!(deliver["type"], "deliver");
+        assert_eq!(deliver["agent"], "worker");
+        assert_eq!(deliver["seq"], 1);
+        assert_eq!(deliver["payload"]["type"], "dm.received");
+        assert_eq!(deliver["payload"]["data"]["text"], "hello");
+
+        fake.post_message("lead", "#general", "standup");
+        let deliver = next_json(&mut stream).await;
+        assert_eq!(deliver["seq"], 2);
+        assert_eq!(deliver["payload"]["data"]["channel_name"], "general");
+
+        sink.send(WsMessage::Text(
+            json!({"type": "delivery.ack", "v": 1, "agent": "worker", "up_to_seq": 2}).to_string(),
+        ))
+        .await
+        .unwrap();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while fake.acked_seq("worker") != Some(2) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("ack should be recorded");
+        assert_eq!(fake.channels(), vec!["general".to_string()]);
+
+        fake.disconnect_nodes();
+        assert_eq!(fake.connected_nodes(), 0);
+        assert_eq!(fake.agent("worker").unwrap().status, "offline");
+    }
+
+    #[tokio::test]
+    async fn relaycast_client_registers_and_sends_through_the_fake() {
+        let fake = FakeRelaycast::start().await.expect("fake starts");
+        let client = RelaycastHttpClient::new(
+            Some(fake.base_url()),
+            fake.workspace_key(),
+            "broker",
+            "claude",
+        );
+
+        let token = client
+            .register_agent_token("broker", None)
+            .await
+            .expect("broker registers");
+        assert_eq!(fake.agent("broker").unwrap().token, token);
+        client
+            .register_agent_token("worker", Some("codex"))
+            .await
+            .expect("worker registers");
+
+        client
+            .ensure_default_channels()
+            .await
+            .expect("default channels are created");
+        assert_eq!(
+            fake.channels(),
+            vec!["engineering".to_string(), "general".to_string()]
+        );
+
+        client
+            .send_to_channel("general", "standup")
+            .await
+            .expect("channel post succeeds");
+        client
+            .send_dm("worker", "hello")
+            .await
+            .expect("dm succeeds");
+        let messages = fake.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].from, "broker");
+        assert_eq!(messages[0].channel.as_deref(), Some("general"));
+        assert_eq!(messages[0].text, "standup");
+        assert_eq!(messages[1].from, "broker");
+        assert_eq!(messages[1].to.as_deref(), Some("worker"));
+        assert_eq!(messages[1].text, "hello");
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod ansi;
+pub(crate) mod process;
 pub(crate) mod terminal;
 pub(crate) mod utf8_stream;
 pub(crate) mod version;
@@ -0,0 +1,283 @@
+//! Process-table helpers used when tearing down worker process trees and
+//! sampling worker resource usage.
+
+use std::time::{Duration, Instant};
+
+/// Return the process group id of `pid` when it leads its own group.
+///
+/// Workers and wrap-mode children are spawned as group (or session) leaders,
+/// so signalling the group reaches every grandchild that did not detach. A
+/// child that shares the broker's group returns `None` — signalling that
+/// group would take the broker down with it.
+#[cfg(unix)]
+pub(crate) fn own_process_group(pid: u32) -> Option<u32> {
+    let pid = nix::unistd::Pid::from_raw(i32::try_from(pid).ok()?);
+    match nix::unistd::getpgid(Some(pid)) {
+        Ok(pgid) if pgid == pid => u32::try_from(pgid.as_raw()).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn own_process_group(_pid: u32) -> Option<u32> {
+    None
+}
+
+/// List live (non-zombie) processes whose process group is `pgid`.
+#[cfg(target_os = "linux")]
+pub(crate) fn process_group_members(pgid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut members: Vec<u32> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                .ok()
+                .and_then(|stat| parse_stat_pgrp(&stat))
+                .is_some_and(|(state, pgrp)| state != 'Z' && pgrp == pgid)
+        })
+        .collect();
+    members.sort_unstable();
+    members
+}
+
+/// Without a readable process table we can only tell whether the group is
+/// still populated, so the leader id stands in for its members.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub(crate) fn process_group_members(pgid: u32) -> Vec<u32> {
+    let Ok(raw) = i32::try_from(pgid) else {
+        return Vec::new();
+    };
+    match nix::sys::signal::killpg(nix::unistd::Pid::from_raw(raw), None) {
+        Ok(()) => vec![pgid],
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn process_group_members(_pgid: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+/// A live process found carrying one of the broker's env markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MarkedProcess {
+    pub(crate) pid: u32,
+    /// Value of the requested marker variable.
+    pub(crate) marker: String,
+    /// Short command name from `/proc/<pid>/comm`, when readable.
+    pub(crate) command: Option<String>,
+}
+
+/// List processes whose environment sets `marker_key` and also contains
+/// `scope_key=scope_value`. Processes owned by other users (unreadable
+/// `environ`) and zombies are skipped.
+#[cfg(target_os = "linux")]
+pub(crate) fn processes_with_env_marker(
+    marker_key: &str,
+    scope_key: &str,
+    scope_value: &str,
+) -> Vec<MarkedProcess> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut found: Vec<MarkedProcess> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            if parse_stat_pgrp(&stat)?.0 == 'Z' {
+                return None;
+            }
+            let environ = std::fs::read(format!("/proc/{pid}/environ")).ok()?;
+            if env_lookup(&environ, scope_key)? != scope_value {
+                return None;
+            }
+            let marker = env_lookup(&environ, marker_key)?;
+            let command = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                .ok()
+                .map(|comm| comm.trim().to_string())
+                .filter(|comm| !comm.is_empty());
+            Some(MarkedProcess {
+                pid,
+                marker,
+                command,
+            })
+        })
+        .collect();
+    found.sort_unstable_by_key(|process| process.pid);
+    found
+}
+
+/// Other platforms do not expose foreign process environments without
+/// elevated privileges, so the audit has nothing to inspect there.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn processes_with_env_marker(
+    _marker_key: &str,
+    _scope_key: &str,
+    _scope_value: &str,
+) -> Vec<MarkedProcess> {
+    Vec::new()
+}
+
+/// Cumulative CPU time of a process at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CpuSample {
+    pub(crate) cpu_time: Duration,
+    pub(crate) at: Instant,
+}
+
+impl CpuSample {
+    /// CPU used between `earlier` and `self` as a percentage of one core, so
+    /// a process saturating two cores reports 200.
+    pub(crate) fn utilization_since(&self, earlier: &CpuSample) -> f64 {
+        let wall = self.at.saturating_duration_since(earlier.at).as_secs_f64();
+        if wall <= 0.0 {
+            return 0.0;
+        }
+        let cpu = self.cpu_time.saturating_sub(earlier.cpu_time).as_secs_f64();
+        cpu / wall * 100.0
+    }
+}
+
+/// User plus system CPU time consumed by `pid`.
+#[cfg(target_os = "linux")]
+pub(crate) fn cpu_time_for_pid(pid: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let ticks = parse_stat_cpu_ticks(&stat)?;
+    let ticks_per_sec = unsafe { nix::libc::sysconf(nix::libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(ticks as f64 / ticks_per_sec as f64))
+}
+
+/// User plus system CPU time consumed by `pid`.
+#[cfg(target_os = "macos")]
+pub(crate) fn cpu_time_for_pid(pid: u32) -> Option<Duration> {
+    use nix::libc;
+    let pid = libc::c_int::try_from(pid).ok()?;
+    let mut info = std::mem::MaybeUninit::<libc::proc_taskinfo>::zeroed();
+    let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+    let written = unsafe {
+        libc::proc_pidinfo(
+            pid,
+            libc::PROC_PIDTASKINFO,
+            0,
+            info.as_mut_ptr().cast(),
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+    let info = unsafe { info.assume_init() };
+    let ticks = info.pti_total_user.saturating_add(info.pti_total_system);
+    Some(Duration::from_nanos(mach_ticks_to_nanos(ticks)))
+}
+
+/// `proc_taskinfo` times are in Mach absolute time units, which are only
+/// nanoseconds on Intel.
+#[cfg(target_os = "macos")]
+#[allow(deprecated)]
+pub(crate) fn mach_ticks_to_nanos(ticks: u64) -> u64 {
+    use nix::libc;
+    let mut timebase = libc::mach_timebase_info { numer: 0, denom: 0 };
+    let status = unsafe { libc::mach_timebase_info(&mut timebase) };
+    if status != 0 || timebase.denom == 0 {
+        return ticks;
+    }
+    (u128::from(ticks) * u128::from(timebase.numer) / u128::from(timebase.denom)) as u64
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn cpu_time_for_pid(_pid: u32) -> Option<Duration> {
+    None
+}
+
+/// Look up `key` in a NUL-separated `KEY=value` environment block.
+#[cfg(any(target_os = "linux", test))]
+pub(crate) fn env_lookup(environ: &[u8], key: &str) -> Option<String> {
+    environ.split(|byte| *byte == 0).find_map(|entry| {
+        let entry = std::str::from_utf8(entry).ok()?;
+        let (entry_key, value) = entry.split_once('=')?;
+        (entry_key == key).then(|| value.to_string())
+    })
+}
+
+/// Extract `(state, pgrp)` from a `/proc/<pid>/stat` line.
+///
+/// The command name is parenthesised and may itself contain spaces or
+/// parentheses, so fields are counted from the last `)`.
+#[cfg(any(target_os = "linux", test))]
+pub(crate) fn parse_stat_pgrp(stat: &str) -> Option<(char, u32)> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let mut fields = rest.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let _ppid = fields.next()?;
+    let pgrp = fields.next()?.parse::<u32>().ok()?;
+    Some((state, pgrp))
+}
+
+/// Extract `utime + stime` (in clock ticks) from a `/proc/<pid>/stat` line.
+#[cfg(any(target_os = "linux", test))]
+pub(crate) fn parse_stat_cpu_ticks(stat: &str) -> Option<u64> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    // Fields 14 and 15 of the full line; `rest` starts at field 3 (state).
+    let mut fields = rest.split_whitespace().skip(11);
+    let utime = fields.next()?.parse::<u64>().ok()?;
+    let stime = fields.next()?.parse::<u64>().ok()?;
+    Some(utime.saturating_add(stime))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{env_lookup, parse_stat_cpu_ticks, parse_stat_pgrp, CpuSample};
+
+    #[test]
+    fn parse_stat_pgrp_handles_parenthesised_command_names() {
+        let stat = "4242 (node (mcp) x) S 4200 4100 4100 0 -1 4194560";
+        assert_eq!(parse_stat_pgrp(stat), Some(('S', 4100)));
+    }
+
+    #[test]
+    fn parse_stat_pgrp_rejects_truncated_lines() {
+        assert_eq!(parse_stat_pgrp("4242 (sleep) S 1"), None);
+        assert_eq!(parse_stat_pgrp("garbage"), None);
+    }
+
+    #[test]
+    fn parse_stat_cpu_ticks_sums_user_and_system_time() {
+        let stat = "4242 (node (mcp) x) R 4200 4100 4100 0 -1 4194560 812 0 3 0 150 45 0 0 20 0";
+        assert_eq!(parse_stat_cpu_ticks(stat), Some(195));
+        assert_eq!(parse_stat_cpu_ticks("4242 (sleep) S 1 2 3"), None);
+    }
+
+    #[test]
+    fn cpu_utilization_is_relative_to_one_core() {
+        let start = Instant::now();
+        let earlier = CpuSample {
+            cpu_time: Duration::from_millis(500),
+            at: start,
+        };
+        let later = CpuSample {
+            cpu_time: Duration::from_millis(4_500),
+            at: start + Duration::from_secs(2),
+        };
+        assert_eq!(later.utilization_since(&earlier), 200.0);
+        assert_eq!(earlier.utilization_since(&earlier), 0.0);
+    }
+
+    #[test]
+    fn env_lookup_matches_whole_keys_only() {
+        let environ = b"RELAY_AGENT_NAME_X=no\0RELAY_AGENT_NAME=Worker=1\0EMPTY=\0";
+        assert_eq!(
+            env_lookup(environ, "RELAY_AGENT_NAME"),
+            Some("Worker=1".to_string())
+        );
+        assert_eq!(env_lookup(environ, "EMPTY"), Some(String::new()));
+        assert_eq!(env_lookup(environ, "MISSING"), None);
+    }
+}
@@ -2,24 +2,31 @@ use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     process::Stdio,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use crate::{
+    broker_config::{env_or, AgentsSection, BrokerConfigFile, LogsSection},
+    headless_template::HEADLESS_TEMPLATE_ENV,
     ids::{RequestId, WorkerName},
     metrics::MetricsCollector,
     protocol::{
         AgentRuntime, AgentSpec, AppServerAuthType, AppServerHostOwnership, HarnessReleasePolicy,
-        HeadlessHarnessConfig, HeadlessHarnessDriver, ProtocolEnvelope, RelayDelivery,
-        ResolvedHarnessConfig, PROTOCOL_VERSION,
+        HeadlessHarnessConfig, HeadlessHarnessDriver, HeadlessProvider, ProtocolEnvelope,
+        RelayDelivery, ResolvedHarnessConfig, PROTOCOL_VERSION,
     },
     relaycast::configure_agent_relay_mcp_with_result,
+    routing::{worker_names_for_team_target, RoutingWorker},
+    scrollback::{scrollback_lines, OutputScrollback},
     supervisor::Supervisor,
     types::AgentResultMcpConfig,
+    util::process::CpuSample,
 };
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::{Child, ChildStdin, Command},
@@ -29,8 +36,8 @@ use tokio::{
 
 use crate::{
     cli::command_parse::{normalize_cli_name, parse_cli_command},
-    runtime::headless_provider_cli_name,
-    spawner::terminate_child,
+    runtime::{headless_provider_cli_name, headless_provider_template},
+    spawner::{terminate_child_tree, KillEscalation, TerminationReport, DEFAULT_KILL_GRACE},
 };
 
 const APP_SERVER_AUTH_ENV_KEYS: [&str; 4] = [
@@ -43,6 +50,10 @@ const DEFAULT_RELEASE_GRACE: Duration = Duration::from_secs(2);
 const APP_SERVER_RELEASE_GRACE: Duration = Duration::from_secs(35);
 
 pub(crate) mod detection;
+pub(crate) mod docker;
+pub(crate) mod kubernetes;
+pub(crate) mod limits;
+pub(crate) mod ssh;
 
 #[derive(Debug)]
 pub(crate) struct WorkerHandle {
@@ -53,10 +64,39 @@ pub(crate) struct WorkerHandle {
     pub(crate) stdin: ChildStdin,
     pub(crate) harness_pid: Option<u32>,
     pub(crate) spawned_at: Instant,
+    /// Set once the worker sent `worker_ready`.
+    pub(crate) ready: bool,
     pub(crate) last_activity_at: Instant,
     pub(crate) context_budget_pct: Option<u8>,
     pub(crate) state: AgentWorkState,
     pub(crate) exit_reason: Option<String>,
+    pub(crate) scrollback: OutputScrollback,
+    /// Last CPU sample taken on the maintenance tick, the baseline for the
+    /// next one.
+    pub(crate) cpu_sample: Option<CpuSample>,
+    /// CPU utilization between the last two samples.
+    pub(crate) cpu_percent: f64,
+    /// Set while the worker process tree is stopped by `pause_agent`.
+    pub(crate) paused_at: Option<Instant>,
+    /// Spawn options not captured by `spec`, kept so `restart_agent` can
+    /// respawn the worker the same way.
+    pub(crate) idle_threshold_secs: Option<u64>,
+    pub(crate) skip_relay_prompt: bool,
+    pub(crate) agent_result: Option<AgentResultMcpConfig>,
+    /// Set when `spec.limits` are enforced through a cgroup.
+    pub(crate) cgroup: Option<limits::AgentCgroup>,
+    /// Set once the memory watchdog reported a breach, so it reports each
+    /// breach once.
+    pub(crate) over_memory_limit: bool,
+}
+
+impl WorkerHandle {
+    /// Remove the worker's cgroup, if it has one.
+    fn discard_cgroup(&mut self) {
+        if let Some(cgroup) = self.cgroup.take() {
+            cgroup.remove();
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -79,7 +119,86 @@ impl AgentWorkState {
 
 #[derive(Debug, Clone)]
 pub(crate) enum WorkerEvent {
-    Message { name: WorkerName, value: Value },
+    Message {
+        name: WorkerName,
+        value: Value,
+    },
+    /// A remote agent's setup finished; see [`WorkerRegistry::finish_start`].
+    RemoteSetup {
+        name: WorkerName,
+        result: Result<(), String>,
+    },
+    /// Result of a background `kubectl get pods` poll.
+    PodStatuses(Result<HashMap<String, kubernetes::PodStatus>, String>),
+}
+
+/// Everything needed to start a worker process once its command is built.
+struct WorkerLaunch {
+    command: Command,
+    spec: AgentSpec,
+    parent: Option<String>,
+    workspace_id: Option<crate::ids::WorkspaceId>,
+    initial_harness_pid: Option<u32>,
+    idle_threshold_secs: Option<u64>,
+    skip_relay_prompt: bool,
+    agent_result: Option<AgentResultMcpConfig>,
+}
+
+/// Slow setup a remote agent needs before its harness can start. It runs in
+/// a background task so docker, kubectl and ssh never block the broker's
+/// event loop.
+enum RemoteSetup {
+    /// Clear a stale container a crashed broker left under the agent's name.
+    Container {
+        container: String,
+        broker_scope: String,
+    },
+    Pod {
+        namespace: Option<String>,
+        pod: String,
+        broker_scope: String,
+        secret: Value,
+        manifest: Value,
+    },
+    /// Write the agent's env file and MCP config on its ssh host.
+    Ssh(ssh::SshProvision),
+}
+
+impl RemoteSetup {
+    async fn run(self) -> Result<()> {
+        match self {
+            RemoteSetup::Container {
+                container,
+                broker_scope,
+            } => {
+                docker::remove_container(&container, &broker_scope).await;
+                Ok(())
+            }
+            RemoteSetup::Pod {
+                namespace,
+                pod,
+                broker_scope,
+                secret,
+                manifest,
+            } => {
+                kubernetes::create_pod(
+                    namespace.as_deref(),
+                    &pod,
+                    &broker_scope,
+                    &secret,
+                    &manifest,
+                )
+                .await
+            }
+            RemoteSetup::Ssh(provision) => provision.run().await,
+        }
+    }
+}
+
+/// A spawned agent whose [`RemoteSetup`] is still running.
+struct StartingWorker {
+    launch: WorkerLaunch,
+    task: tokio::task::JoinHandle<()>,
 }
 
 pub(crate) struct WorkerRegistry {
@@ -87,9 +206,100 @@ pub(crate) struct WorkerRegistry {
     event_tx: mpsc::Sender<WorkerEvent>,
     worker_env: Vec<(String, String)>,
     worker_logs_dir: PathBuf,
+    log_format: WorkerLogFormat,
+    scrollback_lines: usize,
     pub(crate) initial_tasks: HashMap<WorkerName, String>,
     pub(crate) supervisor: Supervisor,
     pub(crate) metrics: MetricsCollector,
+    /// Set while the broker drains; new spawns are refused.
+    pub(crate) draining: bool,
+    /// Cap on concurrently running workers; spawns past it are queued by the
+    /// runtime. `None` means unlimited.
+    pub(crate) max_concurrent: Option<usize>,
+    /// Spawned agents still waiting on their remote setup. They count as
+    /// workers for names, capacity and delivery, but have no process yet.
+    starting: HashMap<WorkerName, StartingWorker>,
+    /// Agents whose setup or launch failed, reported by the next
+    /// [`Self::reap_exited`].
+    failed_starts: Vec<(WorkerName, String)>,
+    /// Container and pod removals still running, by agent name. A respawn
+    /// under the same name waits for its removal, and shutdown for all.
+    discarding: HashMap<WorkerName, tokio::task::JoinHandle<()>>,
+    /// See [`broker_scope`]; set by the runtime once the broker's name is
+    /// known.
+    pub(crate) broker_scope: String,
+    /// The broker config file, for the agent settings read at spawn time.
+    config: Arc<BrokerConfigFile>,
+}
+
+/// Process groups to signal besides the worker's own. The PTY harness is a
+/// session leader of its own below the worker process, so its group has to
+/// be signalled separately.
+fn worker_extra_groups(handle: &WorkerHandle) -> Vec<u32> {
+    match handle.spec.runtime {
+        AgentRuntime::Pty
+        | AgentRuntime::Docker
+        | AgentRuntime::Kubernetes
+        | AgentRuntime::Ssh { .. } => handle.harness_pid.into_iter().collect(),
+        AgentRuntime::Headless => Vec::new(),
+    }
+}
+
+/// Remove the container or pod behind a non-local agent. Stopping the
+/// `docker` or `kubectl` client does not stop it; an ssh session ends with
+/// its client.
+async fn discard_remote_agent(spec: &AgentSpec, broker_scope: &str, namespace: Option<&str>) {
+    match spec.runtime {
+        AgentRuntime::Docker => {
+            let container = docker::container_name(broker_scope, &spec.name);
+            docker::remove_container(&container, broker_scope).await
+        }
+        AgentRuntime::Kubernetes => {
+            let pod = kubernetes::pod_name(broker_scope, &spec.name);
+            kubernetes::delete_pod(namespace, &pod, broker_scope).await
+        }
+        AgentRuntime::Pty | AgentRuntime::Headless | AgentRuntime::Ssh { .. } => {}
+    }
+}
+
+/// Short, stable id for the broker named `name` running in `cwd`. It scopes
+/// container and pod names and labels, so a broker only ever removes the
+/// ones it created, including those of an earlier run that crashed.
+pub(crate) fn broker_scope(cwd: &Path, name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cwd.as_os_str().as_encoded_bytes());
+    hasher.update([0]);
+    hasher.update(name.as_bytes());
+    format!("{:x}", hasher.finalize())[..8].to_string()
+}
+
+/// `AGENT_RELAY_MAX_AGENTS` or `[agents] max_concurrent`; unset or `0`
+/// means no cap.
+fn max_concurrent_agents(config: &AgentsSection) -> Option<usize> {
+    env_or("AGENT_RELAY_MAX_AGENTS", config.max_concurrent).filter(|max| *max > 0)
+}
+
+/// Idle threshold for spawns that don't pass one, from
+/// `AGENT_RELAY_IDLE_THRESHOLD_SECS` or `[agents] idle_threshold_secs`.
+fn default_idle_threshold_secs(config: &AgentsSection) -> Option<u64> {
+    env_or(
+        "AGENT_RELAY_IDLE_THRESHOLD_SECS",
+        config.idle_threshold_secs,
+    )
+}
+
+/// Whether `cli` is opted out of the auto-injected permission-bypass flag,
+/// by `AGENT_RELAY_NO_BYPASS_CLIS` (comma-separated) or else by
+/// `[cli.<name>] bypass_permissions = false`.
+fn bypass_flag_disabled_for(config: &BrokerConfigFile, cli: &str) -> bool {
+    match std::env::var("AGENT_RELAY_NO_BYPASS_CLIS") {
+        Ok(raw) => raw
+            .split(',')
+            .any(|entry| entry.trim().eq_ignore_ascii_case(cli)),
+        Err(_) => config.cli.iter().any(|(name, section)| {
+            name.eq_ignore_ascii_case(cli) && section.bypass_permissions == Some(false)
+        }),
+    }
 }
 
 impl WorkerRegistry {
@@ -98,6 +308,7 @@ impl WorkerRegistry {
         worker_env: Vec<(String, String)>,
         worker_logs_dir: PathBuf,
         broker_start: Instant,
+        config: Arc<BrokerConfigFile>,
     ) -> Self {
         if let Err(error) = std::fs::create_dir_all(&worker_logs_dir) {
             tracing::warn!(
@@ -112,9 +323,18 @@ impl WorkerRegistry {
             event_tx,
             worker_env,
             worker_logs_dir,
+            log_format: WorkerLogFormat::configured(&config.logs),
+            scrollback_lines: scrollback_lines(&config.agents),
             initial_tasks: HashMap::new(),
             supervisor: Supervisor::new(),
             metrics: MetricsCollector::new(broker_start),
+            draining: false,
+            max_concurrent: max_concurrent_agents(&config.agents),
+            starting: HashMap::new(),
+            failed_starts: Vec::new(),
+            discarding: HashMap::new(),
+            broker_scope: broker_scope(Path::new(""), "broker"),
+            config,
         }
     }
 
@@ -158,9 +378,32 @@ impl WorkerRegistry {
                     "last_activity_at": chrono::Utc::now()
                         - chrono::Duration::from_std(handle.last_activity_at.elapsed()).unwrap_or_default(),
                     "context_budget_pct": handle.context_budget_pct,
-                    "current_state": handle.state.as_str(),
+                    "current_state": if handle.paused_at.is_some() {
+                        "paused"
+                    } else {
+                        handle.state.as_str()
+                    },
+                    "paused": handle.paused_at.is_some(),
                 })
             })
+            .chain(self.starting.iter().map(|(name, starting)| {
+                let spec = &starting.launch.spec;
+                json!({
+                    "name": name,
+                    "runtime": spec.runtime,
+                    "provider": spec.provider.clone(),
+                    "cli": spec.cli,
+                    "model": spec.model,
+                    "sessionId": spec.session_id,
+                    "team": spec.team,
+                    "channels": spec.channels,
+                    "parent": starting.launch.parent,
+                    "pid": Value::Null,
+                    "workerPid": Value::Null,
+                    "current_state": "starting",
+                    "paused": false,
+                })
+            }))
             .collect()
     }
 
@@ -205,8 +448,85 @@ impl WorkerRegistry {
         .await
     }
 
+    /// The `docker run` for a docker-runtime agent. Forwards the relay env
+    /// the PTY worker would get; values are read from the worker's env.
+    fn docker_run(
+        &self,
+        spec: &AgentSpec,
+        agent_result: Option<&AgentResultMcpConfig>,
+    ) -> Result<docker::DockerRun> {
+        let image = docker::docker_image(spec, &self.config.docker)?;
+        let workdir = std::path::absolute(spec.cwd.as_deref().unwrap_or("."))
+            .context("failed to resolve docker agent cwd")?;
+        let mut env_keys: Vec<String> = self.worker_env.iter().map(|(k, _)| k.clone()).collect();
+        env_keys.extend(docker::FORWARDED_ENV_KEYS.iter().map(|k| k.to_string()));
+        if let Some(config) = agent_result {
+            env_keys.extend(config.env_pairs().into_iter().map(|(k, _)| k.to_string()));
+        }
+        env_keys.sort();
+        env_keys.dedup();
+        Ok(docker::DockerRun {
+            container: docker::container_name(&self.broker_scope, &spec.name),
+            broker_scope: self.broker_scope.clone(),
+            image,
+            workdir,
+            env_keys,
+            limits: spec.limits,
+            extra_args: docker::extra_run_args(&self.config.docker)?,
+        })
+    }
+
+    /// The relay env a PTY worker's CLI would see, with values, for agents
+    /// that do not inherit the worker's environment.
+    fn remote_agent_env(
+        &self,
+        spec: &AgentSpec,
+        worker_relay_api_key: Option<&str>,
+        skip_relay_prompt: bool,
+        agent_result: Option<&AgentResultMcpConfig>,
+    ) -> Vec<(String, String)> {
+        let mut env = self.worker_env.clone();
+        let mut set = |key: &str, value: String| env.push((key.to_string(), value));
+        if !skip_relay_prompt {
+            if let Some(relay_key) = worker_relay_api_key {
+                set("RELAY_AGENT_TOKEN", relay_key.to_string());
+            }
+            set("RELAY_AGENT_NAME", spec.name.to_string());
+            set("RELAY_AGENT_TYPE", "agent".to_string());
+            set("RELAY_STRICT_AGENT_NAME", "1".to_string());
+        }
+        if let Some(harness) = spec
+            .cli
+            .as_deref()
+            .and_then(crate::telemetry::infer_harness_from_command)
+        {
+            set(
+                "AGENT_RELAY_ORIGIN_ACTOR",
+                crate::telemetry::agent_origin_actor(harness, spec.model.as_deref()),
+            );
+        }
+        if let Some(config) = agent_result {
+            for (key, value) in config.env_pairs() {
+                set(key, value);
+            }
+        }
+        set("CLAUDE_CODE_ENABLE_PROMPT_SUGGESTION", "false".to_string());
+        set("DISABLE_AUTOUPDATER", "1".to_string());
+        env
+    }
+
     pub(crate) fn has_worker(&self, name: &str) -> bool {
-        self.workers.contains_key(name)
+        self.workers.contains_key(name) || self.starting.contains_key(name)
+    }
+
+    /// Sender for events produced by background worker tasks.
+    pub(crate) fn event_sender(&self) -> mpsc::Sender<WorkerEvent> {
+        self.event_tx.clone()
+    }
+
+    /// The worker was spawned but its remote setup has not finished.
+    pub(crate) fn is_starting(&self, name: &str) -> bool {
+        self.starting.contains_key(name)
     }
 
     /// True when a worker named `name` exists and either has no recorded
@@ -228,6 +548,34 @@ impl WorkerRegistry {
         }
     }
 
+    /// Workers addressed by an `@<team>` send from `from` in `workspace_id`;
+    /// empty when `target` names no local team.
+    pub(crate) fn team_members_for_target(
+        &self,
+        target: &str,
+        from: &str,
+        workspace_id: &crate::ids::WorkspaceId,
+    ) -> Vec<String> {
+        let routing_workers: Vec<RoutingWorker<'_>> = self
+            .workers
+            .iter()
+            .map(|(name, handle)| RoutingWorker {
+                name: name.as_str(),
+                channels: &handle.spec.channels,
+                workspace_id: handle.workspace_id.as_deref(),
+                team: handle.spec.team.as_deref(),
+            })
+            .collect();
+        let mut members = worker_names_for_team_target(
+            &routing_workers,
+            target,
+            from,
+            Some(workspace_id.as_str()),
+        );
+        members.sort();
+        members
+    }
+
     pub(crate) fn worker_pid(&self, name: &str) -> Option<u32> {
         self.workers.get(name).and_then(|h| h.child.id())
     }
@@ -236,6 +584,57 @@ impl WorkerRegistry {
         self.workers.get(name).and_then(|h| h.harness_pid)
     }
 
+    /// Whether another worker fits under [`Self::max_concurrent`].
+    pub(crate) fn has_capacity(&self) -> bool {
+        self.free_slots().is_none_or(|free| free > 0)
+    }
+
+    /// Workers counted against `max_concurrent`: the running ones and the
+    /// ones waiting on remote setup.
+    pub(crate) fn occupied_slots(&self) -> usize {
+        self.workers.len() + self.starting.len()
+    }
+
+    /// Workers that can still start under `max_concurrent`; `None` when
+    /// there is no cap.
+    pub(crate) fn free_slots(&self) -> Option<usize> {
+        self.max_concurrent
+            .map(|max| max.saturating_sub(self.occupied_slots()))
+    }
+
+    pub(crate) fn is_paused(&self, name: &str) -> bool {
+        self.workers
+            .get(name)
+            .is_some_and(|h| h.paused_at.is_some())
+    }
+
+    /// Stop (`paused = true`) or continue the worker's process tree. Returns
+    /// false when the worker was already in the requested state. Only local
+    /// agents can be paused: for the other runtimes the tree is just the
+    /// `docker`, `kubectl` or `ssh` client, and the CLI would keep running.
+    pub(crate) fn set_paused(&mut self, name: &str, paused: bool) -> Result<bool> {
+        let handle = self
+            .workers
+            .get_mut(name)
+            .with_context(|| format!("agent_not_found: no worker named '{name}'"))?;
+        if !handle.spec.runtime.is_local() {
+            anyhow::bail!(
+                "unsupported_operation: agent '{name}' does not run on this host and cannot be paused"
+            );
+        }
+        if handle.paused_at.is_some() == paused {
+            return Ok(false);
+        }
+        crate::spawner::suspend_child_tree(&handle.child, &worker_extra_groups(handle), paused)?;
+        handle.paused_at = paused.then(Instant::now);
+        if !paused {
+            // Time spent frozen is not inactivity.
+            handle.last_activity_at = Instant::now();
+        }
+        tracing::info!(target = "broker::pause", name = %name, paused, "worker pause state changed");
+        Ok(true)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub(crate) async fn spawn(
         &mut self,
@@ -247,10 +646,27 @@ impl WorkerRegistry {
         workspace_id: Option<crate::ids::WorkspaceId>,
         agent_result: Option<AgentResultMcpConfig>,
     ) -> Result<AgentSpec> {
-        let mut spec = spec;
-        if self.workers.contains_key(&spec.name) {
+        if self.draining {
+            anyhow::bail!(
+                "broker_draining: not accepting new agents while the broker drains (requested '{}')",
+                spec.name
+            );
+        }
+        if self.has_worker(&spec.name) {
             anyhow::bail!("agent '{}' already exists", spec.name);
         }
+        // Every spawn path ends here, so this is what holds the cap; the
+        // runtime queues API and node spawns before they get this far.
+        if !self.has_capacity() {
+            anyhow::bail!(
+                "at_capacity: {} agents already running (requested '{}')",
+                self.occupied_slots(),
+                spec.name
+            );
+        }
+        let idle_threshold_secs =
+            idle_threshold_secs.or_else(|| default_idle_threshold_secs(&self.config.agents));
+        let mut spec = spec;
 
         tracing::info!(
             target = "broker::spawn",
@@ -267,6 +683,7 @@ impl WorkerRegistry {
         let mut harness_env: Vec<(String, String)> = Vec::new();
         let mut suppress_worker_env: Vec<&'static str> = Vec::new();
         let mut initial_harness_pid: Option<u32> = None;
+        let mut remote_setup: Option<RemoteSetup> = None;
 
         match spec.harness_config.clone() {
             Some(ResolvedHarnessConfig::Pty(config)) => {
@@ -362,20 +779,28 @@ impl WorkerRegistry {
                 // This means any actor who can trigger agent.add gets agents with no permission
                 // guardrails. Future work should make this an explicit opt-in per step/agent.
                 let bypass_flag: Option<&str> = if is_claude
+                    && !bypass_flag_disabled_for(&self.config, "claude")
                     && !effective_args
                         .iter()
                         .any(|a| a.contains("dangerously-skip-permissions"))
                 {
                     Some("--dangerously-skip-permissions")
                 } else if is_codex
+                    && !bypass_flag_disabled_for(&self.config, "codex")
                     && !effective_args
                         .iter()
                         .any(|a| a.contains("dangerously-bypass") || a.contains("full-auto"))
                 {
                     Some("--dangerously-bypass-approvals-and-sandbox")
-                } else if is_gemini && !effective_args.iter().any(|a| a == "--yolo" || a == "-y") {
+                } else if is_gemini
+                    && !bypass_flag_disabled_for(&self.config, "gemini")
+                    && !effective_args.iter().any(|a| a == "--yolo" || a == "-y")
+                {
                     Some("--yolo")
-                } else if is_grok && !effective_args.iter().any(|a| a == "--always-approve") {
+                } else if is_grok
+                    && !bypass_flag_disabled_for(&self.config, "grok")
+                    && !effective_args.iter().any(|a| a == "--always-approve")
+                {
                     Some("--always-approve")
                 } else {
                     None
@@ -483,7 +908,10 @@ impl WorkerRegistry {
                 }
             }
             None => match spec.runtime {
-                AgentRuntime::Pty => {
+                AgentRuntime::Pty
+                | AgentRuntime::Docker
+                | AgentRuntime::Kubernetes
+                | AgentRuntime::Ssh { .. } => {
                     let cli = spec.cli.as_deref().context("pty runtime requires `cli`")?;
                     let (resolved_cli, inline_cli_args) = parse_cli_command(cli)
                         .with_context(|| format!("invalid CLI command '{cli}'"))?;
@@ -496,7 +924,6 @@ impl WorkerRegistry {
                     if let Some(secs) = idle_threshold_secs {
                         command.arg("--idle-threshold-secs").arg(secs.to_string());
                     }
-                    command.arg(&resolved_cli);
 
                     let cli_lower = normalized_cli.to_lowercase();
                     let is_claude = cli_lower == "claude" || cli_lower.starts_with("claude:");
@@ -523,6 +950,9 @@ impl WorkerRegistry {
                                     spec.session_id = Some(thread_id);
                                 }
                                 CodexSessionReference::Unknown => {}
+                                // The host's codex would create the thread,
+                                // not the one in the container or pod.
+                                CodexSessionReference::None if !spec.runtime.is_local() => {}
                                 CodexSessionReference::None => {
                                     if codex_has_positional_arg(&effective_args) {
                                         tracing::debug!(
@@ -566,22 +996,28 @@ impl WorkerRegistry {
                     // This means any actor who can trigger agent.add gets agents with no permission
                     // guardrails. Future work should make this an explicit opt-in per step/agent.
                     let bypass_flag: Option<&str> = if is_claude
+                        && !bypass_flag_disabled_for(&self.config, "claude")
                         && !effective_args
                             .iter()
                             .any(|a| a.contains("dangerously-skip-permissions"))
                     {
                         Some("--dangerously-skip-permissions")
                     } else if is_codex
+                        && !bypass_flag_disabled_for(&self.config, "codex")
                         && !effective_args
                             .iter()
                             .any(|a| a.contains("dangerously-bypass") || a.contains("full-auto"))
                     {
                         Some("--dangerously-bypass-approvals-and-sandbox")
                     } else if is_gemini
+                        && !bypass_flag_disabled_for(&self.config, "gemini")
                         && !effective_args.iter().any(|a| a == "--yolo" || a == "-y")
                     {
                         Some("--yolo")
-                    } else if is_grok && !effective_args.iter().any(|a| a == "--always-approve") {
+                    } else if is_grok
+                        && !bypass_flag_disabled_for(&self.config, "grok")
+                        && !effective_args.iter().any(|a| a == "--always-approve")
+                    {
                         Some("--always-approve")
                     } else {
                         None
@@ -595,17 +1031,39 @@ impl WorkerRegistry {
                         );
                     }
 
-                    let mcp_args = self
-                        .build_mcp_args(
+                    // MCP config files for an ssh agent are written to a
+                    // local staging dir and copied to the remote cwd.
+                    let mcp_staging = match spec.runtime {
+                        AgentRuntime::Ssh { .. } => {
+                            Some(tempfile::tempdir().context("failed to create MCP staging dir")?)
+                        }
+                        _ => None,
+                    };
+                    let mcp_args = if mcp_staging.is_some()
+                        && ssh::HOST_CONFIGURED_MCP_CLIS.contains(&cli_lower.as_str())
+                    {
+                        tracing::warn!(
+                            worker = %spec.name,
+                            cli = %cli_lower,
+                            "not configuring Agent Relay MCP for ssh agent; configure it on the remote host"
+                        );
+                        Vec::new()
+                    } else {
+                        let mcp_cwd = match &mcp_staging {
+                            Some(dir) => dir.path().to_path_buf(),
+                            None => PathBuf::from(spec.cwd.as_deref().unwrap_or(".")),
+                        };
+                        self.build_mcp_args(
                             cli,
                             &spec.name,
                             &effective_args,
-                            Path::new(spec.cwd.as_deref().unwrap_or(".")),
+                            &mcp_cwd,
                             worker_relay_api_key.as_deref(),
                             skip_relay_prompt,
                             agent_result.as_ref(),
                         )
-                        .await?;
+                        .await?
+                    };
 
                     let model_flag = resolve_model_flag_for_cli(
                         &resolved_cli,
@@ -619,28 +1077,98 @@ impl WorkerRegistry {
                         spec.model = Some(model.clone());
                     }
 
-                    let has_extra = bypass_flag.is_some()
-                        || model_flag.is_some()
-                        || !effective_args.is_empty()
-                        || !mcp_args.is_empty()
-                        || !harness_session_args.is_empty();
-                    if has_extra {
-                        command.arg("--");
-                        if let Some(flag) = bypass_flag {
-                            command.arg(flag);
+                    let mut harness_args = Vec::new();
+                    if let Some(flag) = bypass_flag {
+                        harness_args.push(flag.to_string());
+                    }
+                    if let Some(ref model) = model_flag {
+                        harness_args.push("--model".to_string());
+                        harness_args.push(model.clone());
+                    }
+                    harness_args.extend(mcp_args);
+                    harness_args.extend(effective_args);
+                    harness_args.extend(harness_session_args);
+
+                    match spec.runtime {
+                        AgentRuntime::Docker => {
+                            let run_args = self
+                                .docker_run(&spec, agent_result.as_ref())?
+                                .args(&resolved_cli, &harness_args);
+                            remote_setup = Some(RemoteSetup::Container {
+                                container: docker::container_name(&self.broker_scope, &spec.name),
+                                broker_scope: self.broker_scope.clone(),
+                            });
+                            command.arg("docker").arg("--").args(run_args);
                         }
-                        if let Some(ref model) = model_flag {
-                            command.arg("--model");
-                            command.arg(model);
+                        AgentRuntime::Kubernetes => {
+                            let pod = kubernetes::pod_name(&self.broker_scope, &spec.name);
+                            let agent_pod = kubernetes::AgentPod {
+                                pod: pod.clone(),
+                                broker_scope: self.broker_scope.clone(),
+                                image: spec.image.clone(),
+                                cli: resolved_cli,
+                                args: harness_args,
+                                env: self.remote_agent_env(
+                                    &spec,
+                                    worker_relay_api_key.as_deref(),
+                                    skip_relay_prompt,
+                                    agent_result.as_ref(),
+                                ),
+                                limits: spec.limits,
+                            };
+                            let (manifest, container) = agent_pod
+                                .manifest(&kubernetes::pod_template(&self.config.kubernetes)?)?;
+                            let namespace = kubernetes::namespace(&self.config.kubernetes);
+                            command.arg(kubernetes::kubectl_bin()).arg("--").args(
+                                kubernetes::attach_args(namespace.as_deref(), &pod, &container),
+                            );
+                            remote_setup = Some(RemoteSetup::Pod {
+                                namespace,
+                                pod: pod.clone(),
+                                broker_scope: self.broker_scope.clone(),
+                                secret: agent_pod.secret(),
+                                manifest,
+                            });
                         }
-                        for arg in &mcp_args {
-                            command.arg(arg);
-                        }
-                        for arg in &effective_args {
-                            command.arg(arg);
+                        AgentRuntime::Ssh { ref host, ref user } => {
+                            if !spec.limits.is_empty() {
+                                tracing::warn!(
+                                    worker = %spec.name,
+                                    host = %host,
+                                    "resource limits are not enforced for ssh agents"
+                                );
+                            }
+                            let remote = ssh::SshAgent {
+                                host,
+                                user: user.as_deref(),
+                                name: &spec.name,
+                                cwd: spec.cwd.as_deref(),
+                                extra_args: ssh::extra_ssh_args(&self.config.ssh)?,
+                            };
+                            let files = match &mcp_staging {
+                                Some(dir) => ssh::staged_files(dir.path())?,
+                                None => Vec::new(),
+                            };
+                            let script = remote.provision_script(
+                                &self.remote_agent_env(
+                                    &spec,
+                                    worker_relay_api_key.as_deref(),
+                                    skip_relay_prompt,
+                                    agent_result.as_ref(),
+                                ),
+                                &files,
+                            )?;
+                            remote_setup = Some(RemoteSetup::Ssh(remote.provisioning(script)));
+                            command
+                                .arg(ssh::ssh_bin())
+                                .arg("--")
+                                .args(remote.session_args(&resolved_cli, &harness_args)?);
                         }
-                        for arg in &harness_session_args {
-                            command.arg(arg);
+                        _ => {
+                            command.arg(&resolved_cli);
+                            if !harness_args.is_empty() {
+                                command.arg("--").args(&harness_args);
+                            }
                         }
                     }
                 }
@@ -649,7 +1177,14 @@ impl WorkerRegistry {
                         .provider
                         .as_ref()
                         .context("headless runtime requires `provider`")?;
+                    let template = headless_provider_template(provider, &self.config.headless)?;
                     command.arg("headless");
+                    // The worker does not load the config file; hand it the
+                    // one template it runs.
+                    if matches!(provider, HeadlessProvider::Template(_)) {
+                        command.env(HEADLESS_TEMPLATE_ENV, serde_json::to_string(&template)?);
+                    }
+                    let model_flag = template.model_flag;
                     command.arg("--agent-name").arg(&spec.name);
                     let provider_cli = headless_provider_cli_name(provider);
                     command.arg(provider_cli);
@@ -688,10 +1223,22 @@ impl WorkerRegistry {
                         spec.model = Some(model.clone());
                     }
 
+                    let model_arg = match (model_arg, model_flag) {
+                        (Some(model), Some(flag)) => Some((flag, model)),
+                        (Some(_), None) => {
+                            tracing::warn!(
+                                worker = %spec.name,
+                                provider = %provider_cli,
+                                "headless provider template has no model_flag; ignoring model"
+                            );
+                            None
+                        }
+                        (None, _) => None,
+                    };
                     if model_arg.is_some() || !spec.args.is_empty() || !mcp_args.is_empty() {
                         command.arg("--");
-                        if let Some(model) = model_arg {
-                            command.arg("--model");
+                        if let Some((flag, model)) = model_arg {
+                            command.arg(flag);
                             command.arg(model);
                         }
                         for arg in &mcp_args {
@@ -709,6 +1256,12 @@ impl WorkerRegistry {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        // Lead a fresh process group so release can signal the whole tree
+        // (MCP servers, shells) instead of only the direct child. This also
+        // keeps terminal Ctrl-C aimed at the broker, which releases workers
+        // in order during shutdown.
+        #[cfg(unix)]
+        command.process_group(0);
         for (key, value) in &self.worker_env {
             if suppress_worker_env.contains(&key.as_str()) {
                 continue;
@@ -743,7 +1296,7 @@ impl WorkerRegistry {
                 command.env(key, value);
             }
         }
-        if !skip_relay_prompt && matches!(spec.runtime, AgentRuntime::Pty) {
+        if !skip_relay_prompt && spec.runtime != AgentRuntime::Headless {
             if let Some(relay_key) = worker_relay_api_key {
                 command.env("RELAY_AGENT_TOKEN", relay_key);
             }
@@ -758,11 +1311,134 @@ impl WorkerRegistry {
             command.current_dir(cwd);
         }
 
-        let mut child = command.spawn().context("failed to spawn worker")?;
+        let launch = WorkerLaunch {
+            command,
+            spec,
+            parent,
+            workspace_id,
+            initial_harness_pid,
+            idle_threshold_secs,
+            skip_relay_prompt,
+            agent_result,
+        };
+        let Some(setup) = remote_setup else {
+            return self.launch(launch).await;
+        };
+        Ok(self.start_remote_setup(launch, setup))
+    }
+
+    /// Run `setup` in the background, holding the agent as starting until
+    /// [`Self::finish_start`] hears back.
+    fn start_remote_setup(&mut self, launch: WorkerLaunch, setup: RemoteSetup) -> AgentSpec {
+        let spec = launch.spec.clone();
+        let event_tx = self.event_tx.clone();
+        let name = spec.name.clone();
+        let prior_discard = self.discarding.remove(&name);
+        let task = tokio::spawn(async move {
+            if let Some(prior_discard) = prior_discard {
+                let _ = prior_discard.await;
+            }
+            let result = setup.run().await.map_err(|error| format!("{error:#}"));
+            let _ = event_tx
+                .send(WorkerEvent::RemoteSetup { name, result })
+                .await;
+        });
+        tracing::info!(
+            target = "broker::spawn",
+            name = %spec.name,
+            "worker waiting on remote setup"
+        );
+        self.starting
+            .insert(spec.name.clone(), StartingWorker { launch, task });
+        spec
+    }
+
+    /// Remove the container or pod behind a non-local agent without
+    /// waiting for it; see [`Self::discarding`].
+    fn discard_in_background(&mut self, spec: AgentSpec) {
+        if spec.runtime.is_local() {
+            return;
+        }
+        self.discarding.retain(|_, task| !task.is_finished());
+        let prior = self.discarding.remove(&spec.name);
+        let name = spec.name.clone();
+        let broker_scope = self.broker_scope.clone();
+        let namespace = kubernetes::namespace(&self.config.kubernetes);
+        let task = tokio::spawn(async move {
+            if let Some(prior) = prior {
+                let _ = prior.await;
+            }
+            discard_remote_agent(&spec, &broker_scope, namespace.as_deref()).await;
+        });
+        self.discarding.insert(name, task);
+    }
+
+    /// Start a worker whose remote setup finished. Returns the worker's pid
+    /// once it runs; a failure is reported by the next [`Self::reap_exited`].
+    pub(crate) async fn finish_start(
+        &mut self,
+        name: &WorkerName,
+        result: Result<(), String>,
+    ) -> Option<u32> {
+        // Released while its setup ran.
+        let starting = self.starting.remove(name)?;
+        let error = match result {
+            Ok(()) => match self.launch(starting.launch).await {
+                Ok(_) => return self.worker_pid(name),
+                // A worker that did launch is reaped like any other.
+                Err(_) if self.workers.contains_key(name) => return None,
+                Err(error) => format!("{error:#}"),
+            },
+            Err(error) => {
+                self.discard_in_background(starting.launch.spec);
+                error
+            }
+        };
+        tracing::warn!(
+            target = "broker::spawn",
+            name = %name,
+            error = %error,
+            "remote worker failed to start"
+        );
+        self.failed_starts
+            .push((name.clone(), format!("remote_setup_failed: {error}")));
+        None
+    }
+
+    async fn launch(&mut self, launch: WorkerLaunch) -> Result<AgentSpec> {
+        let WorkerLaunch {
+            mut command,
+            spec,
+            parent,
+            workspace_id,
+            initial_harness_pid,
+            idle_threshold_secs,
+            skip_relay_prompt,
+            agent_result,
+        } = launch;
+
+        // Containerised agents get their limits from docker or the pod spec.
+        let cgroup = if spec.runtime.is_local() {
+            limits::prepare_limits(&mut command, spec.name.as_str(), &spec.limits)
+        } else {
+            None
+        };
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                if let Some(cgroup) = &cgroup {
+                    cgroup.remove();
+                }
+                self.discard_in_background(spec);
+                return Err(error).context("failed to spawn worker");
+            }
+        };
         let stdin = child.stdin.take().context("worker missing stdin pipe")?;
         let stdout = child.stdout.take().context("worker missing stdout pipe")?;
         let stderr = child.stderr.take().context("worker missing stderr pipe")?;
         let log_file = self.worker_log_path(&spec.name);
+        let current_delivery = CurrentDelivery::default();
 
         spawn_worker_reader(
             self.event_tx.clone(),
@@ -771,6 +1447,8 @@ impl WorkerRegistry {
             stdout,
             true,
             log_file.clone(),
+            self.log_format,
+            current_delivery.clone(),
         );
         spawn_worker_reader(
             self.event_tx.clone(),
@@ -779,6 +1457,8 @@ impl WorkerRegistry {
             stderr,
             false,
             log_file,
+            self.log_format,
+            current_delivery,
         );
 
         let handle = WorkerHandle {
@@ -789,10 +1469,20 @@ impl WorkerRegistry {
             stdin,
             harness_pid: initial_harness_pid,
             spawned_at: Instant::now(),
+            ready: false,
             last_activity_at: Instant::now(),
             context_budget_pct: None,
             state: AgentWorkState::Working,
             exit_reason: None,
+            scrollback: OutputScrollback::new(self.scrollback_lines),
+            cpu_sample: None,
+            cpu_percent: 0.0,
+            paused_at: None,
+            idle_threshold_secs,
+            skip_relay_prompt,
+            agent_result,
+            cgroup,
+            over_memory_limit: false,
         };
         self.workers.insert(spec.name.clone(), handle);
 
@@ -867,29 +1557,56 @@ impl WorkerRegistry {
             .await
     }
 
-    pub(crate) async fn release(&mut self, name: &str) -> Result<()> {
+    pub(crate) async fn release(&mut self, name: &str) -> Result<TerminationReport> {
         tracing::info!(target = "broker::release", name = %name, "releasing worker");
         self.initial_tasks.remove(name);
+        if let Some(starting) = self.starting.remove(name) {
+            starting.task.abort();
+            self.discard_in_background(starting.launch.spec);
+            return Ok(TerminationReport::default());
+        }
         let mut handle = self
             .workers
             .remove(name)
             .with_context(|| format!("unknown worker '{name}'"))?;
-        let release_grace = release_grace_for_spec(&handle.spec);
+        let escalation = kill_escalation_for_spec(&handle.spec);
+        let extra_groups = worker_extra_groups(&handle);
+        if handle.paused_at.is_some() {
+            // Let a paused worker read the shutdown frame below.
+            if let Err(error) =
+                crate::spawner::suspend_child_tree(&handle.child, &extra_groups, false)
+            {
+                tracing::debug!(target = "broker::release", name = %name, error = %error, "failed to resume paused worker before release");
+            }
+        }
 
         let shutdown_frame = ProtocolEnvelope {
             v: PROTOCOL_VERSION,
             msg_type: "shutdown_worker".to_string(),
             request_id: None,
-            payload: json!({"reason":"release","grace_ms": release_grace.as_millis() as u64}),
+            payload: json!({"reason":"release","grace_ms": escalation.term_grace.as_millis() as u64}),
         };
         let encoded = serde_json::to_string(&shutdown_frame)?;
         let _ = handle.stdin.write_all(encoded.as_bytes()).await;
         let _ = handle.stdin.write_all(b"\n").await;
         let _ = handle.stdin.flush().await;
 
-        let result = terminate_child(&mut handle.child, release_grace).await;
+        let result = terminate_child_tree(&mut handle.child, &extra_groups, escalation).await;
+        handle.discard_cgroup();
+        self.discard_in_background(handle.spec.clone());
         match &result {
-            Ok(()) => tracing::info!(target = "broker::release", name = %name, "worker released"),
+            Ok(report) if !report.survivors.is_empty() => tracing::warn!(
+                target = "broker::release",
+                name = %name,
+                survivors = ?report.survivors,
+                "worker released but processes survived SIGKILL"
+            ),
+            Ok(report) => tracing::info!(
+                target = "broker::release",
+                name = %name,
+                escalated = report.escalated,
+                "worker released"
+            ),
             Err(error) => {
                 tracing::warn!(target = "broker::release", name = %name, error = %error, "worker release failed")
             }
@@ -898,12 +1615,20 @@ impl WorkerRegistry {
     }
 
     pub(crate) async fn shutdown_all(&mut self) -> Result<()> {
-        let names: Vec<WorkerName> = self.workers.keys().cloned().collect();
+        let names: Vec<WorkerName> = self
+            .workers
+            .keys()
+            .chain(self.starting.keys())
+            .cloned()
+            .collect();
         for name in names {
             if let Err(error) = self.release(&name).await {
                 tracing::warn!(target = "agent_relay::broker", name = %name, error = %error, "worker shutdown failed");
             }
         }
+        for (_, task) in self.discarding.drain() {
+            let _ = task.await;
+        }
         Ok(())
     }
 
@@ -912,6 +1637,10 @@ impl WorkerRegistry {
     ) -> Result<Vec<(WorkerName, Option<i32>, Option<String>, Option<String>)>> {
         let names: Vec<WorkerName> = self.workers.keys().cloned().collect();
         let mut exited = Vec::new();
+        for (name, reason) in std::mem::take(&mut self.failed_starts) {
+            self.initial_tasks.remove(&name);
+            exited.push((name, None, None, Some(reason)));
+        }
         for name in names {
             let (status, gone_via_kill0) = if let Some(handle) = self.workers.get_mut(&name) {
                 match handle.child.try_wait() {
@@ -974,25 +1703,32 @@ impl WorkerRegistry {
                 };
                 #[cfg(not(unix))]
                 let signal: Option<String> = None;
-                let reason = self
-                    .workers
-                    .get(&name)
-                    .and_then(|handle| handle.exit_reason.clone());
-                self.workers.remove(&name);
-                self.initial_tasks.remove(&name);
+                let reason = self.remove_exited(&name);
                 exited.push((name, code, signal, reason));
             } else if gone_via_kill0 {
-                let reason = self
-                    .workers
-                    .get(&name)
-                    .and_then(|handle| handle.exit_reason.clone());
-                self.workers.remove(&name);
-                self.initial_tasks.remove(&name);
+                let reason = self.remove_exited(&name);
                 exited.push((name, None, None, reason));
             }
         }
         Ok(exited)
     }
+
+    /// Drop an exited worker and return its exit reason. A cgroup OOM kill
+    /// is reported as [`limits::MEMORY_LIMIT_EXIT_REASON`] unless the worker
+    /// gave a reason of its own.
+    fn remove_exited(&mut self, name: &WorkerName) -> Option<String> {
+        self.initial_tasks.remove(name);
+        let mut handle = self.workers.remove(name)?;
+        let oom_killed = handle
+            .cgroup
+            .as_ref()
+            .is_some_and(|cgroup| cgroup.oom_kills() > 0);
+        handle.discard_cgroup();
+        self.discard_in_background(handle.spec.clone());
+        handle
+            .exit_reason
+            .or_else(|| oom_killed.then(|| limits::MEMORY_LIMIT_EXIT_REASON.to_string()))
+    }
 }
 
 fn release_policy_arg(policy: Option<&HarnessReleasePolicy>) -> &'static str {
@@ -1018,10 +1754,28 @@ fn release_grace_for_spec(spec: &AgentSpec) -> Duration {
         {
             APP_SERVER_RELEASE_GRACE
         }
-        _ => DEFAULT_RELEASE_GRACE,
+        _ => grace_from_env("AGENT_RELAY_RELEASE_GRACE_MS").unwrap_or(DEFAULT_RELEASE_GRACE),
     }
 }
 
+/// SIGTERM grace comes from the spec (app-server aborts need far longer than
+/// a PTY exit); the SIGKILL grace is `AGENT_RELAY_KILL_GRACE_MS` or 1s.
+fn kill_escalation_for_spec(spec: &AgentSpec) -> KillEscalation {
+    KillEscalation {
+        term_grace: release_grace_for_spec(spec),
+        kill_grace: grace_from_env("AGENT_RELAY_KILL_GRACE_MS").unwrap_or(DEFAULT_KILL_GRACE),
+    }
+}
+
+fn grace_from_env(key: &str) -> Option<Duration> {
+    parse_grace_ms(std::env::var(key).ok().as_deref())
+}
+
+fn parse_grace_ms(raw: Option<&str>) -> Option<Duration> {
+    raw.and_then(|raw| raw.trim().parse::<u64>().ok())
+        .map(Duration::from_millis)
+}
+
 fn validate_app_server_config(config: &HeadlessHarnessConfig) -> Result<()> {
     if !matches!(&config.driver, HeadlessHarnessDriver::AppServer) {
         anyhow::bail!("unsupported headless harness driver");
@@ -1405,6 +2159,88 @@ fn codex_models_json_contains_model(bytes: &[u8], model: &str) -> Option<bool> {
     }))
 }
 
+/// On-disk format of `worker-logs/<name>.log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum WorkerLogFormat {
+    /// Raw output, one chunk per line.
+    #[default]
+    Text,
+    /// One JSON object per chunk: `{ts, stream, delivery_id, chunk}`.
+    Json,
+}
+
+impl WorkerLogFormat {
+    /// `AGENT_RELAY_WORKER_LOG_FORMAT=json` (or `[logs] worker_format =
+    /// "json"`) switches to JSON lines.
+    pub(crate) fn configured(config: &LogsSection) -> Self {
+        match env_or(
+            "AGENT_RELAY_WORKER_LOG_FORMAT",
+            config.worker_format.clone(),
+        ) {
+            Some(raw) => Self::parse(&raw).unwrap_or_else(|| {
+                tracing::warn!(
+                    value = %raw,
+                    "ignoring invalid worker log format; expected text or json"
+                );
+                Self::Text
+            }),
+            None => Self::Text,
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "" | "text" | "raw" => Some(Self::Text),
+            "json" | "jsonl" | "ndjson" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Render one worker log entry, newline-terminated when `terminate` is set
+/// (JSON entries always are).
+fn format_worker_log_entry(
+    format: WorkerLogFormat,
+    stream: &str,
+    delivery_id: Option<&str>,
+    chunk: &str,
+    terminate: bool,
+    timestamp: chrono::DateTime<chrono::Utc>,
+) -> String {
+    match format {
+        WorkerLogFormat::Text if terminate && !chunk.ends_with('\n') => format!("{chunk}\n"),
+        WorkerLogFormat::Text => chunk.to_string(),
+        WorkerLogFormat::Json => {
+            let mut entry = json!({
+                "ts": timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "stream": stream,
+                "delivery_id": delivery_id,
+                "chunk": chunk,
+            })
+            .to_string();
+            entry.push('\n');
+            entry
+        }
+    }
+}
+
+/// Delivery a worker frame refers to, used to tag the output that follows it.
+fn frame_delivery_id(frame: &Value) -> Option<&str> {
+    let msg_type = frame.get("type").and_then(Value::as_str)?;
+    if !msg_type.starts_with("delivery_") {
+        return None;
+    }
+    frame
+        .get("payload")
+        .and_then(|payload| payload.get("delivery_id"))
+        .and_then(Value::as_str)
+}
+
+/// Delivery a worker is working on, set by its stdout reader from delivery
+/// frames and read by both readers to tag log entries.
+type CurrentDelivery = std::sync::Arc<std::sync::Mutex<Option<String>>>;
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_worker_reader<R>(
     tx: mpsc::Sender<WorkerEvent>,
     name: WorkerName,
@@ -1412,6 +2248,8 @@ fn spawn_worker_reader<R>(
     reader: R,
     parse_json: bool,
     log_file_path: Option<PathBuf>,
+    log_format: WorkerLogFormat,
+    current_delivery: CurrentDelivery,
 ) where
     R: tokio::io::AsyncRead + Unpin + Send + 'static,
 {
@@ -1420,8 +2258,7 @@ fn spawn_worker_reader<R>(
         log_file_path: &Option<PathBuf>,
         disable_log_file: &mut bool,
         worker_name: &str,
-        chunk: &str,
-        append_newline_if_missing: bool,
+        entry: &str,
     ) {
         if *disable_log_file {
             return;
@@ -1430,7 +2267,7 @@ fn spawn_worker_reader<R>(
             return;
         };
 
-        if let Err(error) = file.write_all(chunk.as_bytes()).await {
+        if let Err(error) = file.write_all(entry.as_bytes()).await {
             if let Some(path) = log_file_path.as_ref() {
                 tracing::warn!(
                     worker = %worker_name,
@@ -1441,22 +2278,6 @@ fn spawn_worker_reader<R>(
             }
             *disable_log_file = true;
             *log_file = None;
-            return;
-        }
-
-        if append_newline_if_missing && !chunk.ends_with('\n') {
-            if let Err(error) = file.write_all(b"\n").await {
-                if let Some(path) = log_file_path.as_ref() {
-                    tracing::warn!(
-                        worker = %worker_name,
-                        path = %path.display(),
-                        error = %error,
-                        "failed writing newline to worker log"
-                    );
-                }
-                *disable_log_file = true;
-                *log_file = None;
-            }
         }
     }
 
@@ -1483,11 +2304,23 @@ fn spawn_worker_reader<R>(
         };
 
         let mut disable_log_file = false;
+        let delivery_id = || {
+            current_delivery
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone()
+        };
 
         let mut lines = BufReader::new(reader).lines();
         while let Ok(Some(line)) = lines.next_line().await {
             if parse_json {
                 if let Ok(value) = serde_json::from_str::<Value>(&line) {
+                    if let Some(id) = frame_delivery_id(&value) {
+                        *current_delivery
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                            Some(id.to_string());
+                    }
                     if value
                         .get("type")
                         .and_then(Value::as_str)
@@ -1498,13 +2331,20 @@ fn spawn_worker_reader<R>(
                             .and_then(|payload| payload.get("chunk"))
                             .and_then(Value::as_str)
                         {
+                            let entry = format_worker_log_entry(
+                                log_format,
+                                stream_name,
+                                delivery_id().as_deref(),
+                                chunk,
+                                false,
+                                chrono::Utc::now(),
+                            );
                             append_log_chunk(
                                 &mut log_file,
                                 &log_file_path,
                                 &mut disable_log_file,
                                 &name,
-                                chunk,
-                                false,
+                                &entry,
                             )
                             .await;
                         }
@@ -1523,13 +2363,20 @@ fn spawn_worker_reader<R>(
                 }
             }
 
+            let entry = format_worker_log_entry(
+                log_format,
+                stream_name,
+                delivery_id().as_deref(),
+                &line,
+                true,
+                chrono::Utc::now(),
+            );
             append_log_chunk(
                 &mut log_file,
                 &log_file_path,
                 &mut disable_log_file,
                 &name,
-                &line,
-                true,
+                &entry,
             )
             .await;
 
@@ -1568,11 +2415,17 @@ fn spawn_worker_reader<R>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::{AppServerHarnessAuth, AppServerHarnessHost};
+    use crate::protocol::{AgentResourceLimits, AppServerHarnessAuth, AppServerHarnessHost};
 
     fn make_registry(env: Vec<(String, String)>) -> WorkerRegistry {
         let (tx, _rx) = mpsc::channel::<WorkerEvent>(16);
-        WorkerRegistry::new(tx, env, PathBuf::from("/tmp/worker-tests"), Instant::now())
+        WorkerRegistry::new(
+            tx,
+            env,
+            PathBuf::from("/tmp/worker-tests"),
+            Instant::now(),
+            Arc::default(),
+        )
     }
 
     #[test]
@@ -1581,6 +2434,186 @@ mod tests {
         assert!(reg.list().is_empty());
     }
 
+    #[tokio::test]
+    async fn draining_registry_refuses_spawn() {
+        let mut reg = make_registry(vec![]);
+        reg.draining = true;
+        let spec = AgentSpec {
+            name: WorkerName::from("late-worker"),
+            runtime: AgentRuntime::Pty,
+            provider: None,
+            cli: Some("codex".to_string()),
+            session_id: None,
+            harness_config: None,
+            model: None,
+            cwd: None,
+            team: None,
+            shadow_of: None,
+            shadow_mode: None,
+            args: Vec::new(),
+            channels: Vec::new(),
+            restart_policy: None,
+            image: None,
+            limits: AgentResourceLimits::default(),
+        };
+
+        let error = reg
+            .spawn(spec, None, None, None, false, None, None)
+            .await
+            .expect_err("draining registry must refuse spawns");
+        assert!(error.to_string().starts_with("broker_draining"));
+        assert!(!reg.has_worker("late-worker"));
+    }
+
+    #[tokio::test]
+    async fn starting_worker_holds_its_name_until_setup_reports_back() {
+        let mut reg = make_registry(vec![]);
+        reg.max_concurrent = Some(1);
+        let name = WorkerName::from("remote-worker");
+        let spec = AgentSpec {
+            name: name.clone(),
+            runtime: AgentRuntime::Pty,
+            provider: None,
+            cli: Some("codex".to_string()),
+            session_id: None,
+            harness_config: None,
+            model: None,
+            cwd: None,
+            team: None,
+            shadow_of: None,
+            shadow_mode: None,
+            args: Vec::new(),
+            channels: Vec::new(),
+            restart_policy: None,
+            image: None,
+            limits: AgentResourceLimits::default(),
+        };
+        let starting = || StartingWorker {
+            launch: WorkerLaunch {
+                command: Command::new("true"),
+                spec: spec.clone(),
+                parent: None,
+                workspace_id: None,
+                initial_harness_pid: None,
+                idle_threshold_secs: None,
+                skip_relay_prompt: false,
+                agent_result: None,
+            },
+            task: tokio::spawn(async {}),
+        };
+        reg.starting.insert(name.clone(), starting());
+
+        assert!(reg.has_worker("remote-worker"));
+        assert!(reg.is_starting("remote-worker"));
+        assert!(!reg.has_capacity());
+        assert_eq!(reg.occupied_slots(), 1);
+        assert_eq!(reg.free_slots(), Some(0));
+        assert_eq!(reg.list()[0]["current_state"], "starting");
+        let error = reg
+            .spawn(spec.clone(), None, None, None, false, None, None)
+            .await
+            .expect_err("a starting worker's name is taken");
+        assert!(error.to_string().contains("already exists"), "{error}");
+
+        assert_eq!(
+            reg.finish_start(&name, Err("pod quota exceeded".to_string()))
+                .await,
+            None
+        );
+        assert!(!reg.has_worker("remote-worker"));
+        let exited = reg.reap_exited().await.unwrap();
+        assert_eq!(
+            exited,
+            vec![(
+                name.clone(),
+                None,
+                None,
+                Some("remote_setup_failed: pod quota exceeded".to_string())
+            )]
+        );
+        assert!(reg.reap_exited().await.unwrap().is_empty());
+
+        // Released while starting: the late setup result is ignored.
+        reg.starting.insert(name.clone(), starting());
+        reg.release("remote-worker").await.unwrap();
+        assert!(!reg.has_worker("remote-worker"));
+        assert_eq!(reg.finish_start(&name, Ok(())).await, None);
+        assert!(reg.reap_exited().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn remote_setup_waits_for_the_previous_discard_of_its_name() {
+        let (tx, mut rx) = mpsc::channel::<WorkerEvent>(16);
+        let mut reg = WorkerRegistry::new(
+            tx,
+            vec![],
+            PathBuf::from("/tmp/worker-tests"),
+            Instant::now(),
+            Arc::default(),
+        );
+        let name = WorkerName::from("docker-worker");
+        let (discarded_tx, discarded_rx) = tokio::sync::oneshot::channel::<()>();
+        reg.discarding.insert(
+            name.clone(),
+            tokio::spawn(async move {
+                let _ = discarded_rx.await;
+            }),
+        );
+        let launch = WorkerLaunch {
+            command: Command::new("true"),
+            spec: AgentSpec {
+                name: name.clone(),
+                runtime: AgentRuntime::Docker,
+                provider: None,
+                cli: Some("codex".to_string()),
+                session_id: None,
+                harness_config: None,
+                model: None,
+                cwd: None,
+                team: None,
+                shadow_of: None,
+                shadow_mode: None,
+                args: Vec::new(),
+                channels: Vec::new(),
+                restart_policy: None,
+                image: None,
+                limits: AgentResourceLimits::default(),
+            },
+            parent: None,
+            workspace_id: None,
+            initial_harness_pid: None,
+            idle_threshold_secs: None,
+            skip_relay_prompt: false,
+            agent_result: None,
+        };
+
+        reg.start_remote_setup(
+            launch,
+            RemoteSetup::Container {
+                container: "agent-relay-setup-order-test".to_string(),
+                broker_scope: reg.broker_scope.clone(),
+            },
+        );
+        assert!(reg.is_starting("docker-worker"));
+        assert!(reg.discarding.is_empty());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), rx.recv())
+                .await
+                .is_err(),
+            "setup must wait for the earlier container removal"
+        );
+
+        discarded_tx.send(()).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(40), rx.recv())
+            .await
+            .expect("setup reports back")
+            .expect("event channel open");
+        assert!(
+            matches!(&event, WorkerEvent::RemoteSetup { name: reported, .. } if *reported == name),
+            "{event:?}"
+        );
+    }
+
     #[test]
     fn has_worker_returns_false_for_unknown() {
         let reg = make_registry(vec![]);
@@ -1709,11 +2742,121 @@ mod tests {
             args: Vec::new(),
             channels: Vec::new(),
             restart_policy: None,
+            image: None,
+            limits: AgentResourceLimits::default(),
         };
 
         assert_eq!(release_grace_for_spec(&spec), APP_SERVER_RELEASE_GRACE);
     }
 
+    #[test]
+    fn parse_grace_ms_accepts_trimmed_millis_and_rejects_garbage() {
+        assert_eq!(
+            parse_grace_ms(Some(" 1500 ")),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(parse_grace_ms(Some("soon")), None);
+        assert_eq!(parse_grace_ms(None), None);
+    }
+
+    #[test]
+    fn worker_log_entries_render_as_text_or_json_lines() {
+        let ts = chrono::DateTime::parse_from_rfc3339("2026-03-01T12:00:00.250Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(
+            format_worker_log_entry(WorkerLogFormat::Text, "stderr", None, "oops", true, ts),
+            "oops\n"
+        );
+        assert_eq!(
+            format_worker_log_entry(WorkerLogFormat::Text, "stdout", None, "\x1b[2K", false, ts),
+            "\x1b[2K"
+        );
+
+        let entry = format_worker_log_entry(
+            WorkerLogFormat::Json,
+            "stdout",
+            Some("del_7"),
+            "done\n",
+            false,
+            ts,
+        );
+        assert!(entry.ends_with('\n'));
+        let value: Value = serde_json::from_str(entry.trim_end()).unwrap();
+        assert_eq!(value["ts"], "2026-03-01T12:00:00.250Z");
+        assert_eq!(value["stream"], "stdout");
+        assert_eq!(value["delivery_id"], "del_7");
+        assert_eq!(value["chunk"], "done\n");
+
+        assert_eq!(
+            WorkerLogFormat::parse(" JSONL "),
+            Some(WorkerLogFormat::Json)
+        );
+        assert_eq!(WorkerLogFormat::parse("text"), Some(WorkerLogFormat::Text));
+        assert_eq!(WorkerLogFormat::parse("xml"), None);
+    }
+
+    #[tokio::test]
+    async fn stderr_log_entries_carry_the_delivery_seen_on_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("worker.log");
+        let (tx, mut rx) = mpsc::channel::<WorkerEvent>(16);
+        let (mut stdout, stdout_reader) = tokio::io::duplex(1024);
+        let (mut stderr, stderr_reader) = tokio::io::duplex(1024);
+        let current_delivery = CurrentDelivery::default();
+        for (stream, reader, parse_json) in [
+            ("stdout", stdout_reader, true),
+            ("stderr", stderr_reader, false),
+        ] {
+            spawn_worker_reader(
+                tx.clone(),
+                WorkerName::from("worker"),
+                stream,
+                reader,
+                parse_json,
+                Some(log_path.clone()),
+                WorkerLogFormat::Json,
+                current_delivery.clone(),
+            );
+        }
+
+        stdout
+            .write_all(
+                b"{\"type\":\"delivery_injected\",\"payload\":{\"delivery_id\":\"del_9\"}}\n",
+            )
+            .await
+            .unwrap();
+        // The stdout reader has recorded the delivery once it forwards the frame.
+        rx.recv().await.expect("delivery frame forwarded");
+        stderr.write_all(b"tool failed\n").await.unwrap();
+        drop(stderr);
+
+        let entry = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let log = tokio::fs::read_to_string(&log_path)
+                    .await
+                    .unwrap_or_default();
+                if let Some(line) = log.lines().next() {
+                    return serde_json::from_str::<Value>(line).unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("stderr line logged");
+        assert_eq!(entry["stream"], "stderr");
+        assert_eq!(entry["delivery_id"], "del_9");
+        assert_eq!(entry["chunk"], "tool failed");
+    }
+
+    #[test]
+    fn frame_delivery_id_only_reads_delivery_frames() {
+        let injected = json!({"type": "delivery_injected", "payload": {"delivery_id": "del_1"}});
+        assert_eq!(frame_delivery_id(&injected), Some("del_1"));
+        let stream = json!({"type": "worker_stream", "payload": {"delivery_id": "del_1"}});
+        assert_eq!(frame_delivery_id(&stream), None);
+    }
+
     #[test]
     fn prepare_claude_session_args_generates_uuid_session_id() {
         let mut args = Vec::new();
@@ -0,0 +1,264 @@
+//! The `docker` agent runtime.
+//!
+//! A docker agent runs under the ordinary PTY worker with `docker run` as its
+//! harness, so injection, idle detection and scrollback behave as they do for
+//! a local PTY agent. The agent's cwd is bind-mounted at the same path inside
+//! the container and used as its working directory. Relay env vars are
+//! passed by name (`-e KEY`), which keeps tokens off the command line.
+//!
+//! Containers are named after the broker scope and the agent, and carry an
+//! `agent-relay.broker` label with the scope; stale-container cleanup only
+//! removes containers with this broker's label, so brokers sharing a docker
+//! host never remove each other's agents.
+//!
+//! The image must provide the agent CLI. `AGENT_RELAY_DOCKER_RUN_ARGS` (or
+//! `[docker] run_args`) adds options to every `docker run`, e.g.
+//! `--network host` when the relay or the broker API is only reachable on
+//! localhost.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    broker_config::{env_args_or, env_or, DockerSection},
+    protocol::{AgentResourceLimits, AgentSpec},
+};
+
+/// How long `docker rm -f` may take before it is given up on.
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Label holding the scope of the broker that owns a container.
+const BROKER_LABEL: &str = "agent-relay.broker";
+
+/// Env vars set on PTY workers outside `worker_env` that the CLI inside the
+/// container also needs.
+pub(crate) const FORWARDED_ENV_KEYS: [&str; 7] = [
+    "RELAY_AGENT_TOKEN",
+    "RELAY_AGENT_NAME",
+    "RELAY_AGENT_TYPE",
+    "RELAY_STRICT_AGENT_NAME",
+    "AGENT_RELAY_ORIGIN_ACTOR",
+    "CLAUDE_CODE_ENABLE_PROMPT_SUGGESTION",
+    "DISABLE_AUTOUPDATER",
+];
+
+/// Container name for an agent of the broker with `broker_scope`. Docker
+/// names allow `[a-zA-Z0-9_.-]`; other characters become `-`, and a hash of
+/// the agent name keeps names that sanitize alike apart.
+pub(crate) fn container_name(broker_scope: &str, agent: &str) -> String {
+    let sanitized: String = agent
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let hash = format!("{:x}", Sha256::digest(agent.as_bytes()));
+    format!("agent-relay-{broker_scope}-{sanitized}-{}", &hash[..8])
+}
+
+/// The spec's `image`, else `AGENT_RELAY_DOCKER_IMAGE` or `[docker] image`.
+pub(crate) fn docker_image(spec: &AgentSpec, config: &DockerSection) -> Result<String> {
+    spec.image
+        .clone()
+        .or_else(|| env_or("AGENT_RELAY_DOCKER_IMAGE", config.image.clone()))
+        .map(|image| image.trim().to_string())
+        .filter(|image| !image.is_empty())
+        .context("docker runtime requires `image` (or AGENT_RELAY_DOCKER_IMAGE)")
+}
+
+/// Extra `docker run` options from `AGENT_RELAY_DOCKER_RUN_ARGS` or
+/// `[docker] run_args`.
+pub(crate) fn extra_run_args(config: &DockerSection) -> Result<Vec<String>> {
+    env_args_or("AGENT_RELAY_DOCKER_RUN_ARGS", config.run_args.as_deref())
+}
+
+/// One `docker run` invocation for an agent.
+pub(crate) struct DockerRun {
+    pub(crate) container: String,
+    /// Set as the container's [`BROKER_LABEL`].
+    pub(crate) broker_scope: String,
+    pub(crate) image: String,
+    /// Host directory mounted at the same path and used as the workdir.
+    pub(crate) workdir: PathBuf,
+    /// Env vars forwarded from the worker process by name.
+    pub(crate) env_keys: Vec<String>,
+    pub(crate) limits: AgentResourceLimits,
+    pub(crate) extra_args: Vec<String>,
+}
+
+impl DockerRun {
+    /// Arguments to `docker` that run `cli cli_args` in the container.
+    pub(crate) fn args(&self, cli: &str, cli_args: &[String]) -> Vec<String> {
+        let workdir = self.workdir.display().to_string();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "-i".to_string(),
+            "-t".to_string(),
+            "--name".to_string(),
+            self.container.clone(),
+            "--label".to_string(),
+            format!("{BROKER_LABEL}={}", self.broker_scope),
+            "-v".to_string(),
+            format!("{workdir}:{workdir}"),
+            "-w".to_string(),
+            workdir,
+        ];
+        for key in &self.env_keys {
+            args.push("-e".to_string());
+            args.push(key.clone());
+        }
+        if let Some(max_memory_mb) = self.limits.max_memory_mb {
+            args.push("--memory".to_string());
+            args.push(format!("{max_memory_mb}m"));
+            // Same as the host cgroup: no swap past the limit.
+            args.push("--memory-swap".to_string());
+            args.push(format!("{max_memory_mb}m"));
+        }
+        if let Some(shares) = self.limits.cpu_shares {
+            args.push("--cpu-shares".to_string());
+            args.push(shares.to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args.push(self.image.clone());
+        args.push(cli.to_string());
+        args.extend(cli_args.iter().cloned());
+        args
+    }
+}
+
+/// Force-remove an agent's container if it carries `broker_scope`'s label.
+/// Clears a stale container left by a crashed broker before a spawn, and one
+/// that outlived its worker after release. Best effort.
+pub(crate) async fn remove_container(container: &str, broker_scope: &str) {
+    let removal = remove_owned_container(container, broker_scope);
+    let error = match tokio::time::timeout(REMOVE_TIMEOUT, removal).await {
+        Ok(Ok(())) => return,
+        Ok(Err(error)) => format!("{error:#}"),
+        Err(_) => format!("docker rm timed out after {}s", REMOVE_TIMEOUT.as_secs()),
+    };
+    tracing::debug!(
+        target = "broker::docker",
+        container = %container,
+        error = %error,
+        "failed to remove agent container"
+    );
+}
+
+/// Arguments to `docker ps` listing the ids of `container` when it carries
+/// `broker_scope`'s label.
+fn owned_container_filter_args(container: &str, broker_scope: &str) -> Vec<String> {
+    // Container names only hold `[a-zA-Z0-9_.-]`, so `.` is the one regex
+    // metacharacter to escape.
+    let pattern = container.replace('.', r"\.");
+    vec![
+        "ps".to_string(),
+        "-aq".to_string(),
+        "--filter".to_string(),
+        format!("name=^/{pattern}$"),
+        "--filter".to_string(),
+        format!("label={BROKER_LABEL}={broker_scope}"),
+    ]
+}
+
+async fn remove_owned_container(container: &str, broker_scope: &str) -> Result<()> {
+    let output = tokio::process::Command::new("docker")
+        .args(owned_container_filter_args(container, broker_scope))
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("failed to run docker ps")?;
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    tokio::process::Command::new("docker")
+        .args(["rm", "-f"])
+        .args(&ids)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .context("failed to run docker rm")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_name_is_sanitized_and_broker_scoped() {
+        let name = container_name("0a1b2c3d", "Worker-1");
+        assert!(name.starts_with("agent-relay-0a1b2c3d-Worker-1-"), "{name}");
+        assert_eq!(name.len(), "agent-relay-0a1b2c3d-Worker-1-".len() + 8);
+        assert!(container_name("0a1b2c3d", "team/lead bot")
+            .starts_with("agent-relay-0a1b2c3d-team-lead-bot-"));
+        assert_ne!(
+            container_name("0a1b2c3d", "a/b"),
+            container_name("0a1b2c3d", "a-b")
+        );
+        assert_ne!(
+            container_name("0a1b2c3d", "x y"),
+            container_name("0a1b2c3d", "x-y")
+        );
+        assert_ne!(
+            container_name("0a1b2c3d", "worker"),
+            container_name("99887766", "worker")
+        );
+    }
+
+    #[test]
+    fn removal_only_matches_this_brokers_container() {
+        assert_eq!(
+            owned_container_filter_args("agent-relay-0a1b2c3d-worker", "0a1b2c3d").join(" "),
+            "ps -aq --filter name=^/agent-relay-0a1b2c3d-worker$ \
+             --filter label=agent-relay.broker=0a1b2c3d"
+        );
+        assert_eq!(
+            owned_container_filter_args("agent-relay-0a1b2c3d-v1.2", "0a1b2c3d")[3],
+            r"name=^/agent-relay-0a1b2c3d-v1\.2$"
+        );
+    }
+
+    #[test]
+    fn run_args_mount_cwd_forward_env_and_apply_limits() {
+        let run = DockerRun {
+            container: "agent-relay-0a1b2c3d-Worker1".to_string(),
+            broker_scope: "0a1b2c3d".to_string(),
+            image: "ghcr.io/acme/agent:latest".to_string(),
+            workdir: PathBuf::from("/srv/project"),
+            env_keys: vec!["RELAY_AGENT_TOKEN".to_string()],
+            limits: AgentResourceLimits {
+                max_memory_mb: Some(512),
+                cpu_shares: Some(256),
+                restart_on_limit: false,
+            },
+            extra_args: vec!["--network".to_string(), "host".to_string()],
+        };
+
+        let args = run.args("claude", &["--model".to_string(), "haiku".to_string()]);
+        assert_eq!(
+            args.join(" "),
+            "run --rm -i -t --name agent-relay-0a1b2c3d-Worker1 \
+             --label agent-relay.broker=0a1b2c3d \
+             -v /srv/project:/srv/project -w /srv/project \
+             -e RELAY_AGENT_TOKEN --memory 512m --memory-swap 512m --cpu-shares 256 \
+             --network host ghcr.io/acme/agent:latest claude --model haiku"
+        );
+    }
+}
@@ -0,0 +1,639 @@
+//! The `kubernetes` agent runtime.
+//!
+//! Each agent becomes a pod whose first container runs the CLI with `stdin`
+//! and `tty` set. The agent's env, relay tokens included, goes into a Secret
+//! of its own that the container reads through `secretKeyRef`, so no token
+//! appears on a command line or in the Pod spec. The broker creates both
+//! with `kubectl create -f -`, and the PTY worker's harness is
+//! `kubectl attach`, which bridges the container's terminal into the usual
+//! PTY worker. Pods and their Secrets are deleted on release and exit, and
+//! the runtime polls pod phases so scheduling and image problems surface as
+//! events.
+//!
+//! Pod names include the broker scope, and pods and Secrets are labelled
+//! with it and with the pod name. Deletes and status polls select on those
+//! labels, so brokers sharing a namespace never touch each other's agents.
+//!
+//! The pod is built from `AGENT_RELAY_K8S_POD_TEMPLATE` (or `[kubernetes]
+//! pod_template`), a JSON Pod manifest for volumes, service accounts, node
+//! selectors and the like; the broker fills in the name, image, command, env
+//! and resources. Host paths are not visible in the pod, so the template has
+//! to provide the workspace. `AGENT_RELAY_K8S_NAMESPACE` (or `[kubernetes]
+//! namespace`) and `AGENT_RELAY_KUBECTL` select the namespace and the kubectl
+//! binary.
+
+use std::{collections::HashMap, process::Stdio, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::broker_config::{env_or, KubernetesSection};
+
+use crate::protocol::AgentResourceLimits;
+
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+const MANAGED_BY: &str = "agent-relay";
+/// Scope of the broker that owns a pod or Secret.
+const BROKER_LABEL: &str = "agent-relay.dev/broker";
+/// The agent pod a pod or Secret belongs to.
+const POD_LABEL: &str = "agent-relay.dev/pod";
+const DEFAULT_CONTAINER: &str = "agent";
+/// How long `kubectl attach` waits for the pod to start, image pull
+/// included.
+const POD_START_TIMEOUT: &str = "5m";
+const KUBECTL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Container waiting reasons that will not clear up on their own.
+const FATAL_WAITING_REASONS: [&str; 4] = [
+    "ImagePullBackOff",
+    "InvalidImageName",
+    "CreateContainerConfigError",
+    "CreateContainerError",
+];
+
+/// Name of the Secret holding the env of the agent running in `pod`.
+pub(crate) fn secret_name(pod: &str) -> String {
+    format!("{pod}-env")
+}
+
+/// Pod name for an agent of the broker with `broker_scope`: a DNS-1123
+/// label made from the scope and the agent name, with a hash suffix so names
+/// differing only in case or punctuation stay apart.
+pub(crate) fn pod_name(broker_scope: &str, agent: &str) -> String {
+    let mut slug: String = agent
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    slug.truncate(32);
+    let slug = slug.trim_matches('-');
+    let hash = format!("{:x}", Sha256::digest(agent.as_bytes()));
+    if slug.is_empty() {
+        format!("agent-relay-{broker_scope}-{}", &hash[..8])
+    } else {
+        format!("agent-relay-{broker_scope}-{slug}-{}", &hash[..8])
+    }
+}
+
+/// Labels every object of an agent pod carries.
+fn owner_labels(broker_scope: &str, pod: &str) -> Value {
+    json!({
+        MANAGED_BY_LABEL: MANAGED_BY,
+        BROKER_LABEL: broker_scope,
+        POD_LABEL: pod,
+    })
+}
+
+/// Selects the pod and Secret of `pod` only when `broker_scope` owns them.
+fn owned_pod_selector(broker_scope: &str, pod: &str) -> String {
+    format!("{MANAGED_BY_LABEL}={MANAGED_BY},{BROKER_LABEL}={broker_scope},{POD_LABEL}={pod}")
+}
+
+pub(crate) fn kubectl_bin() -> String {
+    std::env::var("AGENT_RELAY_KUBECTL")
+        .ok()
+        .filter(|bin| !bin.trim().is_empty())
+        .unwrap_or_else(|| "kubectl".to_string())
+}
+
+/// Agents' namespace, from `AGENT_RELAY_K8S_NAMESPACE` or the config file;
+/// `None` leaves it to kubectl's context.
+pub(crate) fn namespace(config: &KubernetesSection) -> Option<String> {
+    env_or("AGENT_RELAY_K8S_NAMESPACE", config.namespace.clone())
+        .map(|namespace| namespace.trim().to_string())
+        .filter(|namespace| !namespace.is_empty())
+}
+
+fn namespace_args(namespace: Option<&str>) -> Vec<String> {
+    match namespace {
+        Some(namespace) => vec!["-n".to_string(), namespace.to_string()],
+        None => Vec::new(),
+    }
+}
+
+fn kubectl(namespace: Option<&str>, args: &[&str]) -> Command {
+    let mut command = Command::new(kubectl_bin());
+    command.args(namespace_args(namespace)).args(args);
+    command
+}
+
+/// The Pod manifest at `AGENT_RELAY_K8S_POD_TEMPLATE` or `[kubernetes]
+/// pod_template`, or an empty pod.
+pub(crate) fn pod_template(config: &KubernetesSection) -> Result<Value> {
+    let path = match std::env::var_os("AGENT_RELAY_K8S_POD_TEMPLATE") {
+        Some(path) => path,
+        None => match &config.pod_template {
+            Some(path) => path.into(),
+            None => return Ok(json!({ "apiVersion": "v1", "kind": "Pod" })),
+        },
+    };
+    let raw = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "failed to read pod template {}",
+            std::path::Path::new(&path).display()
+        )
+    })?;
+    serde_json::from_str(&raw).context("pod template must be a JSON Pod manifest")
+}
+
+/// One agent's pod.
+pub(crate) struct AgentPod {
+    pub(crate) pod: String,
+    pub(crate) broker_scope: String,
+    /// Overrides the template container's image.
+    pub(crate) image: Option<String>,
+    pub(crate) cli: String,
+    pub(crate) args: Vec<String>,
+    /// Stored in the agent's Secret, not in the Pod spec.
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) limits: AgentResourceLimits,
+}
+
+impl AgentPod {
+    /// The Secret that holds [`AgentPod::env`].
+    pub(crate) fn secret(&self) -> Value {
+        let data: Map<String, Value> = self
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+        json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": {
+                "name": secret_name(&self.pod),
+                "labels": owner_labels(&self.broker_scope, &self.pod),
+            },
+            "type": "Opaque",
+            "stringData": data,
+        })
+    }
+
+    /// `template` with this agent filled into its first container. Returns
+    /// the manifest and that container's name.
+    pub(crate) fn manifest(&self, template: &Value) -> Result<(Value, String)> {
+        let mut pod = template.clone();
+        let Some(root) = pod.as_object_mut() else {
+            bail!("pod template must be a JSON object");
+        };
+        match root.get("kind").and_then(Value::as_str) {
+            None | Some("Pod") => {}
+            Some(other) => bail!("pod template must be a Pod, not a {other}"),
+        }
+        root.insert("apiVersion".into(), json!("v1"));
+        root.insert("kind".into(), json!("Pod"));
+
+        let metadata = object_entry(root, "metadata");
+        metadata.remove("generateName");
+        metadata.insert("name".into(), json!(self.pod));
+        let labels = object_entry(metadata, "labels");
+        if let Value::Object(owner) = owner_labels(&self.broker_scope, &self.pod) {
+            labels.extend(owner);
+        }
+
+        let spec = object_entry(root, "spec");
+        spec.insert("restartPolicy".into(), json!("Never"));
+        let containers = spec.entry("containers").or_insert_with(|| json!([]));
+        let Some(containers) = containers.as_array_mut() else {
+            bail!("pod template `spec.containers` must be an array");
+        };
+        if containers.is_empty() {
+            containers.push(json!({}));
+        }
+        let Some(container) = containers[0].as_object_mut() else {
+            bail!("pod template containers must be objects");
+        };
+
+        let name = container
+            .entry("name")
+            .or_insert_with(|| json!(DEFAULT_CONTAINER))
+            .as_str()
+            .unwrap_or(DEFAULT_CONTAINER)
+            .to_string();
+        if let Some(image) = &self.image {
+            container.insert("image".into(), json!(image));
+        }
+        if container.get("image").and_then(Value::as_str).is_none() {
+            bail!("kubernetes runtime requires `image` (or an image in the pod template)");
+        }
+        container.insert("command".into(), json!([self.cli]));
+        container.insert("args".into(), json!(self.args));
+        container.insert("stdin".into(), json!(true));
+        container.insert("tty".into(), json!(true));
+
+        let env = container.entry("env").or_insert_with(|| json!([]));
+        let Some(env) = env.as_array_mut() else {
+            bail!("pod template container `env` must be an array");
+        };
+        let secret = secret_name(&self.pod);
+        for (key, _) in &self.env {
+            env.retain(|var| var.get("name").and_then(Value::as_str) != Some(key));
+            env.push(json!({
+                "name": key,
+                "valueFrom": { "secretKeyRef": { "name": secret, "key": key } },
+            }));
+        }
+
+        let resources = object_entry(container, "resources");
+        if let Some(max_memory_mb) = self.limits.max_memory_mb {
+            object_entry(resources, "limits")
+                .insert("memory".into(), json!(format!("{max_memory_mb}Mi")));
+        }
+        if let Some(shares) = self.limits.cpu_shares {
+            // Kubernetes maps a request of 1 CPU to 1024 shares.
+            let millicores = (shares.saturating_mul(1000) / 1024).max(1);
+            object_entry(resources, "requests")
+                .insert("cpu".into(), json!(format!("{millicores}m")));
+        }
+
+        Ok((pod, name))
+    }
+}
+
+fn object_entry<'a>(object: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    let entry = object.entry(key).or_insert_with(|| json!({}));
+    if !entry.is_object() {
+        *entry = json!({});
+    }
+    entry
+        .as_object_mut()
+        .expect("entry was just made an object")
+}
+
+/// Arguments to kubectl that attach to the agent's container.
+pub(crate) fn attach_args(namespace: Option<&str>, pod: &str, container: &str) -> Vec<String> {
+    let mut args = namespace_args(namespace);
+    args.extend([
+        "attach".to_string(),
+        "-i".to_string(),
+        "-t".to_string(),
+        pod.to_string(),
+        "-c".to_string(),
+        container.to_string(),
+        format!("--pod-running-timeout={POD_START_TIMEOUT}"),
+    ]);
+    args
+}
+
+async fn run_kubectl(mut command: Command, stdin: Option<&[u8]>) -> Result<Vec<u8>> {
+    command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn().context("failed to run kubectl")?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input).await?;
+    }
+    let output = tokio::time::timeout(KUBECTL_TIMEOUT, child.wait_with_output())
+        .await
+        .context("kubectl timed out")??;
+    if !output.status.success() {
+        bail!(
+            "kubectl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Create the agent's Secret and pod, replacing ones a previous run of this
+/// broker left behind.
+pub(crate) async fn create_pod(
+    namespace: Option<&str>,
+    pod: &str,
+    broker_scope: &str,
+    secret: &Value,
+    manifest: &Value,
+) -> Result<()> {
+    let selector = owned_pod_selector(broker_scope, pod);
+    run_kubectl(
+        kubectl(
+            namespace,
+            &[
+                "delete",
+                "pod,secret",
+                "-l",
+                &selector,
+                "--ignore-not-found",
+                "--wait=true",
+            ],
+        ),
+        None,
+    )
+    .await
+    .context("failed to remove stale agent pod")?;
+    run_kubectl(
+        kubectl(namespace, &["create", "-f", "-"]),
+        Some(&serde_json::to_vec(secret)?),
+    )
+    .await
+    .context("failed to create agent secret")?;
+    if let Err(error) = run_kubectl(
+        kubectl(namespace, &["create", "-f", "-"]),
+        Some(&serde_json::to_vec(manifest)?),
+    )
+    .await
+    {
+        delete_pod(namespace, pod, broker_scope).await;
+        return Err(error.context("failed to create agent pod"));
+    }
+    Ok(())
+}
+
+/// Delete an agent's pod and Secret, if `broker_scope` owns them, without
+/// waiting for the pod to terminate. Best effort.
+pub(crate) async fn delete_pod(namespace: Option<&str>, pod: &str, broker_scope: &str) {
+    let selector = owned_pod_selector(broker_scope, pod);
+    let command = kubectl(
+        namespace,
+        &[
+            "delete",
+            "pod,secret",
+            "-l",
+            &selector,
+            "--ignore-not-found",
+            "--wait=false",
+        ],
+    );
+    if let Err(error) = run_kubectl(command, None).await {
+        tracing::debug!(
+            target = "broker::kubernetes",
+            pod = %pod,
+            error = %error,
+            "failed to delete agent pod"
+        );
+    }
+}
+
+/// A pod's phase and, when there is one, the reason it is not running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PodStatus {
+    pub(crate) phase: String,
+    pub(crate) reason: Option<String>,
+}
+
+impl PodStatus {
+    /// The pod failed or is stuck in a way that needs a new spec.
+    pub(crate) fn is_fatal(&self) -> bool {
+        self.phase == "Failed"
+            || self
+                .reason
+                .as_deref()
+                .is_some_and(|reason| FATAL_WAITING_REASONS.contains(&reason))
+    }
+}
+
+/// Statuses keyed by pod name, from `kubectl get pods -o json`.
+pub(crate) fn parse_pod_statuses(list: &Value) -> HashMap<String, PodStatus> {
+    let Some(items) = list.get("items").and_then(Value::as_array) else {
+        return HashMap::new();
+    };
+    items
+        .iter()
+        .filter_map(|pod| {
+            let name = pod.pointer("/metadata/name")?.as_str()?.to_string();
+            let status = pod.get("status")?;
+            let phase = status
+                .get("phase")
+                .and_then(Value::as_str)
+                .unwrap_or("Unknown")
+                .to_string();
+            let container_reason = status
+                .get("containerStatuses")
+                .and_then(Value::as_array)
+                .and_then(|statuses| statuses.first())
+                .and_then(|container| {
+                    container
+                        .pointer("/state/waiting/reason")
+                        .or_else(|| container.pointer("/state/terminated/reason"))
+                });
+            let unschedulable = status
+                .get("conditions")
+                .and_then(Value::as_array)
+                .and_then(|conditions| {
+                    conditions.iter().find(|condition| {
+                        condition.get("type").and_then(Value::as_str) == Some("PodScheduled")
+                            && condition.get("status").and_then(Value::as_str) == Some("False")
+                    })
+                })
+                .and_then(|condition| condition.get("reason"));
+            let reason = container_reason
+                .or_else(|| status.get("reason"))
+                .or(unschedulable)
+                .and_then(Value::as_str)
+                .map(String::from);
+            Some((name, PodStatus { phase, reason }))
+        })
+        .collect()
+}
+
+/// Statuses of every pod the broker with `broker_scope` manages.
+pub(crate) async fn pod_statuses(
+    namespace: Option<&str>,
+    broker_scope: &str,
+) -> Result<HashMap<String, PodStatus>> {
+    let selector = format!("{MANAGED_BY_LABEL}={MANAGED_BY},{BROKER_LABEL}={broker_scope}");
+    let stdout = run_kubectl(
+        kubectl(namespace, &["get", "pods", "-l", &selector, "-o", "json"]),
+        None,
+    )
+    .await?;
+    let list: Value = serde_json::from_slice(&stdout).context("invalid kubectl output")?;
+    Ok(parse_pod_statuses(&list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pod_names_are_broker_scoped_dns_labels() {
+        let name = pod_name("0a1b2c3d", "Team/Lead_Bot");
+        assert!(
+            name.starts_with("agent-relay-0a1b2c3d-team-lead-bot-"),
+            "{name}"
+        );
+        assert_eq!(name.len(), "agent-relay-0a1b2c3d-team-lead-bot-".len() + 8);
+        assert_ne!(
+            pod_name("0a1b2c3d", "worker"),
+            pod_name("0a1b2c3d", "Worker")
+        );
+        assert_ne!(
+            pod_name("0a1b2c3d", "worker"),
+            pod_name("99887766", "worker")
+        );
+        assert!(pod_name("0a1b2c3d", &"x".repeat(200)).len() <= 63);
+        assert!(pod_name("0a1b2c3d", "@@").starts_with("agent-relay-0a1b2c3d-"));
+    }
+
+    #[test]
+    fn deletes_select_on_the_owning_broker() {
+        assert_eq!(
+            owned_pod_selector("0a1b2c3d", "agent-relay-0a1b2c3d-worker-1234abcd"),
+            "app.kubernetes.io/managed-by=agent-relay,agent-relay.dev/broker=0a1b2c3d,\
+             agent-relay.dev/pod=agent-relay-0a1b2c3d-worker-1234abcd"
+        );
+    }
+
+    #[test]
+    fn manifest_fills_template_container() {
+        let template = json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "labels": { "team": "core" } },
+            "spec": {
+                "serviceAccountName": "agents",
+                "containers": [{
+                    "name": "cli",
+                    "image": "ghcr.io/acme/agent:1",
+                    "env": [{ "name": "RELAY_AGENT_NAME", "value": "stale" }, { "name": "KEEP", "value": "1" }],
+                    "workingDir": "/workspace"
+                }]
+            }
+        });
+        let pod = AgentPod {
+            pod: "agent-relay-worker-1234abcd".to_string(),
+            broker_scope: "0a1b2c3d".to_string(),
+            image: None,
+            cli: "claude".to_string(),
+            args: vec!["--model".to_string(), "haiku".to_string()],
+            env: vec![("RELAY_AGENT_NAME".to_string(), "Worker".to_string())],
+            limits: AgentResourceLimits {
+                max_memory_mb: Some(2048),
+                cpu_shares: Some(512),
+                restart_on_limit: false,
+            },
+        };
+
+        let (manifest, container) = pod.manifest(&template).unwrap();
+        assert_eq!(container, "cli");
+        assert_eq!(manifest["metadata"]["name"], "agent-relay-worker-1234abcd");
+        assert_eq!(manifest["metadata"]["labels"]["team"], "core");
+        assert_eq!(manifest["metadata"]["labels"][MANAGED_BY_LABEL], MANAGED_BY);
+        assert_eq!(manifest["metadata"]["labels"][BROKER_LABEL], "0a1b2c3d");
+        assert_eq!(
+            manifest["metadata"]["labels"][POD_LABEL],
+            "agent-relay-worker-1234abcd"
+        );
+        assert_eq!(manifest["spec"]["restartPolicy"], "Never");
+        assert_eq!(manifest["spec"]["serviceAccountName"], "agents");
+        let container = &manifest["spec"]["containers"][0];
+        assert_eq!(container["image"], "ghcr.io/acme/agent:1");
+        assert_eq!(container["command"], json!(["claude"]));
+        assert_eq!(container["args"], json!(["--model", "haiku"]));
+        assert_eq!(container["tty"], true);
+        assert_eq!(container["workingDir"], "/workspace");
+        assert_eq!(
+            container["env"],
+            json!([
+                { "name": "KEEP", "value": "1" },
+                {
+                    "name": "RELAY_AGENT_NAME",
+                    "valueFrom": {
+                        "secretKeyRef": { "name": "agent-relay-worker-1234abcd-env", "key": "RELAY_AGENT_NAME" }
+                    }
+                }
+            ])
+        );
+        assert_eq!(container["resources"]["limits"]["memory"], "2048Mi");
+        assert_eq!(container["resources"]["requests"]["cpu"], "500m");
+    }
+
+    #[test]
+    fn secret_holds_env_values_kept_out_of_the_pod() {
+        let pod = AgentPod {
+            pod: "agent-relay-w".to_string(),
+            broker_scope: "0a1b2c3d".to_string(),
+            image: Some("node:22".to_string()),
+            cli: "codex".to_string(),
+            args: Vec::new(),
+            env: vec![("RELAY_AGENT_TOKEN".to_string(), "tok_secret".to_string())],
+            limits: AgentResourceLimits::default(),
+        };
+
+        let secret = pod.secret();
+        assert_eq!(secret["kind"], "Secret");
+        assert_eq!(secret["metadata"]["name"], "agent-relay-w-env");
+        assert_eq!(secret["metadata"]["labels"][MANAGED_BY_LABEL], MANAGED_BY);
+        assert_eq!(secret["metadata"]["labels"][BROKER_LABEL], "0a1b2c3d");
+        assert_eq!(secret["stringData"]["RELAY_AGENT_TOKEN"], "tok_secret");
+        let (manifest, _) = pod.manifest(&json!({})).unwrap();
+        assert!(!manifest.to_string().contains("tok_secret"));
+    }
+
+    #[test]
+    fn manifest_requires_an_image_and_a_pod() {
+        let pod = AgentPod {
+            pod: "agent-relay-w".to_string(),
+            broker_scope: "0a1b2c3d".to_string(),
+            image: None,
+            cli: "codex".to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            limits: AgentResourceLimits::default(),
+        };
+        let error = pod.manifest(&json!({})).unwrap_err();
+        assert!(error.to_string().contains("requires `image`"), "{error}");
+        let error = pod.manifest(&json!({ "kind": "Job" })).unwrap_err();
+        assert!(error.to_string().contains("must be a Pod"), "{error}");
+
+        let pod = AgentPod {
+            image: Some("node:22".to_string()),
+            ..pod
+        };
+        let (manifest, container) = pod.manifest(&json!({})).unwrap();
+        assert_eq!(container, DEFAULT_CONTAINER);
+        assert_eq!(manifest["spec"]["containers"][0]["image"], "node:22");
+    }
+
+    #[test]
+    fn pod_statuses_report_waiting_and_scheduling_reasons() {
+        let list = json!({
+            "items": [
+                {
+                    "metadata": { "name": "a" },
+                    "status": {
+                        "phase": "Pending",
+                        "containerStatuses": [{ "state": { "waiting": { "reason": "ImagePullBackOff" } } }]
+                    }
+                },
+                {
+                    "metadata": { "name": "b" },
+                    "status": {
+                        "phase": "Pending",
+                        "conditions": [{ "type": "PodScheduled", "status": "False", "reason": "Unschedulable" }]
+                    }
+                },
+                {
+                    "metadata": { "name": "c" },
+                    "status": { "phase": "Running", "containerStatuses": [{ "state": { "running": {} } }] }
+                }
+            ]
+        });
+
+        let statuses = parse_pod_statuses(&list);
+        assert_eq!(statuses["a"].reason.as_deref(), Some("ImagePullBackOff"));
+        assert!(statuses["a"].is_fatal());
+        assert_eq!(statuses["b"].reason.as_deref(), Some("Unschedulable"));
+        assert!(!statuses["b"].is_fatal());
+        assert_eq!(
+            statuses["c"],
+            PodStatus {
+                phase: "Running".to_string(),
+                reason: None
+            }
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Enforcement for per-agent [`AgentResourceLimits`].
+//!
+//! On Linux a limited worker gets its own cgroup v2 directory, with
+//! `memory.max` and `cpu.weight` set, under `AGENT_RELAY_CGROUP_ROOT` or the
+//! broker's own cgroup. That needs a delegated, writable cgroup (for example
+//! a systemd unit with `Delegate=yes`). cgroup v2 only enables controllers
+//! for children of a cgroup that holds no processes itself, so before using
+//! its own cgroup the broker moves the processes in it into a `broker` leaf
+//! next to the agent cgroups. It only does so when every process there is
+//! the broker or one it started; a shared cgroup such as a login session or
+//! a terminal's scope is left alone. Where no cgroup is usable the worker
+//! starts with `RLIMIT_DATA` and a `nice` level instead; those apply per
+//! process rather than to the whole tree. The runtime's memory watchdog
+//! checks resident usage either way.
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::protocol::AgentResourceLimits;
+
+/// Exit reason recorded when the cgroup OOM killer ended a worker.
+pub(crate) const MEMORY_LIMIT_EXIT_REASON: &str = "memory_limit_exceeded";
+
+#[cfg(target_os = "linux")]
+const CGROUP_FS: &str = "/sys/fs/cgroup";
+
+/// Leaf cgroup the broker's own processes move into; see
+/// [`vacate_cgroup`].
+const BROKER_LEAF: &str = "broker";
+
+pub(crate) fn memory_limit_bytes(max_memory_mb: u64) -> u64 {
+    max_memory_mb.saturating_mul(1024 * 1024)
+}
+
+/// `cpu_shares` as a cgroup v2 `cpu.weight`, keeping 1024 shares at the
+/// default weight of 100.
+pub(crate) fn cpu_weight_for_shares(shares: u64) -> u64 {
+    (shares.saturating_mul(100) / 1024).clamp(1, 10_000)
+}
+
+/// `cpu_shares` as a nice increment. Each nice level is worth about 1.25x
+/// scheduler weight; shares at or above the default leave the priority
+/// alone, since raising it needs privileges.
+pub(crate) fn nice_for_shares(shares: u64) -> i32 {
+    if shares >= 1024 {
+        return 0;
+    }
+    let ratio = 1024.0 / shares.max(1) as f64;
+    ((ratio.ln() / 1.25f64.ln()).round() as i32).clamp(0, 19)
+}
+
+/// The cgroup path from `/proc/self/cgroup`, v2 unified hierarchy only.
+pub(crate) fn own_cgroup_path(proc_self_cgroup: &str) -> Option<&str> {
+    proc_self_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::trim)
+}
+
+/// The `oom_kill` counter from a `memory.events` file.
+pub(crate) fn oom_kill_count(memory_events: &str) -> u64 {
+    memory_events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Directory agent cgroups are created under, when cgroup v2 is mounted.
+/// The broker's own cgroup is vacated on first use, unless it holds
+/// processes the broker did not start.
+#[cfg(target_os = "linux")]
+fn cgroup_root() -> Option<PathBuf> {
+    static OWN_ROOT: std::sync::OnceLock<Option<PathBuf>> = std::sync::OnceLock::new();
+    if let Some(root) = std::env::var_os("AGENT_RELAY_CGROUP_ROOT") {
+        return Some(PathBuf::from(root));
+    }
+    OWN_ROOT
+        .get_or_init(|| {
+            if !Path::new(CGROUP_FS).join("cgroup.controllers").exists() {
+                return None;
+            }
+            let raw = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+            let own = own_cgroup_path(&raw)?.trim_start_matches('/');
+            // The root cgroup may hold processes and enable controllers.
+            if own.is_empty() {
+                return Some(PathBuf::from(CGROUP_FS));
+            }
+            let root = Path::new(CGROUP_FS).join(own);
+            if let Err(error) = vacate_cgroup(&root, is_broker_process) {
+                tracing::warn!(
+                    target = "broker::limits",
+                    root = %root.display(),
+                    error = %error,
+                    "cannot move the broker into a leaf cgroup; agent limits fall back to rlimits"
+                );
+                return None;
+            }
+            Some(root)
+        })
+        .clone()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_root() -> Option<PathBuf> {
+    None
+}
+
+/// The parent pid from a `/proc/<pid>/stat` line.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn stat_parent_pid(stat: &str) -> Option<u32> {
+    // The command name may hold spaces and parens; fields resume after the
+    // last `)` with the state, then the parent pid.
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Whether `pid` is the broker or one of its descendants. A process that
+/// has already exited counts as the broker's, since it cannot be moved.
+#[cfg(target_os = "linux")]
+fn is_broker_process(pid: u32) -> bool {
+    let broker = std::process::id();
+    let mut current = pid;
+    while current != broker {
+        if current <= 1 {
+            return false;
+        }
+        let stat = match std::fs::read_to_string(format!("/proc/{current}/stat")) {
+            Ok(stat) => stat,
+            Err(_) => return current == pid,
+        };
+        match stat_parent_pid(&stat) {
+            Some(parent) => current = parent,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Move every process in `root`, the broker and the workers it already
+/// started, into its [`BROKER_LEAF`] child so `root` can enable controllers
+/// for the agent cgroups beside it. Refuses when `is_own` rejects a process
+/// in `root`, and moves the processes back if a move fails partway.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn vacate_cgroup(root: &Path, is_own: impl Fn(u32) -> bool) -> std::io::Result<()> {
+    let procs = std::fs::read_to_string(root.join("cgroup.procs"))?;
+    let mut pids = Vec::new();
+    for pid in procs.split_whitespace() {
+        let pid: u32 = pid.parse().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid pid '{pid}' in cgroup.procs"),
+            )
+        })?;
+        if !is_own(pid) {
+            return Err(std::io::Error::other(format!(
+                "cgroup also holds process {pid}, which the broker did not start"
+            )));
+        }
+        pids.push(pid);
+    }
+
+    let leaf = root.join(BROKER_LEAF);
+    match std::fs::create_dir(&leaf) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+    for (moved, pid) in pids.iter().enumerate() {
+        if let Err(error) = std::fs::write(leaf.join("cgroup.procs"), pid.to_string()) {
+            // The process exited since the list was read.
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                continue;
+            }
+            for pid in &pids[..moved] {
+                let _ = std::fs::write(root.join("cgroup.procs"), pid.to_string());
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Directory name of an agent's cgroup. Agent names are not validated, so
+/// anything outside `[A-Za-z0-9_.-]` is replaced and a hash of the original
+/// name keeps altered names apart; the result is always one path component.
+pub(crate) fn cgroup_dir_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if sanitized == name {
+        return format!("agent-relay-{name}");
+    }
+    let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+    format!("agent-relay-{sanitized}-{}", &hash[..8])
+}
+
+/// A cgroup created for one worker. Removed when the worker is released or
+/// reaped.
+#[derive(Debug)]
+pub(crate) struct AgentCgroup {
+    path: PathBuf,
+}
+
+impl AgentCgroup {
+    fn create(root: &Path, name: &str, limits: &AgentResourceLimits) -> std::io::Result<Self> {
+        let mut controllers = Vec::new();
+        if limits.max_memory_mb.is_some() {
+            controllers.push("memory");
+        }
+        if limits.cpu_shares.is_some() {
+            controllers.push("cpu");
+        }
+        let enabled = std::fs::read_to_string(root.join("cgroup.subtree_control"))?;
+        for controller in controllers {
+            if !enabled.split_whitespace().any(|c| c == controller) {
+                std::fs::write(
+                    root.join("cgroup.subtree_control"),
+                    format!("+{controller}"),
+                )?;
+            }
+        }
+
+        let path = root.join(cgroup_dir_name(name));
+        match std::fs::create_dir(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+        let cgroup = Self { path };
+        if let Err(error) = cgroup.write_limits(limits) {
+            cgroup.remove();
+            return Err(error);
+        }
+        Ok(cgroup)
+    }
+
+    fn write_limits(&self, limits: &AgentResourceLimits) -> std::io::Result<()> {
+        if let Some(max_memory_mb) = limits.max_memory_mb {
+            std::fs::write(
+                self.path.join("memory.max"),
+                memory_limit_bytes(max_memory_mb).to_string(),
+            )?;
+            // Fail the agent instead of pushing the host into swap.
+            let _ = std::fs::write(self.path.join("memory.swap.max"), "0");
+        }
+        if let Some(shares) = limits.cpu_shares {
+            std::fs::write(
+                self.path.join("cpu.weight"),
+                cpu_weight_for_shares(shares).to_string(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Make the spawned process join this cgroup before it execs. The PTY
+    /// worker forks its harness straight away, so moving the pid after spawn
+    /// would leave the harness outside.
+    #[cfg(unix)]
+    fn join_on_exec(&self, command: &mut tokio::process::Command) -> std::io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+        let procs = std::ffi::CString::new(self.path.join("cgroup.procs").as_os_str().as_bytes())?;
+        // Safety: the hook runs between fork and exec and only makes
+        // async-signal-safe syscalls. Writing `0` moves the writer.
+        unsafe {
+            command.pre_exec(move || {
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                let error = std::io::Error::last_os_error();
+                libc::close(fd);
+                if written != 1 {
+                    return Err(error);
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn join_on_exec(&self, _command: &mut tokio::process::Command) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    pub(crate) fn oom_kills(&self) -> u64 {
+        std::fs::read_to_string(self.path.join("memory.events"))
+            .map(|raw| oom_kill_count(&raw))
+            .unwrap_or(0)
+    }
+
+    /// Remove the (by now empty) cgroup. Best effort: a process that
+    /// survived release keeps it alive.
+    pub(crate) fn remove(&self) {
+        if let Err(error) = std::fs::remove_dir(&self.path) {
+            tracing::debug!(
+                target = "broker::limits",
+                path = %self.path.display(),
+                error = %error,
+                "failed to remove agent cgroup"
+            );
+        }
+    }
+}
+
+/// Set up enforcement for a worker about to be spawned: a cgroup when one
+/// can be created and joined, otherwise rlimits applied to `command`. The
+/// returned cgroup is the caller's to remove.
+pub(crate) fn prepare_limits(
+    command: &mut tokio::process::Command,
+    name: &str,
+    limits: &AgentResourceLimits,
+) -> Option<AgentCgroup> {
+    if limits.is_empty() {
+        return None;
+    }
+    if let Some(root) = cgroup_root() {
+        let created = AgentCgroup::create(&root, name, limits).and_then(|cgroup| {
+            match cgroup.join_on_exec(command) {
+                Ok(()) => Ok(cgroup),
+                Err(error) => {
+                    cgroup.remove();
+                    Err(error)
+                }
+            }
+        });
+        match created {
+            Ok(cgroup) => return Some(cgroup),
+            Err(error) => tracing::warn!(
+                target = "broker::limits",
+                name = %name,
+                root = %root.display(),
+                error = %error,
+                "cgroup limits unavailable; falling back to rlimits"
+            ),
+        }
+    }
+    apply_rlimits(command, limits);
+    None
+}
+
+#[cfg(unix)]
+fn apply_rlimits(command: &mut tokio::process::Command, limits: &AgentResourceLimits) {
+    let data_bytes = limits.max_memory_mb.map(memory_limit_bytes);
+    let nice = limits
+        .cpu_shares
+        .map(nice_for_shares)
+        .filter(|nice| *nice > 0);
+    if data_bytes.is_none() && nice.is_none() {
+        return;
+    }
+    // Safety: the hook runs between fork and exec and only makes
+    // async-signal-safe syscalls.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(bytes) = data_bytes {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_DATA, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(nice) = nice {
+                libc::nice(nice);
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_rlimits(_command: &mut tokio::process::Command, _limits: &AgentResourceLimits) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_shares_map_to_weight_and_nice() {
+        assert_eq!(cpu_weight_for_shares(1024), 100);
+        assert_eq!(cpu_weight_for_shares(512), 50);
+        assert_eq!(cpu_weight_for_shares(2), 1);
+        assert_eq!(cpu_weight_for_shares(1_000_000), 10_000);
+
+        assert_eq!(nice_for_shares(1024), 0);
+        assert_eq!(nice_for_shares(4096), 0);
+        assert_eq!(nice_for_shares(512), 3);
+        assert_eq!(nice_for_shares(2), 19);
+        assert_eq!(nice_for_shares(0), 19);
+    }
+
+    #[test]
+    fn parses_cgroup_files() {
+        assert_eq!(
+            own_cgroup_path("0::/user.slice/user-1000.slice/session-2.scope\n"),
+            Some("/user.slice/user-1000.slice/session-2.scope")
+        );
+        assert_eq!(own_cgroup_path("1:name=systemd:/init.scope\n"), None);
+
+        let events = "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(oom_kill_count(events), 1);
+        assert_eq!(oom_kill_count(""), 0);
+    }
+
+    #[test]
+    fn cgroup_dir_names_stay_inside_the_root() {
+        assert_eq!(cgroup_dir_name("Worker1"), "agent-relay-Worker1");
+        assert_eq!(cgroup_dir_name(".."), "agent-relay-..");
+        let escaped = cgroup_dir_name("../../system.slice");
+        assert!(!escaped.contains('/'), "{escaped}");
+        assert!(escaped.starts_with("agent-relay-..-..-system.slice-"));
+        assert_ne!(cgroup_dir_name("a/b"), cgroup_dir_name("a-b"));
+        assert_eq!(
+            Path::new("/cg").join(cgroup_dir_name("a/../b")).parent(),
+            Some(Path::new("/cg"))
+        );
+    }
+
+    #[test]
+    fn vacating_moves_processes_into_the_broker_leaf() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cgroup.procs"), "4242\n").unwrap();
+
+        vacate_cgroup(root.path(), |_| true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.path().join("broker/cgroup.procs")).unwrap(),
+            "4242"
+        );
+        // A second broker start reuses the leaf.
+        vacate_cgroup(root.path(), |_| true).unwrap();
+    }
+
+    #[test]
+    fn vacating_refuses_a_cgroup_shared_with_other_processes() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cgroup.procs"), "4242\n5151\n").unwrap();
+
+        let error = vacate_cgroup(root.path(), |pid| pid == 4242).unwrap_err();
+        assert!(error.to_string().contains("5151"), "{error}");
+        assert!(!root.path().join("broker").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.path().join("cgroup.procs")).unwrap(),
+            "4242\n5151\n"
+        );
+    }
+
+    #[test]
+    fn parses_parent_pid_from_stat() {
+        assert_eq!(
+            stat_parent_pid("4242 (claude (bot) x) S 4100 4242 4100 0 -1"),
+            Some(4100)
+        );
+        assert_eq!(stat_parent_pid("garbage"), None);
+    }
+
+    #[test]
+    fn cgroup_is_created_with_limits_and_removed() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cgroup.subtree_control"), "").unwrap();
+        let limits = AgentResourceLimits {
+            max_memory_mb: Some(512),
+            cpu_shares: Some(2048),
+            restart_on_limit: false,
+        };
+
+        let cgroup = AgentCgroup::create(root.path(), "Worker1", &limits).unwrap();
+        let dir = root.path().join("agent-relay-Worker1");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("memory.max")).unwrap(),
+            (512 * 1024 * 1024).to_string()
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("cpu.weight")).unwrap(),
+            "200"
+        );
+
+        // A real cgroup directory is removable while its files are not.
+        for file in ["memory.max", "memory.swap.max", "cpu.weight"] {
+            std::fs::remove_file(dir.join(file)).unwrap();
+        }
+        cgroup.remove();
+        assert!(!dir.exists());
+    }
+}
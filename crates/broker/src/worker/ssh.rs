@@ -0,0 +1,303 @@
+//! The `ssh` agent runtime.
+//!
+//! The PTY worker's harness is `ssh -t`, so the CLI runs in a terminal on the
+//! remote host while injection, idle detection and scrollback work as they
+//! do locally. Before the session starts, one non-interactive `ssh ... sh -s`
+//! call provisions the host: the relay env goes into a private file that the
+//! session sources and deletes, and MCP config files the CLI reads from its
+//! cwd (`opencode.json`, `.cursor/mcp.json`) are copied over unless the host
+//! already has them.
+//!
+//! The remote cwd is the agent's `cwd`, which must exist on the host, or the
+//! login directory. Authentication has to work without prompts (keys or an
+//! agent); `AGENT_RELAY_SSH_OPTS` (or `[ssh] opts`) adds options such as
+//! `-p 2222` or `-i ~/.ssh/build_box`, and `AGENT_RELAY_SSH` picks the ssh
+//! binary.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::broker_config::{env_args_or, SshSection};
+
+/// CLIs whose MCP setup runs `<cli> mcp add` against the local install, so
+/// it cannot be done for a remote one; the remote host has to be set up.
+pub(crate) const HOST_CONFIGURED_MCP_CLIS: [&str; 3] = ["gemini", "droid", "grok"];
+
+const PROVISION_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub(crate) fn ssh_bin() -> String {
+    std::env::var("AGENT_RELAY_SSH")
+        .ok()
+        .filter(|bin| !bin.trim().is_empty())
+        .unwrap_or_else(|| "ssh".to_string())
+}
+
+/// Extra ssh options from `AGENT_RELAY_SSH_OPTS` or `[ssh] opts`.
+pub(crate) fn extra_ssh_args(config: &SshSection) -> Result<Vec<String>> {
+    env_args_or("AGENT_RELAY_SSH_OPTS", config.opts.as_deref())
+}
+
+/// Files under `dir`, relative to it, with their contents.
+pub(crate) fn staged_files(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read staged {}", path.display()))?;
+                let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+                files.push((relative, contents));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn quote(value: &str) -> Result<String> {
+    Ok(shlex::try_quote(value)
+        .context("value contains a nul byte")?
+        .into_owned())
+}
+
+fn is_env_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// One agent's remote session.
+pub(crate) struct SshAgent<'a> {
+    pub(crate) host: &'a str,
+    pub(crate) user: Option<&'a str>,
+    pub(crate) name: &'a str,
+    pub(crate) cwd: Option<&'a str>,
+    pub(crate) extra_args: Vec<String>,
+}
+
+impl SshAgent<'_> {
+    fn destination_args(&self) -> Vec<String> {
+        let mut args = self.extra_args.clone();
+        args.extend(["-o".to_string(), "BatchMode=yes".to_string()]);
+        if let Some(user) = self.user {
+            args.extend(["-l".to_string(), user.to_string()]);
+        }
+        args.push(self.host.to_string());
+        args
+    }
+
+    /// Remote env file, as a shell word relative to `$HOME`.
+    fn env_file(&self) -> String {
+        let file: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        format!("\"$HOME\"/.agent-relay/ssh/{file}.env")
+    }
+
+    /// Script for `sh -s` on the host that writes the env file and any
+    /// staged MCP files missing there.
+    pub(crate) fn provision_script(
+        &self,
+        env: &[(String, String)],
+        files: &[(PathBuf, String)],
+    ) -> Result<String> {
+        let delimiter = format!("AGENT_RELAY_EOF_{}", uuid::Uuid::new_v4().simple());
+        let mut script = String::from("set -e\numask 077\nmkdir -p \"$HOME\"/.agent-relay/ssh\n");
+        script.push_str(&format!("cat > {} <<'{delimiter}'\n", self.env_file()));
+        for (key, value) in env.iter().filter(|(key, _)| is_env_key(key)) {
+            script.push_str(&format!("{key}={}\n", quote(value)?));
+        }
+        script.push_str(&format!("{delimiter}\n"));
+        if let Some(cwd) = self.cwd {
+            script.push_str(&format!("cd {}\n", quote(cwd)?));
+        }
+        for (path, contents) in files {
+            let path = quote(&path.to_string_lossy())?;
+            script.push_str(&format!(
+                "if [ ! -e {path} ]; then\nmkdir -p \"$(dirname {path})\"\ncat > {path} <<'{delimiter}'\n{contents}"
+            ));
+            if !contents.ends_with('\n') {
+                script.push('\n');
+            }
+            script.push_str(&format!("{delimiter}\nfi\n"));
+        }
+        Ok(script)
+    }
+
+    /// Arguments to ssh for the interactive session running `cli args`.
+    pub(crate) fn session_args(&self, cli: &str, args: &[String]) -> Result<Vec<String>> {
+        let env_file = self.env_file();
+        let mut remote = format!("set -a && . {env_file} && rm -f {env_file} && set +a");
+        if let Some(cwd) = self.cwd {
+            remote.push_str(&format!(" && cd {}", quote(cwd)?));
+        }
+        remote.push_str(" && exec");
+        for word in std::iter::once(cli).chain(args.iter().map(String::as_str)) {
+            remote.push(' ');
+            remote.push_str(&quote(word)?);
+        }
+
+        let mut ssh_args = vec!["-t".to_string()];
+        ssh_args.extend(self.destination_args());
+        ssh_args.push("--".to_string());
+        // The login shell may not be POSIX; run the command under `sh`.
+        ssh_args.push(format!("sh -c {}", quote(&remote)?));
+        Ok(ssh_args)
+    }
+
+    /// The provisioning call for `script`, detached from this agent's
+    /// borrows so it can run in the background.
+    pub(crate) fn provisioning(&self, script: String) -> SshProvision {
+        SshProvision {
+            host: self.host.to_string(),
+            destination_args: self.destination_args(),
+            script,
+        }
+    }
+}
+
+/// A provisioning script and where to run it; see
+/// [`SshAgent::provisioning`].
+pub(crate) struct SshProvision {
+    host: String,
+    destination_args: Vec<String>,
+    script: String,
+}
+
+impl SshProvision {
+    /// Run the script on the host.
+    pub(crate) async fn run(self) -> Result<()> {
+        let mut command = Command::new(ssh_bin());
+        command
+            .args(&self.destination_args)
+            .args(["--", "sh -s"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command.spawn().context("failed to run ssh")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.script.as_bytes()).await?;
+        }
+        let output = tokio::time::timeout(PROVISION_TIMEOUT, child.wait_with_output())
+            .await
+            .context("ssh provisioning timed out")??;
+        if !output.status.success() {
+            bail!(
+                "failed to provision agent on {}: {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(cwd: Option<&str>) -> SshAgent<'_> {
+        SshAgent {
+            host: "build-01",
+            user: Some("ci"),
+            name: "Worker 1",
+            cwd,
+            extra_args: vec!["-p".to_string(), "2222".to_string()],
+        }
+    }
+
+    #[test]
+    fn session_sources_env_and_execs_cli_in_cwd() {
+        let args = agent(Some("/srv/my repo"))
+            .session_args("claude", &["--model".to_string(), "it's".to_string()])
+            .unwrap();
+        assert_eq!(
+            args[..8],
+            [
+                "-t",
+                "-p",
+                "2222",
+                "-o",
+                "BatchMode=yes",
+                "-l",
+                "ci",
+                "build-01"
+            ]
+        );
+        assert_eq!(args[8], "--");
+        let remote = shlex::split(args[9].strip_prefix("sh -c ").unwrap()).unwrap();
+        assert_eq!(
+            remote,
+            vec![
+                "set -a && . \"$HOME\"/.agent-relay/ssh/Worker-1.env && rm -f \"$HOME\"/.agent-relay/ssh/Worker-1.env \
+                 && set +a && cd '/srv/my repo' && exec claude --model \"it's\""
+            ]
+        );
+    }
+
+    #[test]
+    fn provision_script_writes_env_and_files() {
+        let script = agent(Some("/srv/app"))
+            .provision_script(
+                &[
+                    ("RELAY_AGENT_TOKEN".to_string(), "at_live_'x".to_string()),
+                    ("not a key".to_string(), "skipped".to_string()),
+                ],
+                &[(PathBuf::from(".cursor/mcp.json"), "{}".to_string())],
+            )
+            .unwrap();
+        let delimiter = script
+            .lines()
+            .find_map(|line| line.strip_prefix("cat > \"$HOME\"/.agent-relay/ssh/Worker-1.env <<'"))
+            .and_then(|rest| rest.strip_suffix('\''))
+            .unwrap()
+            .to_string();
+        assert!(
+            script.contains("RELAY_AGENT_TOKEN=\"at_live_'x\"\n"),
+            "{script}"
+        );
+        assert!(!script.contains("skipped"));
+        assert!(script.contains("cd /srv/app\n"));
+        assert!(script.contains(&format!(
+            "if [ ! -e .cursor/mcp.json ]; then\nmkdir -p \"$(dirname .cursor/mcp.json)\"\n\
+             cat > .cursor/mcp.json <<'{delimiter}'\n{{}}\n{delimiter}\nfi\n"
+        )));
+    }
+
+    #[test]
+    fn staged_files_are_relative() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".cursor")).unwrap();
+        std::fs::write(dir.path().join(".cursor/mcp.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("opencode.json"), "{\"a\":1}").unwrap();
+
+        assert_eq!(
+            staged_files(dir.path()).unwrap(),
+            vec![
+                (PathBuf::from(".cursor/mcp.json"), "{}".to_string()),
+                (PathBuf::from("opencode.json"), "{\"a\":1}".to_string()),
+            ]
+        );
+    }
+}
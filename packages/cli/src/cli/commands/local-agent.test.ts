@@ -9,9 +9,10 @@ vi.mock('@agent-relay/harness-driver', () => ({
   },
 }));
 
-import { registerLocalAgentCommands, type LocalAgentDependencies } from './local-agent.js';
+import { parseTeamManifest, registerLocalAgentCommands, type LocalAgentDependencies } from './local-agent.js';
 
 function harness(overrides: Partial<LocalAgentDependencies> = {}) {
+  const listeners: Array<(event: unknown) => void> = [];
   const client = {
     listAgents: vi.fn(async () => [{ name: 'lead' }]),
     spawnPty: vi.fn(async () => undefined),
@@ -19,6 +20,20 @@ function harness(overrides: Partial<LocalAgentDependencies> = {}) {
     setModel: vi.fn(async () => ({ name: 'lead', model: 'opus', success: true })),
     flushPending: vi.fn(async () => ({ flushed: 2 })),
     setInboundDeliveryMode: vi.fn(async (_name: string, mode: string) => ({ mode, flushed: 0 })),
+    connectEvents: vi.fn(),
+    onEvent: vi.fn((listener: (event: unknown) => void) => {
+      listeners.push(listener);
+      return () => undefined;
+    }),
+    spawnTeam: vi.fn(async (manifest: { name: string; agents: Array<{ name: string }> }) => {
+      const result = { team: manifest.name, agents: manifest.agents.map((agent) => agent.name) };
+      queueMicrotask(() => {
+        for (const listener of listeners) {
+          listener({ kind: 'team_ready', team: manifest.name, agents: result.agents, elapsed_ms: 1200 });
+        }
+      });
+      return result;
+    }),
   };
   const attach = vi.fn(async () => 0);
   const log = vi.fn();
@@ -131,6 +146,50 @@ describe('local agent subtree', () => {
     );
   });
 
+  it('team up spawns the manifest and waits for team_ready', async () => {
+    const readFile = vi.fn(
+      () => `name: backend
+channels: [backend]
+agents:
+  - name: Architect
+    runtime: pty
+    cli: claude
+    task: Design the API.
+  - name: Builder
+    runtime: pty
+    cli: codex
+    task: Build what Architect designs.
+    depends_on: [Architect]
+`
+    );
+    const { program, client, log } = harness({ readFile });
+    await program.parseAsync(['local', 'team', 'up', 'team.yaml'], { from: 'user' });
+
+    expect(readFile).toHaveBeenCalledWith('/tmp/project/team.yaml');
+    expect(client.connectEvents).toHaveBeenCalled();
+    expect(client.spawnTeam).toHaveBeenCalledWith(
+      expect.objectContaining({
+        name: 'backend',
+        channels: ['backend'],
+        agents: [
+          expect.objectContaining({ name: 'Architect', cli: 'claude' }),
+          expect.objectContaining({ name: 'Builder', depends_on: ['Architect'] }),
+        ],
+      })
+    );
+    expect(log).toHaveBeenCalledWith('Spawned team backend: Architect, Builder.');
+    expect(log).toHaveBeenCalledWith('Team backend is ready (1200 ms).');
+  });
+
+  it('parseTeamManifest rejects a manifest without agents', () => {
+    expect(() => parseTeamManifest('{"name": "backend", "agents": []}', 'team.json')).toThrow(
+      'team.json: team "backend" has no agents'
+    );
+    expect(() => parseTeamManifest('name: backend', 'team.yaml')).toThrow(
+      'team.yaml: a team manifest needs a "name" and an "agents" list'
+    );
+  });
+
   it('release calls client.release', async () => {
     const { program, client } = harness();
     await program.parseAsync(['local', 'agent', 'release', 'lead'], { from: 'user' });
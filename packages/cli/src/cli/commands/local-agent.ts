@@ -1,6 +1,10 @@
+import fs from 'node:fs';
+import path from 'node:path';
+
 import type { Command } from 'commander';
+import { parse as parseYaml } from 'yaml';
 
-import { HarnessDriverClient } from '@agent-relay/harness-driver';
+import { HarnessDriverClient, type BrokerEvent, type TeamManifest } from '@agent-relay/harness-driver';
 
 import { createBrokerClient } from '../lib/attach-broker.js';
 import {
@@ -51,6 +55,22 @@ function resolveAutoSpawn(
   };
 }
 
+/**
+ * Parse a team manifest. YAML is a superset of JSON, so this reads both.
+ */
+export function parseTeamManifest(text: string, source: string): TeamManifest {
+  const manifest = parseYaml(text) as Partial<TeamManifest> | null;
+  if (!manifest || typeof manifest.name !== 'string' || !Array.isArray(manifest.agents)) {
+    throw new Error(`${source}: a team manifest needs a "name" and an "agents" list`);
+  }
+  if (manifest.agents.length === 0) {
+    throw new Error(`${source}: team "${manifest.name}" has no agents`);
+  }
+  return manifest as TeamManifest;
+}
+
+type TeamOutcome = Extract<BrokerEvent, { kind: 'team_ready' | 'team_failed' }>;
+
 export type AttachMode = 'drive' | 'view' | 'passthrough';
 export type LocalAgentMessageBrokerOptions = BrokerConnectionOptions;
 
@@ -82,6 +102,7 @@ export interface LocalAgentDependencies {
     options: { brokerUrl?: string; apiKey?: string; stateDir?: string }
   ) => Promise<number>;
   cwd: () => string;
+  readFile: (filePath: string) => string;
   readConnectionFile: (stateDir: string) => unknown;
   getDefaultStateDir: () => string;
   env: NodeJS.ProcessEnv;
@@ -95,6 +116,7 @@ function withDefaults(overrides: Partial<LocalAgentDependencies> = {}): LocalAge
   const deps = {
     connect: async (cwd: string) => HarnessDriverClient.connect({ cwd }),
     cwd: () => process.cwd(),
+    readFile: (filePath: string) => fs.readFileSync(filePath, 'utf-8'),
     readConnectionFile: readConnectionFileFromDisk,
     getDefaultStateDir: defaultStateDir,
     env: process.env,
@@ -167,7 +189,7 @@ function brokerOptionsFromOpts(opts: Record<string, unknown>): LocalAgentMessage
 }
 
 /**
- * Register the `local agent …` subtree (and `runtime tail`) onto the driver
+ * Register the `local agent …` subtree (and `team up`, `runtime tail`) onto the driver
  * group. List/spawn/release/kill talk to a running local broker.
  */
 export function registerLocalAgentCommands(
@@ -386,6 +408,48 @@ export function registerLocalAgentCommands(
     });
   });
 
+  const team = group.command('team').description('Bring up teams of broker-spawned agents');
+
+  team
+    .command('up')
+    .description('Spawn every agent in a YAML or JSON team manifest, then wait until all are ready')
+    .argument('<manifest>', 'Path to the team manifest')
+    .option('--no-wait', 'Return once the agents are spawned instead of waiting for them to be ready')
+    .action(async (manifestPath: string, opts: { wait: boolean }) => {
+      await run(deps, async (client) => {
+        const manifest = parseTeamManifest(
+          deps.readFile(path.resolve(deps.cwd(), manifestPath)),
+          manifestPath
+        );
+        // Subscribe before spawning so a fast team_ready is not missed.
+        let outcome: Promise<TeamOutcome> | undefined;
+        if (opts.wait) {
+          client.connectEvents();
+          outcome = new Promise((resolve) => {
+            const unsubscribe = client.onEvent((event) => {
+              if (
+                (event.kind === 'team_ready' || event.kind === 'team_failed') &&
+                event.team === manifest.name
+              ) {
+                unsubscribe();
+                resolve(event);
+              }
+            });
+          });
+        }
+        const result = await client.spawnTeam(manifest);
+        deps.log(`Spawned team ${result.team}: ${result.agents.join(', ')}.`);
+        if (!outcome) {
+          return;
+        }
+        const event = await outcome;
+        if (event.kind === 'team_failed') {
+          throw new Error(`Team ${event.team} failed: ${event.error}`);
+        }
+        deps.log(`Team ${event.team} is ready (${event.elapsed_ms} ms).`);
+      });
+    });
+
   group
     .command('tail')
     .description('Stream broker events (optionally filtered to one agent)')
@@ -27,16 +27,21 @@ import type {
   BrokerStats,
   BrokerStatus,
   CrashInsightsResponse,
+  DeadLetter,
   PendingRelayMessage,
   PtySnapshot,
   InboundDeliveryMode,
+  Plan,
   SnapshotFormat,
+  TeamManifest,
 } from './protocol.js';
 import type {
   SpawnAgentResult,
   SpawnCliInput,
   SpawnHeadlessInput,
   SpawnPtyInput,
+  SpawnTeamResult,
+  RunPlanResult,
   SendMessageInput,
   ListAgent,
 } from './types.js';
@@ -57,7 +62,6 @@ import {
   applySpawnPatch,
   buildSpawnCliBody,
   buildSpawnPtyBody,
-  isBundledHeadlessCli,
   resolveSpawnTransport,
 } from './spawn-request.js';
 import {
@@ -100,12 +104,15 @@ export const SpawnAgentResultSchema = z
   .object({
     success: z.boolean().optional(),
     name: z.string(),
-    runtime: z.enum(['pty', 'headless']),
+    runtime: z.enum(['pty', 'headless', 'docker', 'kubernetes', 'ssh']),
     model: z.string().nullable().optional(),
     pid: optionalNumber,
     pre_registered: z.boolean().optional(),
     warning: z.string().nullable().optional(),
     sessionId: optionalString,
+    /** Set when the broker was at its concurrency cap; watch for `spawn_started`. */
+    queued: z.boolean().optional(),
+    position: optionalNumber,
   })
   .passthrough();
 
@@ -124,6 +131,34 @@ export interface SetInboundDeliveryModeResult {
   flushed: number;
 }
 
+export interface AgentPauseResult {
+  name: string;
+  paused: boolean;
+  /** False when the agent was already in the requested state. */
+  changed: boolean;
+  pending_delivery_count: number;
+}
+
+export interface AgentRestartResult {
+  name: string;
+  pid?: number | null;
+  restart_count: number;
+  /** Deliveries carried over to the new process. */
+  pending_delivery_count: number;
+  task_reinjected: boolean;
+  continuity_restored: boolean;
+}
+
+export interface BrokerDrainResult {
+  draining: boolean;
+  /** True when a drain was already running; `remaining_ms` is then set instead of `timeout_ms`. */
+  already_draining: boolean;
+  timeout_ms?: number;
+  remaining_ms?: number;
+  agent_count?: number;
+  pending_delivery_count: number;
+}
+
 export interface WorkerStreamSubscriptionOptions {
   /** Filter by stream name, for example `stdout` or `stderr`. Defaults to all streams. */
   stream?: string;
@@ -526,16 +561,9 @@ export class HarnessDriverClient {
   ): Promise<SpawnedAgentHandle> {
     const t0 = Date.now();
     const resolvedInput = await this.runBeforeSpawn(beforeCtx);
+    // Headless CLIs other than the bundled ones are broker-configured
+    // templates, so the broker validates them.
     const transport = resolveSpawnTransport(resolvedInput);
-    if (
-      transport === 'headless' &&
-      !isBundledHeadlessCli(resolvedInput.cli) &&
-      !resolvedInput.harnessConfig
-    ) {
-      throw new Error(
-        `cli '${resolvedInput.cli}' does not support headless transport (supported: claude, opencode)`
-      );
-    }
 
     try {
       const rawResult = await this.transport.request<unknown>('/api/spawn', {
@@ -571,6 +599,28 @@ export class HarnessDriverClient {
     return this.spawnCli({ ...input, cli: 'opencode' });
   }
 
+  /**
+   * Spawn every agent in `manifest`, or none of them. Resolves once all are
+   * spawned; a `team_ready` event follows when every member reports ready.
+   */
+  async spawnTeam(manifest: TeamManifest): Promise<SpawnTeamResult> {
+    return this.transport.request<SpawnTeamResult>('/api/team', {
+      method: 'POST',
+      body: JSON.stringify(manifest),
+    });
+  }
+
+  /**
+   * Start `plan` against agents that are already running. Resolves once the
+   * broker accepts it; `plan_*` events report progress and the step outputs.
+   */
+  async runPlan(plan: Plan): Promise<RunPlanResult> {
+    return this.transport.request<RunPlanResult>('/api/plan', {
+      method: 'POST',
+      body: JSON.stringify(plan),
+    });
+  }
+
   async release(name: string, reason?: string): Promise<{ name: string }> {
     const beforeCtx: BeforeAgentReleaseContext = { name, reason, baseUrl: this.baseUrl };
     const t0 = Date.now();
@@ -622,6 +672,19 @@ export class HarnessDriverClient {
     return result.agents;
   }
 
+  /** Deliveries that ran out of retries, oldest first. */
+  async listDeadLetters(): Promise<DeadLetter[]> {
+    const result = await this.transport.request<{ dead_letters: DeadLetter[] }>('/api/dead-letters');
+    return result.dead_letters;
+  }
+
+  /** Deliver a dead letter to its worker again with a fresh retry budget. */
+  async requeueDeadLetter(deliveryId: string): Promise<{ name: string; delivery_id: string }> {
+    return this.transport.request(`/api/dead-letters/${encodeURIComponent(deliveryId)}/requeue`, {
+      method: 'POST',
+    });
+  }
+
   // ── PTY control ────────────────────────────────────────────────────
 
   async sendInput(name: string, data: string): Promise<{ name: string; bytes_written: number }> {
@@ -697,6 +760,25 @@ export class HarnessDriverClient {
     return { flushed: typeof result.flushed === 'number' ? result.flushed : 0 };
   }
 
+  async pause(name: string): Promise<AgentPauseResult> {
+    return this.transport.request<AgentPauseResult>(`/api/spawned/${encodeURIComponent(name)}/pause`, {
+      method: 'POST',
+    });
+  }
+
+  async resume(name: string): Promise<AgentPauseResult> {
+    return this.transport.request<AgentPauseResult>(`/api/spawned/${encodeURIComponent(name)}/resume`, {
+      method: 'POST',
+    });
+  }
+
+  async restart(name: string, reason?: string): Promise<AgentRestartResult> {
+    return this.transport.request<AgentRestartResult>(`/api/spawned/${encodeURIComponent(name)}/restart`, {
+      method: 'POST',
+      ...(reason ? { body: JSON.stringify({ reason }) } : {}),
+    });
+  }
+
   async snapshot(name: string, format: SnapshotFormat = 'plain'): Promise<PtySnapshot> {
     return this.transport.request<PtySnapshot>(
       `/api/spawned/${encodeURIComponent(name)}/snapshot?format=${encodeURIComponent(format)}`
@@ -833,10 +915,25 @@ export class HarnessDriverClient {
     });
   }
 
+  /** Move an agent into `team`, or out of its team with `null`. Sends to `@<team>` reach every member. */
+  async setAgentTeam(name: string, team: string | null): Promise<void> {
+    await this.transport.request(`/api/spawned/${encodeURIComponent(name)}/team`, {
+      method: 'POST',
+      body: JSON.stringify({ team }),
+    });
+  }
+
   // ── Observability ──────────────────────────────────────────────────
 
   async getMetrics(agent?: string): Promise<{
-    agents: Array<{ name: string; pid: number; memory_bytes: number; uptime_secs: number }>;
+    agents: Array<{
+      name: string;
+      pid: number;
+      memory_bytes: number;
+      uptime_secs: number;
+      cpu_time_ms: number;
+      cpu_percent: number;
+    }>;
     broker?: BrokerStats;
   }> {
     const query = agent ? `?agent=${encodeURIComponent(agent)}` : '';
@@ -870,6 +967,14 @@ export class HarnessDriverClient {
    * - For connected brokers (via .connect() or constructor): just disconnects the transport.
    *   Does NOT kill the broker — the caller doesn't own it.
    */
+  /** Ask the broker to drain: it stops taking new work and exits once pending deliveries settle. */
+  async drain(timeoutMs?: number): Promise<BrokerDrainResult> {
+    return this.transport.request<BrokerDrainResult>('/api/drain', {
+      method: 'POST',
+      ...(timeoutMs !== undefined ? { body: JSON.stringify({ timeout_ms: timeoutMs }) } : {}),
+    });
+  }
+
   async shutdown(): Promise<void> {
     if (this.leaseTimer) {
       clearInterval(this.leaseTimer);
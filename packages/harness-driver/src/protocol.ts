@@ -1,7 +1,19 @@
 export const PROTOCOL_VERSION = 2 as const;
 
-export type AgentRuntime = 'pty' | 'headless';
-export type HeadlessProvider = 'claude' | 'opencode';
+/** Remote machine an `ssh` runtime agent runs on. */
+export interface SshTarget {
+  host: string;
+  user?: string;
+}
+
+/**
+ * How an agent's CLI runs. Specs and events carry ssh agents as `{ ssh: SshTarget }`; spawn
+ * replies report them as `'ssh'`.
+ */
+export type AgentRuntime = 'pty' | 'headless' | 'docker' | 'kubernetes' | 'ssh' | { ssh: SshTarget };
+export type BundledHeadlessProvider = 'claude' | 'opencode' | 'codex' | 'gemini';
+/** A bundled provider, or the name of a headless provider template configured on the broker. */
+export type HeadlessProvider = BundledHeadlessProvider | (string & {});
 export type InboundDeliveryMode = 'auto_inject' | 'manual_flush';
 export type SnapshotFormat = 'plain' | 'ansi';
 
@@ -27,6 +39,49 @@ export interface AgentSpec {
   shadow_of?: string;
   shadow_mode?: string;
   restart_policy?: RestartPolicy;
+  /** Container image for the `docker` and `kubernetes` runtimes. */
+  image?: string;
+  max_memory_mb?: number;
+  /** Relative CPU weight; 1024 is the default share. */
+  cpu_shares?: number;
+  restart_on_limit?: boolean;
+}
+
+/** One agent of a {@link TeamManifest}: an {@link AgentSpec} plus how it starts. */
+export interface TeamMember extends AgentSpec {
+  /** Broker spawn profile that fills in what the spec leaves unset. */
+  profile?: string;
+  task?: string;
+  /** Members that must report ready before this member's `task` is delivered. */
+  depends_on?: string[];
+}
+
+export interface TeamManifest {
+  /** Recorded as each member's `team` unless the member sets its own. */
+  name: string;
+  /** Joined by every member on top of its own channels. */
+  channels?: string[];
+  agents: TeamMember[];
+  /** Seconds members have to report ready before the team is released (default 300). */
+  ready_timeout_secs?: number;
+}
+
+/** One task of a {@link Plan}, handed to an already-running agent. */
+export interface PlanStep {
+  id: string;
+  agent: string;
+  task: string;
+  /** Steps that must finish before this one starts. */
+  depends_on?: string[];
+  /** Steps whose outputs are appended to `task`; implies `depends_on`. */
+  inputs_from?: string[];
+}
+
+export interface Plan {
+  name: string;
+  steps: PlanStep[];
+  /** Seconds the whole plan may run before it fails. */
+  timeout_secs?: number;
 }
 
 export type MessageInjectionMode = 'wait' | 'steer';
@@ -189,8 +244,22 @@ export type SdkToBroker =
         initial_task?: string;
         skip_relay_prompt?: boolean;
         invocation_id?: string;
+        /** Broker spawn profile that fills in what `agent` leaves unset. */
+        profile?: string;
+        /** Agents that must report ready before this one is spawned. */
+        after?: string[];
       };
     }
+  | {
+      /** Spawn every agent in the manifest, or none. `team_ready` follows once all report ready. */
+      type: 'spawn_team';
+      payload: { manifest: TeamManifest };
+    }
+  | {
+      /** Run a plan against running agents. `plan_*` events report its progress. */
+      type: 'run_plan';
+      payload: { plan: Plan };
+    }
   | {
       type: 'send_message';
       payload: {
@@ -209,6 +278,18 @@ export type SdkToBroker =
       type: 'release_agent';
       payload: { name: string; reason?: string };
     }
+  | {
+      type: 'pause_agent';
+      payload: { name: string };
+    }
+  | {
+      type: 'resume_agent';
+      payload: { name: string };
+    }
+  | {
+      type: 'restart_agent';
+      payload: { name: string; reason?: string };
+    }
   | {
       type: 'send_input';
       payload: { name: string; data: string };
@@ -221,6 +302,10 @@ export type SdkToBroker =
       type: 'unsubscribe_channels';
       payload: { name: string; channels: string[] };
     }
+  | {
+      type: 'set_agent_team';
+      payload: { name: string; team: string | null };
+    }
   | {
       type: 'set_model';
       payload: { name: string; model: string; timeout_ms?: number };
@@ -229,10 +314,24 @@ export type SdkToBroker =
       type: 'get_metrics';
       payload: { agent?: string };
     }
+  | {
+      /** Read a worker log: the last `lines` lines (default 100), or up to `max_bytes` from byte `offset`. `follow: true` streams appended output as `log_chunk` events; `follow: false` stops it. */
+      type: 'get_logs';
+      payload: { name: string; lines?: number; offset?: number; max_bytes?: number; follow?: boolean };
+    }
   | {
       type: 'list_agents';
       payload: Record<string, never>;
     }
+  | {
+      type: 'list_dead_letters';
+      payload: Record<string, never>;
+    }
+  | {
+      /** Deliver a dead letter to its worker again with a fresh retry budget. */
+      type: 'requeue_dead_letter';
+      payload: { delivery_id: string };
+    }
   | {
       type: 'get_status';
       payload: Record<string, never>;
@@ -241,6 +340,11 @@ export type SdkToBroker =
       type: 'get_crash_insights';
       payload: Record<string, never>;
     }
+  | {
+      /** Refuse new spawns and node deliveries, notify agents, then exit once pending deliveries settle. */
+      type: 'drain';
+      payload: { timeout_ms?: number };
+    }
   | {
       type: 'shutdown';
       payload: Record<string, never>;
@@ -270,6 +374,16 @@ export interface PendingDeliveryInfo {
   last_error?: string;
 }
 
+/** A delivery that ran out of retries, kept in the broker's dead-letter file. */
+export interface DeadLetter {
+  worker_name: string;
+  delivery: RelayDelivery;
+  attempts: number;
+  last_error: string;
+  queued_at_ms: number;
+  dead_at_ms: number;
+}
+
 export interface BrokerStatus {
   agent_count: number;
   agents: Array<{
@@ -381,12 +495,21 @@ export type BrokerEvent =
   | {
       kind: 'agent_released';
       name: string;
+      /** Pids still alive after SIGKILL during release; omitted when empty. */
+      survivors?: number[];
     }
   | {
       kind: 'agent_exit';
       name: string;
       reason: string;
     }
+  | {
+      kind: 'orphan_process_detected';
+      name: string;
+      pid: number;
+      command?: string;
+      reaped: boolean;
+    }
   | {
       kind: 'agent_exited';
       name: string;
@@ -415,6 +538,14 @@ export type BrokerEvent =
       stream: string;
       chunk: string;
     }
+  | {
+      kind: 'log_chunk';
+      name: string;
+      content: string;
+      offset: number;
+      next_offset: number;
+      size: number;
+    }
   | {
       kind: 'delivery_retry';
       name: string;
@@ -422,6 +553,15 @@ export type BrokerEvent =
       event_id: string;
       attempts: number;
     }
+  | {
+      kind: 'delivery_dead_lettered';
+      name: string;
+      delivery_id: string;
+      event_id: string;
+      from: string;
+      attempts: number;
+      last_error: string;
+    }
   | {
       kind: 'delivery_dropped';
       name: string;
@@ -518,6 +658,12 @@ export type BrokerEvent =
       name: string;
       channels: string[];
     }
+  | {
+      kind: 'agent_team_changed';
+      name: string;
+      team?: string | null;
+      previous_team?: string | null;
+    }
   | {
       kind: 'worker_ready';
       name: string;
@@ -589,6 +735,112 @@ export type BrokerEvent =
       kind: 'agent_permanently_dead';
       name: string;
       reason: string;
+    }
+  | {
+      kind: 'agent_paused';
+      name: string;
+      pending_delivery_count: number;
+    }
+  | {
+      kind: 'agent_resumed';
+      name: string;
+      paused_ms: number;
+      pending_delivery_count: number;
+    }
+  | {
+      kind: 'spawn_queued';
+      name: string;
+      /** 1-based position in the spawn queue. */
+      position: number;
+      running: number;
+      max_concurrent: number;
+    }
+  | {
+      /** A spawn held until the agents in its `after` report ready; `after` lists those still pending. */
+      kind: 'spawn_waiting';
+      name: string;
+      after: string[];
+    }
+  | {
+      kind: 'spawn_started';
+      name: string;
+      queued_ms: number;
+      pid?: number | null;
+    }
+  | {
+      kind: 'spawn_failed';
+      name: string;
+      error: string;
+    }
+  | {
+      kind: 'agent_limit_exceeded';
+      name: string;
+      limit: 'memory';
+      max_memory_mb: number;
+      /** Absent when the cgroup OOM killer ended the agent first. */
+      memory_bytes?: number | null;
+      action: 'restart' | 'none' | 'killed';
+    }
+  | {
+      kind: 'agent_pod_status';
+      name: string;
+      pod: string;
+      phase: 'Pending' | 'Running' | 'Succeeded' | 'Failed' | 'Unknown';
+      /** Why the pod is not running, e.g. `ImagePullBackOff`. */
+      reason?: string | null;
+    }
+  | {
+      kind: 'team_ready';
+      team: string;
+      agents: string[];
+      elapsed_ms: number;
+    }
+  | {
+      /** A member exited, or not every member was ready in time; the team was released. */
+      kind: 'team_failed';
+      team: string;
+      error: string;
+    }
+  | {
+      kind: 'plan_step_started';
+      plan: string;
+      step: string;
+      agent: string;
+    }
+  | {
+      kind: 'plan_step_completed';
+      plan: string;
+      step: string;
+      agent: string;
+      /** The agent's `agent_result` data if it submitted one, otherwise what it printed. */
+      output: unknown;
+    }
+  | {
+      kind: 'plan_completed';
+      plan: string;
+      /** Step outputs keyed by step id. */
+      results: Record<string, unknown>;
+      elapsed_ms: number;
+    }
+  | {
+      kind: 'plan_failed';
+      plan: string;
+      step?: string | null;
+      error: string;
+    }
+  | {
+      kind: 'broker_draining';
+      reason: string;
+      timeout_ms: number;
+      agent_count: number;
+      pending_delivery_count: number;
+    }
+  | {
+      kind: 'broker_drained';
+      /** `settled` when every pending delivery finished, `timeout` otherwise. */
+      outcome: 'settled' | 'timeout';
+      elapsed_ms: number;
+      pending_delivery_count: number;
     };
 
 export type BrokerToSdk =
@@ -9,14 +9,9 @@
  */
 import { actionSchemaToJsonSchema, type ActionSchema } from '@agent-relay/sdk/actions';
 
-import type { HeadlessProvider } from './protocol.js';
 import type { AgentTransport, SpawnCliInput, SpawnPtyInput } from './types.js';
 import type { SpawnPatch } from './lifecycle-hooks.js';
 
-export function isBundledHeadlessCli(value: string): value is HeadlessProvider {
-  return value === 'claude' || value === 'opencode';
-}
-
 export function resolveSpawnTransport(input: SpawnCliInput): AgentTransport {
   if (input.transport) return input.transport;
   if (input.harnessConfig) return input.harnessConfig.runtime;
@@ -57,6 +52,11 @@ export function buildSpawnPtyBody(input: SpawnPtyInput): Record<string, unknown>
     ...(input.harnessConfig !== undefined ? { harnessConfig: input.harnessConfig } : {}),
     ...(input.idleThresholdSecs !== undefined ? { idleThresholdSecs: input.idleThresholdSecs } : {}),
     ...(input.restartPolicy !== undefined ? { restartPolicy: input.restartPolicy } : {}),
+    ...(input.profile !== undefined ? { profile: input.profile } : {}),
+    ...(input.after !== undefined ? { after: input.after } : {}),
+    ...(input.maxMemoryMb !== undefined ? { maxMemoryMb: input.maxMemoryMb } : {}),
+    ...(input.cpuShares !== undefined ? { cpuShares: input.cpuShares } : {}),
+    ...(input.restartOnLimit !== undefined ? { restartOnLimit: input.restartOnLimit } : {}),
     ...(input.spawnMode !== undefined ? { spawnMode: input.spawnMode } : {}),
     ...(input.exitAfterTask !== undefined ? { exitAfterTask: input.exitAfterTask } : {}),
     ...(input.skipRelayPrompt !== undefined ? { skipRelayPrompt: input.skipRelayPrompt } : {}),
@@ -83,12 +83,18 @@ export function buildSpawnCliBody(input: SpawnCliInput, transport: AgentTranspor
     ...(input.harnessConfig !== undefined ? { harnessConfig: input.harnessConfig } : {}),
     ...(input.idleThresholdSecs !== undefined ? { idleThresholdSecs: input.idleThresholdSecs } : {}),
     ...(input.restartPolicy !== undefined ? { restartPolicy: input.restartPolicy } : {}),
+    ...(input.profile !== undefined ? { profile: input.profile } : {}),
+    ...(input.after !== undefined ? { after: input.after } : {}),
+    ...(input.maxMemoryMb !== undefined ? { maxMemoryMb: input.maxMemoryMb } : {}),
+    ...(input.cpuShares !== undefined ? { cpuShares: input.cpuShares } : {}),
+    ...(input.restartOnLimit !== undefined ? { restartOnLimit: input.restartOnLimit } : {}),
     ...(input.spawnMode !== undefined ? { spawnMode: input.spawnMode } : {}),
     ...(input.exitAfterTask !== undefined ? { exitAfterTask: input.exitAfterTask } : {}),
     ...(input.skipRelayPrompt !== undefined ? { skipRelayPrompt: input.skipRelayPrompt } : {}),
     ...(input.agentResultSchema !== undefined
       ? { agentResultSchema: resolveAgentResultSchema(input.agentResultSchema) }
       : {}),
+    ...(input.image !== undefined ? { image: input.image } : {}),
     transport,
   };
 }
@@ -36,6 +36,22 @@ export interface SpawnPtyInput {
   shadowMode?: string;
   idleThresholdSecs?: number;
   restartPolicy?: RestartPolicy;
+  /**
+   * Broker spawn profile (`[profiles.<name>]` in the broker config) that fills in fields this
+   * request leaves out; its args come before `args`.
+   */
+  profile?: string;
+  /**
+   * Agents that must report ready before this one is spawned. The broker holds the spawn and
+   * emits `spawn_waiting` until they have; it fails with `spawn_failed` if one exits first.
+   */
+  after?: string[];
+  /** Memory ceiling for the agent's process tree, enforced by a cgroup where available. */
+  maxMemoryMb?: number;
+  /** Relative CPU weight; 1024 is the default share. */
+  cpuShares?: number;
+  /** Restart the agent when it goes over `maxMemoryMb`. */
+  restartOnLimit?: boolean;
   continueFrom?: string;
   harnessConfig?: ResolvedHarnessConfig;
   spawnMode?: SpawnMode;
@@ -65,6 +81,22 @@ export interface SpawnHeadlessInput {
   shadowMode?: string;
   idleThresholdSecs?: number;
   restartPolicy?: RestartPolicy;
+  /**
+   * Broker spawn profile (`[profiles.<name>]` in the broker config) that fills in fields this
+   * request leaves out; its args come before `args`.
+   */
+  profile?: string;
+  /**
+   * Agents that must report ready before this one is spawned. The broker holds the spawn and
+   * emits `spawn_waiting` until they have; it fails with `spawn_failed` if one exits first.
+   */
+  after?: string[];
+  /** Memory ceiling for the agent's process tree, enforced by a cgroup where available. */
+  maxMemoryMb?: number;
+  /** Relative CPU weight; 1024 is the default share. */
+  cpuShares?: number;
+  /** Restart the agent when it goes over `maxMemoryMb`. */
+  restartOnLimit?: boolean;
   continueFrom?: string;
   harnessConfig?: ResolvedHarnessConfig;
   spawnMode?: SpawnMode;
@@ -81,7 +113,8 @@ export interface SpawnHeadlessInput {
   agentToken?: string;
 }
 
-export type AgentTransport = 'pty' | 'headless';
+/** `ssh://[user@]host` runs the CLI on a remote machine over ssh. */
+export type AgentTransport = 'pty' | 'headless' | 'docker' | 'kubernetes' | `ssh://${string}`;
 
 export interface SpawnAgentResult {
   name: string;
@@ -90,10 +123,26 @@ export interface SpawnAgentResult {
   pid?: number;
 }
 
+export interface SpawnTeamResult {
+  team: string;
+  /** Member names in the order they were spawned. */
+  agents: string[];
+}
+
+export interface RunPlanResult {
+  plan: string;
+  steps: number;
+}
+
 export interface SpawnCliInput {
   name: string;
   cli: string;
   transport?: AgentTransport;
+  /**
+   * Container image for the `docker` and `kubernetes` transports; defaults to the broker's
+   * `AGENT_RELAY_DOCKER_IMAGE` or the pod template's image.
+   */
+  image?: string;
   args?: string[];
   channels?: string[];
   task?: string;
@@ -104,6 +153,22 @@ export interface SpawnCliInput {
   shadowMode?: string;
   idleThresholdSecs?: number;
   restartPolicy?: RestartPolicy;
+  /**
+   * Broker spawn profile (`[profiles.<name>]` in the broker config) that fills in fields this
+   * request leaves out; its args come before `args`.
+   */
+  profile?: string;
+  /**
+   * Agents that must report ready before this one is spawned. The broker holds the spawn and
+   * emits `spawn_waiting` until they have; it fails with `spawn_failed` if one exits first.
+   */
+  after?: string[];
+  /** Memory ceiling for the agent's process tree, enforced by a cgroup where available. */
+  maxMemoryMb?: number;
+  /** Relative CPU weight; 1024 is the default share. */
+  cpuShares?: number;
+  /** Restart the agent when it goes over `maxMemoryMb`. */
+  restartOnLimit?: boolean;
   continueFrom?: string;
   harnessConfig?: ResolvedHarnessConfig;
   spawnMode?: SpawnMode;
@@ -8,7 +8,7 @@ from __future__ import annotations
 from typing import Any, Literal
 
 AgentRuntime = Literal["pty", "headless"]
-HeadlessProvider = Literal["claude", "opencode"]
+HeadlessProvider = Literal["claude", "opencode", "codex", "gemini"]
 MessageInjectionMode = Literal["wait", "steer"]
 
 # BrokerEvent is a dict with a 'kind' field discriminator.
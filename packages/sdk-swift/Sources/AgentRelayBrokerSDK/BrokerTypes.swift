@@ -8,6 +8,8 @@ public enum AgentRuntime: String, Codable, Sendable {
 public enum HeadlessProvider: String, Codable, Sendable {
     case claude
     case opencode
+    case codex
+    case gemini
 }
 
 public struct RestartPolicy: Codable, Sendable {
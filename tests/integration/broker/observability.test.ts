@@ -89,6 +89,8 @@ test('broker: getMetrics returns broker and agent metrics', async (t) => {
     assert.equal(typeof agent!.pid, 'number');
     assert.equal(typeof agent!.memory_bytes, 'number');
     assert.equal(typeof agent!.uptime_secs, 'number');
+    assert.equal(typeof agent!.cpu_time_ms, 'number');
+    assert.equal(typeof agent!.cpu_percent, 'number');
 
     // Broker stats
     assert.ok(all.broker, 'broker stats should be present');